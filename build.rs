@@ -23,6 +23,7 @@ use petgraph::visit::Dfs;
 use petgraph::Directed;
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote, TokenStreamExt};
+use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 
 //use petgraph::algo::{dijkstra, min_spanning_tree};
@@ -36,6 +37,8 @@ struct Signal {
     name: String,
     kind: String,
     datatype: TokenStream,
+    // the datatype as written in the specification
+    vss_datatype: String,
     vss_unit_type : Option<TokenStream>,
     complex: String,
     unit: Option<String>,
@@ -49,6 +52,11 @@ struct Signal {
     // key types to include into this type. The boolean indicates
     // whether this key is an enum or not. Enums need the #[topic_key_enum] attribute
     keys: Vec<(String, TokenStream, bool)>,
+    // every combination of key values that this signal has in the
+    // specification. Filled in before the graph is flattened.
+    instances: Vec<Vec<(String, TokenStream)>>,
+    // true for the set-request topic generated for an actuator
+    is_target: bool,
 }
 
 impl PartialEq for Signal {
@@ -84,6 +92,7 @@ fn parse_csv() -> Result<Vec<Signal>, Box<dyn Error>> {
                 name,
                 kind: record[1].into(),
                 datatype: vss_type_to_rust_type(record[2].into()),
+                vss_datatype: record[2].trim().to_owned(),
                 vss_unit_type : vss_type_to_unit_type(record[2].into(), &unit),
                 complex: String::new(),
                 unit: if record[4].len() > 0 {
@@ -107,6 +116,8 @@ fn parse_csv() -> Result<Vec<Signal>, Box<dyn Error>> {
                 id: record[10].into(),
                 default: None,
                 keys: Vec::new(),
+                instances: Vec::new(),
+                is_target: false,
             };
             signals.push(sig);
         }
@@ -116,7 +127,35 @@ fn parse_csv() -> Result<Vec<Signal>, Box<dyn Error>> {
     Ok(signals)
 }
 
+// Generate the kuksa.val.v1 messages and the VAL service with the protoc
+// binary that comes with protoc-bin-vendored
+#[cfg(feature = "kuksa")]
+fn compile_kuksa_protos() {
+    let protoc = protoc_bin_vendored::protoc_bin_path().expect("no protoc for this platform");
+    let include = protoc_bin_vendored::include_path().expect("no protoc includes");
+    env::set_var("PROTOC", protoc);
+    tonic_prost_build::configure()
+        .compile_protos(
+            &[
+                PathBuf::from("proto/kuksa/val/v1/types.proto"),
+                PathBuf::from("proto/kuksa/val/v1/val.proto"),
+            ],
+            &[PathBuf::from("proto"), include],
+        )
+        .expect("failed to compile the kuksa.val.v1 protos");
+}
+
 fn main() {
+    // The inputs of the generated code. Cargo stops rerunning the script for
+    // any change in the package as soon as there is one rerun-if line, which
+    // the compilation of the protos prints too.
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed={}", VSS_CSV_FILE);
+    println!("cargo:rerun-if-env-changed=VSS_MSGS_DIR");
+
+    // The protos are compiled for docs.rs too, the kuksa module includes them
+    #[cfg(feature = "kuksa")]
+    compile_kuksa_protos();
 
     // Don't generate if running in Docs_rs
     if let Ok(val) = env::var("DOCS_RS") {
@@ -125,7 +164,8 @@ fn main() {
         }
     }
 
-    if let Ok(s) = parse_csv() {
+    if let Ok(mut s) = parse_csv() {
+        collect_instances(&mut s);
         let mut g = Graph::<(String, Vec<Signal>), ()>::new();
         let root_node = ("ROOT".to_owned(), vec![]);
 
@@ -160,7 +200,517 @@ fn main() {
     }
 }
 
+// Is this branch name one that is converted into a key when the
+// graph is flattened.
+fn is_key_branch(name: &str, re: &Regex) -> bool {
+    // Yikes - O2 is oxygen and not a key. :-)
+    let lower = name.to_lowercase();
+    (name != "O2" && re.is_match(name))
+        || lower == "left"
+        || lower == "right"
+        || lower == "front"
+        || lower == "rear"
+}
+
+// The key values of a single signal, in the same order that flatten_graph
+// injects the keys. Numbered branches come first, then the side and
+// finally the position.
+fn instance_of(s: &Signal, re: &Regex) -> Vec<(String, TokenStream)> {
+    let mut numbered = Vec::new();
+    let mut side = Vec::new();
+    let mut position = Vec::new();
+    for m in s.module.iter().filter(|m| is_key_branch(m, re)) {
+        match m.to_lowercase().as_str() {
+            "left" => side.push(("side".to_owned(), quote! {crate::v3::Side::Left})),
+            "right" => side.push(("side".to_owned(), quote! {crate::v3::Side::Right})),
+            "front" => position.push(("position".to_owned(), quote! {crate::v3::Position::Front})),
+            "rear" => position.push(("position".to_owned(), quote! {crate::v3::Position::Rear})),
+            _ => {
+                let num_pos = m.chars().position(|c| c.is_numeric()).unwrap();
+                let (key_name, num) = m.split_at(num_pos);
+                let num = proc_macro2::Literal::u8_suffixed(num.parse().unwrap());
+                numbered.push((key_name.to_lowercase(), quote! {#num}));
+            }
+        }
+    }
+    numbered.extend(side);
+    numbered.extend(position);
+    numbered
+}
+
+// The path of the signal in the specification with the branches that are
+// converted into keys removed. This is the path of the generated type.
+fn flat_path(s: &Signal, re: &Regex) -> String {
+    let mut path: Vec<&str> = s
+        .module
+        .iter()
+        .filter(|m| !is_key_branch(m, re))
+        .map(|m| m.as_str())
+        .collect();
+    path.push(&s.name);
+    path.join(".")
+}
+
+// The path of the signal in the specification with the key branches replaced
+// by the name of the key in braces, e.g. `Vehicle.Cabin.Door.Row{row}.{side}.Window.Position`.
+fn path_template(s: &Signal, re: &Regex) -> String {
+    let mut path: Vec<String> = s
+        .module
+        .iter()
+        .map(|m| {
+            if !is_key_branch(m, re) {
+                return m.clone();
+            }
+            match m.to_lowercase().as_str() {
+                "left" | "right" => "{side}".to_owned(),
+                "front" | "rear" => "{position}".to_owned(),
+                _ => {
+                    let num_pos = m.chars().position(|c| c.is_numeric()).unwrap();
+                    let prefix = &m[..num_pos];
+                    format!("{}{{{}}}", prefix, prefix.to_lowercase())
+                }
+            }
+        })
+        .collect();
+    path.push(s.name.clone());
+    path.join(".")
+}
+
+// Gather the instances of every signal before the key branches are removed
+// from the graph. Signals that end up as the same generated type share the
+// same list of instances.
+fn collect_instances(signals: &mut [Signal]) {
+    let re = Regex::new(r".*[0-9]$").unwrap();
+
+    let mut instances: HashMap<String, Vec<Vec<(String, TokenStream)>>> = HashMap::new();
+    for s in signals.iter() {
+        let instance = instance_of(s, &re);
+        if instance.is_empty() {
+            continue;
+        }
+        let list = instances.entry(flat_path(s, &re)).or_default();
+        if !list.iter().any(|i| i.iter().map(|(k, v)| (k, v.to_string())).eq(instance.iter().map(|(k, v)| (k, v.to_string())))) {
+            list.push(instance);
+        }
+    }
+
+    for s in signals.iter_mut() {
+        if let Some(list) = instances.get(&flat_path(s, &re)) {
+            s.instances = list.clone();
+        }
+    }
+}
+
+// Generate the functions that enumerate and validate the key combinations
+// of a keyed topic.
+fn add_instances(s: &Signal) -> TokenStream {
+    if s.keys.is_empty() {
+        return quote! {};
+    }
+
+    let key_names: Vec<&String> = s.keys.iter().map(|(k, _, _)| k).collect();
+    let key_type: Vec<&TokenStream> = s.keys.iter().map(|(_, ty, _)| ty).collect();
+    let key_var: Vec<proc_macro2::Ident> = key_names.iter().map(|k| format_ident!("{}", k)).collect();
+    let instance_var: Vec<proc_macro2::Ident> = key_names
+        .iter()
+        .map(|k| format_ident!("instance_{}", k))
+        .collect();
+
+    // only keep the instances whose keys match the keys of the generated type.
+    let values: Vec<Vec<&TokenStream>> = s
+        .instances
+        .iter()
+        .filter(|i| i.iter().map(|(k, _)| k).eq(key_names.iter().copied()))
+        .map(|i| i.iter().map(|(_, v)| v).collect())
+        .collect();
+
+    if key_var.len() == 1 {
+        let ty = key_type[0];
+        let var = &key_var[0];
+        let instance_var = &instance_var[0];
+        let values = values.iter().map(|v| v[0]);
+        quote! {
+            /// Iterate over all the key values of this topic
+            /// that are defined in the specification.
+            pub fn instances() -> impl Iterator<Item = #ty> {
+                [#(#values),*].into_iter()
+            }
+
+            /// Return true if the key identifies an instance
+            /// that is defined in the specification.
+            pub fn is_valid_instance(#var: &#ty) -> bool {
+                Self::instances().any(|#instance_var| &#instance_var == #var)
+            }
+        }
+    } else {
+        let values = values.iter().map(|v| quote! {(#(#v),*)});
+        quote! {
+            /// Iterate over all the key combinations of this topic
+            /// that are defined in the specification. The keys are
+            /// in the same order as the arguments of `new`.
+            pub fn instances() -> impl Iterator<Item = (#(#key_type),*)> {
+                [#(#values),*].into_iter()
+            }
+
+            /// Return true if the keys identify an instance
+            /// that is defined in the specification.
+            pub fn is_valid_instance(#(#key_var: &#key_type),*) -> bool {
+                Self::instances().any(|(#(#instance_var),*)| #(&#instance_var == #key_var)&&*)
+            }
+        }
+    }
+}
+
 fn add_signal(s: &Signal) -> TokenStream {
+    let re = Regex::new(r".*[0-9]$").unwrap();
+    let path = flat_path(s, &re);
+    let template = path_template(s, &re);
+
+    let mut ts = add_topic(s);
+    ts.extend(add_signal_trait(s, &path, &template));
+
+    if s.kind == "actuator" {
+        // actuators get a second topic that carries the requested value. The
+        // topic with the name of the signal is the feedback of the current value.
+        let mut target = s.clone();
+        target.name = format!("{}Target", s.name);
+        target.is_target = true;
+        target.description = format!("Requested target value of {}. {}", s.name, s.description);
+
+        let signal_name = quote::format_ident!("{}", &s.name);
+        let target_name = quote::format_ident!("{}", &target.name);
+
+        ts.extend(add_topic(&target));
+        ts.extend(add_signal_trait(&target, &path, &template));
+        ts.extend(quote! {
+            impl crate::v3::Actuator for #signal_name {
+                type Target = #target_name;
+            }
+
+            impl crate::v3::ActuatorTarget for #target_name {
+                type Actuator = #signal_name;
+            }
+        });
+    }
+
+    ts
+}
+
+// Implement the common VssSignal trait for a generated topic. The path is passed
+// in separately as the target topic of an actuator shares the path of the actuator.
+fn add_signal_trait(s: &Signal, path: &str, template: &str) -> TokenStream {
+    let signal_name = quote::format_ident!("{}", &s.name);
+    let value_ty = if let Some(unit_ty) = &s.vss_unit_type { unit_ty } else { &s.datatype };
+    let description = &s.description;
+    let unit = if let Some(unit) = &s.unit {
+        quote! {Some(#unit)}
+    } else {
+        quote! {None}
+    };
+    let limit = |l: &Option<String>| match l.as_ref().and_then(|l| l.parse::<f64>().ok()) {
+        Some(l) => {
+            let l = proc_macro2::Literal::f64_suffixed(l);
+            quote! {Some(#l)}
+        }
+        None => quote! {None},
+    };
+    let (min, max) = (limit(&s.min), limit(&s.max));
+    let kind = match s.kind.as_str() {
+        "attribute" => quote! {crate::v3::SignalKind::Attribute},
+        "actuator" => quote! {crate::v3::SignalKind::Actuator},
+        _ => quote! {crate::v3::SignalKind::Sensor},
+    };
+
+    let key_var: Vec<proc_macro2::Ident> = s.keys.iter().map(|(k, _, _)| format_ident!("{}", k)).collect();
+    let key_type: Vec<&TokenStream> = s.keys.iter().map(|(_, ty, _)| ty).collect();
+    // u8 keys are Copy, the enums need a clone
+    let key_field: Vec<TokenStream> = s
+        .keys
+        .iter()
+        .map(|(k, _, is_enum)| {
+            let k = format_ident!("{}", k);
+            if *is_enum {
+                quote! {self.#k.clone()}
+            } else {
+                quote! {self.#k}
+            }
+        })
+        .collect();
+
+    let (key_ty, key_expr, key_arg, key_destructure, key_instances) = match key_var.len() {
+        0 => (quote! {()}, quote! {}, quote! {_key}, quote! {}, quote! {vec![()]}),
+        1 => {
+            let ty = key_type[0];
+            let field = &key_field[0];
+            let var = &key_var[0];
+            (quote! {#ty}, quote! {#field}, quote! {#var}, quote! {}, quote! {Self::instances().collect()})
+        }
+        _ => (
+            quote! {(#(#key_type),*)},
+            quote! {(#(#key_field),*)},
+            quote! {key},
+            quote! {let (#(#key_var),*) = key;},
+            quote! {Self::instances().collect()},
+        ),
+    };
+
+    // the keys by name, for the paths that include the instance
+    let key_names: Vec<&String> = s.keys.iter().map(|(k, _, _)| k).collect();
+    let (key_values, key_from_values) = match key_var.len() {
+        0 => (
+            quote! {
+                fn key_values(_key: &Self::Key) -> Vec<(&'static str, crate::value::KeyValue)> {
+                    Vec::new()
+                }
+            },
+            quote! {
+                fn key_from_values(_values: &[(&str, crate::value::KeyValue)]) -> Option<Self::Key> {
+                    Some(())
+                }
+            },
+        ),
+        1 => {
+            let ty = key_type[0];
+            let name = key_names[0];
+            (
+                quote! {
+                    fn key_values(key: &Self::Key) -> Vec<(&'static str, crate::value::KeyValue)> {
+                        vec![(#name, key.into())]
+                    }
+                },
+                quote! {
+                    fn key_from_values(values: &[(&str, crate::value::KeyValue)]) -> Option<Self::Key> {
+                        crate::value::find_key::<#ty>(values, #name)
+                    }
+                },
+            )
+        }
+        _ => {
+            let index: Vec<syn::Index> = (0..key_var.len()).map(syn::Index::from).collect();
+            (
+                quote! {
+                    fn key_values(key: &Self::Key) -> Vec<(&'static str, crate::value::KeyValue)> {
+                        vec![#((#key_names, (&key.#index).into())),*]
+                    }
+                },
+                quote! {
+                    fn key_from_values(values: &[(&str, crate::value::KeyValue)]) -> Option<Self::Key> {
+                        Some((#(crate::value::find_key::<#key_type>(values, #key_names)?),*))
+                    }
+                },
+            )
+        }
+    };
+
+    // conversion from and to the dynamic value, the units are tuple structs
+    let datatype = &s.datatype;
+    let vss_datatype = &s.vss_datatype;
+    let (value_ref, value_wrap) = match &s.vss_unit_type {
+        Some(unit_ty) => {
+            let unit_ctor = unit_ty.to_string();
+            let unit_ctor = TokenStream::from_str(unit_ctor.split('<').next().unwrap()).unwrap();
+            (quote! {&value.0}, quote! {.map(#unit_ctor)})
+        }
+        None => (quote! {value}, quote! {}),
+    };
+    let value_dyn = quote! {
+        fn value_to_dyn(value: &Self::Value) -> crate::value::Value {
+            crate::value::ToValue::to_value(#value_ref)
+        }
+
+        fn value_from_dyn(value: &crate::value::Value) -> Option<Self::Value> {
+            <#datatype as crate::value::ToValue>::from_value(value)#value_wrap
+        }
+    };
+
+    // numeric values, including the ones that are wrapped in a unit
+    let raw_value = if s.vss_unit_type.is_some() { quote! {value.0} } else { quote! {*value} };
+    let value_as_f64 = match s.datatype.to_string().as_str() {
+        "f64" => quote! {
+            fn value_as_f64(value: &Self::Value) -> Option<f64> {
+                Some(#raw_value)
+            }
+        },
+        "u8" | "i8" | "u16" | "i16" | "u32" | "i32" | "u64" | "i64" | "f32" => quote! {
+            fn value_as_f64(value: &Self::Value) -> Option<f64> {
+                Some(#raw_value as f64)
+            }
+        },
+        "bool" => quote! {
+            fn value_as_f64(value: &Self::Value) -> Option<f64> {
+                Some(if #raw_value { 1.0 } else { 0.0 })
+            }
+        },
+        _ => quote! {
+            fn value_as_f64(_value: &Self::Value) -> Option<f64> {
+                None
+            }
+        },
+    };
+
+    let signal_status = if s.kind == "attribute" || s.is_target {
+        quote! {}
+    } else {
+        quote! {
+            #[cfg(feature = "signal-status")]
+            fn signal_status(&self) -> crate::v3::SignalStatus {
+                self.status
+            }
+            #[cfg(feature = "signal-status")]
+            fn set_signal_status(&mut self, status: crate::v3::SignalStatus) {
+                self.status = status;
+            }
+        }
+    };
+
+    let (timestamp_arg, sample_timestamp, new_call) = if s.kind == "attribute" {
+        (
+            quote! {_timestamp},
+            quote! {None},
+            quote! {Self::new(value, #(#key_var),*)},
+        )
+    } else {
+        (
+            quote! {timestamp},
+            quote! {Some(&self.timestamp)},
+            quote! {Self::new(value, timestamp, #(#key_var),*)},
+        )
+    };
+
+    let ros2_message = ros2_message_name(path, &s.name);
+    let ros2_topic = ros2_topic_name(path, &s.name);
+
+    quote! {
+        impl crate::v3::VssSignal for #signal_name {
+            type Value = #value_ty;
+            type Key = #key_ty;
+
+            const PATH: &'static str = #path;
+            const KIND: crate::v3::SignalKind = #kind;
+            const UNIT: Option<&'static str> = #unit;
+            const DESCRIPTION: &'static str = #description;
+            const MIN: Option<f64> = #min;
+            const MAX: Option<f64> = #max;
+            const DATATYPE: &'static str = #vss_datatype;
+            const PATH_TEMPLATE: &'static str = #template;
+
+            fn signal_value(&self) -> &Self::Value {
+                &self.value
+            }
+
+            fn key(&self) -> Self::Key {
+                #key_expr
+            }
+
+            fn sample_timestamp(&self) -> Option<&crate::v3::Timestamp> {
+                #sample_timestamp
+            }
+
+            fn in_bounds(value: &Self::Value) -> bool {
+                Self::bounds_check(value)
+            }
+
+            fn key_instances() -> Vec<Self::Key> {
+                #key_instances
+            }
+
+            #value_as_f64
+
+            #value_dyn
+
+            #key_values
+
+            #key_from_values
+
+            #signal_status
+
+            #[cfg(feature = "vehicle-id")]
+            fn vehicle_id(&self) -> &str {
+                &self.vehicle_id
+            }
+
+            #[cfg(feature = "vehicle-id")]
+            fn set_vehicle_id(&mut self, vehicle_id: &str) {
+                self.vehicle_id = vehicle_id.to_owned();
+            }
+
+            fn from_parts(value: Self::Value, #timestamp_arg: Option<crate::v3::Timestamp>, #key_arg: Self::Key) -> Option<Self> {
+                #key_destructure
+                #new_call
+            }
+        }
+
+        #[cfg(feature = "ros2")]
+        impl crate::ros2::RosMessage for #signal_name {
+            const MESSAGE: &'static str = #ros2_message;
+            const TOPIC: &'static str = #ros2_topic;
+        }
+
+        #[cfg(feature = "ros2")]
+        impl TopicType for #signal_name {
+            fn typename() -> std::ffi::CString {
+                std::ffi::CString::new(crate::ros2::type_name::<Self>()).expect("Unable to create CString for type name")
+            }
+
+            fn topic_name(maybe_prefix: Option<&str>) -> String {
+                crate::ros2::topic_name::<Self>(maybe_prefix)
+            }
+
+            fn has_key() -> bool {
+                crate::ros2::has_key::<Self>()
+            }
+
+            fn key_cdr(&self) -> Vec<u8> {
+                crate::ros2::key_cdr(self)
+            }
+
+            fn force_md5_keyhash() -> bool {
+                // the key is not of fixed size with the vehicle_id string
+                cfg!(feature = "vehicle-id")
+            }
+        }
+    }
+}
+
+// The name of the ROS 2 message of a generated type, the elements of the path
+// without `Vehicle` and the name of the type, e.g. `CabinDoorWindowPosition`.
+fn ros2_message_name(path: &str, name: &str) -> String {
+    let mut elements: Vec<&str> = path.split('.').skip(1).collect();
+    elements.pop();
+    elements.push(name);
+    elements
+        .iter()
+        .flat_map(|e| e.chars().filter(|c| c.is_ascii_alphanumeric()))
+        .collect()
+}
+
+// The DDS topic name of the ROS 2 topic `/vss/vehicle/cabin/door/window/position`
+fn ros2_topic_name(path: &str, name: &str) -> String {
+    let mut elements: Vec<&str> = path.split('.').collect();
+    elements.pop();
+    elements.push(name);
+    let elements: Vec<String> = elements.iter().map(|e| snake_case(e)).collect();
+    format!("rt/vss/{}", elements.join("/"))
+}
+
+// `IsABSEnabled` becomes `is_abs_enabled`
+fn snake_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().filter(|c| c.is_ascii_alphanumeric()).collect();
+    let mut snake = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_ascii_uppercase() && i > 0 {
+            let previous = chars[i - 1];
+            let next_lower = chars.get(i + 1).is_some_and(|n| n.is_ascii_lowercase());
+            if previous.is_ascii_lowercase() || (previous.is_ascii_uppercase() && next_lower) {
+                snake.push('_');
+            }
+        }
+        snake.push(c.to_ascii_lowercase());
+    }
+    snake
+}
+
+fn add_topic(s: &Signal) -> TokenStream {
     let signal_name = quote::format_ident!("{}", &s.name);
     let mut documentation = format!("{}", &s.description);
     let unit_doc = if let Some(unit) = &s.unit { format!(". The unit of this type is {}",unit)} else { ". This type has no unit defined".to_owned()};
@@ -205,10 +755,11 @@ fn add_signal(s: &Signal) -> TokenStream {
     for (k, ty, is_enum) in &s.keys {
         key_type.push(quote::quote! {#ty});
         key_var.push(quote::format_ident!("{}", k));
+        // the ros2 feature implements TopicType without the derive
         key_attrib.push(if *is_enum {
-            quote::quote! {#[topic_key_enum]}
+            quote::quote! {#[cfg_attr(not(feature = "ros2"), topic_key_enum)]}
         } else {
-            quote::quote! {#[topic_key]}
+            quote::quote! {#[cfg_attr(not(feature = "ros2"), topic_key)]}
         });
     }
 
@@ -230,6 +781,8 @@ fn add_signal(s: &Signal) -> TokenStream {
             const fn bounds_check(_v : &#unit_ty) -> bool { true}
         }
     };
+    let instances = add_instances(s);
+
     let tuple_list = key_var.clone().join_with(", ").to_string();
     let tuple_doc = format!("(value,{})", tuple_list);
 
@@ -265,9 +818,13 @@ fn add_signal(s: &Signal) -> TokenStream {
             #[doc=#documentation]
             #[allow(non_camel_case_types)]
             #[repr(C)]
-            #[derive(Default, Deserialize, Serialize, Topic)]
+            #[derive(Default, Clone, Deserialize, Serialize)]
+            #[cfg_attr(not(feature = "ros2"), derive(Topic))]
             pub struct #signal_name {
                 pub value : #unit_ty,
+                #[cfg(feature = "vehicle-id")]
+                #[cfg_attr(not(feature = "ros2"), topic_key)]
+                pub vehicle_id : String,
                 #(#key_attrib #key_var : #key_type),*
             }
 
@@ -285,13 +842,28 @@ fn add_signal(s: &Signal) -> TokenStream {
                     #(self.#key_var = #key_var);*
                 }
 
+                /// Set the value if it is within bounds as per the specification.
+                /// Returns false and leaves the value unchanged if it is out of bounds.
+                pub fn try_set(&mut self, value: #unit_ty,#(#key_var : #key_type),*) -> bool {
+                    if Self::bounds_check(&value) {
+                        self.set(value, #(#key_var),*);
+                        true
+                    } else {
+                        false
+                    }
+                }
+
                 #verify
 
+                #instances
+
                 /// create a new instance
                 pub fn new(value : #unit_ty, #(#key_var : #key_type),*) -> Option<Self> {
                     if Self::bounds_check(&value) {
                         Some(Self {
                             value,
+                            #[cfg(feature = "vehicle-id")]
+                            vehicle_id : String::new(),
                             #(#key_var),*
                         })
                     }   else {
@@ -301,14 +873,67 @@ fn add_signal(s: &Signal) -> TokenStream {
             }
         }
     } else {
+        // The quality of the value is only carried by sensors and the current
+        // value of actuators. It changes the wire format so it is behind a feature.
+        let (status_field, status_init, status_valid, status_out_of_range, status_doc, status_functions) = if s.is_target {
+            (quote! {}, quote! {}, quote! {}, quote! {}, quote! {}, quote! {})
+        } else {
+            (
+                quote! {
+                    #[cfg(feature = "signal-status")]
+                    pub status : crate::v3::SignalStatus,
+                },
+                quote! {
+                    #[cfg(feature = "signal-status")]
+                    status : crate::v3::SignalStatus::Valid,
+                },
+                quote! {
+                    #[cfg(feature = "signal-status")]
+                    {
+                        self.status = crate::v3::SignalStatus::Valid;
+                    }
+                },
+                quote! {
+                    #[cfg(feature = "signal-status")]
+                    {
+                        self.status = crate::v3::SignalStatus::OutOfRange;
+                    }
+                },
+                quote! {
+                    /// With the `signal-status` feature, the status is set to
+                    /// OutOfRange if the value is out of bounds.
+                },
+                quote! {
+                    /// The quality of the value
+                    #[cfg(feature = "signal-status")]
+                    pub fn status(&self) -> crate::v3::SignalStatus {
+                        self.status
+                    }
+
+                    /// Mark the value as invalid, not available, substituted or timed out.
+                    /// The value is left unchanged.
+                    #[cfg(feature = "signal-status")]
+                    pub fn set_status(&mut self, status: crate::v3::SignalStatus) {
+                        self.status = status;
+                    }
+                },
+            )
+        };
+
         quote! {
             #[doc=#documentation]
             #[allow(non_camel_case_types)]
             #[repr(C)]
-            #[derive(Default, Deserialize, Serialize, Topic)]
+            #[derive(Default, Clone, Deserialize, Serialize)]
+            #[cfg_attr(not(feature = "ros2"), derive(Topic))]
             pub struct #signal_name {
                 pub value : #unit_ty,
+                #[cfg_attr(feature = "ros2", serde(with = "crate::ros2::time"))]
                 pub timestamp : crate::v3::Timestamp ,
+                #status_field
+                #[cfg(feature = "vehicle-id")]
+                #[cfg_attr(not(feature = "ros2"), topic_key)]
+                pub vehicle_id : String,
                 #( #key_attrib pub #key_var : #key_type),*
             }
 
@@ -327,20 +952,42 @@ fn add_signal(s: &Signal) -> TokenStream {
                 pub fn set(&mut self, value: #unit_ty,maybe_timestamp : Option<crate::v3::Timestamp>, #(#key_var : #key_type),*) {
                     assert!(Self::bounds_check(&value));
                     self.value = value;
+                    #status_valid
                     #(self.#key_var = #key_var;)*
                     if let Some(ts) = maybe_timestamp {
                         self.timestamp = ts;
                     }
                 }
 
+                /// Set the value if it is within bounds as per the specification.
+                /// Returns false and leaves the value, the keys and the timestamp
+                /// unchanged if it is out of bounds.
+                #status_doc
+                pub fn try_set(&mut self, value: #unit_ty,maybe_timestamp : Option<crate::v3::Timestamp>, #(#key_var : #key_type),*) -> bool {
+                    if Self::bounds_check(&value) {
+                        self.set(value, maybe_timestamp, #(#key_var),*);
+                        true
+                    } else {
+                        #status_out_of_range
+                        false
+                    }
+                }
+
+                #status_functions
+
                 #verify
 
+                #instances
+
                 /// create a new instance
                 pub fn new(value : #unit_ty, timestamp: Option<crate::v3::Timestamp>, #(#key_var : #key_type),*) -> Option<Self> {
                     if Self::bounds_check(&value) {
                         Some(Self {
                             value,
                             timestamp : timestamp.unwrap_or_default(),
+                            #status_init
+                            #[cfg(feature = "vehicle-id")]
+                            vehicle_id : String::new(),
                             #(#key_var),*
                         })
                     }   else {
@@ -371,7 +1018,11 @@ fn add_module(
     }
 
     let import_cyclonedds_rs = if g[module_index].1.len() > 0 {
-        quote! {use cyclonedds_rs::{*};use cdds_derive::Topic;}
+        quote! {
+            use cyclonedds_rs::{*};
+            #[cfg(not(feature = "ros2"))]
+            use cdds_derive::Topic;
+        }
     } else {
         quote! {}
     };
@@ -386,6 +1037,29 @@ fn add_module(
     }
 }
 
+// Collect the path of the signal and the path of the type for every generated type
+fn add_catalog_entries(
+    g: &Graph<(String, Vec<Signal>), (), Directed, u32>,
+    module_index: NodeIndex,
+    modules: &mut Vec<proc_macro2::Ident>,
+    entries: &mut Vec<(String, TokenStream, bool)>,
+) {
+    let re = Regex::new(r".*[0-9]$").unwrap();
+    modules.push(format_ident!("{}", &g[module_index].0.to_lowercase()));
+    for s in &g[module_index].1 {
+        let name = format_ident!("{}", &s.name);
+        entries.push((
+            flat_path(s, &re),
+            quote! {#(#modules::)*#name},
+            s.kind == "actuator",
+        ));
+    }
+    for c in g.neighbors(module_index) {
+        add_catalog_entries(g, c, modules, entries);
+    }
+    modules.pop();
+}
+
 fn graph_to_output(g: Graph<(String, Vec<Signal>), (), Directed, u32>, root_index: NodeIndex) {
     let outdir = env::var("OUT_DIR").expect("OUT_DIR is not set");
     let bindings_file = Path::new(&outdir).join("bindings.rs");
@@ -394,17 +1068,232 @@ fn graph_to_output(g: Graph<(String, Vec<Signal>), (), Directed, u32>, root_inde
     
     let mut generated_code = String::new();
 
+    let mut catalog = Vec::new();
     for module in g.neighbors(root_index) {
         let ts = add_module(&g, module);
 
         generated_code.push_str(&ts.to_string());
+        add_catalog_entries(&g, module, &mut Vec::new(), &mut catalog);
     }
 
+    // sorted by path, the graph does not keep the order of the specification.
+    // A path with two types means the graph has a branch twice, the match of
+    // visit_signal would silently pick one of them.
+    catalog.sort_by_cached_key(|(path, ty, _)| (path.clone(), ty.to_string()));
+    let duplicates: Vec<&String> = catalog
+        .windows(2)
+        .filter(|pair| pair[0].0 == pair[1].0)
+        .map(|pair| &pair[0].0)
+        .collect();
+    if !duplicates.is_empty() {
+        panic!("Signals with more than one type in the catalog: {:?}", duplicates);
+    }
+    let paths = catalog.iter().map(|(p, _, _)| p);
+    let types = catalog.iter().map(|(_, t, _)| t);
+    let entries = catalog.iter().map(|(_, t, _)| t);
+    let actuators = catalog.iter().filter(|(_, _, actuator)| *actuator);
+    let actuator_paths = actuators.clone().map(|(p, _, _)| p);
+    let actuator_types = actuators.map(|(_, t, _)| t);
+    let catalog = quote! {
+        /// All the signals of the specification, sorted by path. The target topics
+        /// of the actuators are not listed separately, see [visit_target].
+        pub static CATALOG: &[crate::catalog::SignalInfo] = &[#(crate::catalog::SignalInfo::of::<#entries>()),*];
+
+        /// Call the visitor with the type of the signal with this path, see
+        /// [crate::catalog::SignalVisitor].
+        pub fn visit_signal<V: crate::catalog::SignalVisitor>(path: &str, visitor: V) -> Option<V::Output> {
+            match path {
+                #(#paths => Some(visitor.visit::<#types>()),)*
+                _ => None,
+            }
+        }
+
+        /// Call the visitor with the type of the Target topic of the actuator with
+        /// this path. Returns None for sensors and attributes.
+        pub fn visit_target<V: crate::catalog::SignalVisitor>(path: &str, visitor: V) -> Option<V::Output> {
+            match path {
+                #(#actuator_paths => Some(visitor.visit::<<#actuator_types as crate::v3::Actuator>::Target>()),)*
+                _ => None,
+            }
+        }
+    };
+    generated_code.push_str(&catalog.to_string());
+
     let generated_code = rustfmt_generated_code(&generated_code).expect("Unable to run rustfmt");
 
     file.write_all(generated_code.as_bytes()).unwrap();
 
     std::fs::copy(PathBuf::from(&bindings_file), PathBuf::from("src/bindings.rs")).unwrap();
+
+    #[cfg(feature = "ros2")]
+    write_ros2_package(&g);
+}
+
+// The package.xml of vss_msgs, with the version and the first author of the
+// crate
+#[cfg(feature = "ros2")]
+fn ros2_package_xml() -> String {
+    let version = env::var("CARGO_PKG_VERSION").expect("CARGO_PKG_VERSION is not set");
+    let authors = env::var("CARGO_PKG_AUTHORS").unwrap_or_default();
+    let author = authors.split(':').next().unwrap_or_default();
+    let (name, email) = match author.split_once('<') {
+        Some((name, email)) => (name.trim(), email.trim_end_matches('>').trim()),
+        None => (author.trim(), ""),
+    };
+    format!(r#"<?xml version="1.0"?>
+<?xml-model href="http://download.ros.org/schema/package_format3.xsd" schematypens="http://www.w3.org/2001/XMLSchema"?>
+<package format="3">
+  <name>vss_msgs</name>
+  <version>{version}</version>
+  <description>Messages of the topics of the vehicle-signals crate, generated from the Vehicle Signal Specification</description>
+  <maintainer email="{email}">{name}</maintainer>
+  <license>Apache-2.0</license>
+  <buildtool_depend>ament_cmake</buildtool_depend>
+  <buildtool_depend>rosidl_default_generators</buildtool_depend>
+  <depend>builtin_interfaces</depend>
+  <exec_depend>rosidl_default_runtime</exec_depend>
+  <member_of_group>rosidl_interface_packages</member_of_group>
+  <export>
+    <build_type>ament_cmake</build_type>
+  </export>
+</package>
+"#)
+}
+
+#[cfg(feature = "ros2")]
+const ROS2_CMAKE_LISTS: &str = r#"cmake_minimum_required(VERSION 3.8)
+project(vss_msgs)
+
+find_package(ament_cmake REQUIRED)
+find_package(rosidl_default_generators REQUIRED)
+find_package(builtin_interfaces REQUIRED)
+
+file(GLOB msgs RELATIVE ${CMAKE_CURRENT_SOURCE_DIR} msg/*.msg)
+rosidl_generate_interfaces(${PROJECT_NAME} ${msgs} DEPENDENCIES builtin_interfaces)
+
+ament_export_dependencies(rosidl_default_runtime)
+ament_package()
+"#;
+
+// Write the ROS 2 package `vss_msgs` with a message for every generated type
+// to OUT_DIR, and to the directory in VSS_MSGS_DIR if it is set.
+#[cfg(feature = "ros2")]
+fn write_ros2_package(g: &Graph<(String, Vec<Signal>), (), Directed, u32>) {
+    let re = Regex::new(r".*[0-9]$").unwrap();
+    let mut messages = std::collections::BTreeMap::new();
+    for node in g.node_indices() {
+        for s in &g[node].1 {
+            let path = flat_path(s, &re);
+            let mut signals = vec![s.clone()];
+            if s.kind == "actuator" {
+                let mut target = s.clone();
+                target.name = format!("{}Target", s.name);
+                target.is_target = true;
+                target.description = format!("Requested target value of {}. {}", s.name, s.description);
+                signals.push(target);
+            }
+            // identical types in several modules share the message
+            for s in signals {
+                let name = ros2_message_name(&path, &s.name);
+                let message = ros2_message(&path, &s);
+                messages.entry(name).or_insert(message);
+            }
+        }
+    }
+
+    let outdir = env::var("OUT_DIR").expect("OUT_DIR is not set");
+    let mut packages = vec![Path::new(&outdir).join("vss_msgs")];
+    if let Ok(dir) = env::var("VSS_MSGS_DIR") {
+        packages.push(PathBuf::from(dir));
+    }
+    for package in packages {
+        std::fs::create_dir_all(package.join("msg")).expect("create failed");
+        std::fs::write(package.join("package.xml"), ros2_package_xml()).unwrap();
+        std::fs::write(package.join("CMakeLists.txt"), ROS2_CMAKE_LISTS).unwrap();
+        for (name, message) in &messages {
+            std::fs::write(package.join("msg").join(format!("{}.msg", name)), message).unwrap();
+        }
+    }
+}
+
+// The .msg of a message. The fields are in the order of the fields of the
+// type, which is the order of the CDR encoding. The .msg format has no keys,
+// the keys of the topic are marked with comments. Enums are encoded as the
+// index of the variant.
+#[cfg(feature = "ros2")]
+fn ros2_message(path: &str, s: &Signal) -> String {
+    let value_type = ros2_type(&s.vss_datatype).unwrap_or_else(|| {
+        panic!("The datatype {} of {} has no ROS 2 type", s.vss_datatype, path)
+    });
+    let mut message = String::new();
+    for line in format!("{}: {}", path, s.description).lines() {
+        message.push_str(&format!("# {}\n", line.trim()));
+    }
+    let mut keys = Vec::new();
+    if cfg!(feature = "vehicle-id") {
+        keys.push("vehicle_id".to_owned());
+    }
+    keys.extend(s.keys.iter().map(|(key, _, _)| key.clone()));
+    if !keys.is_empty() {
+        message.push_str(&format!("#\n# The keys of the DDS topic are {}.\n", keys.join(", ")));
+    }
+    message.push('\n');
+
+    match &s.unit {
+        Some(unit) => message.push_str(&format!("{} value  # {}\n", value_type, unit)),
+        None => message.push_str(&format!("{} value\n", value_type)),
+    }
+    if s.kind != "attribute" {
+        message.push_str("builtin_interfaces/Time timestamp\n");
+    }
+    if cfg!(feature = "signal-status") && s.kind != "attribute" && !s.is_target {
+        message.push_str("uint32 status\n");
+        let statuses = ["VALID", "INVALID", "NOT_AVAILABLE", "SUBSTITUTED", "OUT_OF_RANGE", "TIMEOUT"];
+        for (i, status) in statuses.iter().enumerate() {
+            message.push_str(&format!("uint32 STATUS_{}={}\n", status, i));
+        }
+    }
+    if cfg!(feature = "vehicle-id") {
+        message.push_str("string vehicle_id  # key\n");
+    }
+    for (key, _, is_enum) in &s.keys {
+        if *is_enum {
+            message.push_str(&format!("uint32 {}  # key\n", key));
+            let variants = match key.as_str() {
+                "side" => ["LEFT", "RIGHT"],
+                "position" => ["FRONT", "REAR"],
+                _ => panic!("Unknown key {} of {}", key, path),
+            };
+            for (i, variant) in variants.iter().enumerate() {
+                message.push_str(&format!("uint32 {}_{}={}\n", key.to_uppercase(), variant, i));
+            }
+        } else {
+            message.push_str(&format!("uint8 {}  # key\n", key));
+        }
+    }
+    message
+}
+
+// The .msg type of a datatype of the specification
+#[cfg(feature = "ros2")]
+fn ros2_type(vss_type: &str) -> Option<String> {
+    let vss = vss_type.trim().trim_end_matches("[]");
+    let is_array = vss_type.trim().ends_with("[]");
+    let ros = match vss {
+        "boolean" => "bool",
+        "float" => "float32",
+        "double" => "float64",
+        // a sequence of sequences has no .msg type
+        "byteBuffer" if is_array => return None,
+        "byteBuffer" => "uint8[]",
+        "uint8" | "int8" | "uint16" | "int16" | "uint32" | "int32" | "uint64" | "int64" | "string" => vss,
+        _ => return None,
+    };
+    if is_array {
+        Some(format!("{}[]", ros))
+    } else {
+        Some(ros.to_owned())
+    }
 }
 
 fn vss_type_to_unit_type(vss_type:&str, vss_data_unit_type:&str) -> Option<TokenStream> {
@@ -670,6 +1559,8 @@ fn flatten_graph(
         }
     }
 
+    let mut signals_to_move = Vec::new();
+
     //now disconnect these nodes
     for node in &nodes_for_removal {
         for parent in g.neighbors_directed(*node, Incoming) {
@@ -680,6 +1571,8 @@ fn flatten_graph(
                 println!("Connecting parent:{} to child:{}", &g[p].0, &g[child].0);
                 edges_to_add.push((p, child));
             }
+            // the signals of the key branch itself, like Door.Row1.Left.IsOpen
+            signals_to_move.push((p, g[*node].1.clone()));
         }
     }
 
@@ -692,8 +1585,15 @@ fn flatten_graph(
         }
     }
 
-    //remove the key nodes
-    for node in nodes_for_removal {
+    for (p, signals) in signals_to_move {
+        g[p].1.extend(signals);
+    }
+
+    //remove the key nodes. Removing a node moves the last node to its index,
+    //so the nodes are removed from the highest index down.
+    nodes_for_removal.sort();
+    nodes_for_removal.dedup();
+    for node in nodes_for_removal.into_iter().rev() {
         g.remove_node(node);
     }
     g
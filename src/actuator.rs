@@ -0,0 +1,491 @@
+// Check project root for LICENCE
+//! Request/response service for actuators on top of the generated topics
+
+use std::ffi::CString;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use cyclonedds_rs::{
+    dds_history_kind, dds_reliability_kind, DDSError, DdsParticipant, DdsQos, DdsReader, DdsWriter,
+    ReaderBuilder, SampleBuffer, TopicBuilder, TopicType, WriterBuilder,
+};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::pubsub::read_all;
+use crate::v3::{Actuator, VssSignal};
+
+/// The state of a request sent to an actuator.
+#[repr(C)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActuatorStatus {
+    /// The request is valid and the actuator is moving towards the target.
+    Accepted = 1,
+    /// The value is out of bounds or the instance does not exist.
+    Rejected = 2,
+    /// The actuator has reached the target.
+    Completed = 3,
+    /// The actuator did not reach the target in time.
+    TimedOut = 4,
+}
+
+/// A request to move an actuator to the value in `target`. The
+/// correlation id is echoed in every response for this request.
+#[derive(Serialize, Deserialize)]
+pub struct ActuatorRequest<R> {
+    pub correlation_id: u64,
+    pub target: R,
+}
+
+/// The response of the actuator server to a request.
+#[derive(Serialize, Deserialize)]
+pub struct ActuatorResponse<R> {
+    pub correlation_id: u64,
+    pub status: ActuatorStatus,
+    pub target: R,
+}
+
+// The request and response topics are keyed on the keys of the target,
+// so each instance of an actuator is a separate DDS instance.
+impl<R: VssSignal> TopicType for ActuatorRequest<R> {
+    fn typename() -> CString {
+        let mut name = R::typename().into_string().expect("type name is not UTF-8");
+        name.push_str("Request");
+        CString::new(name).expect("Unable to create CString for type name")
+    }
+
+    fn topic_name(maybe_prefix: Option<&str>) -> String {
+        format!("{}/request", R::topic_name(maybe_prefix))
+    }
+
+    fn has_key() -> bool {
+        R::has_key()
+    }
+
+    fn key_cdr(&self) -> Vec<u8> {
+        self.target.key_cdr()
+    }
+
+    fn force_md5_keyhash() -> bool {
+        R::force_md5_keyhash()
+    }
+}
+
+impl<R: VssSignal> TopicType for ActuatorResponse<R> {
+    fn typename() -> CString {
+        let mut name = R::typename().into_string().expect("type name is not UTF-8");
+        name.push_str("Response");
+        CString::new(name).expect("Unable to create CString for type name")
+    }
+
+    fn topic_name(maybe_prefix: Option<&str>) -> String {
+        format!("{}/response", R::topic_name(maybe_prefix))
+    }
+
+    fn has_key() -> bool {
+        R::has_key()
+    }
+
+    fn key_cdr(&self) -> Vec<u8> {
+        self.target.key_cdr()
+    }
+
+    fn force_md5_keyhash() -> bool {
+        R::force_md5_keyhash()
+    }
+}
+
+#[derive(Debug)]
+pub enum ActuatorError {
+    /// The requested value is outside the limits of the specification.
+    OutOfBounds,
+    Dds(DDSError),
+}
+
+impl fmt::Display for ActuatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ActuatorError::OutOfBounds => f.write_str("the requested value is out of bounds"),
+            ActuatorError::Dds(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ActuatorError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ActuatorError::OutOfBounds => None,
+            ActuatorError::Dds(e) => Some(e),
+        }
+    }
+}
+
+impl From<DDSError> for ActuatorError {
+    fn from(e: DDSError) -> Self {
+        ActuatorError::Dds(e)
+    }
+}
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+const BUFFER_LEN: usize = 16;
+
+// Requests and responses must not be lost, so all of them are kept.
+fn service_qos() -> Result<DdsQos, DDSError> {
+    let mut qos = DdsQos::create()?;
+    qos.set_reliability(
+        dds_reliability_kind::DDS_RELIABILITY_RELIABLE,
+        Duration::from_millis(100),
+    )
+    .set_history(dds_history_kind::DDS_HISTORY_KEEP_ALL, 0);
+    Ok(qos)
+}
+
+fn create_writer<T: TopicType>(
+    participant: &DdsParticipant,
+    qos: DdsQos,
+) -> Result<DdsWriter<T>, DDSError> {
+    let topic = TopicBuilder::<T>::new()
+        .with_qos(qos.clone())
+        .create(participant)?;
+    WriterBuilder::new()
+        .with_qos(qos)
+        .create(participant, topic)
+}
+
+fn create_reader<T: TopicType>(
+    participant: &DdsParticipant,
+    qos: DdsQos,
+) -> Result<DdsReader<T>, DDSError> {
+    let topic = TopicBuilder::<T>::new()
+        .with_qos(qos.clone())
+        .create(participant)?;
+    ReaderBuilder::new()
+        .with_qos(qos)
+        .create(participant, topic)
+}
+
+/// Return true if the current value is close enough to the target. Numeric values
+/// are compared with the tolerance, all other values, including booleans, must
+/// be equal.
+pub fn within_tolerance<A: Actuator>(
+    current: &A::Value,
+    target: &A::Value,
+    tolerance: f64,
+) -> bool {
+    // booleans are numbers for value_as_f64, but a tolerance makes no sense for them
+    if A::DATATYPE == "boolean" {
+        return current == target;
+    }
+    match (A::value_as_f64(current), A::value_as_f64(target)) {
+        (Some(current), Some(target)) => (current - target).abs() <= tolerance,
+        _ => current == target,
+    }
+}
+
+/// Client side of the actuator service. Sends requests for a new value and
+/// receives the status of each request.
+pub struct ActuatorClient<A: Actuator> {
+    writer: DdsWriter<ActuatorRequest<A::Target>>,
+    reader: DdsReader<ActuatorResponse<A::Target>>,
+    buffer: SampleBuffer<ActuatorResponse<A::Target>>,
+    next_id: u64,
+    timeout: Duration,
+    pending: Vec<(u64, Instant)>,
+}
+
+impl<A: Actuator> ActuatorClient<A> {
+    pub fn new(participant: &DdsParticipant) -> Result<Self, DDSError> {
+        let qos = service_qos()?;
+        // start the correlation ids at a different point for every client so that
+        // responses meant for another client are not mistaken for ours.
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        Ok(Self {
+            writer: create_writer(participant, qos.clone())?,
+            reader: create_reader(participant, qos)?,
+            buffer: SampleBuffer::new(BUFFER_LEN),
+            next_id: seed,
+            timeout: DEFAULT_TIMEOUT,
+            pending: Vec::new(),
+        })
+    }
+
+    /// The time to wait for the request to complete before reporting `TimedOut`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Request the given instance of the actuator to move to `value`.
+    /// Returns the correlation id of the request.
+    pub fn request(&mut self, value: A::Value, key: A::Key) -> Result<u64, ActuatorError> {
+        let target = A::request(value, key).ok_or(ActuatorError::OutOfBounds)?;
+        Ok(self.send(target)?)
+    }
+
+    /// Send a request that has already been created.
+    /// Returns the correlation id of the request.
+    pub fn send(&mut self, target: A::Target) -> Result<u64, DDSError> {
+        let correlation_id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+        self.writer.write(Arc::new(ActuatorRequest {
+            correlation_id,
+            target,
+        }))?;
+        self.pending
+            .push((correlation_id, Instant::now() + self.timeout));
+        Ok(correlation_id)
+    }
+
+    /// Check for responses to the requests sent by this client. Requests that have not
+    /// completed within the timeout are reported as `TimedOut`. A request is
+    /// not reported anymore once it is Rejected, Completed or TimedOut.
+    pub fn poll(&mut self) -> Vec<(u64, ActuatorStatus)> {
+        let mut statuses = Vec::new();
+        let pending = &mut self.pending;
+        read_all(&self.reader, &mut self.buffer, true, |response| {
+            if let Some(index) = pending
+                .iter()
+                .position(|(id, _)| *id == response.correlation_id)
+            {
+                if response.status != ActuatorStatus::Accepted {
+                    pending.remove(index);
+                }
+                statuses.push((response.correlation_id, response.status));
+            }
+        });
+
+        let now = Instant::now();
+        pending.retain(|(id, deadline)| {
+            if *deadline <= now {
+                statuses.push((*id, ActuatorStatus::TimedOut));
+                false
+            } else {
+                true
+            }
+        });
+        statuses
+    }
+
+    /// True if the request has not been rejected, completed or timed out yet.
+    pub fn is_pending(&self, correlation_id: u64) -> bool {
+        self.pending.iter().any(|(id, _)| *id == correlation_id)
+    }
+}
+
+struct PendingRequest<R> {
+    correlation_id: u64,
+    target: R,
+    deadline: Instant,
+}
+
+// Keeps track of accepted requests until the actuator reaches the target
+// or the request times out.
+struct CompletionTracker<A: Actuator> {
+    tolerance: f64,
+    timeout: Duration,
+    pending: Vec<PendingRequest<A::Target>>,
+}
+
+impl<A: Actuator> CompletionTracker<A> {
+    fn new() -> Self {
+        Self {
+            tolerance: 0.0,
+            timeout: DEFAULT_TIMEOUT,
+            pending: Vec::new(),
+        }
+    }
+
+    // Validate a request against the specification
+    fn is_valid(target: &A::Target) -> bool {
+        A::Target::in_bounds(target.signal_value()) && A::key_instances().contains(&target.key())
+    }
+
+    fn add(&mut self, correlation_id: u64, target: A::Target, now: Instant) {
+        self.pending.push(PendingRequest {
+            correlation_id,
+            target,
+            deadline: now + self.timeout,
+        });
+    }
+
+    // remove the requests that are completed by the current value
+    fn complete(&mut self, current: &A) -> Vec<PendingRequest<A::Target>> {
+        let tolerance = self.tolerance;
+        let (done, pending) = self.pending.drain(..).partition(|p| {
+            p.target.key() == current.key()
+                && within_tolerance::<A>(current.signal_value(), p.target.signal_value(), tolerance)
+        });
+        self.pending = pending;
+        done
+    }
+
+    // remove the requests whose deadline has passed
+    fn expire(&mut self, now: Instant) -> Vec<PendingRequest<A::Target>> {
+        let (expired, pending) = self.pending.drain(..).partition(|p| p.deadline <= now);
+        self.pending = pending;
+        expired
+    }
+}
+
+/// Server side of the actuator service. This is used by the component that owns
+/// the actuator. It validates the requests, publishes the current value and
+/// reports when a request has completed.
+pub struct ActuatorServer<A: Actuator> {
+    reader: DdsReader<ActuatorRequest<A::Target>>,
+    buffer: SampleBuffer<ActuatorRequest<A::Target>>,
+    response_writer: DdsWriter<ActuatorResponse<A::Target>>,
+    current_writer: DdsWriter<A>,
+    tracker: CompletionTracker<A>,
+}
+
+impl<A: Actuator> ActuatorServer<A> {
+    pub fn new(participant: &DdsParticipant) -> Result<Self, DDSError> {
+        let qos = service_qos()?;
+        Ok(Self {
+            reader: create_reader(participant, qos.clone())?,
+            buffer: SampleBuffer::new(BUFFER_LEN),
+            response_writer: create_writer(participant, qos)?,
+            current_writer: crate::qos::create_writer(participant)?,
+            tracker: CompletionTracker::new(),
+        })
+    }
+
+    /// Numeric values within this distance of the target are treated as having
+    /// reached the target. The default is 0.
+    pub fn with_tolerance(mut self, tolerance: f64) -> Self {
+        self.tracker.tolerance = tolerance;
+        self
+    }
+
+    /// The time the actuator has to reach the target before the request is
+    /// reported as `TimedOut`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.tracker.timeout = timeout;
+        self
+    }
+
+    /// Take the new requests. Invalid requests are rejected, the valid ones
+    /// are accepted and returned so the caller can move the actuator.
+    /// Requests that have timed out are reported to the clients.
+    pub fn poll(&mut self) -> Result<Vec<A::Target>, DDSError> {
+        let now = Instant::now();
+        let mut requests = Vec::new();
+        read_all(&self.reader, &mut self.buffer, true, |request| {
+            requests.push((request.correlation_id, request.target.clone()));
+        });
+
+        let mut accepted = Vec::new();
+        for (correlation_id, target) in requests {
+            if CompletionTracker::<A>::is_valid(&target) {
+                self.respond(correlation_id, ActuatorStatus::Accepted, target.clone())?;
+                accepted.push(target.clone());
+                self.tracker.add(correlation_id, target, now);
+            } else {
+                self.respond(correlation_id, ActuatorStatus::Rejected, target)?;
+            }
+        }
+
+        for expired in self.tracker.expire(now) {
+            self.respond(
+                expired.correlation_id,
+                ActuatorStatus::TimedOut,
+                expired.target,
+            )?;
+        }
+        Ok(accepted)
+    }
+
+    /// Publish the current value of the actuator. Requests for this instance
+    /// that are within the tolerance of the current value are completed.
+    pub fn publish_current(&mut self, current: A) -> Result<(), DDSError> {
+        for done in self.tracker.complete(&current) {
+            self.respond(done.correlation_id, ActuatorStatus::Completed, done.target)?;
+        }
+        self.current_writer.write(Arc::new(current))
+    }
+
+    fn respond(
+        &mut self,
+        correlation_id: u64,
+        status: ActuatorStatus,
+        target: A::Target,
+    ) -> Result<(), DDSError> {
+        self.response_writer.write(Arc::new(ActuatorResponse {
+            correlation_id,
+            status,
+            target,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::units::Percent;
+    use crate::v3::vehicle::cabin::door::shade::Position;
+    use crate::v3::vehicle::cabin::door::window::Switch;
+    use crate::v3::vehicle::cabin::door::IsOpen;
+    use crate::v3::Side;
+
+    #[test]
+    fn tolerance() {
+        assert!(within_tolerance::<Position>(
+            &Percent(49),
+            &Percent(50),
+            1.0
+        ));
+        assert!(!within_tolerance::<Position>(
+            &Percent(48),
+            &Percent(50),
+            1.0
+        ));
+        assert!(within_tolerance::<Position>(
+            &Percent(50),
+            &Percent(50),
+            0.0
+        ));
+        // booleans, enums and strings must be equal whatever the tolerance
+        assert!(!within_tolerance::<IsOpen>(&true, &false, 1.0));
+        assert!(within_tolerance::<IsOpen>(&true, &true, 1.0));
+        assert!(!within_tolerance::<Switch>(
+            &"CLOSE".to_owned(),
+            &"OPEN".to_owned(),
+            10.0
+        ));
+    }
+
+    #[test]
+    fn tracker() {
+        let mut tracker = CompletionTracker::<Position>::new();
+        tracker.tolerance = 2.0;
+        let now = Instant::now();
+
+        let target = Position::request(Percent(80), (1, Side::Left)).unwrap();
+        assert!(CompletionTracker::<Position>::is_valid(&target));
+        tracker.add(7, target, now);
+
+        // another instance does not complete the request
+        let other = Position::new(Percent(80), None, 2, Side::Left).unwrap();
+        assert!(tracker.complete(&other).is_empty());
+
+        let close = Position::new(Percent(79), None, 1, Side::Left).unwrap();
+        let done = tracker.complete(&close);
+        assert_eq!(done.len(), 1);
+        assert_eq!(done[0].correlation_id, 7);
+
+        let target = Position::request(Percent(10), (1, Side::Right)).unwrap();
+        tracker.add(8, target, now);
+        assert!(tracker.expire(now).is_empty());
+        assert_eq!(tracker.expire(now + DEFAULT_TIMEOUT).len(), 1);
+    }
+
+    #[test]
+    fn invalid_instance_is_rejected() {
+        let mut target = Position::request(Percent(10), (1, Side::Left)).unwrap();
+        target.row = 3;
+        assert!(!CompletionTracker::<Position>::is_valid(&target));
+    }
+}
@@ -0,0 +1,161 @@
+// Check project root for LICENCE
+//! Access to the topics of signals chosen at runtime for the protocol servers
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use cyclonedds_rs::{DDSError, DdsParticipant};
+
+use crate::catalog::{self, DynReader, DynSample, DynWriter, SignalInfo};
+use crate::naming::NamingPolicy;
+use crate::v3::SignalKind;
+
+/// Where the protocol servers, like [crate::viss::VissServer], read and write
+/// the values of the signals
+pub trait Backend: Send {
+    /// Start receiving the samples of a signal
+    fn add(&mut self, info: &'static SignalInfo) -> Result<(), DDSError>;
+    /// The samples received since the last call
+    fn take(&mut self) -> Vec<DynSample>;
+    fn write(&mut self, sample: &DynSample) -> Result<(), DDSError>;
+    /// Start receiving the requests for an actuator on its Target topic
+    fn add_target(&mut self, info: &'static SignalInfo) -> Result<(), DDSError>;
+    /// The requests received since the last call
+    fn take_targets(&mut self) -> Vec<DynSample>;
+    /// Request a new value for an actuator on its Target topic. Fails with
+    /// [DDSError::BadParameter] if the signal is not an actuator.
+    fn write_target(&mut self, sample: &DynSample) -> Result<(), DDSError>;
+}
+
+/// Reads and writes the topics of the signals on a DDS domain
+pub struct DdsBackend {
+    participant: DdsParticipant,
+    naming: NamingPolicy,
+    readers: Vec<Box<dyn DynReader>>,
+    writers: BTreeMap<&'static str, Box<dyn DynWriter>>,
+    target_readers: Vec<Box<dyn DynReader>>,
+    target_writers: BTreeMap<&'static str, Box<dyn DynWriter>>,
+}
+
+impl DdsBackend {
+    pub fn new(participant: DdsParticipant, naming: NamingPolicy) -> Self {
+        Self {
+            participant,
+            naming,
+            readers: Vec::new(),
+            writers: BTreeMap::new(),
+            target_readers: Vec::new(),
+            target_writers: BTreeMap::new(),
+        }
+    }
+}
+
+impl Backend for DdsBackend {
+    fn add(&mut self, info: &'static SignalInfo) -> Result<(), DDSError> {
+        if !self.readers.iter().any(|r| r.signal() == info.path) {
+            self.readers
+                .push(info.subscribe(&self.participant, &self.naming)?);
+        }
+        Ok(())
+    }
+
+    fn take(&mut self) -> Vec<DynSample> {
+        self.readers.iter_mut().flat_map(|r| r.take()).collect()
+    }
+
+    fn write(&mut self, sample: &DynSample) -> Result<(), DDSError> {
+        if !self.writers.contains_key(sample.signal) {
+            let info = catalog::find(sample.signal).ok_or(DDSError::BadParameter)?;
+            let writer = info.publish(&self.participant, &self.naming)?;
+            self.writers.insert(info.path, writer);
+        }
+        self.writers.get_mut(sample.signal).unwrap().write(sample)
+    }
+
+    fn add_target(&mut self, info: &'static SignalInfo) -> Result<(), DDSError> {
+        if !self.target_readers.iter().any(|r| r.signal() == info.path) {
+            self.target_readers
+                .push(info.subscribe_target(&self.participant, &self.naming)?);
+        }
+        Ok(())
+    }
+
+    fn take_targets(&mut self) -> Vec<DynSample> {
+        self.target_readers
+            .iter_mut()
+            .flat_map(|r| r.take())
+            .collect()
+    }
+
+    fn write_target(&mut self, sample: &DynSample) -> Result<(), DDSError> {
+        if !self.target_writers.contains_key(sample.signal) {
+            let info = catalog::find(sample.signal).ok_or(DDSError::BadParameter)?;
+            let writer = info.publish_target(&self.participant, &self.naming)?;
+            self.target_writers.insert(info.path, writer);
+        }
+        self.target_writers
+            .get_mut(sample.signal)
+            .unwrap()
+            .write(sample)
+    }
+}
+
+/// A backend without DDS: the samples that are written or published on a
+/// clone of the backend are the samples that are received, and the same for
+/// the requests on the Target topics. For tests and simulations.
+#[derive(Clone, Default)]
+pub struct Loopback {
+    samples: Arc<Mutex<Vec<DynSample>>>,
+    targets: Arc<Mutex<Vec<DynSample>>>,
+}
+
+impl Loopback {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn publish(&self, sample: DynSample) {
+        self.samples.lock().unwrap().push(sample);
+    }
+
+    /// Publish a request for an actuator
+    pub fn publish_target(&self, sample: DynSample) {
+        self.targets.lock().unwrap().push(sample);
+    }
+}
+
+impl Backend for Loopback {
+    fn add(&mut self, _info: &'static SignalInfo) -> Result<(), DDSError> {
+        Ok(())
+    }
+
+    fn take(&mut self) -> Vec<DynSample> {
+        std::mem::take(&mut *self.samples.lock().unwrap())
+    }
+
+    fn write(&mut self, sample: &DynSample) -> Result<(), DDSError> {
+        self.publish(sample.clone());
+        Ok(())
+    }
+
+    fn add_target(&mut self, info: &'static SignalInfo) -> Result<(), DDSError> {
+        match info.kind {
+            SignalKind::Actuator => Ok(()),
+            _ => Err(DDSError::BadParameter),
+        }
+    }
+
+    fn take_targets(&mut self) -> Vec<DynSample> {
+        std::mem::take(&mut *self.targets.lock().unwrap())
+    }
+
+    fn write_target(&mut self, sample: &DynSample) -> Result<(), DDSError> {
+        match catalog::find(sample.signal) {
+            Some(info) if info.kind == SignalKind::Actuator => {
+                self.publish_target(sample.clone());
+                Ok(())
+            }
+            _ => Err(DDSError::BadParameter),
+        }
+    }
+}
@@ -0,0 +1,249 @@
+// Check project root for LICENCE
+//! Cache of the latest value of every subscribed signal
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+use cyclonedds_rs::{DDSError, DdsParticipant};
+
+use crate::catalog::{self, DynReader, DynSample, SignalInfo};
+use crate::naming::NamingPolicy;
+use crate::v3::{Timestamp, VssSignal};
+use crate::value::Value;
+
+/// The latest value of an instance of a signal
+#[derive(Debug, Clone, PartialEq)]
+pub struct CachedValue {
+    pub value: Value,
+    /// When the sample was received by the cache
+    pub received: Instant,
+    /// The timestamp set by the publisher. Attributes don't have a timestamp.
+    pub source_timestamp: Option<Timestamp>,
+}
+
+impl CachedValue {
+    /// The time since the sample was received
+    pub fn age(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.received)
+    }
+}
+
+/// A copy of the cache at one point in time. The values of all the signals are
+/// from the same call to [VehicleStateCache::update].
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub taken: Instant,
+    pub values: BTreeMap<String, CachedValue>,
+}
+
+impl Snapshot {
+    /// The latest value of an instance path like `Vehicle.Cabin.Door.Row1.Left.IsOpen`
+    pub fn get(&self, instance_path: &str) -> Option<&CachedValue> {
+        self.values.get(instance_path)
+    }
+}
+
+/// Holds the latest value of every instance of the subscribed signals. The
+/// signals are chosen by type with [VehicleStateCache::add] or by path with
+/// [VehicleStateCache::add_glob]. The received samples are applied by
+/// [VehicleStateCache::update], so the values don't change between two
+/// updates. Share the cache behind a mutex to read it from other threads.
+pub struct VehicleStateCache {
+    naming: NamingPolicy,
+    readers: Vec<Box<dyn DynReader>>,
+    values: BTreeMap<String, CachedValue>,
+    max_age: Option<Duration>,
+}
+
+impl Default for VehicleStateCache {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VehicleStateCache {
+    pub fn new() -> Self {
+        Self::with_naming(&NamingPolicy::default())
+    }
+
+    /// Subscribe with a topic name prefix or partition. See [NamingPolicy].
+    pub fn with_naming(naming: &NamingPolicy) -> Self {
+        Self {
+            naming: naming.clone(),
+            readers: Vec::new(),
+            values: BTreeMap::new(),
+            max_age: None,
+        }
+    }
+
+    pub fn naming(&self) -> &NamingPolicy {
+        &self.naming
+    }
+
+    pub fn max_age(&self) -> Option<Duration> {
+        self.max_age
+    }
+
+    /// Values older than this are stale, see [VehicleStateCache::is_stale]
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Subscribe to a signal. Subscribing twice to the same signal does nothing.
+    pub fn add<T: VssSignal>(&mut self, participant: &DdsParticipant) -> Result<(), DDSError> {
+        if !self.is_subscribed(T::PATH) {
+            let reader = catalog::subscribe::<T>(participant, &self.naming)?;
+            self.readers.push(reader);
+        }
+        Ok(())
+    }
+
+    /// Subscribe to all the signals of the catalog whose path matches the
+    /// pattern, see [catalog::glob]. Returns the number of new subscriptions.
+    pub fn add_glob(
+        &mut self,
+        participant: &DdsParticipant,
+        pattern: &str,
+    ) -> Result<usize, DDSError> {
+        let mut count = 0;
+        for info in catalog::glob(pattern) {
+            if self.add_info(participant, info)? {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Subscribe to a signal of the catalog. Returns false if the cache was
+    /// already subscribed.
+    pub fn add_info(
+        &mut self,
+        participant: &DdsParticipant,
+        info: &SignalInfo,
+    ) -> Result<bool, DDSError> {
+        if self.is_subscribed(info.path) {
+            return Ok(false);
+        }
+        self.readers.push(info.subscribe(participant, &self.naming)?);
+        Ok(true)
+    }
+
+    fn is_subscribed(&self, path: &str) -> bool {
+        self.readers.iter().any(|r| r.signal() == path)
+    }
+
+    /// Take the received samples and update the cache. Returns the number of
+    /// samples.
+    pub fn update(&mut self) -> usize {
+        let now = Instant::now();
+        let mut count = 0;
+        for reader in &mut self.readers {
+            for sample in reader.take() {
+                Self::apply(&mut self.values, sample, now);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// Put a sample into the cache, as if it was received now
+    pub fn insert(&mut self, sample: DynSample) {
+        Self::apply(&mut self.values, sample, Instant::now());
+    }
+
+    fn apply(values: &mut BTreeMap<String, CachedValue>, sample: DynSample, now: Instant) {
+        values.insert(
+            sample.path,
+            CachedValue {
+                value: sample.value,
+                received: now,
+                source_timestamp: sample.source_timestamp,
+            },
+        );
+    }
+
+    /// The latest value of an instance path like `Vehicle.Cabin.Door.Row1.Left.IsOpen`
+    pub fn get(&self, instance_path: &str) -> Option<&CachedValue> {
+        self.values.get(instance_path)
+    }
+
+    /// The values of the instance paths that match a glob pattern like
+    /// `Vehicle.Chassis.Axle.Row*.Wheel.*.Tire.Pressure`, see [catalog::glob_match]
+    pub fn matching<'a>(
+        &'a self,
+        pattern: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a CachedValue)> + 'a {
+        self.values
+            .iter()
+            .filter(move |(path, _)| catalog::glob_match(pattern, path))
+            .map(|(path, cached)| (path.as_str(), cached))
+    }
+
+    /// The latest value of an instance of `T`
+    pub fn value<T: VssSignal>(&self, key: &T::Key) -> Option<T::Value> {
+        let cached = self.get(&catalog::instance_path::<T>(key))?;
+        T::value_from_dyn(&cached.value)
+    }
+
+    /// True if there is no value for the instance or the value is older than
+    /// the maximum age. Values never go stale without a maximum age.
+    pub fn is_stale(&self, instance_path: &str) -> bool {
+        match self.get(instance_path) {
+            Some(cached) => self.is_too_old(cached, Instant::now()),
+            None => true,
+        }
+    }
+
+    /// The instance paths whose value is older than the maximum age
+    pub fn stale(&self) -> Vec<&str> {
+        let now = Instant::now();
+        self.values
+            .iter()
+            .filter(|(_, cached)| self.is_too_old(cached, now))
+            .map(|(path, _)| path.as_str())
+            .collect()
+    }
+
+    fn is_too_old(&self, cached: &CachedValue, now: Instant) -> bool {
+        self.max_age.is_some_and(|max| cached.age(now) > max)
+    }
+
+    /// A copy of all the values
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            taken: Instant::now(),
+            values: self.values.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::units::Percent;
+    use crate::v3::vehicle::cabin::door::window::Position;
+    use crate::v3::Side;
+
+    #[test]
+    fn latest_value_and_staleness() {
+        let mut cache = VehicleStateCache::new().with_max_age(Duration::from_millis(50));
+        let path = "Vehicle.Cabin.Door.Row1.Left.Window.Position";
+        assert!(cache.is_stale(path));
+
+        let position = Position::new(Percent(20), None, 1, Side::Left).unwrap();
+        cache.insert(DynSample::of(&position));
+        let position = Position::new(Percent(30), None, 1, Side::Left).unwrap();
+        cache.insert(DynSample::of(&position));
+        assert_eq!(cache.get(path).unwrap().value, Value::UInt(30));
+        assert_eq!(cache.value::<Position>(&(1, Side::Left)), Some(Percent(30)));
+        assert_eq!(cache.value::<Position>(&(1, Side::Right)), None);
+        assert!(!cache.is_stale(path));
+
+        let snapshot = cache.snapshot();
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(cache.is_stale(path));
+        assert_eq!(cache.stale(), vec![path]);
+        assert_eq!(snapshot.get(path).unwrap().value, Value::UInt(30));
+    }
+}
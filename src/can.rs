@@ -0,0 +1,760 @@
+// Check project root for LICENCE
+//! Publish the signals of CAN frames on the topics of the specification
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use cyclonedds_rs::{DDSError, DdsParticipant};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::backend::{Backend, DdsBackend};
+use crate::catalog::{self, DynSample, SignalInfo};
+use crate::dbc::{Dbc, DbcError, DbcMessage, DbcSignal};
+use crate::naming::NamingPolicy;
+use crate::units::{self, conversion};
+use crate::v3::{SignalKind, Timestamp};
+use crate::value::Value;
+
+#[derive(Debug)]
+pub enum CanError {
+    Dbc(DbcError),
+    UnknownMessage(String),
+    UnknownSignal {
+        message: String,
+        signal: String,
+    },
+    /// The path is not an instance of a signal of the catalog
+    UnknownPath(String),
+    /// The unit of the DBC signal can't be converted to the unit of the VSS
+    /// signal
+    Unit {
+        path: String,
+        dbc: String,
+        vss: Option<&'static str>,
+    },
+    #[cfg(feature = "toml")]
+    Toml(toml::de::Error),
+    Io(io::Error),
+    Dds(DDSError),
+}
+
+impl fmt::Display for CanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CanError::Dbc(e) => e.fmt(f),
+            CanError::UnknownMessage(message) => write!(f, "no message {} in the DBC", message),
+            CanError::UnknownSignal { message, signal } => {
+                write!(
+                    f,
+                    "no signal {} in the message {} of the DBC",
+                    signal, message
+                )
+            }
+            CanError::UnknownPath(path) => write!(f, "unknown path {}", path),
+            CanError::Unit { path, dbc, vss } => write!(
+                f,
+                "the unit {} of the DBC signal can't be converted to the unit {} of {}",
+                dbc,
+                vss.unwrap_or("(none)"),
+                path
+            ),
+            #[cfg(feature = "toml")]
+            CanError::Toml(e) => e.fmt(f),
+            CanError::Io(e) => e.fmt(f),
+            CanError::Dds(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for CanError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CanError::Dbc(e) => Some(e),
+            #[cfg(feature = "toml")]
+            CanError::Toml(e) => Some(e),
+            CanError::Io(e) => Some(e),
+            CanError::Dds(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<DbcError> for CanError {
+    fn from(e: DbcError) -> Self {
+        CanError::Dbc(e)
+    }
+}
+
+impl From<io::Error> for CanError {
+    fn from(e: io::Error) -> Self {
+        CanError::Io(e)
+    }
+}
+
+impl From<DDSError> for CanError {
+    fn from(e: DDSError) -> Self {
+        CanError::Dds(e)
+    }
+}
+
+/// A classic CAN frame
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CanFrame {
+    /// The identifier without the extended frame flag
+    pub id: u32,
+    pub extended: bool,
+    pub data: Vec<u8>,
+}
+
+// The units of the specification for other spellings of DBC files
+const UNIT_ALIASES: [(&str, &str); 18] = [
+    ("%", "percent"),
+    ("degC", "celsius"),
+    ("\u{b0}C", "celsius"),
+    ("degF", "fahrenheit"),
+    ("\u{b0}F", "fahrenheit"),
+    ("K", "kelvin"),
+    ("deg", "degrees"),
+    ("\u{b0}", "degrees"),
+    ("deg/s", "degrees/s"),
+    ("\u{b0}/s", "degrees/s"),
+    ("kph", "km/h"),
+    ("km/hr", "km/h"),
+    ("m/s2", "m/s^2"),
+    ("N.m", "Nm"),
+    ("1/min", "rpm"),
+    ("L", "l"),
+    ("L/h", "l/h"),
+    ("mL", "ml"),
+];
+
+fn vss_unit(unit: &str) -> &str {
+    UNIT_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == unit)
+        .map_or(unit, |(_, vss)| vss)
+}
+
+/// The mapping of a signal of a DBC file to an instance of a signal of the
+/// specification. The VSS value is `physical * factor + offset` converted
+/// from the unit of the DBC signal to the unit of the VSS signal, or the entry
+/// of `values` for the description of the raw value in the value table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SignalMapping {
+    pub message: String,
+    pub signal: String,
+    /// An instance path like `Vehicle.Cabin.Door.Row1.Left.IsOpen`
+    pub path: String,
+    #[serde(default = "units::one")]
+    pub factor: f64,
+    #[serde(default)]
+    pub offset: f64,
+    /// The unit of the DBC signal, e.g. `mph`, when the DBC file has none or
+    /// spells it in a way that is not known
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// The VSS values of the descriptions of the value table, e.g.
+    /// `Drive = "DRIVE"`. Without it the description itself is used for
+    /// string signals.
+    #[serde(default)]
+    pub values: BTreeMap<String, String>,
+}
+
+/// The mappings of a mapping file, e.g. in TOML:
+///
+/// ```toml
+/// [[signal]]
+/// message = "EngineData"
+/// signal = "EngineSpeed"
+/// path = "Vehicle.Powertrain.CombustionEngine.Speed"
+///
+/// [[signal]]
+/// message = "Doors"
+/// signal = "DriverDoor"
+/// path = "Vehicle.Cabin.Door.Row1.Left.IsOpen"
+/// values = { Open = "true", Closed = "false" }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CanMappingConfig {
+    #[serde(default)]
+    pub signal: Vec<SignalMapping>,
+}
+
+struct Mapped {
+    config: SignalMapping,
+    info: &'static SignalInfo,
+    // the factor and the offset from the DBC unit to the VSS unit
+    conversion: (f64, f64),
+    // index of the message and of the signal in the DBC
+    message: usize,
+    signal: usize,
+}
+
+/// Decodes CAN frames into samples of the specification with a DBC file and
+/// a mapping, and encodes samples of actuators into CAN frames
+pub struct CanMapping {
+    dbc: Dbc,
+    mapped: Vec<Mapped>,
+    // the latest payload of each message, the base of the encoded frames
+    payloads: BTreeMap<u32, Vec<u8>>,
+}
+
+impl CanMapping {
+    pub fn new(dbc: Dbc, config: &CanMappingConfig) -> Result<Self, CanError> {
+        let mut mapped = Vec::new();
+        for m in &config.signal {
+            let message = dbc
+                .messages
+                .iter()
+                .position(|d| d.name == m.message)
+                .ok_or_else(|| CanError::UnknownMessage(m.message.clone()))?;
+            let signal = dbc.messages[message]
+                .signals
+                .iter()
+                .position(|s| s.name == m.signal)
+                .ok_or_else(|| CanError::UnknownSignal {
+                    message: m.message.clone(),
+                    signal: m.signal.clone(),
+                })?;
+            let info = catalog::find_instance(&m.path)
+                .ok_or_else(|| CanError::UnknownPath(m.path.clone()))?;
+            let unit = m
+                .unit
+                .as_deref()
+                .unwrap_or(&dbc.messages[message].signals[signal].unit);
+            let conversion = match (vss_unit(unit), info.unit) {
+                ("", _) => Some((1.0, 0.0)),
+                (unit, Some(vss)) => conversion(unit, vss),
+                (_, None) => None,
+            }
+            .ok_or_else(|| CanError::Unit {
+                path: m.path.clone(),
+                dbc: unit.to_owned(),
+                vss: info.unit,
+            })?;
+            mapped.push(Mapped {
+                config: m.clone(),
+                info,
+                conversion,
+                message,
+                signal,
+            });
+        }
+        Ok(Self {
+            dbc,
+            mapped,
+            payloads: BTreeMap::new(),
+        })
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn from_toml(dbc: Dbc, text: &str) -> Result<Self, CanError> {
+        let config: CanMappingConfig = toml::from_str(text).map_err(CanError::Toml)?;
+        Self::new(dbc, &config)
+    }
+
+    /// Load a DBC file and a TOML mapping file
+    #[cfg(feature = "toml")]
+    pub fn load(
+        dbc: impl AsRef<std::path::Path>,
+        mapping: impl AsRef<std::path::Path>,
+    ) -> Result<Self, CanError> {
+        Self::from_toml(Dbc::load(dbc)?, &std::fs::read_to_string(mapping)?)
+    }
+
+    pub fn dbc(&self) -> &Dbc {
+        &self.dbc
+    }
+
+    /// The signals of the specification that are mapped
+    pub fn signals(&self) -> impl Iterator<Item = &'static SignalInfo> + '_ {
+        self.mapped.iter().map(|m| m.info)
+    }
+
+    fn parts(&self, mapped: &Mapped) -> (&DbcMessage, &DbcSignal) {
+        let message = &self.dbc.messages[mapped.message];
+        (message, &message.signals[mapped.signal])
+    }
+
+    fn to_value(&self, mapped: &Mapped, data: &[u8]) -> Option<Value> {
+        let (message, signal) = self.parts(mapped);
+        if !message.is_present(signal, data) {
+            return None;
+        }
+        if let Some(description) = signal.describe(signal.raw(data)?) {
+            if let Some(text) = mapped.config.values.get(description) {
+                return Value::parse(mapped.info.datatype, text);
+            }
+            if mapped.info.datatype == "string" {
+                return Some(Value::String(description.to_owned()));
+            }
+        }
+        let (factor, offset) = mapped.conversion;
+        let value = signal.decode(data)? * mapped.config.factor + mapped.config.offset;
+        let value = value * factor + offset;
+        Some(match mapped.info.datatype {
+            "boolean" => Value::Bool(value != 0.0),
+            datatype if catalog::is_integer(datatype) => Value::Float(value.round()),
+            _ => Value::Float(value),
+        })
+    }
+
+    /// The samples of the mapped signals of a frame. The values are checked
+    /// against the datatype and bounds of the specification, the values that
+    /// don't fit are dropped.
+    pub fn decode(&mut self, frame: &CanFrame, timestamp: Option<Timestamp>) -> Vec<DynSample> {
+        let message = match self.dbc.message(frame.id) {
+            Some(message) if message.extended == frame.extended => message.name.clone(),
+            _ => return Vec::new(),
+        };
+        self.payloads.insert(frame.id, frame.data.clone());
+        self.mapped
+            .iter()
+            .filter(|m| m.config.message == message)
+            .filter_map(|m| {
+                let value = self.to_value(m, &frame.data)?;
+                DynSample::new(&m.config.path, value, timestamp.clone())
+                    .and_then(DynSample::checked)
+            })
+            .collect()
+    }
+
+    fn to_raw(&self, mapped: &Mapped, value: &Value, data: &mut [u8]) -> Option<()> {
+        let (_, signal) = self.parts(mapped);
+        let text = value.to_string();
+        let description = mapped
+            .config
+            .values
+            .iter()
+            .find(|(_, v)| **v == text)
+            .map(|(description, _)| description.as_str())
+            .or_else(|| matches!(value, Value::String(_)).then_some(text.as_str()));
+        if let Some(raw) = description.and_then(|d| signal.lookup(d)) {
+            return signal.write_raw(data, raw);
+        }
+        let value = match value {
+            Value::Bool(b) => *b as u8 as f64,
+            value => value.as_f64()?,
+        };
+        let (factor, offset) = mapped.conversion;
+        let value = (value - offset) / factor;
+        signal.encode(data, (value - mapped.config.offset) / mapped.config.factor)
+    }
+
+    /// The frame that carries a sample of an actuator. The other signals of
+    /// the message keep the values of the latest received frame. Returns None
+    /// for sensors and attributes, and for values that don't fit the signal.
+    pub fn encode(&mut self, sample: &DynSample) -> Option<CanFrame> {
+        let mapped = self
+            .mapped
+            .iter()
+            .find(|m| m.config.path == sample.path && m.info.kind == SignalKind::Actuator)?;
+        let (message, _) = self.parts(mapped);
+        let (id, extended) = (message.id, message.extended);
+        let mut data = self
+            .payloads
+            .get(&id)
+            .cloned()
+            .unwrap_or_else(|| vec![0; message.size]);
+        self.to_raw(mapped, &sample.value, &mut data)?;
+        self.payloads.insert(id, data.clone());
+        Some(CanFrame { id, extended, data })
+    }
+}
+
+/// A raw SocketCAN socket bound to an interface, e.g. `can0` or `vcan0`
+#[cfg(target_os = "linux")]
+pub struct CanSocket {
+    fd: std::os::fd::OwnedFd,
+}
+
+#[cfg(target_os = "linux")]
+impl CanSocket {
+    pub fn open(interface: &str) -> io::Result<Self> {
+        use std::os::fd::{AsRawFd, FromRawFd};
+
+        let name = std::ffi::CString::new(interface)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        // SAFETY: plain socket calls, the address is a zeroed sockaddr_can
+        // with the family and the interface set
+        unsafe {
+            let index = libc::if_nametoindex(name.as_ptr());
+            if index == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let fd = libc::socket(libc::PF_CAN, libc::SOCK_RAW, libc::CAN_RAW);
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let socket = Self {
+                fd: std::os::fd::OwnedFd::from_raw_fd(fd),
+            };
+            let mut address: libc::sockaddr_can = std::mem::zeroed();
+            address.can_family = libc::AF_CAN as libc::sa_family_t;
+            address.can_ifindex = index as i32;
+            let bound = libc::bind(
+                socket.fd.as_raw_fd(),
+                &address as *const libc::sockaddr_can as *const libc::sockaddr,
+                std::mem::size_of::<libc::sockaddr_can>() as libc::socklen_t,
+            );
+            if bound < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(socket)
+        }
+    }
+
+    /// Make [CanSocket::read] return an error of kind `WouldBlock` after the
+    /// timeout
+    pub fn set_read_timeout(&self, timeout: Duration) -> io::Result<()> {
+        use std::os::fd::AsRawFd;
+
+        let time = libc::timeval {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            tv_usec: timeout.subsec_micros() as libc::suseconds_t,
+        };
+        // SAFETY: the option is a timeval
+        let set = unsafe {
+            libc::setsockopt(
+                self.fd.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                &time as *const libc::timeval as *const libc::c_void,
+                std::mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        };
+        if set < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Read a frame. Error and remote frames are skipped.
+    pub fn read(&self) -> io::Result<CanFrame> {
+        use std::os::fd::AsRawFd;
+
+        loop {
+            // SAFETY: the kernel writes at most a can_frame
+            let frame = unsafe {
+                let mut frame: libc::can_frame = std::mem::zeroed();
+                let size = std::mem::size_of::<libc::can_frame>();
+                let read = libc::read(
+                    self.fd.as_raw_fd(),
+                    &mut frame as *mut libc::can_frame as *mut libc::c_void,
+                    size,
+                );
+                if read < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                if read as usize != size {
+                    return Err(io::ErrorKind::UnexpectedEof.into());
+                }
+                frame
+            };
+            if frame.can_id & (libc::CAN_ERR_FLAG | libc::CAN_RTR_FLAG) != 0 {
+                continue;
+            }
+            let extended = frame.can_id & libc::CAN_EFF_FLAG != 0;
+            let length = (frame.can_dlc as usize).min(frame.data.len());
+            return Ok(CanFrame {
+                id: frame.can_id
+                    & if extended {
+                        libc::CAN_EFF_MASK
+                    } else {
+                        libc::CAN_SFF_MASK
+                    },
+                extended,
+                data: frame.data[..length].to_vec(),
+            });
+        }
+    }
+
+    pub fn write(&self, frame: &CanFrame) -> io::Result<()> {
+        use std::os::fd::AsRawFd;
+
+        // SAFETY: a zeroed can_frame is valid, the data is at most 8 bytes
+        let mut raw: libc::can_frame = unsafe { std::mem::zeroed() };
+        if frame.data.len() > raw.data.len() {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        raw.can_id = frame.id
+            | if frame.extended {
+                libc::CAN_EFF_FLAG
+            } else {
+                0
+            };
+        raw.can_dlc = frame.data.len() as u8;
+        raw.data[..frame.data.len()].copy_from_slice(&frame.data);
+        let size = std::mem::size_of::<libc::can_frame>();
+        // SAFETY: writes a can_frame
+        let written = unsafe {
+            libc::write(
+                self.fd.as_raw_fd(),
+                &raw as *const libc::can_frame as *const libc::c_void,
+                size,
+            )
+        };
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// Publishes the mapped signals of the received CAN frames on their topics,
+/// and sends the requests on the Target topics of the mapped actuators as
+/// CAN frames
+pub struct CanBridge<B: Backend> {
+    mapping: CanMapping,
+    backend: B,
+}
+
+impl CanBridge<DdsBackend> {
+    pub fn with_dds(
+        mapping: CanMapping,
+        participant: DdsParticipant,
+        naming: NamingPolicy,
+    ) -> Result<Self, DDSError> {
+        Self::new(mapping, DdsBackend::new(participant, naming))
+    }
+}
+
+impl<B: Backend> CanBridge<B> {
+    pub fn new(mapping: CanMapping, mut backend: B) -> Result<Self, DDSError> {
+        for info in mapping.signals() {
+            if info.kind == SignalKind::Actuator {
+                backend.add_target(info)?;
+            }
+        }
+        Ok(Self { mapping, backend })
+    }
+
+    /// Publish the samples of a received frame. Returns the number of samples.
+    pub fn receive(&mut self, frame: &CanFrame) -> Result<usize, DDSError> {
+        let samples = self.mapping.decode(frame, Some(Timestamp::default()));
+        for sample in &samples {
+            self.backend.write(sample)?;
+        }
+        Ok(samples.len())
+    }
+
+    /// The frames for the new requests of the mapped actuators
+    pub fn frames(&mut self) -> Vec<CanFrame> {
+        self.backend
+            .take_targets()
+            .iter()
+            .filter_map(|sample| self.mapping.encode(sample))
+            .collect()
+    }
+
+    /// Bridge a socket until an error occurs
+    #[cfg(target_os = "linux")]
+    pub fn run(&mut self, socket: &CanSocket) -> Result<(), CanError> {
+        socket.set_read_timeout(Duration::from_millis(10))?;
+        loop {
+            match socket.read() {
+                Ok(frame) => {
+                    self.receive(&frame)?;
+                }
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                    ) => {}
+                Err(e) => return Err(e.into()),
+            }
+            for frame in self.frames() {
+                socket.write(&frame)?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Loopback;
+
+    const DBC: &str = r#"
+BO_ 256 Powertrain: 8 Engine
+ SG_ VehicleSpeed : 0|16@1+ (0.01,0) [0|655.35] "km/h" Body
+ SG_ Gear : 16|4@1+ (1,0) [0|15] "" Body
+
+BO_ 512 Hvac: 2 Body
+ SG_ FanLeft : 0|8@1+ (0.5,0) [0|127.5] "%" Hvac
+ SG_ DriverDoor : 8|1@1+ (1,0) [0|1] "" Hvac
+
+VAL_ 256 Gear 0 "Neutral" 14 "Reverse" 15 "Park" ;
+VAL_ 512 DriverDoor 0 "Closed" 1 "Open" ;
+"#;
+
+    fn mapping(message: &str, signal: &str, path: &str) -> SignalMapping {
+        SignalMapping {
+            message: message.to_owned(),
+            signal: signal.to_owned(),
+            path: path.to_owned(),
+            factor: 1.0,
+            offset: 0.0,
+            unit: None,
+            values: BTreeMap::new(),
+        }
+    }
+
+    fn config() -> CanMappingConfig {
+        let gear = SignalMapping {
+            values: [("Reverse", "-1"), ("Park", "126"), ("Neutral", "0")]
+                .into_iter()
+                .map(|(d, v)| (d.to_owned(), v.to_owned()))
+                .collect(),
+            ..mapping(
+                "Powertrain",
+                "Gear",
+                "Vehicle.Powertrain.Transmission.SelectedGear",
+            )
+        };
+        let door = SignalMapping {
+            values: [("Open", "true"), ("Closed", "false")]
+                .into_iter()
+                .map(|(d, v)| (d.to_owned(), v.to_owned()))
+                .collect(),
+            ..mapping("Hvac", "DriverDoor", "Vehicle.Cabin.Door.Row1.Left.IsOpen")
+        };
+        let fan = SignalMapping {
+            factor: 2.0,
+            ..mapping(
+                "Hvac",
+                "FanLeft",
+                "Vehicle.Cabin.HVAC.Station.Row1.Left.FanSpeed",
+            )
+        };
+        CanMappingConfig {
+            signal: vec![
+                mapping("Powertrain", "VehicleSpeed", "Vehicle.Speed"),
+                gear,
+                door,
+                fan,
+            ],
+        }
+    }
+
+    #[test]
+    fn decode_and_encode() {
+        let dbc = Dbc::parse(DBC).unwrap();
+        let mut can = CanMapping::new(dbc.clone(), &config()).unwrap();
+        let frame = CanFrame {
+            id: 256,
+            extended: false,
+            data: vec![0x88, 0x13, 0x0e, 0, 0, 0, 0, 0],
+        };
+        let speed = can.decode(&frame, None);
+        assert_eq!(speed[0].path, "Vehicle.Speed");
+        assert_eq!(speed[0].value, Value::Float(50.0));
+        assert_eq!(speed[1].value, Value::Int(-1));
+
+        let frame = CanFrame {
+            id: 512,
+            extended: false,
+            data: vec![40, 1],
+        };
+        let samples = can.decode(&frame, None);
+        assert_eq!(samples[0].path, "Vehicle.Cabin.Door.Row1.Left.IsOpen");
+        assert_eq!(samples[0].value, Value::Bool(true));
+        assert_eq!(samples[1].value, Value::UInt(40));
+
+        // actuators only, the door keeps its state
+        let fan = DynSample::new(
+            "Vehicle.Cabin.HVAC.Station.Row1.Left.FanSpeed",
+            Value::UInt(60),
+            None,
+        )
+        .unwrap();
+        assert_eq!(can.encode(&fan).unwrap().data, vec![60, 1]);
+        assert!(can.encode(&speed[0]).is_none());
+
+        // the DBC unit can't be converted to the VSS unit, with or without
+        // scaling
+        for factor in [1.0, 2.0] {
+            let speed = SignalMapping {
+                factor,
+                ..mapping("Hvac", "FanLeft", "Vehicle.Speed")
+            };
+            assert!(matches!(
+                CanMapping::new(
+                    dbc.clone(),
+                    &CanMappingConfig {
+                        signal: vec![speed]
+                    }
+                ),
+                Err(CanError::Unit { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn units() {
+        let dbc = Dbc::parse(DBC).unwrap();
+        let config = |unit: &str| CanMappingConfig {
+            signal: vec![SignalMapping {
+                unit: Some(unit.to_owned()),
+                ..mapping("Powertrain", "VehicleSpeed", "Vehicle.Speed")
+            }],
+        };
+        let frame = CanFrame {
+            id: 256,
+            extended: false,
+            data: vec![0x88, 0x13, 0, 0, 0, 0, 0, 0],
+        };
+        let mut can = CanMapping::new(dbc.clone(), &config("kph")).unwrap();
+        assert_eq!(can.decode(&frame, None)[0].value, Value::Float(50.0));
+        // 50 mph
+        let mut can = CanMapping::new(dbc.clone(), &config("mph")).unwrap();
+        let Value::Float(speed) = can.decode(&frame, None)[0].value else {
+            panic!("not a float")
+        };
+        assert!((speed - 80.4672).abs() < 1e-3);
+        assert!(matches!(
+            CanMapping::new(dbc, &config("degC")),
+            Err(CanError::Unit { .. })
+        ));
+        assert_eq!(vss_unit("%"), "percent");
+        assert_eq!(vss_unit("degC"), "celsius");
+        assert_eq!(vss_unit("km/h"), "km/h");
+    }
+
+    #[test]
+    fn bridge() {
+        let loopback = Loopback::new();
+        let can = CanMapping::new(Dbc::parse(DBC).unwrap(), &config()).unwrap();
+        let mut bridge = CanBridge::new(can, loopback.clone()).unwrap();
+        let frame = CanFrame {
+            id: 512,
+            extended: false,
+            data: vec![20, 0],
+        };
+        assert_eq!(bridge.receive(&frame).unwrap(), 2);
+        // the current values published from CAN are not sent back
+        assert_eq!(loopback.clone().take().len(), 2);
+        assert!(bridge.frames().is_empty());
+
+        let fan = DynSample::new(
+            "Vehicle.Cabin.HVAC.Station.Row1.Left.FanSpeed",
+            Value::UInt(100),
+            None,
+        )
+        .unwrap();
+        loopback.publish(fan.clone());
+        assert!(bridge.frames().is_empty());
+        // the requests for a new value are
+        loopback.publish_target(fan);
+        assert_eq!(
+            bridge.frames(),
+            vec![CanFrame {
+                id: 512,
+                extended: false,
+                data: vec![100, 0],
+            }]
+        );
+    }
+}
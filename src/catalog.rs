@@ -0,0 +1,516 @@
+// Check project root for LICENCE
+//! Runtime catalog of the signals of the specification
+
+use cyclonedds_rs::{DDSError, DdsParticipant, DdsReader, SampleBuffer};
+
+use crate::naming::NamingPolicy;
+use crate::pubsub::{read_all, VssPublisher};
+use crate::v3::{SignalKind, Timestamp, VssSignal};
+use crate::value::{KeyValue, Value};
+
+const BUFFER_LEN: usize = 16;
+
+/// The description of a signal from the specification, and the operations on
+/// its topic for code that chooses the signal at runtime. The catalog of all the
+/// signals is [crate::v3::CATALOG].
+pub struct SignalInfo {
+    /// The path of the signal without the key branches, see [VssSignal::PATH]
+    pub path: &'static str,
+    /// The path with placeholders for the keys, see [VssSignal::PATH_TEMPLATE]
+    pub template: &'static str,
+    pub kind: SignalKind,
+    /// The datatype as written in the specification
+    pub datatype: &'static str,
+    pub unit: Option<&'static str>,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub description: &'static str,
+    instance_paths: fn() -> Vec<String>,
+}
+
+impl SignalInfo {
+    pub const fn of<T: VssSignal>() -> Self {
+        Self {
+            path: T::PATH,
+            template: T::PATH_TEMPLATE,
+            kind: T::KIND,
+            datatype: T::DATATYPE,
+            unit: T::UNIT,
+            min: T::MIN,
+            max: T::MAX,
+            description: T::DESCRIPTION,
+            instance_paths: instance_paths::<T>,
+        }
+    }
+
+    /// The paths of all the instances of the signal that are defined in the
+    /// specification, e.g. `Vehicle.Cabin.Door.Row1.Left.Window.Position`.
+    pub fn instance_paths(&self) -> Vec<String> {
+        (self.instance_paths)()
+    }
+
+    /// Return true if the path is an instance of this signal
+    pub fn is_instance(&self, instance_path: &str) -> bool {
+        parse_template(self.template, instance_path).is_some()
+    }
+
+    /// The names of the keys in the order of the template, e.g. `row` and `side`
+    pub fn key_names(&self) -> Vec<&'static str> {
+        self.template
+            .split('.')
+            .filter_map(|t| key_name(t.split_once('{')?.1.split_once('}')?.0))
+            .collect()
+    }
+
+    /// The values of the keys of an instance path. Returns None if the path is
+    /// not an instance of this signal.
+    pub fn keys(&self, instance_path: &str) -> Option<Vec<(&'static str, KeyValue)>> {
+        parse_template(self.template, instance_path)
+    }
+
+    /// Call the visitor with the type of the signal
+    pub fn visit<V: SignalVisitor>(&self, visitor: V) -> V::Output {
+        crate::v3::visit_signal(self.path, visitor).expect("every signal of the catalog has a type")
+    }
+
+    /// Create a reader for the topic of the signal
+    pub fn subscribe(
+        &self,
+        participant: &DdsParticipant,
+        naming: &NamingPolicy,
+    ) -> Result<Box<dyn DynReader>, DDSError> {
+        self.visit(Subscribe {
+            participant,
+            naming,
+        })
+    }
+
+    /// Create a writer for the topic of the signal
+    pub fn publish(
+        &self,
+        participant: &DdsParticipant,
+        naming: &NamingPolicy,
+    ) -> Result<Box<dyn DynWriter>, DDSError> {
+        self.visit(Publish {
+            participant,
+            naming,
+        })
+    }
+
+    /// Create a reader for the Target topic of an actuator, where the requests
+    /// for a new value are published. Fails with [DDSError::BadParameter] for
+    /// sensors and attributes.
+    pub fn subscribe_target(
+        &self,
+        participant: &DdsParticipant,
+        naming: &NamingPolicy,
+    ) -> Result<Box<dyn DynReader>, DDSError> {
+        crate::v3::visit_target(
+            self.path,
+            Subscribe {
+                participant,
+                naming,
+            },
+        )
+        .ok_or(DDSError::BadParameter)?
+    }
+
+    /// Create a writer for the Target topic of an actuator. Setting an
+    /// actuator publishes the requested value there, the topic of the signal
+    /// carries the current value. Fails with [DDSError::BadParameter] for
+    /// sensors and attributes.
+    pub fn publish_target(
+        &self,
+        participant: &DdsParticipant,
+        naming: &NamingPolicy,
+    ) -> Result<Box<dyn DynWriter>, DDSError> {
+        crate::v3::visit_target(
+            self.path,
+            Publish {
+                participant,
+                naming,
+            },
+        )
+        .ok_or(DDSError::BadParameter)?
+    }
+}
+
+/// Code that runs for a signal whose type is only known at runtime. The type
+/// is found with [SignalInfo::visit] or [crate::v3::visit_signal].
+pub trait SignalVisitor {
+    type Output;
+    fn visit<T: VssSignal>(self) -> Self::Output;
+}
+
+struct Subscribe<'a> {
+    participant: &'a DdsParticipant,
+    naming: &'a NamingPolicy,
+}
+
+impl SignalVisitor for Subscribe<'_> {
+    type Output = Result<Box<dyn DynReader>, DDSError>;
+
+    fn visit<T: VssSignal>(self) -> Self::Output {
+        subscribe::<T>(self.participant, self.naming)
+    }
+}
+
+struct Publish<'a> {
+    participant: &'a DdsParticipant,
+    naming: &'a NamingPolicy,
+}
+
+// Converts the value of a sample to the datatype of its signal
+struct Check<'a>(&'a DynSample);
+
+impl SignalVisitor for Check<'_> {
+    type Output = Option<Value>;
+
+    fn visit<T: VssSignal>(self) -> Self::Output {
+        let sample = self.0.to_sample::<T>()?;
+        Some(T::value_to_dyn(sample.signal_value()))
+    }
+}
+
+impl SignalVisitor for Publish<'_> {
+    type Output = Result<Box<dyn DynWriter>, DDSError>;
+
+    fn visit<T: VssSignal>(self) -> Self::Output {
+        publish::<T>(self.participant, self.naming)
+    }
+}
+
+/// Find a signal by its path without the key branches
+pub fn find(path: &str) -> Option<&'static SignalInfo> {
+    crate::v3::CATALOG
+        .binary_search_by(|s| s.path.cmp(path))
+        .ok()
+        .map(|i| &crate::v3::CATALOG[i])
+}
+
+/// Find the signal of an instance path like `Vehicle.Cabin.Door.Row1.Left.Window.Position`
+pub fn find_instance(instance_path: &str) -> Option<&'static SignalInfo> {
+    crate::v3::CATALOG
+        .iter()
+        .find(|s| s.is_instance(instance_path))
+}
+
+/// The signals whose path matches a glob pattern. `*` matches any part of a
+/// single path element and `**` matches any number of elements, e.g.
+/// `Vehicle.Cabin.Door.*` or `Vehicle.**.Pressure`. The pattern is matched
+/// against the path without the key branches.
+pub fn glob(pattern: &str) -> impl Iterator<Item = &'static SignalInfo> + '_ {
+    crate::v3::CATALOG
+        .iter()
+        .filter(move |s| glob_match(pattern, s.path))
+}
+
+/// The signals with an instance path that matches a glob pattern, e.g.
+/// `Vehicle.Chassis.Axle.Row*.Wheel.*.Tire.Pressure`. See [glob].
+pub fn glob_instances(pattern: &str) -> impl Iterator<Item = &'static SignalInfo> + '_ {
+    crate::v3::CATALOG.iter().filter(move |s| {
+        s.instance_paths()
+            .iter()
+            .any(|path| glob_match(pattern, path))
+    })
+}
+
+/// A glob pattern that matches every instance path of `T`, e.g.
+/// `Vehicle.Cabin.Door.Row*.*.Window.Position`
+pub fn instance_glob<T: VssSignal>() -> String {
+    let mut pattern = String::new();
+    let mut rest = T::PATH_TEMPLATE;
+    while let (Some(open), Some(close)) = (rest.find('{'), rest.find('}')) {
+        pattern.push_str(&rest[..open]);
+        pattern.push('*');
+        rest = &rest[close + 1..];
+    }
+    pattern.push_str(rest);
+    pattern
+}
+
+/// Match a path against a glob pattern, see [glob]
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split('.').collect();
+    let path: Vec<&str> = path.split('.').collect();
+    match_elements(&pattern, &path)
+}
+
+fn match_elements(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|i| match_elements(rest, &path[i..])),
+        Some((p, rest)) => match path.split_first() {
+            Some((e, path)) => {
+                match_element(p.as_bytes(), e.as_bytes()) && match_elements(rest, path)
+            }
+            None => false,
+        },
+    }
+}
+
+fn match_element(pattern: &[u8], text: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((b'*', rest)) => (0..=text.len()).any(|i| match_element(rest, &text[i..])),
+        Some((c, rest)) => text.first() == Some(c) && match_element(rest, &text[1..]),
+    }
+}
+
+/// The path of an instance of a signal, e.g. `Vehicle.Cabin.Door.Row1.Left.Window.Position`
+pub fn instance_path<T: VssSignal>(key: &T::Key) -> String {
+    let mut path = T::PATH_TEMPLATE.to_owned();
+    for (name, value) in T::key_values(key) {
+        path = path.replace(&format!("{{{}}}", name), &value.to_string());
+    }
+    path
+}
+
+/// The key of an instance path. Returns None if the path is not an instance of `T`.
+pub fn parse_instance_path<T: VssSignal>(instance_path: &str) -> Option<T::Key> {
+    let values = parse_template(T::PATH_TEMPLATE, instance_path)?;
+    T::key_from_values(&values)
+}
+
+// Match a path against a template and return the values of the keys
+fn parse_template(template: &str, path: &str) -> Option<Vec<(&'static str, KeyValue)>> {
+    let template: Vec<&str> = template.split('.').collect();
+    let path: Vec<&str> = path.split('.').collect();
+    if template.len() != path.len() {
+        return None;
+    }
+    let mut values = Vec::new();
+    for (t, p) in template.iter().zip(path) {
+        match (t.find('{'), t.find('}')) {
+            (Some(open), Some(close)) => {
+                let (prefix, suffix) = (&t[..open], &t[close + 1..]);
+                if p.len() < prefix.len() + suffix.len()
+                    || !p.starts_with(prefix)
+                    || !p.ends_with(suffix)
+                {
+                    return None;
+                }
+                let name = key_name(&t[open + 1..close])?;
+                let text = &p[prefix.len()..p.len() - suffix.len()];
+                values.push((name, KeyValue::parse(name, text)?));
+            }
+            _ if *t == p => {}
+            _ => return None,
+        }
+    }
+    Some(values)
+}
+
+// The key names as static strings, the templates only use these
+fn key_name(name: &str) -> Option<&'static str> {
+    ["row", "pos", "sensor", "bank", "side", "position"]
+        .into_iter()
+        .find(|n| *n == name)
+}
+
+fn instance_paths<T: VssSignal>() -> Vec<String> {
+    T::key_instances().iter().map(instance_path::<T>).collect()
+}
+
+/// True for the integer datatypes of the specification, like `uint8` or `int32`
+pub fn is_integer(datatype: &str) -> bool {
+    datatype.starts_with("int") || datatype.starts_with("uint")
+}
+
+/// A sample of a signal whose type is only known at runtime
+#[derive(Debug, Clone, PartialEq)]
+pub struct DynSample {
+    /// The path of the signal without the key branches
+    pub signal: &'static str,
+    /// The path of the instance, e.g. `Vehicle.Cabin.Door.Row1.Left.Window.Position`
+    pub path: String,
+    pub value: Value,
+    /// The timestamp set by the publisher. Attributes don't have a timestamp.
+    pub source_timestamp: Option<Timestamp>,
+}
+
+impl DynSample {
+    /// A sample of the instance path. Returns None if the path is not an
+    /// instance of a signal of the catalog.
+    pub fn new(
+        instance_path: &str,
+        value: Value,
+        source_timestamp: Option<Timestamp>,
+    ) -> Option<Self> {
+        Some(Self {
+            signal: find_instance(instance_path)?.path,
+            path: instance_path.to_owned(),
+            value,
+            source_timestamp,
+        })
+    }
+
+    /// Convert to a sample of `T`. Returns None if the path is not an instance
+    /// of `T`, the value does not fit the datatype or is out of bounds.
+    pub fn to_sample<T: VssSignal>(&self) -> Option<T> {
+        let key = parse_instance_path::<T>(&self.path)?;
+        let value = T::value_from_dyn(&self.value)?;
+        T::from_parts(value, self.source_timestamp.clone(), key)
+    }
+
+    /// Convert the value to the datatype of the signal, e.g. an integer to a
+    /// float. Returns None if the value does not fit the datatype or is out of
+    /// bounds.
+    pub fn checked(mut self) -> Option<Self> {
+        let info = find(self.signal)?;
+        self.value = info.visit(Check(&self))?;
+        Some(self)
+    }
+
+    pub fn of<T: VssSignal>(sample: &T) -> Self {
+        Self {
+            signal: T::PATH,
+            path: instance_path::<T>(&sample.key()),
+            value: T::value_to_dyn(sample.signal_value()),
+            source_timestamp: sample.sample_timestamp().cloned(),
+        }
+    }
+}
+
+/// A reader for a topic whose type is only known at runtime
+pub trait DynReader: Send {
+    /// The path of the signal without the key branches
+    fn signal(&self) -> &'static str;
+    /// Take the available samples without blocking
+    fn take(&mut self) -> Vec<DynSample>;
+}
+
+struct TypedReader<T: VssSignal> {
+    reader: DdsReader<T>,
+    buffer: SampleBuffer<T>,
+}
+
+impl<T: VssSignal> DynReader for TypedReader<T> {
+    fn signal(&self) -> &'static str {
+        T::PATH
+    }
+
+    fn take(&mut self) -> Vec<DynSample> {
+        let mut samples = Vec::new();
+        read_all(&self.reader, &mut self.buffer, true, |sample| {
+            samples.push(DynSample::of(sample))
+        });
+        samples
+    }
+}
+
+/// A writer for a topic whose type is only known at runtime
+pub trait DynWriter: Send {
+    /// The path of the signal without the key branches
+    fn signal(&self) -> &'static str;
+    /// Publish a sample. Fails with [DDSError::BadParameter] if the sample is
+    /// not an instance of the signal, or the value does not fit the datatype
+    /// or is out of bounds.
+    fn write(&mut self, sample: &DynSample) -> Result<(), DDSError>;
+}
+
+struct TypedWriter<T: VssSignal> {
+    publisher: VssPublisher<T>,
+}
+
+impl<T: VssSignal> DynWriter for TypedWriter<T> {
+    fn signal(&self) -> &'static str {
+        T::PATH
+    }
+
+    fn write(&mut self, sample: &DynSample) -> Result<(), DDSError> {
+        let sample = sample.to_sample::<T>().ok_or(DDSError::BadParameter)?;
+        self.publisher.publish(sample)
+    }
+}
+
+/// Create a writer that publishes [DynSample] as samples of `T`
+pub fn publish<T: VssSignal>(
+    participant: &DdsParticipant,
+    naming: &NamingPolicy,
+) -> Result<Box<dyn DynWriter>, DDSError> {
+    Ok(Box::new(TypedWriter::<T> {
+        publisher: VssPublisher::with_naming(participant, naming)?,
+    }))
+}
+
+/// Create a reader that returns the samples of `T` as [DynSample]
+pub fn subscribe<T: VssSignal>(
+    participant: &DdsParticipant,
+    naming: &NamingPolicy,
+) -> Result<Box<dyn DynReader>, DDSError> {
+    Ok(Box::new(TypedReader::<T> {
+        reader: naming.create_reader(participant)?,
+        buffer: SampleBuffer::new(BUFFER_LEN),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::vehicle::cabin::door::window::Position;
+    use crate::v3::Side;
+
+    #[test]
+    fn instance_paths() {
+        let path = instance_path::<Position>(&(1, Side::Left));
+        assert_eq!(path, "Vehicle.Cabin.Door.Row1.Left.Window.Position");
+        assert!(parse_instance_path::<Position>(&path) == Some((1, Side::Left)));
+        assert!(
+            parse_instance_path::<Position>("Vehicle.Cabin.Door.Row1.Up.Window.Position").is_none()
+        );
+
+        let info = find_instance(&path).unwrap();
+        assert_eq!(info.path, "Vehicle.Cabin.Door.Window.Position");
+        assert_eq!(info.instance_paths().len(), 4);
+        assert!(find("Vehicle.Speed").is_some());
+        assert_eq!(
+            instance_glob::<Position>(),
+            "Vehicle.Cabin.Door.Row*.*.Window.Position"
+        );
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob_match(
+            "Vehicle.Cabin.Door.*",
+            "Vehicle.Cabin.Door.IsOpen"
+        ));
+        assert!(!glob_match(
+            "Vehicle.Cabin.Door.*",
+            "Vehicle.Cabin.Door.Window.Position"
+        ));
+        assert!(glob_match(
+            "Vehicle.**.Position",
+            "Vehicle.Cabin.Door.Window.Position"
+        ));
+        assert!(glob_match(
+            "Vehicle.Cabin.**",
+            "Vehicle.Cabin.Door.Window.Position"
+        ));
+        assert!(glob_match("Vehicle.Sp*", "Vehicle.Speed"));
+        assert!(glob("Vehicle.Cabin.Door.Window.*").count() > 1);
+    }
+
+    struct TypeName;
+
+    impl SignalVisitor for TypeName {
+        type Output = &'static str;
+
+        fn visit<T: VssSignal>(self) -> Self::Output {
+            std::any::type_name::<T>()
+        }
+    }
+
+    #[test]
+    fn targets() {
+        let target = crate::v3::visit_target("Vehicle.Cabin.Door.Shade.Position", TypeName);
+        assert_eq!(
+            target,
+            Some(std::any::type_name::<
+                crate::v3::vehicle::cabin::door::shade::PositionTarget,
+            >())
+        );
+        assert!(crate::v3::visit_target("Vehicle.Speed", TypeName).is_none());
+    }
+}
@@ -0,0 +1,317 @@
+// Check project root for LICENCE
+//! Publish-on-change with deadband, heartbeat and rate limiting
+
+use std::time::{Duration, Instant};
+
+use cyclonedds_rs::{DDSError, DdsParticipant};
+
+use crate::naming::NamingPolicy;
+use crate::pubsub::VssPublisher;
+use crate::v3::VssSignal;
+
+const DEFAULT_HEARTBEAT: Duration = Duration::from_secs(1);
+const DEFAULT_MIN_INTERVAL: Duration = Duration::from_millis(10);
+// Fraction of the range of the signal used as the default deadband
+const RANGE_FRACTION: f64 = 0.005;
+const DEFAULT_RELATIVE: f64 = 0.01;
+
+/// When a [ChangeDetectingWriter] publishes a sample.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangePolicy {
+    /// Publish if the value moved more than this since the last published value
+    pub absolute: Option<f64>,
+    /// Publish if the value moved more than this fraction of the last published value
+    pub relative: Option<f64>,
+    /// Publish even if the value did not change once this period has expired
+    pub heartbeat: Option<Duration>,
+    /// Never publish an instance more often than this
+    pub min_interval: Option<Duration>,
+}
+
+impl ChangePolicy {
+    /// Publish on every change of the value, without heartbeat or rate limit.
+    pub fn on_change() -> Self {
+        Self {
+            absolute: None,
+            relative: None,
+            heartbeat: None,
+            min_interval: None,
+        }
+    }
+
+    /// Defaults for a signal. The absolute deadband is half a percent of the range
+    /// from the specification, or a typical resolution of the unit when the
+    /// specification has no range. Values without a known unit or range use a
+    /// relative deadband of one percent. The heartbeat is one second and the rate
+    /// is limited to 100 samples per second.
+    pub fn for_signal<T: VssSignal>() -> Self {
+        let absolute = match (T::MIN, T::MAX) {
+            (Some(min), Some(max)) if max > min => Some((max - min) * RANGE_FRACTION),
+            _ => T::UNIT.and_then(unit_resolution),
+        };
+        Self {
+            absolute,
+            relative: if absolute.is_none() {
+                Some(DEFAULT_RELATIVE)
+            } else {
+                None
+            },
+            heartbeat: Some(DEFAULT_HEARTBEAT),
+            min_interval: Some(DEFAULT_MIN_INTERVAL),
+        }
+    }
+
+    pub fn with_absolute(mut self, deadband: f64) -> Self {
+        self.absolute = Some(deadband);
+        self
+    }
+
+    pub fn with_relative(mut self, deadband: f64) -> Self {
+        self.relative = Some(deadband);
+        self
+    }
+
+    pub fn with_heartbeat(mut self, period: Duration) -> Self {
+        self.heartbeat = Some(period);
+        self
+    }
+
+    /// Limit the rate to `rate` samples per second for each instance. A rate that
+    /// is not a positive number, or too small for an interval, removes the limit.
+    pub fn with_max_rate(mut self, rate: f64) -> Self {
+        self.min_interval = Duration::try_from_secs_f64(1.0 / rate).ok();
+        self
+    }
+
+    // True if the value moved enough to be published. Values that are not numbers
+    // are published on every change.
+    fn has_changed<T: VssSignal>(&self, last: &T::Value, value: &T::Value) -> bool {
+        let (last_f, value_f) = match (T::value_as_f64(last), T::value_as_f64(value)) {
+            (Some(last), Some(value)) => (last, value),
+            _ => return last != value,
+        };
+        let delta = (value_f - last_f).abs();
+        match (self.absolute, self.relative) {
+            (None, None) => last != value,
+            (absolute, relative) => {
+                absolute.is_some_and(|a| delta > a)
+                    || relative.is_some_and(|r| delta > r * last_f.abs())
+            }
+        }
+    }
+}
+
+// A typical resolution for the units of the specification
+fn unit_resolution(unit: &str) -> Option<f64> {
+    match unit {
+        "celsius" | "degrees" | "km/h" | "percent" => Some(0.5),
+        "kPa" | "mm" | "degrees/s" => Some(1.0),
+        "V" | "A" | "m/s^2" | "l" | "kW" | "kWh" | "Nm" => Some(0.1),
+        "rpm" => Some(10.0),
+        "Pa" | "W" => Some(10.0),
+        _ => None,
+    }
+}
+
+struct Published<T: VssSignal> {
+    key: T::Key,
+    value: T::Value,
+    at: Instant,
+    // a change that was held back by the rate limit
+    pending: Option<T>,
+}
+
+/// Decides which samples are published. Every instance of a keyed topic is
+/// tracked separately.
+pub struct ChangeDetector<T: VssSignal> {
+    policy: ChangePolicy,
+    published: Vec<Published<T>>,
+}
+
+impl<T: VssSignal> ChangeDetector<T> {
+    pub fn new(policy: ChangePolicy) -> Self {
+        Self {
+            policy,
+            published: Vec::new(),
+        }
+    }
+
+    pub fn policy(&self) -> &ChangePolicy {
+        &self.policy
+    }
+
+    /// Returns true if the sample must be published now. A changed sample that is
+    /// held back by the rate limit is kept and returned by [ChangeDetector::flush]
+    /// once the rate limit allows it, unless a newer sample replaces it.
+    pub fn check(&mut self, sample: &T, now: Instant) -> bool {
+        let key = sample.key();
+        let value = sample.signal_value();
+        let index = match self.published.iter().position(|p| p.key == key) {
+            Some(index) => index,
+            None => {
+                self.published.push(Published {
+                    key,
+                    value: value.clone(),
+                    at: now,
+                    pending: None,
+                });
+                return true;
+            }
+        };
+
+        let policy = &self.policy;
+        let last = &mut self.published[index];
+        let elapsed = now.saturating_duration_since(last.at);
+        let changed = policy.has_changed::<T>(&last.value, value);
+        let heartbeat = policy.heartbeat.is_some_and(|h| elapsed >= h);
+        if !changed && !heartbeat {
+            if last.pending.is_some() {
+                // the value went back within the deadband
+                last.pending = None;
+            }
+            return false;
+        }
+        if policy.min_interval.is_some_and(|m| elapsed < m) {
+            if changed {
+                last.pending = Some(sample.clone());
+            }
+            return false;
+        }
+        last.value = value.clone();
+        last.at = now;
+        last.pending = None;
+        true
+    }
+
+    /// The samples that were held back by the rate limit and can be published now
+    pub fn flush(&mut self, now: Instant) -> Vec<T> {
+        let min_interval = self.policy.min_interval.unwrap_or_default();
+        let mut ready = Vec::new();
+        for last in &mut self.published {
+            if now.saturating_duration_since(last.at) >= min_interval {
+                if let Some(sample) = last.pending.take() {
+                    last.value = sample.signal_value().clone();
+                    last.at = now;
+                    ready.push(sample);
+                }
+            }
+        }
+        ready
+    }
+}
+
+/// A writer that only publishes a sample when the value moved more than a
+/// deadband or when the heartbeat period expires, and limits the rate of
+/// samples. See [ChangePolicy].
+pub struct ChangeDetectingWriter<T: VssSignal> {
+    publisher: VssPublisher<T>,
+    detector: ChangeDetector<T>,
+}
+
+impl<T: VssSignal> ChangeDetectingWriter<T> {
+    /// Create a writer with the defaults for the signal, see [ChangePolicy::for_signal]
+    pub fn new(participant: &DdsParticipant) -> Result<Self, DDSError> {
+        Self::with_policy(participant, ChangePolicy::for_signal::<T>())
+    }
+
+    pub fn with_policy(
+        participant: &DdsParticipant,
+        policy: ChangePolicy,
+    ) -> Result<Self, DDSError> {
+        Ok(Self {
+            publisher: VssPublisher::new(participant)?,
+            detector: ChangeDetector::new(policy),
+        })
+    }
+
+    pub fn with_naming(
+        participant: &DdsParticipant,
+        naming: &NamingPolicy,
+        policy: ChangePolicy,
+    ) -> Result<Self, DDSError> {
+        Ok(Self {
+            publisher: VssPublisher::with_naming(participant, naming)?,
+            detector: ChangeDetector::new(policy),
+        })
+    }
+
+    /// Offer a sample. Returns true if it was published.
+    pub fn write(&mut self, sample: T) -> Result<bool, DDSError> {
+        if self.detector.check(&sample, Instant::now()) {
+            self.publisher.publish(sample)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Publish the changes that were held back by the rate limit. Call this
+    /// periodically if the samples are not offered at a regular rate.
+    pub fn flush(&mut self) -> Result<usize, DDSError> {
+        let ready = self.detector.flush(Instant::now());
+        let count = ready.len();
+        for sample in ready {
+            self.publisher.publish(sample)?;
+        }
+        Ok(count)
+    }
+
+    pub fn detector(&self) -> &ChangeDetector<T> {
+        &self.detector
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::units::KiloPascal;
+    use crate::v3::vehicle::chassis::axle::wheel::tire::Pressure;
+    use crate::v3::Side;
+
+    fn pressure(value: u16, side: Side) -> Pressure {
+        Pressure::new(KiloPascal(value), None, 1, side).unwrap()
+    }
+
+    #[test]
+    fn deadband_heartbeat_and_rate() {
+        let policy = ChangePolicy::on_change()
+            .with_absolute(2.0)
+            .with_heartbeat(Duration::from_secs(1))
+            .with_max_rate(10.0);
+        let mut detector = ChangeDetector::new(policy);
+        let t0 = Instant::now();
+        let ms = |ms| t0 + Duration::from_millis(ms);
+
+        assert!(detector.check(&pressure(200, Side::Left), t0));
+        assert!(detector.check(&pressure(200, Side::Right), t0));
+        // within the deadband
+        assert!(!detector.check(&pressure(202, Side::Left), ms(200)));
+        assert!(detector.check(&pressure(203, Side::Left), ms(300)));
+        // changed, but faster than the rate limit
+        assert!(!detector.check(&pressure(210, Side::Left), ms(350)));
+        assert!(detector.flush(ms(380)).is_empty());
+        let flushed = detector.flush(ms(400));
+        assert_eq!(flushed.len(), 1);
+        assert_eq!(flushed[0].value, KiloPascal(210));
+        // heartbeat
+        assert!(!detector.check(&pressure(200, Side::Right), ms(900)));
+        assert!(detector.check(&pressure(200, Side::Right), ms(1000)));
+    }
+
+    #[test]
+    fn invalid_rate_is_no_limit() {
+        for rate in [0.0, -1.0, f64::NAN, f64::MIN_POSITIVE / 10.0] {
+            let policy = ChangePolicy::for_signal::<Pressure>().with_max_rate(rate);
+            assert_eq!(policy.min_interval, None);
+        }
+        let policy = ChangePolicy::on_change().with_max_rate(f64::INFINITY);
+        assert_eq!(policy.min_interval, Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn defaults_from_spec() {
+        let policy = ChangePolicy::for_signal::<Pressure>();
+        assert_eq!(policy.absolute, Some(1.0));
+        assert_eq!(policy.relative, None);
+    }
+}
@@ -0,0 +1,479 @@
+// Check project root for LICENCE
+//! Time series of signals as Arrow record batches and Parquet files
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use arrow::array::{
+    ArrayRef, ArrowPrimitiveType, BooleanArray, ListArray, PrimitiveArray, StringArray,
+    TimestampNanosecondArray, UInt8Array,
+};
+use arrow::buffer::{NullBuffer, OffsetBuffer};
+use arrow::datatypes::{self, DataType, Field, Schema, TimeUnit};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use cyclonedds_rs::{DDSError, DdsParticipant};
+use parquet::arrow::ArrowWriter;
+use parquet::basic::Compression;
+use parquet::errors::ParquetError;
+use parquet::file::properties::WriterProperties;
+
+use crate::catalog::{self, DynReader, DynSample, SignalInfo};
+use crate::naming::NamingPolicy;
+use crate::v3::{Timestamp, VssSignal};
+use crate::value::{KeyValue, ToValue, Value};
+
+#[derive(Debug)]
+pub enum ExportError {
+    Arrow(ArrowError),
+    Parquet(ParquetError),
+    Io(io::Error),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExportError::Arrow(e) => e.fmt(f),
+            ExportError::Parquet(e) => e.fmt(f),
+            ExportError::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExportError::Arrow(e) => Some(e),
+            ExportError::Parquet(e) => Some(e),
+            ExportError::Io(e) => Some(e),
+        }
+    }
+}
+
+impl From<ArrowError> for ExportError {
+    fn from(e: ArrowError) -> Self {
+        ExportError::Arrow(e)
+    }
+}
+
+impl From<ParquetError> for ExportError {
+    fn from(e: ParquetError) -> Self {
+        ExportError::Parquet(e)
+    }
+}
+
+impl From<io::Error> for ExportError {
+    fn from(e: io::Error) -> Self {
+        ExportError::Io(e)
+    }
+}
+
+/// The Arrow type of a datatype of the specification
+pub fn data_type(datatype: &str) -> DataType {
+    if let Some(item) = datatype.strip_suffix("[]") {
+        return DataType::List(Arc::new(Field::new("item", data_type(item), true)));
+    }
+    match datatype {
+        "boolean" => DataType::Boolean,
+        "int8" => DataType::Int8,
+        "int16" => DataType::Int16,
+        "int32" => DataType::Int32,
+        "int64" => DataType::Int64,
+        "uint8" => DataType::UInt8,
+        "uint16" => DataType::UInt16,
+        "uint32" => DataType::UInt32,
+        "uint64" => DataType::UInt64,
+        "float" => DataType::Float32,
+        "double" => DataType::Float64,
+        _ => DataType::Utf8,
+    }
+}
+
+fn key_type(name: &str) -> DataType {
+    match name {
+        "side" | "position" => DataType::Utf8,
+        _ => DataType::UInt8,
+    }
+}
+
+/// The schema of the record batches of a signal: the source timestamp, a
+/// column for each key of the topic and the value. The value field carries
+/// the datatype, unit, description and limits of the specification as
+/// metadata, the schema the path of the signal.
+pub fn schema(info: &SignalInfo) -> Schema {
+    let mut fields = vec![Field::new(
+        "timestamp",
+        DataType::Timestamp(TimeUnit::Nanosecond, Some("UTC".into())),
+        true,
+    )];
+    for name in info.key_names() {
+        fields.push(Field::new(name, key_type(name), false));
+    }
+    let mut metadata = HashMap::new();
+    metadata.insert("datatype".to_owned(), info.datatype.to_owned());
+    metadata.insert("description".to_owned(), info.description.to_owned());
+    if let Some(unit) = info.unit {
+        metadata.insert("unit".to_owned(), unit.to_owned());
+    }
+    if let Some(min) = info.min {
+        metadata.insert("min".to_owned(), min.to_string());
+    }
+    if let Some(max) = info.max {
+        metadata.insert("max".to_owned(), max.to_string());
+    }
+    fields.push(Field::new("value", data_type(info.datatype), true).with_metadata(metadata));
+    let metadata = HashMap::from([("path".to_owned(), info.path.to_owned())]);
+    Schema::new(fields).with_metadata(metadata)
+}
+
+fn primitive<T: ArrowPrimitiveType>(values: &[&Value]) -> ArrayRef
+where
+    T::Native: ToValue,
+{
+    Arc::new(
+        values
+            .iter()
+            .map(|v| T::Native::from_value(v))
+            .collect::<PrimitiveArray<T>>(),
+    )
+}
+
+// The values as an array of the Arrow type of the datatype. Values that don't
+// fit the datatype are null.
+fn values_array(datatype: &str, values: &[&Value]) -> Result<ArrayRef, ArrowError> {
+    if let Some(item) = datatype.strip_suffix("[]") {
+        fn items(value: &Value) -> &[Value] {
+            match value {
+                Value::Array(a) => a.as_slice(),
+                _ => &[],
+            }
+        }
+        let flat: Vec<&Value> = values.iter().flat_map(|v| items(v)).collect();
+        let lengths = values.iter().map(|v| items(v).len());
+        let valid: Vec<bool> = values
+            .iter()
+            .map(|v| matches!(v, Value::Array(_)))
+            .collect();
+        let field = Arc::new(Field::new("item", data_type(item), true));
+        let list = ListArray::try_new(
+            field,
+            OffsetBuffer::from_lengths(lengths),
+            values_array(item, &flat)?,
+            Some(NullBuffer::from(valid)),
+        )?;
+        return Ok(Arc::new(list));
+    }
+    let array: ArrayRef = match datatype {
+        "boolean" => Arc::new(
+            values
+                .iter()
+                .map(|v| bool::from_value(v))
+                .collect::<BooleanArray>(),
+        ),
+        "int8" => primitive::<datatypes::Int8Type>(values),
+        "int16" => primitive::<datatypes::Int16Type>(values),
+        "int32" => primitive::<datatypes::Int32Type>(values),
+        "int64" => primitive::<datatypes::Int64Type>(values),
+        "uint8" => primitive::<datatypes::UInt8Type>(values),
+        "uint16" => primitive::<datatypes::UInt16Type>(values),
+        "uint32" => primitive::<datatypes::UInt32Type>(values),
+        "uint64" => primitive::<datatypes::UInt64Type>(values),
+        "float" => primitive::<datatypes::Float32Type>(values),
+        "double" => primitive::<datatypes::Float64Type>(values),
+        _ => Arc::new(
+            values
+                .iter()
+                .map(|v| String::from_value(v))
+                .collect::<StringArray>(),
+        ),
+    };
+    Ok(array)
+}
+
+/// The samples of a signal as a record batch with the [schema] of the signal
+pub fn record_batch(info: &SignalInfo, samples: &[DynSample]) -> Result<RecordBatch, ArrowError> {
+    let mut keys = Vec::with_capacity(samples.len());
+    for sample in samples {
+        keys.push(info.keys(&sample.path).ok_or_else(|| {
+            ArrowError::InvalidArgumentError(format!(
+                "{} is not an instance of {}",
+                sample.path, info.path
+            ))
+        })?);
+    }
+    let mut timestamps = Vec::with_capacity(samples.len());
+    for sample in samples {
+        timestamps.push(match &sample.source_timestamp {
+            Some(t) => Some(nanoseconds(t).ok_or_else(|| {
+                ArrowError::InvalidArgumentError(format!(
+                    "the timestamp of {} does not fit in 64 bit nanoseconds",
+                    sample.path
+                ))
+            })?),
+            None => None,
+        });
+    }
+    let timestamps = TimestampNanosecondArray::from(timestamps);
+    let mut columns: Vec<ArrayRef> = vec![Arc::new(timestamps.with_timezone("UTC"))];
+    for (i, name) in info.key_names().into_iter().enumerate() {
+        let column = keys.iter().map(|k| &k[i].1);
+        let array: ArrayRef = match key_type(name) {
+            DataType::UInt8 => Arc::new(
+                column
+                    .map(|k| match k {
+                        KeyValue::Index(i) => Some(*i),
+                        _ => None,
+                    })
+                    .collect::<UInt8Array>(),
+            ),
+            _ => Arc::new(column.map(|k| Some(k.to_string())).collect::<StringArray>()),
+        };
+        columns.push(array);
+    }
+    let values: Vec<&Value> = samples.iter().map(|s| &s.value).collect();
+    columns.push(values_array(info.datatype, &values)?);
+    RecordBatch::try_new(Arc::new(schema(info)), columns)
+}
+
+// The nanoseconds since the epoch, None if they don't fit in an i64
+fn nanoseconds(t: &Timestamp) -> Option<i64> {
+    i64::try_from(t.sec)
+        .ok()?
+        .checked_mul(1_000_000_000)?
+        .checked_add(t.nsec as i64)
+}
+
+// The partition of a sample, the UTC date of its timestamp
+fn partition(sample: &DynSample) -> String {
+    let date = sample
+        .source_timestamp
+        .as_ref()
+        .and_then(|t| chrono::DateTime::from_timestamp(t.sec as i64, t.nsec))
+        .map(|t| t.format("%Y-%m-%d").to_string());
+    format!("date={}", date.as_deref().unwrap_or("unknown"))
+}
+
+/// Collects samples of any signal and converts them to one record batch per
+/// signal, or writes them to Parquet files partitioned by signal and date:
+/// `<dir>/Vehicle.Speed/date=2023-10-19/part-00000.parquet`. The partitions
+/// can be read with `polars.scan_parquet("<dir>/Vehicle.Speed/**/*.parquet")`.
+#[derive(Default)]
+pub struct TimeSeries {
+    samples: BTreeMap<&'static str, Vec<DynSample>>,
+    readers: Vec<Box<dyn DynReader>>,
+}
+
+impl TimeSeries {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, sample: DynSample) {
+        self.samples.entry(sample.signal).or_default().push(sample);
+    }
+
+    pub fn push_sample<T: VssSignal>(&mut self, sample: &T) {
+        self.push(DynSample::of(sample));
+    }
+
+    /// The number of samples that were not written yet
+    pub fn len(&self) -> usize {
+        self.samples.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Subscribe to the signals whose path matches the pattern, see [catalog::glob]
+    pub fn subscribe(
+        &mut self,
+        participant: &DdsParticipant,
+        naming: &NamingPolicy,
+        pattern: &str,
+    ) -> Result<usize, DDSError> {
+        let mut count = 0;
+        for info in catalog::glob(pattern) {
+            if !self.readers.iter().any(|r| r.signal() == info.path) {
+                self.readers.push(info.subscribe(participant, naming)?);
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Collect the received samples. Returns the number of samples.
+    pub fn poll(&mut self) -> usize {
+        let mut samples = Vec::new();
+        for reader in &mut self.readers {
+            samples.extend(reader.take());
+        }
+        let count = samples.len();
+        for sample in samples {
+            self.push(sample);
+        }
+        count
+    }
+
+    /// A record batch for each signal with samples
+    pub fn batches(&self) -> Result<Vec<RecordBatch>, ArrowError> {
+        self.samples
+            .iter()
+            .filter_map(|(path, samples)| Some((catalog::find(path)?, samples)))
+            .map(|(info, samples)| record_batch(info, samples))
+            .collect()
+    }
+
+    /// Write the samples to new Parquet files in the partitions of `dir` and
+    /// remove them. Returns the paths of the files. On an error, the samples
+    /// that were not written are kept.
+    pub fn write_parquet(&mut self, dir: impl AsRef<Path>) -> Result<Vec<PathBuf>, ExportError> {
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build();
+        let mut files = Vec::new();
+        let paths: Vec<&'static str> = self.samples.keys().copied().collect();
+        for path in paths {
+            let samples = self.samples.remove(path).unwrap_or_default();
+            let info = match catalog::find(path) {
+                Some(info) => info,
+                None => continue,
+            };
+            let mut partitions: BTreeMap<String, Vec<DynSample>> = BTreeMap::new();
+            for sample in samples {
+                partitions
+                    .entry(partition(&sample))
+                    .or_default()
+                    .push(sample);
+            }
+            while let Some((partition, samples)) = partitions.pop_first() {
+                let dir = dir.as_ref().join(path).join(partition);
+                match write_partition(&dir, info, &samples, &properties) {
+                    Ok(file) => files.push(file),
+                    Err(e) => {
+                        let unwritten = self.samples.entry(path).or_default();
+                        unwritten.extend(samples);
+                        unwritten.extend(partitions.into_values().flatten());
+                        return Err(e);
+                    }
+                }
+            }
+        }
+        Ok(files)
+    }
+}
+
+// Write the samples to a new file in the directory of a partition
+fn write_partition(
+    dir: &Path,
+    info: &SignalInfo,
+    samples: &[DynSample],
+    properties: &WriterProperties,
+) -> Result<PathBuf, ExportError> {
+    fs::create_dir_all(dir)?;
+    let file = (0..)
+        .map(|i| dir.join(format!("part-{:05}.parquet", i)))
+        .find(|f| !f.exists())
+        .expect("a free file name");
+    let batch = record_batch(info, samples)?;
+    let mut writer = ArrowWriter::try_new(
+        File::create(&file)?,
+        batch.schema(),
+        Some(properties.clone()),
+    )?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::units::{KiloPascal, KilometrePerHour};
+    use crate::v3::vehicle::chassis::axle::wheel::tire::Pressure;
+    use crate::v3::vehicle::Speed;
+    use crate::v3::Side;
+    use arrow::array::{Array, AsArray};
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+
+    #[test]
+    fn batches_and_parquet() {
+        let mut series = TimeSeries::new();
+        for (value, row, side) in [(210, 1, Side::Left), (230, 2, Side::Right)] {
+            series.push_sample(&Pressure::new(KiloPascal(value), None, row, side).unwrap());
+        }
+        let mut speed = Speed::new(KilometrePerHour(50.0), None).unwrap();
+        speed.timestamp = Timestamp {
+            sec: 1_697_712_000,
+            nsec: 0,
+        };
+        series.push_sample(&speed);
+        assert_eq!(series.len(), 3);
+
+        let batches = series.batches().unwrap();
+        let pressure = batches
+            .iter()
+            .find(|b| b.schema().metadata()["path"] == Pressure::PATH)
+            .unwrap();
+        let names: Vec<&str> = pressure
+            .schema_ref()
+            .fields()
+            .iter()
+            .map(|f| f.name().as_str())
+            .collect();
+        assert_eq!(names, ["timestamp", "row", "side", "value"]);
+        assert_eq!(pressure.column(2).as_string::<i32>().value(1), "Right");
+        let value = pressure
+            .schema_ref()
+            .field_with_name("value")
+            .unwrap()
+            .clone();
+        assert_eq!(value.data_type(), &DataType::UInt16);
+        assert_eq!(value.metadata()["unit"], "kPa");
+
+        let dir = std::env::temp_dir().join(format!("vss-columns-{}", std::process::id()));
+        let files = series.write_parquet(&dir).unwrap();
+        assert!(series.is_empty());
+        assert!(files
+            .iter()
+            .any(|f| f.ends_with("Vehicle.Speed/date=2023-10-19/part-00000.parquet")));
+        let file = File::open(
+            files
+                .iter()
+                .find(|f| f.starts_with(dir.join("Vehicle.Speed")))
+                .unwrap(),
+        )
+        .unwrap();
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+            .unwrap()
+            .build()
+            .unwrap();
+        let batch = reader.into_iter().next().unwrap().unwrap();
+        assert_eq!(batch.num_rows(), 1);
+        let value = batch.schema_ref().field_with_name("value").unwrap().clone();
+        assert_eq!(value.metadata()["unit"], "km/h");
+        assert!(!batch.column(0).is_null(0));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keep_the_samples_that_are_not_written() {
+        let mut series = TimeSeries::new();
+        let mut speed = Speed::new(KilometrePerHour(50.0), None).unwrap();
+        speed.timestamp = Timestamp {
+            sec: u64::MAX,
+            nsec: 0,
+        };
+        series.push_sample(&speed);
+        assert!(series.batches().is_err());
+
+        let dir = std::env::temp_dir().join(format!("vss-columns-kept-{}", std::process::id()));
+        assert!(series.write_parquet(&dir).is_err());
+        assert_eq!(series.len(), 1);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
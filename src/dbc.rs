@@ -0,0 +1,433 @@
+// Check project root for LICENCE
+//! Messages and signals of DBC files, and their encoding in CAN payloads
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+
+// A CAN FD payload has 64 bytes
+const MAX_PAYLOAD_BITS: u32 = 64 * 8;
+
+#[derive(Debug)]
+pub enum DbcError {
+    /// A line that can't be parsed, the lines start at 1
+    Syntax {
+        line: usize,
+        text: String,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for DbcError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DbcError::Syntax { line, text } => write!(f, "line {}: can't parse {}", line, text),
+            DbcError::Io(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for DbcError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            DbcError::Syntax { .. } => None,
+            DbcError::Io(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for DbcError {
+    fn from(e: io::Error) -> Self {
+        DbcError::Io(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteOrder {
+    /// Intel, `@1` in DBC files. The start bit is the least significant bit.
+    LittleEndian,
+    /// Motorola, `@0` in DBC files. The start bit is the most significant bit.
+    BigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplex {
+    /// The signal that selects the multiplexed signals, `M`
+    Multiplexor,
+    /// A signal present when the multiplexor has this value, e.g. `m2`
+    Multiplexed(u64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DbcSignal {
+    pub name: String,
+    pub start_bit: u32,
+    pub size: u32,
+    pub byte_order: ByteOrder,
+    pub signed: bool,
+    pub factor: f64,
+    pub offset: f64,
+    pub min: f64,
+    pub max: f64,
+    pub unit: String,
+    pub multiplex: Option<Multiplex>,
+    /// The value table of the signal, from `VAL_`
+    pub values: BTreeMap<i64, String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DbcMessage {
+    /// The CAN identifier without the extended frame flag
+    pub id: u32,
+    pub extended: bool,
+    pub name: String,
+    pub size: usize,
+    pub signals: Vec<DbcSignal>,
+}
+
+/// The messages of a DBC file. Only the messages, the signals and the value
+/// tables are read, the other sections are skipped.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Dbc {
+    pub messages: Vec<DbcMessage>,
+}
+
+// The bit of the payload at a position of the DBC numbering
+fn bit(data: &[u8], position: u32) -> Option<bool> {
+    let byte = data.get(position as usize / 8)?;
+    Some(byte >> (position % 8) & 1 == 1)
+}
+
+fn set_bit(data: &mut [u8], position: u32, value: bool) -> Option<()> {
+    let byte = data.get_mut(position as usize / 8)?;
+    let mask = 1 << (position % 8);
+    if value {
+        *byte |= mask
+    } else {
+        *byte &= !mask
+    }
+    Some(())
+}
+
+impl DbcSignal {
+    // The positions of the bits of the signal, the least significant first
+    fn positions(&self) -> Vec<u32> {
+        match self.byte_order {
+            ByteOrder::LittleEndian => (self.start_bit..self.start_bit + self.size).collect(),
+            ByteOrder::BigEndian => {
+                let mut positions = Vec::with_capacity(self.size as usize);
+                let mut position = self.start_bit;
+                for _ in 0..self.size {
+                    positions.push(position);
+                    position = if position.is_multiple_of(8) {
+                        position + 15
+                    } else {
+                        position - 1
+                    };
+                }
+                positions.reverse();
+                positions
+            }
+        }
+    }
+
+    /// The raw value of the signal in a payload, sign extended for signed
+    /// signals
+    pub fn raw(&self, data: &[u8]) -> Option<i64> {
+        let mut raw = 0u64;
+        for (i, position) in self.positions().into_iter().enumerate() {
+            raw |= (bit(data, position)? as u64) << i;
+        }
+        if self.signed && self.size < 64 && raw >> (self.size - 1) & 1 == 1 {
+            raw |= u64::MAX << self.size;
+        }
+        Some(raw as i64)
+    }
+
+    /// The physical value of the signal in a payload
+    pub fn decode(&self, data: &[u8]) -> Option<f64> {
+        let raw = self.raw(data)?;
+        let raw = if self.signed {
+            raw as f64
+        } else {
+            raw as u64 as f64
+        };
+        Some(raw * self.factor + self.offset)
+    }
+
+    /// Write a raw value in a payload
+    pub fn write_raw(&self, data: &mut [u8], raw: i64) -> Option<()> {
+        for (i, position) in self.positions().into_iter().enumerate() {
+            set_bit(data, position, (raw as u64) >> i & 1 == 1)?;
+        }
+        Some(())
+    }
+
+    /// Write a physical value in a payload. Returns None if the value is out
+    /// of the range of the signal or the signal doesn't fit the payload.
+    pub fn encode(&self, data: &mut [u8], value: f64) -> Option<()> {
+        let limited = self.min != 0.0 || self.max != 0.0;
+        if limited && !(self.min..=self.max).contains(&value) {
+            return None;
+        }
+        let raw = ((value - self.offset) / self.factor).round();
+        let (low, high) = if self.signed {
+            (
+                -(2f64.powi(self.size as i32 - 1)),
+                2f64.powi(self.size as i32 - 1) - 1.0,
+            )
+        } else {
+            (0.0, 2f64.powi(self.size as i32) - 1.0)
+        };
+        if !(low..=high).contains(&raw) {
+            return None;
+        }
+        let raw = if self.signed {
+            raw as i64
+        } else {
+            raw as u64 as i64
+        };
+        self.write_raw(data, raw)
+    }
+
+    /// The description of a raw value in the value table
+    pub fn describe(&self, raw: i64) -> Option<&str> {
+        self.values.get(&raw).map(String::as_str)
+    }
+
+    /// The raw value of a description of the value table
+    pub fn lookup(&self, description: &str) -> Option<i64> {
+        self.values
+            .iter()
+            .find(|(_, d)| d.as_str() == description)
+            .map(|(raw, _)| *raw)
+    }
+}
+
+impl DbcMessage {
+    pub fn signal(&self, name: &str) -> Option<&DbcSignal> {
+        self.signals.iter().find(|s| s.name == name)
+    }
+
+    /// True if the signal is present in a payload, i.e. it is not multiplexed
+    /// or the multiplexor has its value
+    pub fn is_present(&self, signal: &DbcSignal, data: &[u8]) -> bool {
+        match signal.multiplex {
+            Some(Multiplex::Multiplexed(value)) => self
+                .signals
+                .iter()
+                .find(|s| s.multiplex == Some(Multiplex::Multiplexor))
+                .and_then(|m| m.raw(data))
+                .is_some_and(|raw| raw as u64 == value),
+            _ => true,
+        }
+    }
+}
+
+// The text between two delimiters and the rest after the closing one
+fn between(text: &str, open: char, close: char) -> Option<(&str, &str)> {
+    let start = text.find(open)? + 1;
+    let end = start + text[start..].find(close)?;
+    Some((&text[start..end], &text[end + 1..]))
+}
+
+// ` SG_ EngineSpeed m1 : 24|16@1+ (0.125,0) [0|8031.875] "rpm" Vector__XXX`
+fn parse_signal(line: &str) -> Option<DbcSignal> {
+    let (head, rest) = line.trim().strip_prefix("SG_")?.split_once(':')?;
+    let mut head = head.split_whitespace();
+    let name = head.next()?.to_owned();
+    let multiplex = match head.next() {
+        None => None,
+        Some("M") => Some(Multiplex::Multiplexor),
+        Some(m) => Some(Multiplex::Multiplexed(m.strip_prefix('m')?.parse().ok()?)),
+    };
+    let rest = rest.trim_start();
+    let (layout, rest) = rest.split_once(char::is_whitespace)?;
+    let (start_bit, layout) = layout.split_once('|')?;
+    let (size, layout) = layout.split_once('@')?;
+    let byte_order = match layout.get(..1)? {
+        "1" => ByteOrder::LittleEndian,
+        "0" => ByteOrder::BigEndian,
+        _ => return None,
+    };
+    let signed = match layout.get(1..)? {
+        "+" => false,
+        "-" => true,
+        _ => return None,
+    };
+    let (scale, rest) = between(rest, '(', ')')?;
+    let (factor, offset) = scale.split_once(',')?;
+    let (range, rest) = between(rest, '[', ']')?;
+    let (min, max) = range.split_once('|')?;
+    let (unit, _) = between(rest, '"', '"')?;
+    let size: u32 = size.parse().ok()?;
+    let start_bit: u32 = start_bit.parse().ok()?;
+    if size == 0 || size > 64 || start_bit >= MAX_PAYLOAD_BITS {
+        return None;
+    }
+    let signal = DbcSignal {
+        name,
+        start_bit,
+        size,
+        byte_order,
+        signed,
+        factor: factor.trim().parse().ok()?,
+        offset: offset.trim().parse().ok()?,
+        min: min.trim().parse().ok()?,
+        max: max.trim().parse().ok()?,
+        unit: unit.to_owned(),
+        multiplex,
+        values: BTreeMap::new(),
+    };
+    // the bits of the signal must be in the payload
+    if signal.positions().iter().any(|p| *p >= MAX_PAYLOAD_BITS) {
+        return None;
+    }
+    Some(signal)
+}
+
+// `BO_ 256 EngineData: 8 Engine`
+fn parse_message(line: &str) -> Option<DbcMessage> {
+    let (head, rest) = line.strip_prefix("BO_")?.split_once(':')?;
+    let mut head = head.split_whitespace();
+    let id: u32 = head.next()?.parse().ok()?;
+    let name = head.next()?.to_owned();
+    let size = rest.split_whitespace().next()?.parse().ok()?;
+    Some(DbcMessage {
+        id: id & 0x1fff_ffff,
+        extended: id & 0x8000_0000 != 0,
+        name,
+        size,
+        signals: Vec::new(),
+    })
+}
+
+// `VAL_ 256 Gear 0 "Park" 1 "Drive" ;`
+fn parse_values(line: &str) -> Option<(u32, String, BTreeMap<i64, String>)> {
+    let rest = line.strip_prefix("VAL_ ")?.trim();
+    let (id, rest) = rest.split_once(char::is_whitespace)?;
+    let (signal, mut rest) = rest.trim_start().split_once(char::is_whitespace)?;
+    let mut values = BTreeMap::new();
+    while let Some((raw, tail)) = rest.trim_start().split_once(char::is_whitespace) {
+        let (description, tail) = between(tail, '"', '"')?;
+        values.insert(raw.parse().ok()?, description.to_owned());
+        rest = tail;
+    }
+    if rest.trim() != ";" {
+        return None;
+    }
+    let id: u32 = id.parse().ok()?;
+    Some((id & 0x1fff_ffff, signal.to_owned(), values))
+}
+
+impl Dbc {
+    pub fn parse(text: &str) -> Result<Self, DbcError> {
+        let mut dbc = Dbc::default();
+        for (i, line) in text.lines().enumerate() {
+            let syntax = || DbcError::Syntax {
+                line: i + 1,
+                text: line.to_owned(),
+            };
+            let trimmed = line.trim_start();
+            if trimmed.starts_with("BO_ ") {
+                dbc.messages
+                    .push(parse_message(trimmed).ok_or_else(syntax)?);
+            } else if trimmed.starts_with("SG_ ") {
+                let signal = parse_signal(trimmed).ok_or_else(syntax)?;
+                dbc.messages
+                    .last_mut()
+                    .ok_or_else(syntax)?
+                    .signals
+                    .push(signal);
+            } else if trimmed.starts_with("VAL_ ") {
+                let (id, name, values) = parse_values(trimmed).ok_or_else(syntax)?;
+                let signal = dbc
+                    .messages
+                    .iter_mut()
+                    .filter(|m| m.id == id)
+                    .flat_map(|m| m.signals.iter_mut())
+                    .find(|s| s.name == name)
+                    .ok_or_else(syntax)?;
+                signal.values = values;
+            }
+        }
+        Ok(dbc)
+    }
+
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, DbcError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn message(&self, id: u32) -> Option<&DbcMessage> {
+        self.messages.iter().find(|m| m.id == id)
+    }
+
+    pub fn message_by_name(&self, name: &str) -> Option<&DbcMessage> {
+        self.messages.iter().find(|m| m.name == name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DBC: &str = r#"VERSION ""
+
+BU_: Engine Body
+
+BO_ 256 EngineData: 8 Engine
+ SG_ EngineSpeed : 0|16@1+ (0.25,0) [0|16383.75] "rpm" Body
+ SG_ CoolantTemp : 16|8@1+ (1,-40) [-40|215] "degC" Body
+ SG_ Torque : 31|12@0- (0.5,0) [-1024|1023.5] "Nm" Body
+
+BO_ 2147484160 Doors: 2 Body
+ SG_ Row M : 0|4@1+ (1,0) [0|15] "" Engine
+ SG_ LeftOpen m1 : 8|1@1+ (1,0) [0|1] "" Engine
+ SG_ Gear : 9|3@1+ (1,0) [0|7] "" Engine
+
+CM_ SG_ 256 EngineSpeed "Speed of the crankshaft";
+VAL_ 2147484160 Gear 0 "Park" 1 "Reverse" 2 "Neutral" 3 "Drive" ;
+"#;
+
+    #[test]
+    fn parse_and_decode() {
+        let dbc = Dbc::parse(DBC).unwrap();
+        let engine = dbc.message(256).unwrap();
+        let doors = dbc.message_by_name("Doors").unwrap();
+        assert_eq!((doors.id, doors.extended), (512, true));
+        assert_eq!(doors.signal("Gear").unwrap().describe(3), Some("Drive"));
+
+        let mut data = [0u8; 8];
+        let speed = engine.signal("EngineSpeed").unwrap();
+        let coolant = engine.signal("CoolantTemp").unwrap();
+        let torque = engine.signal("Torque").unwrap();
+        speed.encode(&mut data, 3000.0).unwrap();
+        coolant.encode(&mut data, 90.0).unwrap();
+        torque.encode(&mut data, -100.5).unwrap();
+        assert_eq!(&data[..3], &[0xe0, 0x2e, 130]);
+        assert_eq!(speed.decode(&data), Some(3000.0));
+        assert_eq!(coolant.decode(&data), Some(90.0));
+        assert_eq!(torque.decode(&data), Some(-100.5));
+        // Motorola: the start bit is the most significant bit of byte 3
+        assert_eq!(&data[3..5], &[0xf3, 0x70]);
+        assert!(coolant.encode(&mut data, 300.0).is_none());
+
+        let left = doors.signal("LeftOpen").unwrap();
+        assert!(doors.is_present(left, &[0x01, 0x01]));
+        assert!(!doors.is_present(left, &[0x02, 0x01]));
+
+        assert!(matches!(
+            Dbc::parse("BO_ 1 A: 8 X\n SG_ B : 0|x@1+ (1,0) [0|1] \"\" X\n"),
+            Err(DbcError::Syntax { line: 2, .. })
+        ));
+        // signals that don't fit in 64 bytes
+        for layout in ["4294967290|16@1+", "504|16@1+", "511|16@0+"] {
+            let dbc = format!("BO_ 1 A: 64 X\n SG_ B : {} (1,0) [0|1] \"\" X\n", layout);
+            assert!(matches!(
+                Dbc::parse(&dbc),
+                Err(DbcError::Syntax { line: 2, .. })
+            ));
+        }
+        assert!(Dbc::parse("BO_ 1 A: 64 X\n SG_ B : 496|16@1+ (1,0) [0|1] \"\" X\n").is_ok());
+    }
+}
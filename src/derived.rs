@@ -0,0 +1,369 @@
+// Check project root for LICENCE
+//! Signals computed from the values of other signals
+
+use std::time::{Duration, Instant};
+
+use cyclonedds_rs::{DDSError, DdsParticipant};
+
+use crate::cache::VehicleStateCache;
+use crate::catalog::{self, DynSample};
+use crate::expr::{Expr, ExprError, History, Inputs};
+use crate::pubsub::VssPublisher;
+use crate::v3::{Timestamp, VssSignal};
+use crate::value::Value;
+
+/// When a derived signal is computed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trigger {
+    /// When the value of any of the inputs was received since the last computation
+    OnChange,
+    /// At a fixed period, whether the inputs changed or not
+    Periodic(Duration),
+}
+
+type Compute<T> = Box<dyn FnMut(&VehicleStateCache) -> Option<<T as VssSignal>::Value> + Send>;
+
+// Track the values in the cache that changed since the last computation. The
+// time of a sample is its source timestamp, or when it was received since
+// `start` if it has none.
+fn track(history: &mut History, start: Instant, cache: &VehicleStateCache, inputs: &[String]) {
+    for input in inputs {
+        for (path, cached) in cache.matching(input) {
+            let Some(value) = cached.value.as_f64() else {
+                continue;
+            };
+            let time = match &cached.source_timestamp {
+                Some(t) => t.sec as f64 + t.nsec as f64 * 1e-9,
+                None => match cached.received.checked_duration_since(start) {
+                    Some(elapsed) => elapsed.as_secs_f64(),
+                    None => -start.duration_since(cached.received).as_secs_f64(),
+                },
+            };
+            history.update(path, value, time);
+        }
+    }
+}
+
+struct State<'a> {
+    cache: &'a VehicleStateCache,
+    history: &'a History,
+}
+
+impl Inputs for State<'_> {
+    fn values(&self, path: &str) -> Vec<f64> {
+        self.cache
+            .matching(path)
+            .filter_map(|(_, cached)| cached.value.as_f64())
+            .collect()
+    }
+
+    fn rates(&self, path: &str) -> Vec<f64> {
+        self.history.rates(path)
+    }
+}
+
+/// A signal of the specification whose value is computed from the values of
+/// other signals in a [VehicleStateCache], with an expression or a closure.
+/// The samples are regular samples of `T`, so consumers can't tell them from a
+/// sensor.
+pub struct DerivedSignal<T: VssSignal> {
+    key: T::Key,
+    inputs: Vec<String>,
+    compute: Compute<T>,
+    trigger: Trigger,
+    last: Option<Instant>,
+}
+
+impl<T: VssSignal> DerivedSignal<T> {
+    /// Compute the value of an instance of `T` with an expression, see [Expr].
+    /// The unit of the expression must be the unit of `T`, or convertible to
+    /// it, see [Expr::factor_to]. The result is rounded for integer signals.
+    /// `rate(path)` is computed from the last two samples of the path seen by
+    /// the computations, so it has no value until the path changed once.
+    pub fn from_expr(text: &str, key: T::Key) -> Result<Self, ExprError> {
+        let expr = Expr::parse(text)?;
+        let factor = expr.factor_to(T::UNIT)?;
+        let inputs: Vec<&str> = expr.paths();
+        let inputs: Vec<String> = inputs.into_iter().map(str::to_owned).collect();
+        let integer = catalog::is_integer(T::DATATYPE);
+        let tracked = inputs.clone();
+        let start = Instant::now();
+        let mut history = History::new();
+        let compute = move |cache: &VehicleStateCache| {
+            track(&mut history, start, cache, &tracked);
+            let state = State {
+                cache,
+                history: &history,
+            };
+            let value = expr.eval(&state)? * factor;
+            let value = if integer { value.round() } else { value };
+            T::value_from_dyn(&Value::Float(value))
+        };
+        Ok(Self {
+            key,
+            inputs,
+            compute: Box::new(compute),
+            trigger: Trigger::OnChange,
+            last: None,
+        })
+    }
+
+    /// Compute the value of an instance of `T` with a closure that reads typed
+    /// values from the cache, e.g. with [VehicleStateCache::value]. The units
+    /// are checked by the types. The inputs are the instance paths the closure
+    /// reads, they may contain `*`, see [catalog::instance_glob]. They decide
+    /// when the value is computed and whether it is stale.
+    pub fn from_fn<F>(inputs: &[&str], key: T::Key, compute: F) -> Self
+    where
+        F: Fn(&VehicleStateCache) -> Option<T::Value> + Send + 'static,
+    {
+        Self {
+            key,
+            inputs: inputs.iter().map(|i| i.to_string()).collect(),
+            compute: Box::new(compute),
+            trigger: Trigger::OnChange,
+            last: None,
+        }
+    }
+
+    pub fn with_trigger(mut self, trigger: Trigger) -> Self {
+        self.trigger = trigger;
+        self
+    }
+
+    /// The instance paths of the inputs, they may contain `*`
+    pub fn inputs(&self) -> &[String] {
+        &self.inputs
+    }
+
+    pub fn key(&self) -> &T::Key {
+        &self.key
+    }
+
+    /// True if an input is missing from the cache or older than the maximum
+    /// age of the cache
+    pub fn is_stale(&self, cache: &VehicleStateCache) -> bool {
+        self.inputs.iter().any(|input| {
+            let mut matching = cache.matching(input).peekable();
+            matching.peek().is_none() || matching.any(|(path, _)| cache.is_stale(path))
+        })
+    }
+
+    fn has_changed(&self, cache: &VehicleStateCache) -> bool {
+        self.inputs.iter().any(|input| {
+            cache
+                .matching(input)
+                .any(|(_, cached)| self.last.is_none_or(|last| cached.received > last))
+        })
+    }
+
+    /// Compute a sample if the trigger fires. Returns None if the trigger did
+    /// not fire, the value can't be computed or is out of bounds. The value is
+    /// not computed from stale inputs, unless the `signal-status` feature is
+    /// enabled. Then the sample is published with the status
+    /// [crate::v3::SignalStatus::Timeout].
+    pub fn evaluate(&mut self, cache: &VehicleStateCache, now: Instant) -> Option<T> {
+        let fire = match self.trigger {
+            Trigger::OnChange => self.has_changed(cache),
+            Trigger::Periodic(period) => self
+                .last
+                .is_none_or(|last| now.saturating_duration_since(last) >= period),
+        };
+        if !fire {
+            return None;
+        }
+        self.last = Some(now);
+        let value = (self.compute)(cache)?;
+        let sample = T::from_parts(value, Some(Timestamp::default()), self.key.clone())?;
+        mark_stale(sample, self.is_stale(cache))
+    }
+}
+
+#[cfg(feature = "signal-status")]
+fn mark_stale<T: VssSignal>(mut sample: T, stale: bool) -> Option<T> {
+    if stale {
+        sample.set_signal_status(crate::v3::SignalStatus::Timeout);
+    }
+    Some(sample)
+}
+
+#[cfg(not(feature = "signal-status"))]
+fn mark_stale<T: VssSignal>(sample: T, stale: bool) -> Option<T> {
+    if stale {
+        None
+    } else {
+        Some(sample)
+    }
+}
+
+trait Output {
+    fn evaluate(&mut self, cache: &mut VehicleStateCache, now: Instant) -> Result<bool, DDSError>;
+}
+
+struct Published<T: VssSignal> {
+    signal: DerivedSignal<T>,
+    publisher: VssPublisher<T>,
+}
+
+impl<T: VssSignal> Output for Published<T> {
+    fn evaluate(&mut self, cache: &mut VehicleStateCache, now: Instant) -> Result<bool, DDSError> {
+        match self.signal.evaluate(cache, now) {
+            Some(sample) => {
+                // derived signals can be inputs of the signals added later
+                cache.insert(DynSample::of(&sample));
+                self.publisher.publish(sample)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+}
+
+/// Computes derived signals from the values in a [VehicleStateCache] and
+/// publishes them on their topics. The signals are computed in the order they
+/// were added, so a signal can use the signals that were added before.
+pub struct DerivedSignals {
+    cache: VehicleStateCache,
+    outputs: Vec<Box<dyn Output>>,
+}
+
+impl DerivedSignals {
+    /// The topics are named with the naming policy of the cache
+    pub fn new(cache: VehicleStateCache) -> Self {
+        Self {
+            cache,
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Subscribe to the inputs of the signal and create a writer for it
+    pub fn add<T: VssSignal>(
+        &mut self,
+        participant: &DdsParticipant,
+        signal: DerivedSignal<T>,
+    ) -> Result<(), DDSError> {
+        for input in signal.inputs() {
+            for info in catalog::glob_instances(input) {
+                self.cache.add_info(participant, info)?;
+            }
+        }
+        let publisher = VssPublisher::with_naming(participant, self.cache.naming())?;
+        self.outputs.push(Box::new(Published { signal, publisher }));
+        Ok(())
+    }
+
+    /// Update the cache and publish the signals whose trigger fired. Call this
+    /// at least as often as the shortest period of the triggers. Returns the
+    /// number of published samples.
+    pub fn update(&mut self) -> Result<usize, DDSError> {
+        self.cache.update();
+        let now = Instant::now();
+        let mut count = 0;
+        for output in &mut self.outputs {
+            if output.evaluate(&mut self.cache, now)? {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    pub fn cache(&self) -> &VehicleStateCache {
+        &self.cache
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::units::{KiloPascal, KilometrePerHour, Litre};
+    use crate::v3::vehicle::chassis::axle::wheel::tire::Pressure;
+    use crate::v3::vehicle::powertrain::fuelsystem::{ConsumptionSinceStart, InstantConsumption};
+    use crate::v3::vehicle::{AverageSpeed, Speed};
+    use crate::v3::Side;
+
+    #[test]
+    fn expression_and_triggers() {
+        assert!(matches!(
+            DerivedSignal::<AverageSpeed>::from_expr(
+                "avg(Vehicle.Chassis.Axle.Row*.Wheel.*.Tire.Pressure)",
+                ()
+            ),
+            Err(ExprError::Unit { .. })
+        ));
+
+        let mut cache = VehicleStateCache::new().with_max_age(Duration::from_millis(50));
+        let mut average =
+            DerivedSignal::<AverageSpeed>::from_expr("Vehicle.Speed / 2 + 10", ()).unwrap();
+        let t0 = Instant::now();
+        assert!(average.evaluate(&cache, t0).is_none());
+
+        let speed = Speed::new(KilometrePerHour(40.0), None).unwrap();
+        cache.insert(DynSample::of(&speed));
+        let sample = average.evaluate(&cache, Instant::now()).unwrap();
+        assert_eq!(sample.value, KilometrePerHour(30.0));
+        // no new input
+        assert!(average.evaluate(&cache, Instant::now()).is_none());
+
+        let mut periodic = DerivedSignal::<Pressure>::from_fn(
+            &[&catalog::instance_glob::<Pressure>()],
+            (1, Side::Left),
+            |cache: &VehicleStateCache| {
+                let values: Vec<u16> = Pressure::key_instances()
+                    .iter()
+                    .filter_map(|key| cache.value::<Pressure>(key))
+                    .map(|p| p.0)
+                    .collect();
+                let sum: u32 = values.iter().map(|v| *v as u32).sum();
+                Some(KiloPascal((sum / values.len().max(1) as u32) as u16))
+            },
+        )
+        .with_trigger(Trigger::Periodic(Duration::from_secs(1)));
+        for (value, row, side) in [(200, 1, Side::Left), (220, 2, Side::Right)] {
+            let pressure = Pressure::new(KiloPascal(value), None, row, side).unwrap();
+            cache.insert(DynSample::of(&pressure));
+        }
+        let t1 = Instant::now();
+        assert_eq!(
+            periodic.evaluate(&cache, t1).unwrap().value,
+            KiloPascal(210)
+        );
+        assert!(periodic
+            .evaluate(&cache, t1 + Duration::from_millis(500))
+            .is_none());
+
+        // stale inputs
+        std::thread::sleep(Duration::from_millis(60));
+        let stale = periodic.evaluate(&cache, t1 + Duration::from_secs(1));
+        #[cfg(not(feature = "signal-status"))]
+        assert!(stale.is_none());
+        #[cfg(feature = "signal-status")]
+        assert_eq!(
+            stale.unwrap().signal_status(),
+            crate::v3::SignalStatus::Timeout
+        );
+    }
+
+    #[test]
+    fn consumption_from_rate() {
+        let mut consumption = DerivedSignal::<InstantConsumption>::from_expr(
+            "rate(Vehicle.Powertrain.FuelSystem.ConsumptionSinceStart) / Vehicle.Speed",
+            (),
+        )
+        .unwrap();
+        let mut cache = VehicleStateCache::new();
+        let used = |litres: f32, sec: u64| {
+            let timestamp = Timestamp { sec, nsec: 0 };
+            ConsumptionSinceStart::new(Litre(litres), Some(timestamp)).unwrap()
+        };
+        let speed = Speed::new(KilometrePerHour(60.0), None).unwrap();
+        cache.insert(DynSample::of(&speed));
+        cache.insert(DynSample::of(&used(1.0, 10)));
+        // a single sample has no rate
+        assert!(consumption.evaluate(&cache, Instant::now()).is_none());
+
+        // 0.01 l in 10 s is 3.6 l/h, or 6 l/100km at 60 km/h
+        cache.insert(DynSample::of(&used(1.01, 20)));
+        let sample = consumption.evaluate(&cache, Instant::now()).unwrap();
+        assert!((sample.value.0 - 6.0).abs() < 1e-3);
+    }
+}
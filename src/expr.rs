@@ -0,0 +1,784 @@
+// Check project root for LICENCE
+//! A small expression language over the values of signals
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::catalog;
+use crate::units::{self, Dimension};
+
+/// Errors when parsing an expression or checking its units
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprError {
+    /// The text is not a valid expression. The position is a byte offset.
+    Parse { position: usize, message: String },
+    /// A path does not match any instance of a signal of the catalog
+    UnknownPath(String),
+    /// The units don't agree, e.g. when adding a speed to a pressure
+    Unit {
+        expected: Option<String>,
+        found: Option<String>,
+    },
+}
+
+impl fmt::Display for ExprError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExprError::Parse { position, message } => {
+                write!(f, "{} at position {}", message, position)
+            }
+            ExprError::UnknownPath(path) => write!(f, "unknown path {}", path),
+            ExprError::Unit { expected, found } => write!(
+                f,
+                "expected the unit {}, found {}",
+                expected.as_deref().unwrap_or("none"),
+                found.as_deref().unwrap_or("none")
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ExprError {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+/// The functions of the language. The aggregates take any number of
+/// arguments, and a path argument contributes the values of all the instances
+/// it matches. `rate` is the change per second of the value of a path.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Func {
+    Abs,
+    Min,
+    Max,
+    Avg,
+    Sum,
+    Count,
+    Rate,
+}
+
+impl Func {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "abs" => Some(Func::Abs),
+            "min" => Some(Func::Min),
+            "max" => Some(Func::Max),
+            "avg" => Some(Func::Avg),
+            "sum" => Some(Func::Sum),
+            "count" => Some(Func::Count),
+            "rate" => Some(Func::Rate),
+            _ => None,
+        }
+    }
+}
+
+/// The values the paths of an expression refer to
+pub trait Inputs {
+    /// The values of the instances that match a path
+    fn values(&self, path: &str) -> Vec<f64>;
+    /// The changes per second of the values of the instances that match a path
+    fn rates(&self, _path: &str) -> Vec<f64> {
+        Vec::new()
+    }
+}
+
+impl<F: Fn(&str) -> Vec<f64>> Inputs for F {
+    fn values(&self, path: &str) -> Vec<f64> {
+        self(path)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Tracked {
+    value: f64,
+    time: f64,
+    rate: Option<f64>,
+}
+
+/// The latest value of each instance it was given, and its change per second
+/// since the value before, for `rate()`
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    values: BTreeMap<String, Tracked>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the value of an instance at a time in seconds. The rate is only
+    /// known when the instance had a value at an earlier time. The same value
+    /// at the same time is ignored, so a sample can be recorded more than once.
+    pub fn update(&mut self, path: &str, value: f64, time: f64) {
+        let last = self.values.get(path);
+        if last.is_some_and(|last| last.time == time && last.value == value) {
+            return;
+        }
+        let rate = last
+            .filter(|last| time > last.time)
+            .map(|last| (value - last.value) / (time - last.time));
+        self.values
+            .insert(path.to_owned(), Tracked { value, time, rate });
+    }
+
+    /// The latest values of the instances that match a path
+    pub fn matching<'a>(&'a self, path: &'a str) -> impl Iterator<Item = (&'a String, f64)> {
+        self.tracked(path).map(|(p, t)| (p, t.value))
+    }
+
+    fn tracked<'a>(&'a self, path: &'a str) -> impl Iterator<Item = (&'a String, &'a Tracked)> {
+        self.values
+            .iter()
+            .filter(move |(p, _)| catalog::glob_match(path, p))
+    }
+}
+
+impl Inputs for History {
+    fn values(&self, path: &str) -> Vec<f64> {
+        self.matching(path).map(|(_, value)| value).collect()
+    }
+
+    fn rates(&self, path: &str) -> Vec<f64> {
+        self.tracked(path).filter_map(|(_, t)| t.rate).collect()
+    }
+}
+
+/// An arithmetic expression over the values of signals, e.g.
+/// `avg(Vehicle.Chassis.Axle.Row*.Wheel.*.Tire.Pressure)` or
+/// `Vehicle.Powertrain.FuelSystem.Range / 1000`. Paths are instance paths and
+/// may contain `*` to match several instances, see [catalog::glob_match].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Path(String),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Call(Func, Vec<Expr>),
+}
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Self, ExprError> {
+        let mut parser = Parser { text, pos: 0 };
+        let expr = parser.expr()?;
+        parser.skip_space();
+        if parser.pos < text.len() {
+            return Err(parser.error("unexpected text after the expression"));
+        }
+        Ok(expr)
+    }
+
+    /// The paths used by the expression
+    pub fn paths(&self) -> Vec<&str> {
+        let mut paths = Vec::new();
+        self.collect_paths(&mut paths);
+        paths
+    }
+
+    fn collect_paths<'a>(&'a self, paths: &mut Vec<&'a str>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Path(p) => paths.push(p),
+            Expr::Neg(e) => e.collect_paths(paths),
+            Expr::Binary(_, l, r) => {
+                l.collect_paths(paths);
+                r.collect_paths(paths);
+            }
+            Expr::Call(_, args) => args.iter().for_each(|a| a.collect_paths(paths)),
+        }
+    }
+
+    /// Evaluate the expression. Returns None if a value is missing, or if a
+    /// path outside of an aggregate does not match exactly one instance.
+    pub fn eval(&self, values: &dyn Inputs) -> Option<f64> {
+        match self {
+            Expr::Number(n) => Some(*n),
+            Expr::Path(p) => single(values.values(p)),
+            Expr::Neg(e) => e.eval(values).map(|v| -v),
+            Expr::Binary(op, l, r) => {
+                let (l, r) = (l.eval(values)?, r.eval(values)?);
+                match op {
+                    Op::Add => Some(l + r),
+                    Op::Sub => Some(l - r),
+                    Op::Mul => Some(l * r),
+                    Op::Div if r != 0.0 => Some(l / r),
+                    Op::Div => None,
+                }
+            }
+            Expr::Call(Func::Abs, args) => args[0].eval(values).map(f64::abs),
+            Expr::Call(Func::Rate, args) => match &args[0] {
+                Expr::Path(p) => single(values.rates(p)),
+                _ => None,
+            },
+            Expr::Call(func, args) => {
+                let mut all = Vec::new();
+                for arg in args {
+                    match arg {
+                        Expr::Path(p) => all.extend(values.values(p)),
+                        e => all.push(e.eval(values)?),
+                    }
+                }
+                match func {
+                    Func::Count => Some(all.len() as f64),
+                    _ if all.is_empty() => None,
+                    Func::Min => all.into_iter().reduce(f64::min),
+                    Func::Max => all.into_iter().reduce(f64::max),
+                    Func::Sum => Some(all.iter().sum()),
+                    Func::Avg => Some(all.iter().sum::<f64>() / all.len() as f64),
+                    Func::Abs | Func::Rate => unreachable!(),
+                }
+            }
+        }
+    }
+
+    /// The unit of the result, from the units of the signals in the catalog.
+    /// Numbers have no unit and take the unit of the other operand. Adding,
+    /// subtracting or comparing values with different units is an error. Products and
+    /// quotients of two units are written like `km/h*s`.
+    pub fn unit(&self) -> Result<Option<String>, ExprError> {
+        match self {
+            Expr::Number(_) => Ok(None),
+            Expr::Path(p) => path_unit(p),
+            Expr::Neg(e) => e.unit(),
+            Expr::Binary(op, l, r) => {
+                let (l, r) = (l.unit()?, r.unit()?);
+                match (op, l, r) {
+                    (Op::Div, None, Some(r)) => Ok(Some(format!("1/{}", r))),
+                    (_, None, u) | (_, u, None) => Ok(u),
+                    (Op::Add | Op::Sub, l, r) => same_unit(l, r),
+                    (Op::Mul, Some(l), Some(r)) => Ok(Some(format!("{}*{}", l, r))),
+                    (Op::Div, Some(l), Some(r)) if l == r => Ok(None),
+                    (Op::Div, Some(l), Some(r)) => Ok(Some(format!("{}/{}", l, r))),
+                }
+            }
+            Expr::Call(Func::Count, _) => Ok(None),
+            Expr::Call(Func::Rate, args) => Ok(Some(match args[0].unit()? {
+                Some(unit) => format!("{}/s", unit),
+                None => "1/s".to_owned(),
+            })),
+            Expr::Call(_, args) => args
+                .iter()
+                .try_fold(None, |unit, arg| same_unit(unit, arg.unit()?)),
+        }
+    }
+
+    /// The factor that converts the result to a unit of the catalog, e.g. 1
+    /// for the unit of the expression and 360000 from the `l/s/km/h` of
+    /// `rate(Vehicle.Powertrain.FuelSystem.ConsumptionSinceStart) / Vehicle.Speed`
+    /// to `l/100km`. The units of [units::dimension] without an offset can be
+    /// converted.
+    pub fn factor_to(&self, unit: Option<&str>) -> Result<f64, ExprError> {
+        let found = self.unit()?;
+        if found.as_deref() == unit {
+            return Ok(1.0);
+        }
+        let to = match unit {
+            Some(unit) => unit_dimension(unit),
+            None => Some(Dimension::NONE),
+        };
+        match (self.dimension(), to) {
+            (Some(from), Some(to)) if from.exponents == to.exponents => Ok(from.factor / to.factor),
+            _ => Err(ExprError::Unit {
+                expected: unit.map(str::to_owned),
+                found,
+            }),
+        }
+    }
+
+    // The dimension of the result, None if a unit is not in the table
+    fn dimension(&self) -> Option<Dimension> {
+        // the operands of sums and aggregates have the same unit or none
+        let first = |args: &[&Expr]| match args.iter().find(|a| matches!(a.unit(), Ok(Some(_)))) {
+            Some(arg) => arg.dimension(),
+            None => Some(Dimension::NONE),
+        };
+        match self {
+            Expr::Number(_) => Some(Dimension::NONE),
+            Expr::Path(p) => match path_unit(p).ok()? {
+                Some(unit) => unit_dimension(&unit),
+                None => Some(Dimension::NONE),
+            },
+            Expr::Neg(e) => e.dimension(),
+            Expr::Binary(Op::Mul, l, r) => Some(l.dimension()? * r.dimension()?),
+            Expr::Binary(Op::Div, l, r) => Some(l.dimension()? / r.dimension()?),
+            Expr::Binary(_, l, r) => first(&[l, r]),
+            Expr::Call(Func::Count, _) => Some(Dimension::NONE),
+            Expr::Call(Func::Rate, args) => Some(args[0].dimension()? / Dimension::SECOND),
+            Expr::Call(_, args) => first(&args.iter().collect::<Vec<_>>()),
+        }
+    }
+}
+
+// A unit with an offset, like celsius, is not a factor of the SI unit, so an
+// expression in celsius only fits a signal in celsius
+fn unit_dimension(unit: &str) -> Option<Dimension> {
+    units::dimension(unit).filter(|d| d.offset == 0.0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cmp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+/// A condition over the values of signals, e.g.
+/// `Vehicle.Speed > 0 implies Vehicle.Powertrain.Transmission.SelectedGear != 126`.
+/// Conditions compare expressions, see [Expr], and are combined with `and`,
+/// `or`, `not` and `implies`. An expression without comparison is true if it
+/// is not zero, booleans are 0 or 1.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Compare(Cmp, Expr, Expr),
+    Value(Expr),
+    Not(Box<Condition>),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Implies(Box<Condition>, Box<Condition>),
+}
+
+impl Condition {
+    pub fn parse(text: &str) -> Result<Self, ExprError> {
+        let mut parser = Parser { text, pos: 0 };
+        let condition = parser.condition()?;
+        parser.skip_space();
+        if parser.pos < text.len() {
+            return Err(parser.error("unexpected text after the condition"));
+        }
+        Ok(condition)
+    }
+
+    /// The paths used by the condition
+    pub fn paths(&self) -> Vec<&str> {
+        match self {
+            Condition::Compare(_, l, r) => {
+                let mut paths = l.paths();
+                paths.extend(r.paths());
+                paths
+            }
+            Condition::Value(e) => e.paths(),
+            Condition::Not(c) => c.paths(),
+            Condition::And(l, r) | Condition::Or(l, r) | Condition::Implies(l, r) => {
+                let mut paths = l.paths();
+                paths.extend(r.paths());
+                paths
+            }
+        }
+    }
+
+    /// Evaluate the condition. Returns None if it depends on a value that is
+    /// missing.
+    pub fn eval(&self, values: &dyn Inputs) -> Option<bool> {
+        match self {
+            Condition::Compare(cmp, l, r) => {
+                let (l, r) = (l.eval(values)?, r.eval(values)?);
+                Some(match cmp {
+                    Cmp::Lt => l < r,
+                    Cmp::Le => l <= r,
+                    Cmp::Gt => l > r,
+                    Cmp::Ge => l >= r,
+                    Cmp::Eq => l == r,
+                    Cmp::Ne => l != r,
+                })
+            }
+            Condition::Value(e) => e.eval(values).map(|v| v != 0.0),
+            Condition::Not(c) => c.eval(values).map(|b| !b),
+            Condition::And(l, r) => match (l.eval(values), r.eval(values)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            },
+            Condition::Or(l, r) => match (l.eval(values), r.eval(values)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            },
+            Condition::Implies(l, r) => match l.eval(values)? {
+                false => Some(true),
+                true => r.eval(values),
+            },
+        }
+    }
+
+    /// Check that the paths exist and that the units of the compared
+    /// expressions agree
+    pub fn check_units(&self) -> Result<(), ExprError> {
+        match self {
+            Condition::Compare(_, l, r) => same_unit(l.unit()?, r.unit()?).map(|_| ()),
+            Condition::Value(e) => e.unit().map(|_| ()),
+            Condition::Not(c) => c.check_units(),
+            Condition::And(l, r) | Condition::Or(l, r) | Condition::Implies(l, r) => {
+                l.check_units()?;
+                r.check_units()
+            }
+        }
+    }
+}
+
+fn single(values: Vec<f64>) -> Option<f64> {
+    match values.as_slice() {
+        [v] => Some(*v),
+        _ => None,
+    }
+}
+
+fn same_unit(l: Option<String>, r: Option<String>) -> Result<Option<String>, ExprError> {
+    match (l, r) {
+        (None, u) | (u, None) => Ok(u),
+        (Some(l), Some(r)) if l == r => Ok(Some(l)),
+        (l, r) => Err(ExprError::Unit {
+            expected: l,
+            found: r,
+        }),
+    }
+}
+
+// The unit of the signals whose instances match the path
+fn path_unit(path: &str) -> Result<Option<String>, ExprError> {
+    let mut signals = catalog::glob_instances(path);
+    let first = signals
+        .next()
+        .ok_or_else(|| ExprError::UnknownPath(path.to_owned()))?;
+    let unit = first.unit.map(str::to_owned);
+    signals.try_fold(unit, |unit, s| same_unit(unit, s.unit.map(str::to_owned)))
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, message: &str) -> ExprError {
+        ExprError::Parse {
+            position: self.pos,
+            message: message.to_owned(),
+        }
+    }
+
+    fn skip_space(&mut self) {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_space();
+        self.text[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, ExprError> {
+        let mut left = self.term()?;
+        loop {
+            let op = if self.eat('+') {
+                Op::Add
+            } else if self.eat('-') {
+                Op::Sub
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.term()?));
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, ExprError> {
+        let mut left = self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                Op::Mul
+            } else if self.eat('/') {
+                Op::Div
+            } else {
+                return Ok(left);
+            };
+            left = Expr::Binary(op, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ExprError> {
+        if self.eat('-') {
+            Ok(Expr::Neg(Box::new(self.unary()?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, ExprError> {
+        match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                if !self.eat(')') {
+                    return Err(self.error("expected `)`"));
+                }
+                Ok(expr)
+            }
+            Some(c) if c.is_ascii_digit() => self.number(),
+            Some(c) if c.is_ascii_alphabetic() => self.name(),
+            Some(_) => Err(self.error("expected a number, a path or a function")),
+            None => Err(self.error("unexpected end of the expression")),
+        }
+    }
+
+    fn number(&mut self) -> Result<Expr, ExprError> {
+        let start = self.pos;
+        let len = self.text[start..]
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(self.text.len() - start);
+        let number = self.text[start..start + len]
+            .parse()
+            .map_err(|_| self.error("invalid number"))?;
+        self.pos += len;
+        Ok(Expr::Number(number))
+    }
+
+    // The length of the name at the current position
+    fn name_len(&mut self) -> usize {
+        self.skip_space();
+        self.text[self.pos..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '.' || c == '*' || c == '_'))
+            .unwrap_or(self.text.len() - self.pos)
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let len = self.name_len();
+        if &self.text[self.pos..self.pos + len] == keyword {
+            self.pos += len;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn name(&mut self) -> Result<Expr, ExprError> {
+        let start = self.pos;
+        let len = self.name_len();
+        let name = &self.text[start..start + len];
+        self.pos += len;
+        match name {
+            "true" => return Ok(Expr::Number(1.0)),
+            "false" => return Ok(Expr::Number(0.0)),
+            _ => {}
+        }
+        if !self.eat('(') {
+            return Ok(Expr::Path(name.to_owned()));
+        }
+        let func = Func::parse(name).ok_or_else(|| ExprError::Parse {
+            position: start,
+            message: format!("unknown function `{}`", name),
+        })?;
+        let mut args = Vec::new();
+        if !self.eat(')') {
+            loop {
+                args.push(self.expr()?);
+                if self.eat(')') {
+                    break;
+                }
+                if !self.eat(',') {
+                    return Err(self.error("expected `,` or `)`"));
+                }
+            }
+        }
+        let valid = match func {
+            Func::Abs => args.len() == 1,
+            Func::Rate => matches!(args.as_slice(), [Expr::Path(_)]),
+            _ => !args.is_empty(),
+        };
+        if !valid {
+            return Err(ExprError::Parse {
+                position: start,
+                message: format!("wrong number of arguments for `{}`", name),
+            });
+        }
+        Ok(Expr::Call(func, args))
+    }
+
+    fn or(&mut self) -> Result<Condition, ExprError> {
+        let mut left = self.and()?;
+        while self.eat_keyword("or") {
+            left = Condition::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Condition, ExprError> {
+        let mut left = self.not()?;
+        while self.eat_keyword("and") {
+            left = Condition::And(Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Condition, ExprError> {
+        if self.eat_keyword("not") {
+            return Ok(Condition::Not(Box::new(self.not()?)));
+        }
+        if self.peek() == Some('(') {
+            // either a condition in parentheses or the start of an expression
+            let start = self.pos;
+            self.pos += 1;
+            if let Ok(condition) = self.condition() {
+                if self.eat(')') && !matches!(self.peek(), Some(c) if "<>=!+-*/".contains(c)) {
+                    return Ok(condition);
+                }
+            }
+            self.pos = start;
+        }
+        self.compare()
+    }
+
+    fn compare(&mut self) -> Result<Condition, ExprError> {
+        let left = self.expr()?;
+        self.skip_space();
+        let rest = &self.text[self.pos..];
+        let operator = [
+            ("<=", Cmp::Le),
+            (">=", Cmp::Ge),
+            ("==", Cmp::Eq),
+            ("!=", Cmp::Ne),
+            ("<", Cmp::Lt),
+            (">", Cmp::Gt),
+        ]
+        .into_iter()
+        .find(|(op, _)| rest.starts_with(op));
+        match operator {
+            Some((op, cmp)) => {
+                self.pos += op.len();
+                Ok(Condition::Compare(cmp, left, self.expr()?))
+            }
+            None => Ok(Condition::Value(left)),
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition, ExprError> {
+        let left = self.or()?;
+        if self.eat_keyword("implies") {
+            Ok(Condition::Implies(Box::new(left), Box::new(self.or()?)))
+        } else {
+            Ok(left)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_eval_and_units() {
+        let expr =
+            Expr::parse("avg(Vehicle.Chassis.Axle.Row*.Wheel.*.Tire.Pressure) * 2 - -1").unwrap();
+        let values = |p: &str| {
+            if p.contains('*') {
+                vec![10.0, 20.0]
+            } else {
+                vec![]
+            }
+        };
+        assert_eq!(expr.eval(&values), Some(31.0));
+        assert_eq!(expr.unit(), Ok(Some("kPa".to_owned())));
+        assert_eq!(
+            expr.paths(),
+            vec!["Vehicle.Chassis.Axle.Row*.Wheel.*.Tire.Pressure"]
+        );
+
+        // a path outside of an aggregate must match a single instance
+        let expr = Expr::parse("Vehicle.Chassis.Axle.Row*.Wheel.*.Tire.Pressure").unwrap();
+        assert_eq!(expr.eval(&values), None);
+
+        let expr =
+            Expr::parse("Vehicle.Speed + Vehicle.Chassis.Axle.Row1.Wheel.Left.Tire.Pressure")
+                .unwrap();
+        assert!(matches!(expr.unit(), Err(ExprError::Unit { .. })));
+        let expr = Expr::parse("Vehicle.Speed / Vehicle.AverageSpeed").unwrap();
+        assert_eq!(expr.unit(), Ok(None));
+        assert_eq!(
+            Expr::parse("Vehicle.Nothing").unwrap().unit(),
+            Err(ExprError::UnknownPath("Vehicle.Nothing".to_owned()))
+        );
+        assert!(Expr::parse("avg(").is_err());
+        assert!(Expr::parse("foo(Vehicle.Speed)").is_err());
+        assert!(Expr::parse("1 2").is_err());
+        assert!(Expr::parse("rate(1)").is_err());
+    }
+
+    #[test]
+    fn unit_conversion() {
+        let expr = Expr::parse("Vehicle.Speed * 2").unwrap();
+        assert_eq!(expr.factor_to(Some("km/h")), Ok(1.0));
+        assert!((expr.factor_to(Some("m/s")).unwrap() - 1.0 / 3.6).abs() < 1e-12);
+        assert!(matches!(
+            expr.factor_to(Some("kPa")),
+            Err(ExprError::Unit { .. })
+        ));
+
+        // litres per second divided by km/h
+        let expr = Expr::parse(
+            "rate(Vehicle.Powertrain.FuelSystem.ConsumptionSinceStart) / Vehicle.Speed",
+        )
+        .unwrap();
+        assert_eq!(expr.unit(), Ok(Some("l/s/km/h".to_owned())));
+        let factor = expr.factor_to(Some("l/100km")).unwrap();
+        assert!((factor - 360_000.0).abs() < 1e-6);
+        assert!(expr.factor_to(Some("l/h")).is_err());
+    }
+
+    #[test]
+    fn conditions() {
+        let values = |p: &str| match p {
+            "Vehicle.Speed" => vec![12.0],
+            "Vehicle.Powertrain.Transmission.SelectedGear" => vec![126.0],
+            "Vehicle.Cabin.Door.Row*.*.IsOpen" => vec![0.0, 1.0, 0.0, 0.0],
+            _ => vec![],
+        };
+        let parked = Condition::parse(
+            "Vehicle.Speed > 0 implies Vehicle.Powertrain.Transmission.SelectedGear != 126",
+        )
+        .unwrap();
+        assert_eq!(parked.eval(&values), Some(false));
+        assert!(parked.check_units().is_ok());
+
+        let doors =
+            Condition::parse("not (max(Vehicle.Cabin.Door.Row*.*.IsOpen) and Vehicle.Speed > 5)")
+                .unwrap();
+        assert_eq!(doors.eval(&values), Some(false));
+        let grouped = Condition::parse("(Vehicle.Speed - 2) * 2 == 20 or false").unwrap();
+        assert_eq!(grouped.eval(&values), Some(true));
+        // unknown values
+        let missing = Condition::parse("Vehicle.AverageSpeed > 3").unwrap();
+        assert_eq!(missing.eval(&values), None);
+        assert_eq!(
+            Condition::parse("Vehicle.AverageSpeed > 3 and Vehicle.Speed < 0")
+                .unwrap()
+                .eval(&values),
+            Some(false)
+        );
+
+        let mixed =
+            Condition::parse("Vehicle.Speed > Vehicle.Powertrain.FuelSystem.Level").unwrap();
+        assert!(matches!(mixed.check_units(), Err(ExprError::Unit { .. })));
+        let rate = Condition::parse(
+            "abs(rate(Vehicle.Powertrain.TractionBattery.StateOfCharge.Current)) <= 5",
+        )
+        .unwrap();
+        assert!(rate.check_units().is_ok());
+        assert_eq!(rate.eval(&values), None);
+        assert!(Condition::parse("Vehicle.Speed >").is_err());
+    }
+}
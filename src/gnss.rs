@@ -0,0 +1,515 @@
+// Check project root for LICENCE
+//! Samples of the Vehicle.CurrentLocation signals from NMEA 0183 sentences or gpsd reports
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, BufRead};
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, NaiveTime, SecondsFormat, Utc};
+use cyclonedds_rs::DDSError;
+use serde_derive::Deserialize;
+
+use crate::backend::Backend;
+use crate::catalog::DynSample;
+use crate::v3::Timestamp;
+use crate::value::Value;
+
+#[derive(Debug)]
+pub enum GnssError {
+    /// A sentence with a wrong checksum
+    Checksum(String),
+    /// A sentence or a field that can't be parsed
+    Syntax(String),
+    /// A value outside of the range of the signal
+    Range {
+        path: &'static str,
+        value: Value,
+    },
+    Json(serde_json::Error),
+    Io(io::Error),
+    Dds(DDSError),
+}
+
+impl fmt::Display for GnssError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GnssError::Checksum(sentence) => write!(f, "wrong checksum: {}", sentence),
+            GnssError::Syntax(text) => write!(f, "can't parse {}", text),
+            GnssError::Range { path, value } => {
+                write!(f, "{:?} is out of the range of {}", value, path)
+            }
+            GnssError::Json(e) => e.fmt(f),
+            GnssError::Io(e) => e.fmt(f),
+            GnssError::Dds(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for GnssError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GnssError::Json(e) => Some(e),
+            GnssError::Io(e) => Some(e),
+            GnssError::Dds(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for GnssError {
+    fn from(e: serde_json::Error) -> Self {
+        GnssError::Json(e)
+    }
+}
+
+impl From<io::Error> for GnssError {
+    fn from(e: io::Error) -> Self {
+        GnssError::Io(e)
+    }
+}
+
+impl From<DDSError> for GnssError {
+    fn from(e: DDSError) -> Self {
+        GnssError::Dds(e)
+    }
+}
+
+const LATITUDE: &str = "Vehicle.CurrentLocation.Latitude";
+const LONGITUDE: &str = "Vehicle.CurrentLocation.Longitude";
+const ALTITUDE: &str = "Vehicle.CurrentLocation.Altitude";
+const HEADING: &str = "Vehicle.CurrentLocation.Heading";
+const HORIZONTAL_ACCURACY: &str = "Vehicle.CurrentLocation.HorizontalAccuracy";
+const VERTICAL_ACCURACY: &str = "Vehicle.CurrentLocation.VerticalAccuracy";
+const FIX_TYPE: &str = "Vehicle.CurrentLocation.GNSSReceiver.FixType";
+const TIMESTAMP: &str = "Vehicle.CurrentLocation.Timestamp";
+
+// The PRNs of the SBAS satellites, in NMEA and in gpsd numbering
+fn is_sbas(prn: u16) -> bool {
+    matches!(prn, 33..=64 | 120..=158)
+}
+
+fn iso8601(time: DateTime<Utc>) -> Value {
+    Value::String(time.to_rfc3339_opts(SecondsFormat::Millis, true))
+}
+
+// The fields of a sentence without the address and the checksum
+struct Fields<'a> {
+    sentence: &'a str,
+    fields: Vec<&'a str>,
+}
+
+impl<'a> Fields<'a> {
+    fn text(&self, i: usize) -> Option<&'a str> {
+        self.fields.get(i).copied().filter(|f| !f.is_empty())
+    }
+
+    fn number<T: FromStr>(&self, i: usize) -> Result<Option<T>, GnssError> {
+        self.text(i)
+            .map(|f| f.parse().map_err(|_| self.syntax()))
+            .transpose()
+    }
+
+    // `ddmm.mmmm,N` or `dddmm.mmmm,E`
+    fn coordinate(&self, i: usize) -> Result<Option<f64>, GnssError> {
+        let value: f64 = match self.number(i)? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let degrees = (value / 100.0).trunc();
+        let value = degrees + (value - degrees * 100.0) / 60.0;
+        match self.text(i + 1) {
+            Some("N") | Some("E") => Ok(Some(value)),
+            Some("S") | Some("W") => Ok(Some(-value)),
+            _ => Err(self.syntax()),
+        }
+    }
+
+    // `hhmmss.ss` and `ddmmyy`
+    fn time(&self, time: usize, date: NaiveDate) -> Result<Option<DateTime<Utc>>, GnssError> {
+        self.text(time)
+            .map(|t| {
+                NaiveTime::parse_from_str(t, "%H%M%S%.f")
+                    .map(|t| date.and_time(t).and_utc())
+                    .map_err(|_| self.syntax())
+            })
+            .transpose()
+    }
+
+    fn syntax(&self) -> GnssError {
+        GnssError::Syntax(self.sentence.to_owned())
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "class")]
+enum Report {
+    #[serde(rename = "TPV")]
+    Tpv(Tpv),
+    #[serde(rename = "SKY")]
+    Sky(Sky),
+    #[serde(other)]
+    Other,
+}
+
+// A time-position-velocity report of gpsd
+#[derive(Debug, Deserialize)]
+struct Tpv {
+    #[serde(default)]
+    mode: u8,
+    status: Option<u8>,
+    time: Option<String>,
+    lat: Option<f64>,
+    lon: Option<f64>,
+    #[serde(rename = "altHAE")]
+    alt_hae: Option<f64>,
+    alt: Option<f64>,
+    track: Option<f64>,
+    eph: Option<f64>,
+    epx: Option<f64>,
+    epy: Option<f64>,
+    epv: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Sky {
+    #[serde(default)]
+    satellites: Vec<Satellite>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Satellite {
+    #[serde(rename = "PRN")]
+    prn: u16,
+    #[serde(default)]
+    used: bool,
+    gnssid: Option<u8>,
+}
+
+/// The state of a GNSS receiver across sentences: the fix type needs the
+/// quality of GGA, and the dimension and the satellites in use of GSA.
+#[derive(Debug, Default)]
+pub struct Receiver {
+    // the GGA fix quality: 0 invalid, 1 GPS, 2 DGPS, 4 RTK fixed, 5 RTK float
+    quality: u8,
+    // 2 or 3 from GSA, 1 for no fix
+    dimension: Option<u8>,
+    // the dimension when there is no GSA: 3 with an altitude
+    implied: u8,
+    // whether an SBAS satellite is used, for each talker and system of GSA
+    sbas: BTreeMap<String, bool>,
+}
+
+impl Receiver {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The allowed value of `GNSSReceiver.FixType` for the current state
+    pub fn fix_type(&self) -> String {
+        let sbas = self.sbas.values().any(|s| *s);
+        let (satellite, ground) = match self.quality {
+            1 => (false, false),
+            2 => (sbas, !sbas),
+            4 | 5 => (sbas, true),
+            _ => return "NONE".to_owned(),
+        };
+        let dimension = match self.dimension.unwrap_or(self.implied) {
+            2 => "TWO_D",
+            3 => "THREE_D",
+            _ => return "NONE".to_owned(),
+        };
+        let augmentation = match (satellite, ground) {
+            (false, false) => "",
+            (true, false) => "_SATELLITE_BASED_AUGMENTATION",
+            (false, true) => "_GROUND_BASED_AUGMENTATION",
+            (true, true) => "_SATELLITE_AND_GROUND_BASED_AUGMENTATION",
+        };
+        format!("{}{}", dimension, augmentation)
+    }
+
+    /// The samples of an NMEA 0183 sentence, e.g. `$GPGGA,...*47`. GGA,
+    /// RMC, GSA, VTG and GST sentences of any talker are decoded, the other
+    /// sentences have no samples. The checksum is checked when present.
+    pub fn nmea(
+        &mut self,
+        sentence: &str,
+        timestamp: Option<Timestamp>,
+    ) -> Result<Vec<DynSample>, GnssError> {
+        let sentence = sentence.trim();
+        let syntax = || GnssError::Syntax(sentence.to_owned());
+        let body = sentence.strip_prefix('$').ok_or_else(syntax)?;
+        let body = match body.split_once('*') {
+            Some((body, checksum)) => {
+                let expected = u8::from_str_radix(checksum, 16).map_err(|_| syntax())?;
+                if body.bytes().fold(0, |sum, b| sum ^ b) != expected {
+                    return Err(GnssError::Checksum(sentence.to_owned()));
+                }
+                body
+            }
+            None => body,
+        };
+        let fields = Fields {
+            sentence,
+            fields: body.split(',').collect(),
+        };
+        let address = fields.fields[0];
+        if address.len() != 5 || !address.is_ascii() {
+            return Ok(Vec::new());
+        }
+        let (talker, kind) = address.split_at(2);
+        let values = match kind {
+            "GGA" => self.gga(&fields)?,
+            "RMC" => self.rmc(&fields)?,
+            "GSA" => self.gsa(talker, &fields)?,
+            "VTG" => fields
+                .number(1)?
+                .map(|course| (HEADING, Value::Float(course)))
+                .into_iter()
+                .collect(),
+            "GST" => self.gst(&fields)?,
+            _ => Vec::new(),
+        };
+        samples(values, timestamp)
+    }
+
+    fn gga(&mut self, f: &Fields) -> Result<Vec<(&'static str, Value)>, GnssError> {
+        self.quality = f.number(6)?.unwrap_or(0);
+        let mut values = Vec::new();
+        if self.quality != 0 {
+            if let (Some(latitude), Some(longitude)) = (f.coordinate(2)?, f.coordinate(4)?) {
+                values.push((LATITUDE, Value::Float(latitude)));
+                values.push((LONGITUDE, Value::Float(longitude)));
+            }
+            // above the mean sea level, the geoid separation gives the
+            // altitude above the ellipsoid
+            let altitude: Option<f64> = f.number(9)?;
+            self.implied = if altitude.is_some() { 3 } else { 2 };
+            if let Some(altitude) = altitude {
+                let separation: f64 = f.number(11)?.unwrap_or(0.0);
+                values.push((ALTITUDE, Value::Float(altitude + separation)));
+            }
+        }
+        values.push((FIX_TYPE, Value::String(self.fix_type())));
+        Ok(values)
+    }
+
+    fn rmc(&mut self, f: &Fields) -> Result<Vec<(&'static str, Value)>, GnssError> {
+        let mut values = Vec::new();
+        if f.text(2) != Some("A") {
+            return Ok(values);
+        }
+        if let (Some(latitude), Some(longitude)) = (f.coordinate(3)?, f.coordinate(5)?) {
+            values.push((LATITUDE, Value::Float(latitude)));
+            values.push((LONGITUDE, Value::Float(longitude)));
+        }
+        if let Some(course) = f.number(8)? {
+            values.push((HEADING, Value::Float(course)));
+        }
+        if let Some(date) = f.text(9) {
+            let date = NaiveDate::parse_from_str(date, "%d%m%y").map_err(|_| f.syntax())?;
+            if let Some(time) = f.time(1, date)? {
+                values.push((TIMESTAMP, iso8601(time)));
+            }
+        }
+        Ok(values)
+    }
+
+    fn gsa(&mut self, talker: &str, f: &Fields) -> Result<Vec<(&'static str, Value)>, GnssError> {
+        self.dimension = f.number(2)?;
+        let mut sbas = false;
+        for i in 3..=14 {
+            sbas |= f.number(i)?.is_some_and(is_sbas);
+        }
+        // NMEA 4.10 adds the system, a GN talker has a GSA for each system
+        let system = f.text(18).unwrap_or_default();
+        self.sbas.insert(format!("{}{}", talker, system), sbas);
+        Ok(vec![(FIX_TYPE, Value::String(self.fix_type()))])
+    }
+
+    // The standard deviations of the latitude, longitude and altitude errors
+    fn gst(&mut self, f: &Fields) -> Result<Vec<(&'static str, Value)>, GnssError> {
+        let mut values = Vec::new();
+        if let (Some(latitude), Some(longitude)) = (f.number::<f64>(6)?, f.number::<f64>(7)?) {
+            values.push((HORIZONTAL_ACCURACY, Value::Float(latitude.hypot(longitude))));
+        }
+        if let Some(altitude) = f.number(8)? {
+            values.push((VERTICAL_ACCURACY, Value::Float(altitude)));
+        }
+        Ok(values)
+    }
+
+    /// The samples of a line of the JSON stream of gpsd. TPV reports have
+    /// the location, SKY reports the satellites in use, the other reports
+    /// have no samples.
+    pub fn gpsd(
+        &mut self,
+        line: &str,
+        timestamp: Option<Timestamp>,
+    ) -> Result<Vec<DynSample>, GnssError> {
+        let values = match serde_json::from_str(line)? {
+            Report::Tpv(tpv) => self.tpv(tpv, line)?,
+            Report::Sky(sky) => {
+                let sbas = sky
+                    .satellites
+                    .iter()
+                    .any(|s| s.used && (s.gnssid == Some(1) || is_sbas(s.prn)));
+                self.sbas.insert("gpsd".to_owned(), sbas);
+                Vec::new()
+            }
+            Report::Other => Vec::new(),
+        };
+        samples(values, timestamp)
+    }
+
+    fn tpv(&mut self, tpv: Tpv, line: &str) -> Result<Vec<(&'static str, Value)>, GnssError> {
+        // the status in the GGA fix quality, dead reckoning only, time only
+        // and simulated fixes have no GNSS fix
+        self.quality = match tpv.status.unwrap_or(1) {
+            0 | 1 | 6 => 1,
+            2 => 2,
+            3 => 4,
+            4 => 5,
+            _ => 0,
+        };
+        self.dimension = Some(tpv.mode);
+        let mut values = Vec::new();
+        if let (Some(latitude), Some(longitude)) = (tpv.lat, tpv.lon) {
+            values.push((LATITUDE, Value::Float(latitude)));
+            values.push((LONGITUDE, Value::Float(longitude)));
+        }
+        if let Some(altitude) = tpv.alt_hae.or(tpv.alt) {
+            values.push((ALTITUDE, Value::Float(altitude)));
+        }
+        if let Some(track) = tpv.track {
+            values.push((HEADING, Value::Float(track)));
+        }
+        let horizontal = tpv.eph.or_else(|| Some(tpv.epx?.hypot(tpv.epy?)));
+        if let Some(accuracy) = horizontal {
+            values.push((HORIZONTAL_ACCURACY, Value::Float(accuracy)));
+        }
+        if let Some(accuracy) = tpv.epv {
+            values.push((VERTICAL_ACCURACY, Value::Float(accuracy)));
+        }
+        values.push((FIX_TYPE, Value::String(self.fix_type())));
+        if let Some(time) = tpv.time {
+            let time = DateTime::parse_from_rfc3339(&time)
+                .map_err(|_| GnssError::Syntax(line.to_owned()))?;
+            values.push((TIMESTAMP, iso8601(time.with_timezone(&Utc))));
+        }
+        Ok(values)
+    }
+}
+
+// The samples of the values checked against the specification
+fn samples(
+    values: Vec<(&'static str, Value)>,
+    timestamp: Option<Timestamp>,
+) -> Result<Vec<DynSample>, GnssError> {
+    values
+        .into_iter()
+        .map(|(path, value)| {
+            DynSample::new(path, value.clone(), timestamp.clone())
+                .and_then(DynSample::checked)
+                .ok_or(GnssError::Range { path, value })
+        })
+        .collect()
+}
+
+/// Decode NMEA sentences and gpsd JSON reports, e.g. from a serial port, a
+/// gpsd socket or a log, and write the samples to a backend until the end
+/// of the input. The lines that can't be decoded are skipped. Returns the
+/// number of samples.
+pub fn publish<R: BufRead, B: Backend>(input: R, backend: &mut B) -> Result<usize, GnssError> {
+    let mut receiver = Receiver::new();
+    let mut published = 0;
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim();
+        let samples = if line.starts_with('{') {
+            receiver.gpsd(line, Some(Timestamp::default()))
+        } else if line.starts_with('$') {
+            receiver.nmea(line, Some(Timestamp::default()))
+        } else {
+            continue;
+        };
+        for sample in samples.unwrap_or_default() {
+            backend.write(&sample)?;
+            published += 1;
+        }
+    }
+    Ok(published)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(samples: Vec<DynSample>) -> Vec<(String, Value)> {
+        samples.into_iter().map(|s| (s.path, s.value)).collect()
+    }
+
+    #[test]
+    fn nmea() {
+        let mut receiver = Receiver::new();
+        let gga = "$GPGGA,123519,4807.038,N,01131.000,E,1,08,0.9,545.4,M,46.9,M,,*47";
+        let decoded = values(receiver.nmea(gga, None).unwrap());
+        assert_eq!(decoded[0].0, LATITUDE);
+        assert!((decoded[0].1.as_f64().unwrap() - 48.1173).abs() < 1e-9);
+        assert!((decoded[1].1.as_f64().unwrap() - 11.516_666).abs() < 1e-6);
+        assert!((decoded[2].1.as_f64().unwrap() - 592.3).abs() < 1e-9);
+        assert_eq!(decoded[3].1, Value::String("THREE_D".to_owned()));
+
+        let gsa = "$GPGSA,A,2,04,05,,09,12,,,24,,,,,2.5,1.3,2.1*38";
+        let dgps = gga.replace(",1,08,", ",2,08,").replace("*47", "");
+        receiver.nmea(&dgps, None).unwrap();
+        let decoded = values(receiver.nmea(gsa, None).unwrap());
+        assert_eq!(
+            decoded[0].1,
+            Value::String("TWO_D_GROUND_BASED_AUGMENTATION".to_owned())
+        );
+
+        let rmc = "$GPRMC,123519,A,4807.038,S,01131.000,W,022.4,084.4,230394,003.1,W*65";
+        let decoded = values(receiver.nmea(rmc, None).unwrap());
+        assert!(decoded[0].1.as_f64().unwrap() < 0.0);
+        assert_eq!(decoded[2], (HEADING.to_owned(), Value::Float(84.4)));
+        assert_eq!(
+            decoded[3].1,
+            Value::String("1994-03-23T12:35:19.000Z".to_owned())
+        );
+
+        assert!(matches!(
+            receiver.nmea(&gga.replace("*47", "*48"), None),
+            Err(GnssError::Checksum(_))
+        ));
+        assert!(matches!(
+            receiver.nmea("$GPVTG,400.0,T,,M,0.0,N,0.0,K", None),
+            Err(GnssError::Range { path: HEADING, .. })
+        ));
+    }
+
+    #[test]
+    fn gpsd() {
+        let mut receiver = Receiver::new();
+        let sky = r#"{"class":"SKY","satellites":[{"PRN":133,"used":true,"gnssid":1}]}"#;
+        assert!(receiver.gpsd(sky, None).unwrap().is_empty());
+        let tpv = r#"{"class":"TPV","mode":3,"status":2,"time":"2024-05-01T10:00:01.5Z",
+                      "lat":52.5,"lon":13.4,"altHAE":80.2,"track":271.0,"eph":3.5,"epv":5.0}"#;
+        let decoded = values(receiver.gpsd(&tpv.replace('\n', ""), None).unwrap());
+        assert_eq!(decoded.len(), 8);
+        assert_eq!(
+            decoded[4],
+            (HORIZONTAL_ACCURACY.to_owned(), Value::Float(3.5))
+        );
+        assert_eq!(
+            decoded[6].1,
+            Value::String("THREE_D_SATELLITE_BASED_AUGMENTATION".to_owned())
+        );
+        assert_eq!(
+            decoded[7].1,
+            Value::String("2024-05-01T10:00:01.500Z".to_owned())
+        );
+        let version = r#"{"class":"VERSION","release":"3.25"}"#;
+        assert!(receiver.gpsd(version, None).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,769 @@
+// Check project root for LICENCE
+//! A gRPC service with the kuksa.val.v1 API of the Eclipse KUKSA.val databroker
+
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+
+use cyclonedds_rs::{DDSError, DdsParticipant};
+use futures_core::Stream;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tonic::transport::server::TcpIncoming;
+use tonic::{Request, Response, Status};
+
+use crate::backend::{Backend, DdsBackend};
+use crate::catalog::{self, DynSample, SignalInfo};
+use crate::naming::NamingPolicy;
+use crate::v3::{SignalKind, Timestamp};
+use crate::value::Value;
+
+/// The messages and the client and server of the VAL service generated from
+/// `proto/kuksa/val/v1`
+pub mod proto {
+    tonic::include_proto!("kuksa.val.v1");
+}
+
+use proto::datapoint::Value as DatapointValue;
+use proto::metadata::EntrySpecific;
+use proto::val_server::{Val, ValServer};
+use proto::value_restriction::Type as Restriction;
+use proto::{
+    DataEntry, DataEntryError, DataType, Datapoint, EntryType, EntryUpdate, Field, Metadata,
+    ValueRestriction, View,
+};
+
+// How often the subscriptions are updated with the new samples
+const UPDATE_PERIOD: Duration = Duration::from_millis(10);
+
+/// The kuksa.val.v1 data type of a datatype of the specification
+pub fn data_type(datatype: &str) -> DataType {
+    match datatype {
+        "string" => DataType::String,
+        "boolean" => DataType::Boolean,
+        "int8" => DataType::Int8,
+        "int16" => DataType::Int16,
+        "int32" => DataType::Int32,
+        "int64" => DataType::Int64,
+        "uint8" => DataType::Uint8,
+        "uint16" => DataType::Uint16,
+        "uint32" => DataType::Uint32,
+        "uint64" => DataType::Uint64,
+        "float" => DataType::Float,
+        "double" => DataType::Double,
+        "string[]" => DataType::StringArray,
+        "boolean[]" => DataType::BooleanArray,
+        "int8[]" => DataType::Int8Array,
+        "int16[]" => DataType::Int16Array,
+        "int32[]" => DataType::Int32Array,
+        "int64[]" => DataType::Int64Array,
+        "uint8[]" => DataType::Uint8Array,
+        "uint16[]" => DataType::Uint16Array,
+        "uint32[]" => DataType::Uint32Array,
+        "uint64[]" => DataType::Uint64Array,
+        "float[]" => DataType::FloatArray,
+        "double[]" => DataType::DoubleArray,
+        _ => DataType::Unspecified,
+    }
+}
+
+/// The metadata of a signal: the datatype, the kind, the unit, the
+/// description and the limits of the specification
+pub fn metadata(info: &SignalInfo) -> Metadata {
+    let restriction = match data_type(info.datatype) {
+        DataType::Int8 | DataType::Int16 | DataType::Int32 | DataType::Int64 => {
+            Some(Restriction::Signed(proto::ValueRestrictionInt {
+                min: info.min.map(|v| v as i64),
+                max: info.max.map(|v| v as i64),
+                allowed_values: Vec::new(),
+            }))
+        }
+        DataType::Uint8 | DataType::Uint16 | DataType::Uint32 | DataType::Uint64 => {
+            Some(Restriction::Unsigned(proto::ValueRestrictionUint {
+                min: info.min.map(|v| v as u64),
+                max: info.max.map(|v| v as u64),
+                allowed_values: Vec::new(),
+            }))
+        }
+        DataType::Float | DataType::Double => {
+            Some(Restriction::FloatingPoint(proto::ValueRestrictionFloat {
+                min: info.min,
+                max: info.max,
+                allowed_values: Vec::new(),
+            }))
+        }
+        _ => None,
+    }
+    .filter(|_| info.min.is_some() || info.max.is_some());
+    let (entry_type, entry_specific) = match info.kind {
+        SignalKind::Sensor => (EntryType::Sensor, EntrySpecific::Sensor(proto::Sensor {})),
+        SignalKind::Actuator => (
+            EntryType::Actuator,
+            EntrySpecific::Actuator(proto::Actuator {}),
+        ),
+        SignalKind::Attribute => (
+            EntryType::Attribute,
+            EntrySpecific::Attribute(proto::Attribute {}),
+        ),
+    };
+    Metadata {
+        data_type: data_type(info.datatype) as i32,
+        entry_type: entry_type as i32,
+        description: Some(info.description.to_owned()),
+        comment: None,
+        deprecation: None,
+        unit: info.unit.map(str::to_owned),
+        value_restriction: restriction.map(|r| ValueRestriction { r#type: Some(r) }),
+        entry_specific: Some(entry_specific),
+    }
+}
+
+fn items<T>(value: &Value, item: impl Fn(&Value) -> Option<T>) -> Option<Vec<T>> {
+    match value {
+        Value::Array(values) => values.iter().map(item).collect(),
+        _ => None,
+    }
+}
+
+fn as_i64(value: &Value) -> Option<i64> {
+    match value {
+        Value::Int(i) => Some(*i),
+        Value::UInt(u) => i64::try_from(*u).ok(),
+        _ => None,
+    }
+}
+
+fn as_u64(value: &Value) -> Option<u64> {
+    match value {
+        Value::Int(i) => u64::try_from(*i).ok(),
+        Value::UInt(u) => Some(*u),
+        _ => None,
+    }
+}
+
+fn as_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        _ => None,
+    }
+}
+
+fn as_bool(value: &Value) -> Option<bool> {
+    match value {
+        Value::Bool(b) => Some(*b),
+        _ => None,
+    }
+}
+
+/// A value of a signal as a datapoint of the datatype of the signal, e.g.
+/// `uint8` values are sent as `uint32`
+pub fn datapoint(
+    datatype: &str,
+    value: &Value,
+    timestamp: Option<&Timestamp>,
+) -> Option<Datapoint> {
+    let int32 = |v: &Value| as_i64(v).and_then(|i| i32::try_from(i).ok());
+    let uint32 = |v: &Value| as_u64(v).and_then(|u| u32::try_from(u).ok());
+    let value = match data_type(datatype) {
+        DataType::String => DatapointValue::String(as_string(value)?),
+        DataType::Boolean => DatapointValue::Bool(as_bool(value)?),
+        DataType::Int8 | DataType::Int16 | DataType::Int32 => DatapointValue::Int32(int32(value)?),
+        DataType::Int64 => DatapointValue::Int64(as_i64(value)?),
+        DataType::Uint8 | DataType::Uint16 | DataType::Uint32 => {
+            DatapointValue::Uint32(uint32(value)?)
+        }
+        DataType::Uint64 => DatapointValue::Uint64(as_u64(value)?),
+        DataType::Float => DatapointValue::Float(value.as_f64()? as f32),
+        DataType::Double => DatapointValue::Double(value.as_f64()?),
+        DataType::StringArray => DatapointValue::StringArray(proto::StringArray {
+            values: items(value, as_string)?,
+        }),
+        DataType::BooleanArray => DatapointValue::BoolArray(proto::BoolArray {
+            values: items(value, as_bool)?,
+        }),
+        DataType::Int8Array | DataType::Int16Array | DataType::Int32Array => {
+            DatapointValue::Int32Array(proto::Int32Array {
+                values: items(value, int32)?,
+            })
+        }
+        DataType::Int64Array => DatapointValue::Int64Array(proto::Int64Array {
+            values: items(value, as_i64)?,
+        }),
+        DataType::Uint8Array | DataType::Uint16Array | DataType::Uint32Array => {
+            DatapointValue::Uint32Array(proto::Uint32Array {
+                values: items(value, uint32)?,
+            })
+        }
+        DataType::Uint64Array => DatapointValue::Uint64Array(proto::Uint64Array {
+            values: items(value, as_u64)?,
+        }),
+        DataType::FloatArray => DatapointValue::FloatArray(proto::FloatArray {
+            values: items(value, |v| v.as_f64().map(|f| f as f32))?,
+        }),
+        DataType::DoubleArray => DatapointValue::DoubleArray(proto::DoubleArray {
+            values: items(value, Value::as_f64)?,
+        }),
+        _ => return None,
+    };
+    Some(Datapoint {
+        timestamp: timestamp.map(|t| prost_types::Timestamp {
+            seconds: t.sec as i64,
+            nanos: t.nsec as i32,
+        }),
+        value: Some(value),
+    })
+}
+
+/// The value of a datapoint. The value still has to be converted to the
+/// datatype of the signal, see [DynSample::checked].
+pub fn value(datapoint: &Datapoint) -> Option<Value> {
+    fn array<T: Clone>(values: &[T], item: impl Fn(T) -> Value) -> Value {
+        Value::Array(values.iter().cloned().map(item).collect())
+    }
+    Some(match datapoint.value.as_ref()? {
+        DatapointValue::String(s) => Value::String(s.clone()),
+        DatapointValue::Bool(b) => Value::Bool(*b),
+        DatapointValue::Int32(i) => Value::Int(*i as i64),
+        DatapointValue::Int64(i) => Value::Int(*i),
+        DatapointValue::Uint32(u) => Value::UInt(*u as u64),
+        DatapointValue::Uint64(u) => Value::UInt(*u),
+        DatapointValue::Float(f) => Value::Float(*f as f64),
+        DatapointValue::Double(f) => Value::Float(*f),
+        DatapointValue::StringArray(a) => array(&a.values, Value::String),
+        DatapointValue::BoolArray(a) => array(&a.values, Value::Bool),
+        DatapointValue::Int32Array(a) => array(&a.values, |i| Value::Int(i as i64)),
+        DatapointValue::Int64Array(a) => array(&a.values, Value::Int),
+        DatapointValue::Uint32Array(a) => array(&a.values, |u| Value::UInt(u as u64)),
+        DatapointValue::Uint64Array(a) => array(&a.values, Value::UInt),
+        DatapointValue::FloatArray(a) => array(&a.values, |f| Value::Float(f as f64)),
+        DatapointValue::DoubleArray(a) => array(&a.values, Value::Float),
+    })
+}
+
+fn timestamp(datapoint: &Datapoint) -> Option<Timestamp> {
+    let t = datapoint.timestamp.as_ref()?;
+    Some(Timestamp {
+        sec: u64::try_from(t.seconds).ok()?,
+        nsec: u32::try_from(t.nanos).ok()?,
+    })
+}
+
+fn error(path: &str, code: u32, reason: &str, message: &str) -> DataEntryError {
+    DataEntryError {
+        path: path.to_owned(),
+        error: Some(proto::Error {
+            code,
+            reason: reason.to_owned(),
+            message: message.to_owned(),
+        }),
+    }
+}
+
+// The fields of a view, the fields of the request for `VIEW_FIELDS`
+fn fields(view: i32, requested: &[i32]) -> Vec<Field> {
+    match View::try_from(view).unwrap_or_default() {
+        View::Unspecified | View::CurrentValue => vec![Field::Path, Field::Value],
+        View::TargetValue => vec![Field::Path, Field::ActuatorTarget],
+        View::Metadata => vec![Field::Path, Field::Metadata],
+        View::Fields => requested
+            .iter()
+            .filter_map(|f| Field::try_from(*f).ok())
+            .collect(),
+        View::All => vec![
+            Field::Path,
+            Field::Value,
+            Field::ActuatorTarget,
+            Field::Metadata,
+        ],
+    }
+}
+
+// The pattern of a path of a request: a signal, a branch or a glob pattern
+// like `Vehicle.Cabin.Door.*.*.IsOpen`
+fn pattern(path: &str) -> String {
+    format!("{}.**", path)
+}
+
+// The instance paths that match the path of a request
+fn resolve(path: &str) -> Vec<(String, &'static SignalInfo)> {
+    let pattern = pattern(path);
+    let mut found = Vec::new();
+    for info in catalog::glob_instances(&pattern) {
+        for instance in info.instance_paths() {
+            if catalog::glob_match(&pattern, &instance) {
+                found.push((instance, info));
+            }
+        }
+    }
+    found
+}
+
+struct Subscriber {
+    patterns: Vec<(String, Vec<Field>)>,
+    sender: mpsc::UnboundedSender<Result<proto::SubscribeResponse, Status>>,
+}
+
+struct Broker<B: Backend> {
+    backend: B,
+    // the latest sample and the latest sample of the Target topic of the
+    // instance paths
+    latest: BTreeMap<String, DynSample>,
+    targets: BTreeMap<String, DynSample>,
+    subscribers: Vec<Subscriber>,
+    updating: bool,
+}
+
+impl<B: Backend> Broker<B> {
+    // Read the topic of the signal, and the Target topic of an actuator when
+    // the target is requested
+    fn add(&mut self, info: &'static SignalInfo, fields: &[Field]) -> Result<(), DDSError> {
+        self.backend.add(info)?;
+        if info.kind == SignalKind::Actuator && fields.contains(&Field::ActuatorTarget) {
+            self.backend.add_target(info)?;
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Vec<DynSample> {
+        let mut samples = self.backend.take();
+        for sample in &samples {
+            self.latest.insert(sample.path.clone(), sample.clone());
+        }
+        let targets = self.backend.take_targets();
+        for target in &targets {
+            self.targets.insert(target.path.clone(), target.clone());
+        }
+        samples.extend(targets);
+        samples
+    }
+
+    fn entry(&self, path: &str, info: &SignalInfo, fields: &[Field]) -> DataEntry {
+        let point = |sample: Option<&DynSample>| {
+            sample.and_then(|s| datapoint(info.datatype, &s.value, s.source_timestamp.as_ref()))
+        };
+        let mut entry = DataEntry::default();
+        for field in fields {
+            match field {
+                Field::Path => entry.path = path.to_owned(),
+                Field::Value => entry.value = point(self.latest.get(path)),
+                Field::ActuatorTarget => entry.actuator_target = point(self.targets.get(path)),
+                Field::Unspecified => {}
+                _ => entry.metadata = Some(metadata(info)),
+            }
+        }
+        entry
+    }
+
+    // Send the new samples to the subscribers and drop the closed ones
+    fn update(&mut self) {
+        let samples = self.receive();
+        let mut subscribers = std::mem::take(&mut self.subscribers);
+        subscribers.retain(|subscriber| {
+            let mut updates = Vec::new();
+            for sample in &samples {
+                let fields = subscriber
+                    .patterns
+                    .iter()
+                    .find(|(p, _)| catalog::glob_match(p, &sample.path))
+                    .map(|(_, fields)| fields);
+                if let (Some(fields), Some(info)) = (fields, catalog::find(sample.signal)) {
+                    updates.push(EntryUpdate {
+                        entry: Some(self.entry(&sample.path, info, fields)),
+                        fields: fields.iter().map(|f| *f as i32).collect(),
+                    });
+                }
+            }
+            if updates.is_empty() {
+                !subscriber.sender.is_closed()
+            } else {
+                let response = proto::SubscribeResponse { updates };
+                subscriber.sender.send(Ok(response)).is_ok()
+            }
+        });
+        self.subscribers = subscribers;
+    }
+}
+
+/// The VAL service of kuksa.val.v1 on top of the generated topics. Get reads
+/// the latest samples of the topics, Set writes the topics, Subscribe streams
+/// the samples of the topics and the metadata comes from the specification.
+/// Paths are instance paths like `Vehicle.Cabin.Door.Row1.Left.IsOpen`, a
+/// branch selects the signals below it and `*` matches an element.
+///
+/// The `actuator_target` of an entry is the Target topic of the actuator: a
+/// set of the target publishes a request there, and the value stays the
+/// current value on the topic of the actuator.
+pub struct KuksaService<B: Backend> {
+    broker: Arc<Mutex<Broker<B>>>,
+}
+
+impl<B: Backend> Clone for KuksaService<B> {
+    fn clone(&self) -> Self {
+        Self {
+            broker: self.broker.clone(),
+        }
+    }
+}
+
+impl KuksaService<DdsBackend> {
+    pub fn with_dds(participant: DdsParticipant, naming: NamingPolicy) -> Self {
+        Self::new(DdsBackend::new(participant, naming))
+    }
+}
+
+impl<B: Backend + 'static> KuksaService<B> {
+    pub fn new(backend: B) -> Self {
+        Self {
+            broker: Arc::new(Mutex::new(Broker {
+                backend,
+                latest: BTreeMap::new(),
+                targets: BTreeMap::new(),
+                subscribers: Vec::new(),
+                updating: false,
+            })),
+        }
+    }
+
+    /// The service for a [tonic::transport::Server]
+    pub fn into_server(self) -> ValServer<Self> {
+        ValServer::new(self)
+    }
+
+    /// Serve the clients that connect to the listener, e.g. on
+    /// `127.0.0.1:55555` like the databroker
+    pub async fn serve(self, listener: TcpListener) -> Result<(), tonic::transport::Error> {
+        tonic::transport::Server::builder()
+            .add_service(self.into_server())
+            .serve_with_incoming(TcpIncoming::from(listener))
+            .await
+    }
+
+    // Update the subscribers until the service is dropped
+    fn start_updates(&self) {
+        let broker: Weak<Mutex<Broker<B>>> = Arc::downgrade(&self.broker);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(UPDATE_PERIOD);
+            loop {
+                interval.tick().await;
+                match broker.upgrade() {
+                    Some(broker) => broker.lock().unwrap().update(),
+                    None => break,
+                }
+            }
+        });
+    }
+}
+
+type SubscribeStream = Pin<Box<dyn Stream<Item = Result<proto::SubscribeResponse, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl<B: Backend + 'static> Val for KuksaService<B> {
+    async fn get(
+        &self,
+        request: Request<proto::GetRequest>,
+    ) -> Result<Response<proto::GetResponse>, Status> {
+        let mut broker = self.broker.lock().unwrap();
+        let mut response = proto::GetResponse::default();
+        let mut found = Vec::new();
+        for entry in &request.get_ref().entries {
+            let instances = resolve(&entry.path);
+            if instances.is_empty() {
+                response
+                    .errors
+                    .push(error(&entry.path, 404, "not_found", "no such signal"));
+            }
+            let fields = fields(entry.view, &entry.fields);
+            for (path, info) in instances {
+                if broker.add(info, &fields).is_err() {
+                    response.errors.push(error(
+                        &path,
+                        503,
+                        "unavailable",
+                        "the topic can't be read",
+                    ));
+                } else {
+                    found.push((path, info, fields.clone()));
+                }
+            }
+        }
+        broker.receive();
+        for (path, info, fields) in found {
+            response.entries.push(broker.entry(&path, info, &fields));
+        }
+        if !response.errors.is_empty() {
+            response.error = Some(proto::Error {
+                code: 404,
+                reason: "not_found".to_owned(),
+                message: "some entries were not found".to_owned(),
+            });
+        }
+        Ok(Response::new(response))
+    }
+
+    async fn set(
+        &self,
+        request: Request<proto::SetRequest>,
+    ) -> Result<Response<proto::SetResponse>, Status> {
+        let mut broker = self.broker.lock().unwrap();
+        let mut response = proto::SetResponse::default();
+        for update in &request.get_ref().updates {
+            let entry = update.entry.clone().unwrap_or_default();
+            let info = match catalog::find_instance(&entry.path) {
+                Some(info) => info,
+                None => {
+                    response
+                        .errors
+                        .push(error(&entry.path, 404, "not_found", "no such signal"));
+                    continue;
+                }
+            };
+            let fields = fields(View::Fields as i32, &update.fields);
+            let mut samples = Vec::new();
+            for (field, point) in [
+                (Field::Value, &entry.value),
+                (Field::ActuatorTarget, &entry.actuator_target),
+            ] {
+                if !fields.contains(&field) {
+                    continue;
+                }
+                if field == Field::ActuatorTarget && info.kind != SignalKind::Actuator {
+                    response.errors.push(error(
+                        &entry.path,
+                        400,
+                        "invalid_argument",
+                        "not an actuator",
+                    ));
+                    continue;
+                }
+                let sample = point.as_ref().and_then(|point| {
+                    DynSample::new(&entry.path, value(point)?, timestamp(point))
+                        .and_then(DynSample::checked)
+                });
+                match sample {
+                    Some(sample) => samples.push((field, sample)),
+                    None => response.errors.push(error(
+                        &entry.path,
+                        400,
+                        "invalid_argument",
+                        "the value does not fit the datatype or is out of bounds",
+                    )),
+                }
+            }
+            // the value is the current value on the topic of the signal, the
+            // target is a request on the Target topic of the actuator
+            for (field, sample) in samples {
+                let written = match field {
+                    Field::ActuatorTarget => broker.backend.write_target(&sample),
+                    _ => broker.backend.write(&sample),
+                };
+                if written.is_err() {
+                    response.errors.push(error(
+                        &entry.path,
+                        503,
+                        "unavailable",
+                        "the topic can't be written",
+                    ));
+                }
+            }
+        }
+        if !response.errors.is_empty() {
+            response.error = Some(proto::Error {
+                code: 400,
+                reason: "invalid_argument".to_owned(),
+                message: "some entries were not set".to_owned(),
+            });
+        }
+        Ok(Response::new(response))
+    }
+
+    type SubscribeStream = SubscribeStream;
+
+    async fn subscribe(
+        &self,
+        request: Request<proto::SubscribeRequest>,
+    ) -> Result<Response<Self::SubscribeStream>, Status> {
+        let mut broker = self.broker.lock().unwrap();
+        let mut patterns = Vec::new();
+        let mut instances = Vec::new();
+        for entry in &request.get_ref().entries {
+            let found = resolve(&entry.path);
+            if found.is_empty() {
+                return Err(Status::not_found(entry.path.clone()));
+            }
+            let fields = fields(entry.view, &entry.fields);
+            for (path, info) in found {
+                broker
+                    .add(info, &fields)
+                    .map_err(|_| Status::unavailable(info.path))?;
+                instances.push((path, info, fields.clone()));
+            }
+            patterns.push((pattern(&entry.path), fields));
+        }
+        // the current values come first
+        broker.receive();
+        let updates = instances
+            .iter()
+            .filter(|(path, _, _)| broker.latest.contains_key(path))
+            .map(|(path, info, fields)| EntryUpdate {
+                entry: Some(broker.entry(path, info, fields)),
+                fields: fields.iter().map(|f| *f as i32).collect(),
+            })
+            .collect();
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let _ = sender.send(Ok(proto::SubscribeResponse { updates }));
+        broker.subscribers.push(Subscriber { patterns, sender });
+        if !broker.updating {
+            broker.updating = true;
+            self.start_updates();
+        }
+        let stream = futures_util::stream::poll_fn(move |cx| receiver.poll_recv(cx));
+        Ok(Response::new(Box::pin(stream)))
+    }
+
+    async fn get_server_info(
+        &self,
+        _request: Request<proto::GetServerInfoRequest>,
+    ) -> Result<Response<proto::GetServerInfoResponse>, Status> {
+        Ok(Response::new(proto::GetServerInfoResponse {
+            name: env!("CARGO_PKG_NAME").to_owned(),
+            version: env!("CARGO_PKG_VERSION").to_owned(),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::proto::val_client::ValClient;
+    use super::*;
+    use crate::backend::Loopback;
+    use crate::v3::units::KilometrePerHour;
+    use crate::v3::vehicle::Speed;
+
+    fn speed(value: f32) -> DynSample {
+        DynSample::of(&Speed::new(KilometrePerHour(value), None).unwrap())
+    }
+
+    fn request(path: &str, view: View) -> proto::EntryRequest {
+        proto::EntryRequest {
+            path: path.to_owned(),
+            view: view as i32,
+            fields: Vec::new(),
+        }
+    }
+
+    fn update(path: &str, point: Datapoint, field: Field) -> EntryUpdate {
+        let mut entry = DataEntry {
+            path: path.to_owned(),
+            ..Default::default()
+        };
+        if field == Field::Value {
+            entry.value = Some(point);
+        } else {
+            entry.actuator_target = Some(point);
+        }
+        EntryUpdate {
+            entry: Some(entry),
+            fields: vec![field as i32],
+        }
+    }
+
+    fn uint32(value: u32) -> Datapoint {
+        Datapoint {
+            timestamp: None,
+            value: Some(DatapointValue::Uint32(value)),
+        }
+    }
+
+    #[test]
+    fn in_process_client() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let loopback = Loopback::new();
+            loopback.publish(speed(50.0));
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let address = listener.local_addr().unwrap();
+            tokio::spawn(KuksaService::new(loopback.clone()).serve(listener));
+            let mut client = ValClient::connect(format!("http://{}", address))
+                .await
+                .unwrap();
+
+            let info = client
+                .get_server_info(proto::GetServerInfoRequest {})
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(info.name, "vehicle-signals");
+
+            let get = proto::GetRequest {
+                entries: vec![
+                    request("Vehicle.Speed", View::All),
+                    request("Vehicle.Cabin.HVAC.Station.Row1", View::Metadata),
+                    request("Vehicle.Nothing", View::CurrentValue),
+                ],
+            };
+            let response = client.get(get).await.unwrap().into_inner();
+            let speed_entry = &response.entries[0];
+            assert_eq!(
+                speed_entry.value.as_ref().unwrap().value,
+                Some(DatapointValue::Float(50.0))
+            );
+            let metadata = speed_entry.metadata.as_ref().unwrap();
+            assert_eq!(metadata.unit.as_deref(), Some("km/h"));
+            assert_eq!(metadata.data_type, DataType::Float as i32);
+            assert_eq!(metadata.entry_type, EntryType::Sensor as i32);
+            assert!(response
+                .entries
+                .iter()
+                .any(|e| e.path == "Vehicle.Cabin.HVAC.Station.Row1.Left.FanSpeed"));
+            assert_eq!(response.errors.len(), 1);
+            assert_eq!(response.errors[0].path, "Vehicle.Nothing");
+
+            // the subscription starts with the current value
+            let subscribe = proto::SubscribeRequest {
+                entries: vec![proto::SubscribeEntry {
+                    path: "Vehicle.Speed".to_owned(),
+                    view: View::CurrentValue as i32,
+                    fields: Vec::new(),
+                }],
+            };
+            let mut stream = client.subscribe(subscribe).await.unwrap().into_inner();
+            let first = stream.message().await.unwrap().unwrap();
+            assert_eq!(first.updates.len(), 1);
+            loopback.publish(speed(60.0));
+            let next = stream.message().await.unwrap().unwrap();
+            let entry = next.updates[0].entry.as_ref().unwrap();
+            assert_eq!(
+                entry.value.as_ref().unwrap().value,
+                Some(DatapointValue::Float(60.0))
+            );
+
+            let fan_speed = "Vehicle.Cabin.HVAC.Station.Row1.Left.FanSpeed";
+            let set = proto::SetRequest {
+                updates: vec![
+                    update(fan_speed, uint32(40), Field::ActuatorTarget),
+                    update(fan_speed, uint32(140), Field::ActuatorTarget),
+                    update("Vehicle.Speed", uint32(10), Field::ActuatorTarget),
+                ],
+            };
+            let response = client.set(set).await.unwrap().into_inner();
+            assert_eq!(response.errors.len(), 2);
+
+            // the target is written to the Target topic, the current value is
+            // unchanged
+            let get = proto::GetRequest {
+                entries: vec![request(fan_speed, View::All)],
+            };
+            let response = client.get(get).await.unwrap().into_inner();
+            let entry = &response.entries[0];
+            assert_eq!(
+                entry.actuator_target.as_ref().unwrap().value,
+                Some(DatapointValue::Uint32(40))
+            );
+            assert!(entry.value.is_none());
+        });
+    }
+}
@@ -2,17 +2,52 @@
 
 
 
+pub mod actuator;
+pub mod backend;
+pub mod cache;
+#[cfg(feature = "can")]
+pub mod can;
+pub mod catalog;
+pub mod change;
+#[cfg(feature = "parquet")]
+pub mod columns;
+pub mod dbc;
+pub mod derived;
+pub mod expr;
+pub mod gnss;
+#[cfg(feature = "kuksa")]
+pub mod kuksa;
+pub mod mcap;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+pub mod naming;
+pub mod obd;
+pub mod pubsub;
+pub mod qos;
+pub mod record;
+#[cfg(feature = "ros2")]
+pub mod ros2;
+pub mod rules;
+pub mod signal;
+pub mod someip;
+pub mod stream;
+pub mod table;
 pub mod units;
+pub mod value;
+pub mod vhal;
+#[cfg(feature = "viss")]
+pub mod viss;
 /// This is version 2 of the Vehicle Signal Interface.
 /// The major number of the interface is part of the module path.
 pub mod v3 {
     // Check project root for LICENCE
     use serde_derive::{Deserialize, Serialize};
     pub use crate::units;
+    pub use crate::signal::{Actuator, ActuatorTarget, SignalKind, SignalStatus, VssSignal};
     use chrono::{Utc, Timelike};
 
     #[repr(C)]
-    #[derive(Serialize, Deserialize, PartialEq, Clone)]
+    #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
     /// The side of the vehicle an attribute or signal is related to.
     /// This type is used as a key inside topics.
     pub enum Side {
@@ -27,7 +62,7 @@ pub mod v3 {
     }
 
     #[repr(C)]
-    #[derive(Serialize, Deserialize, PartialEq, Clone)]
+    #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
     /// Front or Rear of the vehicle
     /// This type is used as a key inside topics.
     pub enum Position {
@@ -43,7 +78,7 @@ pub mod v3 {
 
     /// A global IEEE 1588/802.1AS timestamp is 80 bits in total, divided into two parts
     #[repr(C)]
-    #[derive(Serialize, Deserialize, PartialEq, Clone)]
+    #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
     pub struct Timestamp {
         ///seconds since epoch
         pub sec: u64,
@@ -67,4 +102,63 @@ mod tests {
     fn it_works() {
         assert_eq!(2 + 2, 4);
     }
+
+    #[test]
+    fn door_window_instances() {
+        use crate::v3::vehicle::cabin::door::window::Position;
+        use crate::v3::Side;
+
+        assert_eq!(Position::instances().count(), 4);
+        assert!(Position::is_valid_instance(&2, &Side::Left));
+        assert!(!Position::is_valid_instance(&3, &Side::Left));
+    }
+
+    #[test]
+    fn actuator_target() {
+        use crate::v3::units::Percent;
+        use crate::v3::vehicle::cabin::door::shade::Position;
+        use crate::v3::{Actuator, Side};
+
+        let target = Position::request(Percent(40), (1, Side::Left)).unwrap();
+        let mut current = Position::new(Percent(10), None, 1, Side::Left).unwrap();
+        assert!(!current.has_reached(&target));
+        current.set(Percent(40), None, 1, Side::Left);
+        assert!(current.has_reached(&target));
+        assert!(Position::request(Percent(101), (1, Side::Left)).is_none());
+    }
+
+    #[test]
+    fn try_set_out_of_bounds() {
+        use crate::v3::units::Percent;
+        use crate::v3::vehicle::cabin::door::window::Position;
+        use crate::v3::{Side, Timestamp};
+
+        let before = Timestamp { sec: 10, nsec: 0 };
+        let after = Timestamp { sec: 20, nsec: 0 };
+        let mut position = Position::new(Percent(20), Some(before.clone()), 1, Side::Left).unwrap();
+        assert!(!position.try_set(Percent(120), Some(after.clone()), 2, Side::Left));
+        assert_eq!(position.value, Percent(20));
+        assert_eq!(position.row, 1);
+        assert!(position.timestamp == before);
+        assert!(position.try_set(Percent(30), Some(after.clone()), 2, Side::Left));
+        assert_eq!(position.value, Percent(30));
+        assert_eq!(position.row, 2);
+        assert!(position.timestamp == after);
+    }
+
+    #[cfg(feature = "signal-status")]
+    #[test]
+    fn signal_status() {
+        use crate::v3::units::Percent;
+        use crate::v3::vehicle::cabin::door::window::Position;
+        use crate::v3::{Side, SignalStatus};
+
+        assert_eq!(Position::default().status(), SignalStatus::NotAvailable);
+        let mut position = Position::new(Percent(20), None, 1, Side::Left).unwrap();
+        assert_eq!(position.status(), SignalStatus::Valid);
+        position.try_set(Percent(120), None, 1, Side::Left);
+        assert_eq!(position.status(), SignalStatus::OutOfRange);
+        position.try_set(Percent(10), None, 1, Side::Left);
+        assert_eq!(position.status(), SignalStatus::Valid);
+    }
 }
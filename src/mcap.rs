@@ -0,0 +1,700 @@
+// Check project root for LICENCE
+//! Minimal reader and writer for MCAP files
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+
+const MAGIC: &[u8; 8] = b"\x89MCAP0\r\n";
+
+const OP_HEADER: u8 = 0x01;
+const OP_FOOTER: u8 = 0x02;
+const OP_SCHEMA: u8 = 0x03;
+const OP_CHANNEL: u8 = 0x04;
+const OP_MESSAGE: u8 = 0x05;
+const OP_CHUNK: u8 = 0x06;
+const OP_MESSAGE_INDEX: u8 = 0x07;
+const OP_CHUNK_INDEX: u8 = 0x08;
+const OP_STATISTICS: u8 = 0x0b;
+const OP_SUMMARY_OFFSET: u8 = 0x0e;
+const OP_DATA_END: u8 = 0x0f;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_owned())
+}
+
+/// A channel of an MCAP file. The schema is inlined as the files written
+/// here have one schema per channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub id: u16,
+    pub topic: String,
+    pub message_encoding: String,
+    pub metadata: BTreeMap<String, String>,
+    pub schema_name: String,
+    pub schema_encoding: String,
+    pub schema: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub channel_id: u16,
+    pub sequence: u32,
+    /// Nanoseconds since the epoch when the message was recorded
+    pub log_time: u64,
+    /// Nanoseconds since the epoch when the message was published
+    pub publish_time: u64,
+    pub data: Vec<u8>,
+}
+
+// Builds the content of a record
+#[derive(Default)]
+struct Record(Vec<u8>);
+
+impl Record {
+    fn u16(mut self, v: u16) -> Self {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    fn u32(mut self, v: u32) -> Self {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    fn u64(mut self, v: u64) -> Self {
+        self.0.extend_from_slice(&v.to_le_bytes());
+        self
+    }
+
+    fn string(self, s: &str) -> Self {
+        self.bytes(s.as_bytes())
+    }
+
+    fn bytes(mut self, b: &[u8]) -> Self {
+        self = self.u32(b.len() as u32);
+        self.0.extend_from_slice(b);
+        self
+    }
+
+    fn map(self, map: &BTreeMap<String, String>) -> Self {
+        let entries = map
+            .iter()
+            .fold(Record::default(), |r, (k, v)| r.string(k).string(v));
+        self.bytes(&entries.0)
+    }
+
+    fn raw(mut self, b: &[u8]) -> Self {
+        self.0.extend_from_slice(b);
+        self
+    }
+}
+
+// Chunks are closed when their records reach this size
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// The CRC-32 of the data, continued from the CRC of the data before it. This
+/// is the CRC of zlib, which MCAP uses for its checksums.
+pub fn crc32(crc: u32, data: &[u8]) -> u32 {
+    let mut crc = !crc;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+// The records of the chunk that is being written
+#[derive(Default)]
+struct Chunk {
+    records: Vec<u8>,
+    start: u64,
+    end: u64,
+    // log time and offset in the records of the messages of every channel
+    indexes: BTreeMap<u16, Vec<(u64, u64)>>,
+}
+
+/// Writes an MCAP file with uncompressed chunks, their message indexes and a
+/// summary section with the schemas, the channels, the statistics and the
+/// chunk indexes, so that readers can seek in the file.
+pub struct McapWriter<W: Write> {
+    out: W,
+    // bytes written so far and their CRC
+    position: u64,
+    crc: u32,
+    channels: u16,
+    chunk: Chunk,
+    // the summary section without the offsets of its groups
+    schemas: Vec<u8>,
+    channel_records: Vec<u8>,
+    chunk_indexes: Vec<u8>,
+    message_count: u64,
+    chunk_count: u32,
+    message_start: u64,
+    message_end: u64,
+    channel_message_counts: BTreeMap<u16, u64>,
+}
+
+// Append a record with its opcode and length
+fn encode(out: &mut Vec<u8>, op: u8, record: &Record) {
+    out.push(op);
+    out.extend_from_slice(&(record.0.len() as u64).to_le_bytes());
+    out.extend_from_slice(&record.0);
+}
+
+impl<W: Write> McapWriter<W> {
+    pub fn new(out: W, profile: &str) -> io::Result<Self> {
+        let mut writer = Self {
+            out,
+            position: 0,
+            crc: 0,
+            channels: 0,
+            chunk: Chunk::default(),
+            schemas: Vec::new(),
+            channel_records: Vec::new(),
+            chunk_indexes: Vec::new(),
+            message_count: 0,
+            chunk_count: 0,
+            message_start: 0,
+            message_end: 0,
+            channel_message_counts: BTreeMap::new(),
+        };
+        writer.write(MAGIC)?;
+        let header = Record::default()
+            .string(profile)
+            .string(concat!("vehicle-signals ", env!("CARGO_PKG_VERSION")));
+        writer.record(OP_HEADER, &header)?;
+        Ok(writer)
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.out.write_all(bytes)?;
+        self.position += bytes.len() as u64;
+        self.crc = crc32(self.crc, bytes);
+        Ok(())
+    }
+
+    fn record(&mut self, op: u8, record: &Record) -> io::Result<()> {
+        let mut bytes = Vec::with_capacity(9 + record.0.len());
+        encode(&mut bytes, op, record);
+        self.write(&bytes)
+    }
+
+    /// Add a channel with its own schema. Returns the id of the channel.
+    pub fn add_channel(
+        &mut self,
+        topic: &str,
+        message_encoding: &str,
+        metadata: &BTreeMap<String, String>,
+        schema_name: &str,
+        schema_encoding: &str,
+        schema: &[u8],
+    ) -> io::Result<u16> {
+        // schema ids start at 1, 0 means no schema
+        self.channels += 1;
+        let id = self.channels;
+        let schema = Record::default()
+            .u16(id)
+            .string(schema_name)
+            .string(schema_encoding)
+            .bytes(schema);
+        encode(&mut self.chunk.records, OP_SCHEMA, &schema);
+        encode(&mut self.schemas, OP_SCHEMA, &schema);
+        let channel = Record::default()
+            .u16(id)
+            .u16(id)
+            .string(topic)
+            .string(message_encoding)
+            .map(metadata);
+        encode(&mut self.chunk.records, OP_CHANNEL, &channel);
+        encode(&mut self.channel_records, OP_CHANNEL, &channel);
+        Ok(id)
+    }
+
+    pub fn write_message(&mut self, message: &Message) -> io::Result<()> {
+        let record = Record::default()
+            .u16(message.channel_id)
+            .u32(message.sequence)
+            .u64(message.log_time)
+            .u64(message.publish_time)
+            .raw(&message.data);
+        let chunk = &mut self.chunk;
+        if chunk.indexes.is_empty() {
+            chunk.start = message.log_time;
+            chunk.end = message.log_time;
+        }
+        chunk.start = chunk.start.min(message.log_time);
+        chunk.end = chunk.end.max(message.log_time);
+        chunk
+            .indexes
+            .entry(message.channel_id)
+            .or_default()
+            .push((message.log_time, chunk.records.len() as u64));
+        encode(&mut chunk.records, OP_MESSAGE, &record);
+
+        if self.message_count == 0 {
+            self.message_start = message.log_time;
+            self.message_end = message.log_time;
+        }
+        self.message_count += 1;
+        self.message_start = self.message_start.min(message.log_time);
+        self.message_end = self.message_end.max(message.log_time);
+        *self
+            .channel_message_counts
+            .entry(message.channel_id)
+            .or_default() += 1;
+
+        if self.chunk.records.len() >= CHUNK_SIZE {
+            self.flush_chunk()?;
+        }
+        Ok(())
+    }
+
+    // Write the current chunk and its message indexes
+    fn flush_chunk(&mut self) -> io::Result<()> {
+        if self.chunk.records.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::take(&mut self.chunk);
+        let size = chunk.records.len() as u64;
+        let chunk_start = self.position;
+        let record = Record::default()
+            .u64(chunk.start)
+            .u64(chunk.end)
+            .u64(size)
+            .u32(crc32(0, &chunk.records))
+            .string("")
+            .u64(size)
+            .raw(&chunk.records);
+        self.record(OP_CHUNK, &record)?;
+        let chunk_length = self.position - chunk_start;
+
+        let index_start = self.position;
+        let mut offsets = Record::default();
+        for (channel_id, entries) in &chunk.indexes {
+            offsets = offsets.u16(*channel_id).u64(self.position);
+            let entries = entries.iter().fold(Record::default(), |r, (time, offset)| {
+                r.u64(*time).u64(*offset)
+            });
+            let index = Record::default().u16(*channel_id).bytes(&entries.0);
+            self.record(OP_MESSAGE_INDEX, &index)?;
+        }
+        let chunk_index = Record::default()
+            .u64(chunk.start)
+            .u64(chunk.end)
+            .u64(chunk_start)
+            .u64(chunk_length)
+            .bytes(&offsets.0)
+            .u64(self.position - index_start)
+            .string("")
+            .u64(size)
+            .u64(size);
+        encode(&mut self.chunk_indexes, OP_CHUNK_INDEX, &chunk_index);
+        self.chunk_count += 1;
+        Ok(())
+    }
+
+    /// Write the last chunk, the summary and the end of the file and return
+    /// the output
+    pub fn finish(mut self) -> io::Result<W> {
+        self.flush_chunk()?;
+        let crc = self.crc;
+        self.record(OP_DATA_END, &Record::default().u32(crc))?;
+
+        let counts = self
+            .channel_message_counts
+            .iter()
+            .fold(Record::default(), |r, (id, count)| r.u16(*id).u64(*count));
+        let statistics = Record::default()
+            .u64(self.message_count)
+            .u16(self.channels)
+            .u32(self.channels as u32)
+            .u32(0)
+            .u32(0)
+            .u32(self.chunk_count)
+            .u64(self.message_start)
+            .u64(self.message_end)
+            .bytes(&counts.0);
+        let mut statistics_record = Vec::new();
+        encode(&mut statistics_record, OP_STATISTICS, &statistics);
+
+        // the groups of the summary and their offsets
+        let summary_start = self.position;
+        let mut summary = Vec::new();
+        let mut offsets = Vec::new();
+        for (op, group) in [
+            (OP_SCHEMA, &self.schemas),
+            (OP_CHANNEL, &self.channel_records),
+            (OP_STATISTICS, &statistics_record),
+            (OP_CHUNK_INDEX, &self.chunk_indexes),
+        ] {
+            if group.is_empty() {
+                continue;
+            }
+            let offset = Record::default()
+                .raw(&[op])
+                .u64(summary_start + summary.len() as u64)
+                .u64(group.len() as u64);
+            encode(&mut offsets, OP_SUMMARY_OFFSET, &offset);
+            summary.extend_from_slice(group);
+        }
+        let summary_offset_start = summary_start + summary.len() as u64;
+        summary.extend_from_slice(&offsets);
+
+        // the CRC of the summary covers the footer up to the CRC
+        let mut footer = vec![OP_FOOTER];
+        footer.extend_from_slice(&20u64.to_le_bytes());
+        footer.extend_from_slice(&summary_start.to_le_bytes());
+        footer.extend_from_slice(&summary_offset_start.to_le_bytes());
+        summary.extend_from_slice(&footer);
+        let summary_crc = crc32(0, &summary);
+        summary.extend_from_slice(&summary_crc.to_le_bytes());
+        self.write(&summary)?;
+        self.write(MAGIC)?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+// Reads the fields of a record
+struct Fields<'a>(&'a [u8]);
+
+impl<'a> Fields<'a> {
+    fn take(&mut self, n: usize) -> io::Result<&'a [u8]> {
+        if self.0.len() < n {
+            return Err(invalid("truncated record"));
+        }
+        let (head, tail) = self.0.split_at(n);
+        self.0 = tail;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn bytes(&mut self) -> io::Result<&'a [u8]> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| invalid("invalid string"))
+    }
+
+    fn map(&mut self) -> io::Result<BTreeMap<String, String>> {
+        let mut entries = Fields(self.bytes()?);
+        let mut map = BTreeMap::new();
+        while !entries.0.is_empty() {
+            map.insert(entries.string()?, entries.string()?);
+        }
+        Ok(map)
+    }
+}
+
+/// Reads the messages of an MCAP file in the order they are stored. Chunks
+/// are supported if they are not compressed.
+pub struct McapReader<R: Read> {
+    input: R,
+    schemas: BTreeMap<u16, (String, String, Vec<u8>)>,
+    channels: BTreeMap<u16, Channel>,
+    // messages of the current chunk
+    pending: Vec<Message>,
+    done: bool,
+}
+
+impl<R: Read> McapReader<R> {
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not an MCAP file"));
+        }
+        Ok(Self {
+            input,
+            schemas: BTreeMap::new(),
+            channels: BTreeMap::new(),
+            pending: Vec::new(),
+            done: false,
+        })
+    }
+
+    /// The channels that were read so far
+    pub fn channel(&self, id: u16) -> Option<&Channel> {
+        self.channels.get(&id)
+    }
+
+    fn next_record(&mut self) -> io::Result<Option<(u8, Vec<u8>)>> {
+        let mut op = [0; 1];
+        if self.input.read(&mut op)? == 0 {
+            return Ok(None);
+        }
+        let mut len = [0; 8];
+        self.input.read_exact(&mut len)?;
+        // the length is not trusted for the allocation, the content grows
+        // with what is actually read
+        let len = u64::from_le_bytes(len);
+        let mut content = Vec::new();
+        (&mut self.input).take(len).read_to_end(&mut content)?;
+        if content.len() as u64 != len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated record",
+            ));
+        }
+        Ok(Some((op[0], content)))
+    }
+
+    // Returns a message if the record is a message
+    fn handle(&mut self, op: u8, content: &[u8]) -> io::Result<Option<Message>> {
+        let mut f = Fields(content);
+        match op {
+            OP_SCHEMA => {
+                let id = f.u16()?;
+                let schema = (f.string()?, f.string()?, f.bytes()?.to_vec());
+                self.schemas.insert(id, schema);
+            }
+            OP_CHANNEL => {
+                let id = f.u16()?;
+                let schema_id = f.u16()?;
+                let topic = f.string()?;
+                let message_encoding = f.string()?;
+                let metadata = f.map()?;
+                let (schema_name, schema_encoding, schema) =
+                    self.schemas.get(&schema_id).cloned().unwrap_or_default();
+                self.channels.insert(
+                    id,
+                    Channel {
+                        id,
+                        topic,
+                        message_encoding,
+                        metadata,
+                        schema_name,
+                        schema_encoding,
+                        schema,
+                    },
+                );
+            }
+            OP_MESSAGE => {
+                return Ok(Some(Message {
+                    channel_id: f.u16()?,
+                    sequence: f.u32()?,
+                    log_time: f.u64()?,
+                    publish_time: f.u64()?,
+                    data: f.0.to_vec(),
+                }))
+            }
+            OP_CHUNK => {
+                f.take(8 + 8 + 8)?;
+                let crc = f.u32()?;
+                if !f.string()?.is_empty() {
+                    return Err(invalid("compressed chunks are not supported"));
+                }
+                let len = f.u64()? as usize;
+                let records = f.take(len)?;
+                // a CRC of 0 means that the writer did not compute it
+                if crc != 0 && crc32(0, records) != crc {
+                    return Err(invalid("invalid chunk CRC"));
+                }
+                let mut records = Fields(records);
+                let mut messages = Vec::new();
+                while !records.0.is_empty() {
+                    let op = records.take(1)?[0];
+                    // a chunk in a chunk would recurse without limit
+                    if op == OP_CHUNK {
+                        return Err(invalid("chunk inside a chunk"));
+                    }
+                    let len = records.u64()? as usize;
+                    if let Some(message) = self.handle(op, records.take(len)?)? {
+                        messages.push(message);
+                    }
+                }
+                messages.reverse();
+                self.pending = messages;
+            }
+            // the summary only repeats the records of the data section
+            OP_DATA_END | OP_FOOTER => self.done = true,
+            _ => {}
+        }
+        Ok(None)
+    }
+}
+
+impl<R: Read> Iterator for McapReader<R> {
+    type Item = io::Result<Message>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(message) = self.pending.pop() {
+                return Some(Ok(message));
+            }
+            if self.done {
+                return None;
+            }
+            let (op, content) = match self.next_record() {
+                Ok(Some(record)) => record,
+                Ok(None) => return None,
+                Err(e) => return Some(Err(e)),
+            };
+            match self.handle(op, &content) {
+                Ok(Some(message)) => return Some(Ok(message)),
+                Ok(None) => {}
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn write_and_read() {
+        let mut writer = McapWriter::new(Vec::new(), "").unwrap();
+        let mut metadata = BTreeMap::new();
+        metadata.insert("unit".to_owned(), "km/h".to_owned());
+        let id = writer
+            .add_channel(
+                "Vehicle.Speed",
+                "json",
+                &metadata,
+                "Vehicle.Speed",
+                "jsonschema",
+                b"{}",
+            )
+            .unwrap();
+        for i in 0..3 {
+            let message = Message {
+                channel_id: id,
+                sequence: i,
+                log_time: 1000 + i as u64,
+                publish_time: 1000,
+                data: vec![i as u8],
+            };
+            writer.write_message(&message).unwrap();
+        }
+        let bytes = writer.finish().unwrap();
+        assert!(bytes.ends_with(MAGIC));
+
+        let mut reader = McapReader::new(bytes.as_slice()).unwrap();
+        let messages: Vec<Message> = reader.by_ref().map(Result::unwrap).collect();
+        assert_eq!(messages.len(), 3);
+        assert_eq!(messages[2].log_time, 1002);
+        let channel = reader.channel(id).unwrap();
+        assert_eq!(channel.topic, "Vehicle.Speed");
+        assert_eq!(channel.schema_encoding, "jsonschema");
+        assert_eq!(channel.metadata.get("unit").unwrap(), "km/h");
+    }
+
+    #[test]
+    fn summary_and_crcs() {
+        let mut writer = McapWriter::new(Vec::new(), "").unwrap();
+        let id = writer
+            .add_channel(
+                "Vehicle.Speed",
+                "cdr",
+                &BTreeMap::new(),
+                "Speed",
+                "omgidl",
+                b"",
+            )
+            .unwrap();
+        let message = Message {
+            channel_id: id,
+            sequence: 0,
+            log_time: 1000,
+            publish_time: 1000,
+            data: vec![1, 2, 3],
+        };
+        writer.write_message(&message).unwrap();
+        let bytes = writer.finish().unwrap();
+
+        let u64_at = |i: usize| u64::from_le_bytes(bytes[i..i + 8].try_into().unwrap()) as usize;
+        let u32_at = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        let footer = bytes.len() - MAGIC.len() - 29;
+        assert_eq!(bytes[footer], OP_FOOTER);
+        let summary_start = u64_at(footer + 9);
+        let summary_offset_start = u64_at(footer + 17);
+        assert_eq!(bytes[summary_start], OP_SCHEMA);
+        assert_eq!(bytes[summary_offset_start], OP_SUMMARY_OFFSET);
+        assert_eq!(
+            crc32(0, &bytes[summary_start..footer + 25]),
+            u32_at(footer + 25)
+        );
+        let data_end = summary_start - 13;
+        assert_eq!(bytes[data_end], OP_DATA_END);
+        assert_eq!(crc32(0, &bytes[..data_end]), u32_at(data_end + 9));
+
+        // the summary offsets point at the groups of the summary
+        let mut groups = Vec::new();
+        let mut i = summary_offset_start;
+        while bytes[i] == OP_SUMMARY_OFFSET {
+            groups.push(bytes[i + 9]);
+            assert_eq!(bytes[u64_at(i + 10)], bytes[i + 9]);
+            i += 9 + 17;
+        }
+        assert_eq!(
+            groups,
+            [OP_SCHEMA, OP_CHANNEL, OP_STATISTICS, OP_CHUNK_INDEX]
+        );
+
+        // a changed byte in the chunk fails its CRC
+        let mut corrupted = bytes.clone();
+        let position = corrupted.windows(3).position(|w| w == [1, 2, 3]).unwrap();
+        corrupted[position] = 9;
+        let mut reader = McapReader::new(corrupted.as_slice()).unwrap();
+        let error = reader.next().unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(0, b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn truncated_record() {
+        // a record that claims to be 2^60 bytes long
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(OP_MESSAGE);
+        bytes.extend_from_slice(&(1u64 << 60).to_le_bytes());
+        bytes.extend_from_slice(&[0; 16]);
+        let mut reader = McapReader::new(bytes.as_slice()).unwrap();
+        let error = reader.next().unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn nested_chunks() {
+        // a chunk record without compression around the records
+        let chunk = |records: &[u8]| {
+            let mut record = vec![OP_CHUNK];
+            let mut content = vec![0; 8 + 8 + 8 + 4 + 4];
+            content.extend_from_slice(&(records.len() as u64).to_le_bytes());
+            content.extend_from_slice(records);
+            record.extend_from_slice(&(content.len() as u64).to_le_bytes());
+            record.extend_from_slice(&content);
+            record
+        };
+        let mut nested = Vec::new();
+        for _ in 0..1000 {
+            nested = chunk(&nested);
+        }
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&nested);
+        let mut reader = McapReader::new(bytes.as_slice()).unwrap();
+        let error = reader.next().unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,738 @@
+// Check project root for LICENCE
+//! Bridge between the signals and MQTT topics named by their instance paths
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::io;
+use std::time::{Duration, Instant};
+
+use cyclonedds_rs::{DDSError, DdsParticipant};
+use rumqttc::{AsyncClient, ClientError, Event, MqttOptions, Packet, QoS};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::backend::{Backend, DdsBackend};
+use crate::catalog::{self, DynSample, SignalInfo};
+use crate::naming::NamingPolicy;
+use crate::v3::{SignalKind, Timestamp};
+use crate::value::Value;
+
+const UPDATE_PERIOD: Duration = Duration::from_millis(10);
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Debug)]
+pub enum MqttError {
+    /// A pattern of the configuration matches no signal, or a set request
+    /// names a path that is not an instance path of a signal
+    UnknownPath(String),
+    /// A set request for a signal that is not an actuator
+    NotActuator(String),
+    /// A set request for an actuator that matches no pattern of the
+    /// configuration
+    NotConfigured(String),
+    /// The value of a set request is not valid for the signal
+    InvalidValue(String),
+    /// A rate limit of the configuration is not a positive number of messages
+    /// per second
+    RateLimit(String),
+    Json(serde_json::Error),
+    CborEncode(ciborium::ser::Error<io::Error>),
+    CborDecode(ciborium::de::Error<io::Error>),
+    Client(ClientError),
+    #[cfg(feature = "toml")]
+    Toml(toml::de::Error),
+    Io(io::Error),
+    Dds(DDSError),
+}
+
+impl fmt::Display for MqttError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MqttError::UnknownPath(path) => write!(f, "unknown path {}", path),
+            MqttError::NotActuator(path) => write!(f, "{} is not an actuator", path),
+            MqttError::NotConfigured(path) => write!(f, "{} is not bridged", path),
+            MqttError::InvalidValue(path) => write!(f, "invalid value for {}", path),
+            MqttError::RateLimit(pattern) => write!(f, "invalid rate limit for {}", pattern),
+            MqttError::Json(e) => e.fmt(f),
+            MqttError::CborEncode(e) => e.fmt(f),
+            MqttError::CborDecode(e) => e.fmt(f),
+            MqttError::Client(e) => e.fmt(f),
+            #[cfg(feature = "toml")]
+            MqttError::Toml(e) => e.fmt(f),
+            MqttError::Io(e) => e.fmt(f),
+            MqttError::Dds(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for MqttError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MqttError::Json(e) => Some(e),
+            MqttError::CborEncode(e) => Some(e),
+            MqttError::CborDecode(e) => Some(e),
+            MqttError::Client(e) => Some(e),
+            #[cfg(feature = "toml")]
+            MqttError::Toml(e) => Some(e),
+            MqttError::Io(e) => Some(e),
+            MqttError::Dds(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<serde_json::Error> for MqttError {
+    fn from(e: serde_json::Error) -> Self {
+        MqttError::Json(e)
+    }
+}
+
+impl From<ciborium::ser::Error<io::Error>> for MqttError {
+    fn from(e: ciborium::ser::Error<io::Error>) -> Self {
+        MqttError::CborEncode(e)
+    }
+}
+
+impl From<ciborium::de::Error<io::Error>> for MqttError {
+    fn from(e: ciborium::de::Error<io::Error>) -> Self {
+        MqttError::CborDecode(e)
+    }
+}
+
+impl From<ClientError> for MqttError {
+    fn from(e: ClientError) -> Self {
+        MqttError::Client(e)
+    }
+}
+
+impl From<io::Error> for MqttError {
+    fn from(e: io::Error) -> Self {
+        MqttError::Io(e)
+    }
+}
+
+impl From<DDSError> for MqttError {
+    fn from(e: DDSError) -> Self {
+        MqttError::Dds(e)
+    }
+}
+
+/// The encoding of the payloads
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    #[default]
+    Json,
+    Cbor,
+}
+
+/// The configuration of a bridge, e.g. in TOML:
+///
+/// ```toml
+/// signals = ["Vehicle.Speed", "Vehicle.Cabin.Door.**"]
+/// format = "cbor"
+///
+/// [rate_limits]
+/// "Vehicle.Speed" = 2.0
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MqttConfig {
+    /// Glob patterns of the instance paths to forward, see [catalog::glob]
+    pub signals: Vec<String>,
+    /// The prefix of the topics of the samples
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    /// The prefix of the topics of the set requests of actuators
+    #[serde(default = "default_set_prefix")]
+    pub set_prefix: String,
+    #[serde(default)]
+    pub format: Format,
+    /// The highest number of messages per second of the instance paths that
+    /// match a pattern. The latest sample of a path is sent when its interval
+    /// has passed. The rates must be greater than zero.
+    #[serde(default)]
+    pub rate_limits: BTreeMap<String, f64>,
+    /// The number of messages kept while the broker is unreachable, the oldest
+    /// are dropped first
+    #[serde(default = "default_store_limit")]
+    pub store_limit: usize,
+}
+
+fn default_prefix() -> String {
+    "vss".to_owned()
+}
+
+fn default_set_prefix() -> String {
+    "vss/set".to_owned()
+}
+
+fn default_store_limit() -> usize {
+    10_000
+}
+
+impl MqttConfig {
+    /// Forward the signals whose instance paths match the patterns
+    pub fn new<S: Into<String>>(signals: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            signals: signals.into_iter().map(Into::into).collect(),
+            prefix: default_prefix(),
+            set_prefix: default_set_prefix(),
+            format: Format::default(),
+            rate_limits: BTreeMap::new(),
+            store_limit: default_store_limit(),
+        }
+    }
+}
+
+/// The payload of the samples and of the set requests
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Payload {
+    pub value: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<Timestamp>,
+}
+
+// A set request may also be a bare value
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Request {
+    Payload(Payload),
+    Value(Value),
+}
+
+/// A message to publish
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub topic: String,
+    pub payload: Vec<u8>,
+}
+
+/// Forwards the samples of the configured signals to MQTT topics named by
+/// their instance paths, e.g. `vss/Vehicle/Cabin/Door/Row1/Left/IsOpen`, and
+/// writes the set requests of the configured actuators published on
+/// `vss/set/Vehicle/Cabin/Door/Row1/Left/Shade/Position` to the Target topic
+/// of the actuator.
+///
+/// The bridge can be driven without a network with [MqttBridge::poll] and
+/// [MqttBridge::receive], or connected to a broker with [MqttBridge::serve].
+pub struct MqttBridge<B: Backend> {
+    backend: B,
+    config: MqttConfig,
+    signals: Vec<&'static SignalInfo>,
+    connected: bool,
+    // the latest samples held back by a rate limit
+    pending: BTreeMap<String, DynSample>,
+    last_sent: BTreeMap<String, Instant>,
+    // the messages published while the broker was unreachable
+    store: VecDeque<Message>,
+}
+
+impl MqttBridge<DdsBackend> {
+    pub fn with_dds(
+        participant: DdsParticipant,
+        naming: NamingPolicy,
+        config: MqttConfig,
+    ) -> Result<Self, MqttError> {
+        Self::new(DdsBackend::new(participant, naming), config)
+    }
+}
+
+// The interval between the messages of a rate limit. Rates that are not
+// positive or that are too small for a Duration have none.
+fn interval(rate: f64) -> Option<Duration> {
+    if rate > 0.0 {
+        Duration::try_from_secs_f64(1.0 / rate).ok()
+    } else {
+        None
+    }
+}
+
+impl<B: Backend> MqttBridge<B> {
+    pub fn new(mut backend: B, config: MqttConfig) -> Result<Self, MqttError> {
+        for (pattern, rate) in &config.rate_limits {
+            if interval(*rate).is_none() {
+                return Err(MqttError::RateLimit(pattern.clone()));
+            }
+        }
+        let mut signals: Vec<&'static SignalInfo> = Vec::new();
+        for pattern in &config.signals {
+            let matched: Vec<_> = catalog::glob_instances(pattern).collect();
+            if matched.is_empty() {
+                return Err(MqttError::UnknownPath(pattern.clone()));
+            }
+            for info in matched {
+                if !signals.iter().any(|s| s.path == info.path) {
+                    backend.add(info)?;
+                    signals.push(info);
+                }
+            }
+        }
+        Ok(Self {
+            backend,
+            config,
+            signals,
+            connected: false,
+            pending: BTreeMap::new(),
+            last_sent: BTreeMap::new(),
+            store: VecDeque::new(),
+        })
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn from_toml(backend: B, text: &str) -> Result<Self, MqttError> {
+        let config: MqttConfig = toml::from_str(text).map_err(MqttError::Toml)?;
+        Self::new(backend, config)
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn load(backend: B, path: impl AsRef<std::path::Path>) -> Result<Self, MqttError> {
+        Self::from_toml(backend, &std::fs::read_to_string(path)?)
+    }
+
+    /// The signals of the specification that are forwarded
+    pub fn signals(&self) -> impl Iterator<Item = &'static SignalInfo> + '_ {
+        self.signals.iter().copied()
+    }
+
+    /// The topic of the samples of an instance path
+    pub fn topic(&self, path: &str) -> String {
+        format!("{}/{}", self.config.prefix, path.replace('.', "/"))
+    }
+
+    /// The topic of the set requests of an instance path
+    pub fn set_topic(&self, path: &str) -> String {
+        format!("{}/{}", self.config.set_prefix, path.replace('.', "/"))
+    }
+
+    pub fn encode(&self, payload: &Payload) -> Result<Vec<u8>, MqttError> {
+        match self.config.format {
+            Format::Json => Ok(serde_json::to_vec(payload)?),
+            Format::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(payload, &mut bytes)?;
+                Ok(bytes)
+            }
+        }
+    }
+
+    pub fn decode(&self, bytes: &[u8]) -> Result<Payload, MqttError> {
+        let request = match self.config.format {
+            Format::Json => serde_json::from_slice(bytes)?,
+            Format::Cbor => ciborium::from_reader(bytes)?,
+        };
+        Ok(match request {
+            Request::Payload(payload) => payload,
+            Request::Value(value) => Payload {
+                value,
+                timestamp: None,
+            },
+        })
+    }
+
+    /// Whether the broker is reachable. Messages are stored while it is not
+    /// and returned by the first [MqttBridge::poll] after it is again.
+    pub fn set_connected(&mut self, connected: bool) {
+        self.connected = connected;
+    }
+
+    /// The number of messages stored while the broker is unreachable
+    pub fn stored(&self) -> usize {
+        self.store.len()
+    }
+
+    /// The messages to publish now for the samples received from the backend,
+    /// after the messages stored while the broker was unreachable
+    pub fn poll(&mut self, now: Instant) -> Result<Vec<Message>, MqttError> {
+        let mut ready = Vec::new();
+        for (path, sample) in std::mem::take(&mut self.pending) {
+            if self.due(&path, now) {
+                self.last_sent.insert(path, now);
+                ready.push(sample);
+            } else {
+                self.pending.insert(path, sample);
+            }
+        }
+        for sample in self.backend.take() {
+            if !self
+                .config
+                .signals
+                .iter()
+                .any(|p| catalog::glob_match(p, &sample.path))
+            {
+                continue;
+            }
+            if self.due(&sample.path, now) && !self.pending.contains_key(&sample.path) {
+                self.last_sent.insert(sample.path.clone(), now);
+                ready.push(sample);
+            } else {
+                self.pending.insert(sample.path.clone(), sample);
+            }
+        }
+        for sample in ready {
+            let payload = Payload {
+                value: sample.value,
+                timestamp: sample.source_timestamp,
+            };
+            let message = Message {
+                topic: self.topic(&sample.path),
+                payload: self.encode(&payload)?,
+            };
+            self.store(message);
+        }
+        if self.connected {
+            Ok(self.store.drain(..).collect())
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    /// Keep a message until the broker is reachable
+    pub fn store(&mut self, message: Message) {
+        if self.store.len() >= self.config.store_limit {
+            self.store.pop_front();
+        }
+        if self.config.store_limit > 0 {
+            self.store.push_back(message);
+        }
+    }
+
+    /// Write the set request published on a topic below the set prefix to the
+    /// Target topic of the actuator. Only the actuators that match a pattern
+    /// of the configuration can be set.
+    pub fn receive(&mut self, topic: &str, bytes: &[u8]) -> Result<DynSample, MqttError> {
+        let path = topic
+            .strip_prefix(self.config.set_prefix.as_str())
+            .and_then(|p| p.strip_prefix('/'))
+            .map(|p| p.replace('/', "."))
+            .ok_or_else(|| MqttError::UnknownPath(topic.to_owned()))?;
+        let info =
+            catalog::find_instance(&path).ok_or_else(|| MqttError::UnknownPath(path.clone()))?;
+        if info.kind != SignalKind::Actuator {
+            return Err(MqttError::NotActuator(path));
+        }
+        if !self
+            .config
+            .signals
+            .iter()
+            .any(|p| catalog::glob_match(p, &path))
+        {
+            return Err(MqttError::NotConfigured(path));
+        }
+        let payload = self.decode(bytes)?;
+        let timestamp = payload.timestamp.unwrap_or_default();
+        let sample = DynSample::new(&path, payload.value, Some(timestamp))
+            .and_then(DynSample::checked)
+            .ok_or(MqttError::InvalidValue(path))?;
+        self.backend.write_target(&sample)?;
+        Ok(sample)
+    }
+
+    fn interval(&self, path: &str) -> Option<Duration> {
+        self.config
+            .rate_limits
+            .iter()
+            .find(|(pattern, _)| catalog::glob_match(pattern, path))
+            .and_then(|(_, rate)| interval(*rate))
+    }
+
+    // Whether a sample of a path can be sent without exceeding its rate limit
+    fn due(&self, path: &str, now: Instant) -> bool {
+        match (self.interval(path), self.last_sent.get(path)) {
+            (Some(interval), Some(last)) => now.duration_since(*last) >= interval,
+            _ => true,
+        }
+    }
+
+    /// Connect to a broker and bridge the signals until an error occurs. The
+    /// connection is retried while the broker is unreachable.
+    ///
+    /// The messages are stored by the bridge while it is not connected, or
+    /// when the request queue of the client is full. Once handed to the
+    /// client they are not kept until their PubAck, so the messages in flight
+    /// when the connection is lost may be dropped.
+    pub async fn serve(mut self, options: MqttOptions) -> Result<(), MqttError> {
+        let (client, mut eventloop) = AsyncClient::new(options, CHANNEL_CAPACITY);
+        let subscription = format!("{}/#", self.config.set_prefix);
+        let mut interval = tokio::time::interval(UPDATE_PERIOD);
+        loop {
+            tokio::select! {
+                event = eventloop.poll() => match event {
+                    Ok(Event::Incoming(Packet::ConnAck(_))) => {
+                        client.try_subscribe(subscription.as_str(), QoS::AtLeastOnce)?;
+                        self.set_connected(true);
+                    }
+                    Ok(Event::Incoming(Packet::Publish(publish))) => {
+                        // invalid requests are dropped
+                        let _ = self.receive(&publish.topic, &publish.payload);
+                    }
+                    Ok(_) => {}
+                    Err(_) => {
+                        self.set_connected(false);
+                        tokio::time::sleep(RECONNECT_DELAY).await;
+                    }
+                },
+                _ = interval.tick() => {
+                    for message in self.poll(Instant::now())? {
+                        let topic = message.topic.clone();
+                        let payload = message.payload.clone();
+                        if client.try_publish(topic, QoS::AtLeastOnce, false, payload).is_err() {
+                            self.store(message);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Bridge the signals on the current thread until an error occurs
+    pub fn run(self, options: MqttOptions) -> Result<(), MqttError> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        runtime.block_on(self.serve(options))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Loopback;
+
+    const IS_OPEN: &str = "Vehicle.Cabin.Door.Row1.Left.IsOpen";
+    const SHADE: &str = "Vehicle.Cabin.Door.Row1.Left.Shade.Position";
+
+    fn sample(path: &str, value: Value) -> DynSample {
+        DynSample::new(path, value, Some(Timestamp { sec: 10, nsec: 0 })).unwrap()
+    }
+
+    #[test]
+    fn rate_limits_and_store() {
+        let loopback = Loopback::new();
+        let mut config = MqttConfig::new(["Vehicle.Speed", "Vehicle.Cabin.Door.**"]);
+        config.format = Format::Cbor;
+        config.rate_limits.insert("Vehicle.Speed".to_owned(), 2.0);
+        config.store_limit = 2;
+        let mut bridge = MqttBridge::new(loopback.clone(), config).unwrap();
+        assert!(bridge
+            .signals()
+            .any(|s| s.path == "Vehicle.Cabin.Door.IsOpen"));
+        assert!(matches!(
+            MqttBridge::new(Loopback::new(), MqttConfig::new(["Vehicle.Nothing"])),
+            Err(MqttError::UnknownPath(_))
+        ));
+        for rate in [0.0, -1.0, f64::NAN, 1e-320] {
+            let mut config = MqttConfig::new(["Vehicle.Speed"]);
+            config.rate_limits.insert("Vehicle.Speed".to_owned(), rate);
+            assert!(matches!(
+                MqttBridge::new(Loopback::new(), config),
+                Err(MqttError::RateLimit(_))
+            ));
+        }
+
+        // stored while the broker is unreachable, the oldest are dropped
+        let start = Instant::now();
+        loopback.publish(sample(IS_OPEN, Value::Bool(false)));
+        loopback.publish(sample(IS_OPEN, Value::Bool(true)));
+        loopback.publish(sample("Vehicle.Speed", Value::Float(10.0)));
+        loopback.publish(sample(
+            "Vehicle.Cabin.Door.Row1.Left.Window.Position",
+            Value::UInt(3),
+        ));
+        assert!(bridge.poll(start).unwrap().is_empty());
+        loopback.publish(sample("Vehicle.Speed", Value::Float(12.0)));
+        assert!(bridge.poll(start).unwrap().is_empty());
+        assert_eq!(bridge.stored(), 2);
+        bridge.set_connected(true);
+        let messages = bridge.poll(start).unwrap();
+        assert_eq!(bridge.stored(), 0);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].topic, "vss/Vehicle/Speed");
+        assert_eq!(
+            messages[1].topic,
+            "vss/Vehicle/Cabin/Door/Row1/Left/Window/Position"
+        );
+        assert_eq!(
+            bridge.decode(&messages[0].payload).unwrap(),
+            Payload {
+                value: Value::Float(10.0),
+                timestamp: Some(Timestamp { sec: 10, nsec: 0 })
+            }
+        );
+
+        // the latest speed is sent when the interval has passed
+        loopback.publish(sample("Vehicle.Speed", Value::Float(20.0)));
+        loopback.publish(sample("Vehicle.Speed", Value::Float(21.0)));
+        assert!(bridge
+            .poll(start + Duration::from_millis(100))
+            .unwrap()
+            .is_empty());
+        let messages = bridge.poll(start + Duration::from_millis(500)).unwrap();
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].topic, "vss/Vehicle/Speed");
+        assert_eq!(
+            bridge.decode(&messages[0].payload).unwrap().value,
+            Value::Float(21.0)
+        );
+
+        // set requests of actuators only
+        let request = bridge
+            .encode(&Payload {
+                value: Value::UInt(40),
+                timestamp: None,
+            })
+            .unwrap();
+        let written = bridge.receive(&bridge.set_topic(SHADE), &request).unwrap();
+        assert_eq!(written.path, SHADE);
+        assert!(loopback.clone().take().is_empty());
+        assert_eq!(loopback.clone().take_targets(), [written]);
+        assert!(matches!(
+            bridge.receive(&bridge.set_topic("Vehicle.Speed"), &request),
+            Err(MqttError::NotActuator(_))
+        ));
+        // an actuator that is not bridged
+        assert!(matches!(
+            bridge.receive(
+                &bridge.set_topic("Vehicle.Cabin.HVAC.Station.Row1.Left.FanSpeed"),
+                &request
+            ),
+            Err(MqttError::NotConfigured(_))
+        ));
+        assert!(loopback.clone().take_targets().is_empty());
+    }
+
+    // A broker that forwards every publication to the connections subscribed
+    // to a matching filter
+    async fn broker(listener: tokio::net::TcpListener) {
+        let (sender, _) = tokio::sync::broadcast::channel::<rumqttc::Publish>(64);
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            tokio::spawn(connection(stream, sender.clone()));
+        }
+    }
+
+    async fn connection(
+        mut stream: tokio::net::TcpStream,
+        sender: tokio::sync::broadcast::Sender<rumqttc::Publish>,
+    ) {
+        use rumqttc::{ConnAck, ConnectReturnCode, PubAck, Publish, SubAck, SubscribeReasonCode};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut published = sender.subscribe();
+        let mut filters: Vec<String> = Vec::new();
+        let mut input = bytes::BytesMut::new();
+        loop {
+            let mut output = bytes::BytesMut::new();
+            tokio::select! {
+                read = stream.read_buf(&mut input) => {
+                    if !matches!(read, Ok(n) if n > 0) {
+                        return;
+                    }
+                    while let Ok(packet) = Packet::read(&mut input, 1 << 20) {
+                        let reply = match packet {
+                            Packet::Connect(_) => Some(Packet::ConnAck(ConnAck::new(
+                                ConnectReturnCode::Success,
+                                false,
+                            ))),
+                            Packet::Subscribe(subscribe) => {
+                                let codes = subscribe
+                                    .filters
+                                    .iter()
+                                    .map(|f| SubscribeReasonCode::Success(f.qos))
+                                    .collect();
+                                filters.extend(subscribe.filters.into_iter().map(|f| f.path));
+                                Some(Packet::SubAck(SubAck::new(subscribe.pkid, codes)))
+                            }
+                            Packet::Publish(publish) => {
+                                let ack = (publish.qos == QoS::AtLeastOnce)
+                                    .then(|| Packet::PubAck(PubAck::new(publish.pkid)));
+                                let _ = sender.send(publish);
+                                ack
+                            }
+                            Packet::PingReq => Some(Packet::PingResp),
+                            _ => None,
+                        };
+                        if let Some(reply) = reply {
+                            reply.write(&mut output, 1 << 20).unwrap();
+                        }
+                    }
+                }
+                Ok(publish) = published.recv() => {
+                    if filters.iter().any(|f| rumqttc::matches(&publish.topic, f)) {
+                        let publish = Publish::new(publish.topic, QoS::AtMostOnce, publish.payload);
+                        Packet::Publish(publish).write(&mut output, 1 << 20).unwrap();
+                    }
+                }
+            }
+            if stream.write_all(&output).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    #[test]
+    fn broker_round_trip() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let port = listener.local_addr().unwrap().port();
+            tokio::spawn(broker(listener));
+
+            let (client, mut eventloop) =
+                AsyncClient::new(MqttOptions::new("test", "127.0.0.1", port), 16);
+            client.subscribe("vss/#", QoS::AtMostOnce).await.unwrap();
+            while !matches!(
+                eventloop.poll().await,
+                Ok(Event::Incoming(Packet::SubAck(_)))
+            ) {}
+
+            let loopback = Loopback::new();
+            loopback.publish(sample(IS_OPEN, Value::Bool(true)));
+            let bridge =
+                MqttBridge::new(loopback.clone(), MqttConfig::new(["Vehicle.Cabin.Door.**"]))
+                    .unwrap();
+            tokio::spawn(bridge.serve(MqttOptions::new("bridge", "127.0.0.1", port)));
+
+            // the sample is forwarded
+            let deadline = tokio::time::sleep(Duration::from_secs(10));
+            tokio::pin!(deadline);
+            let publish = loop {
+                let event = tokio::select! {
+                    event = eventloop.poll() => event.unwrap(),
+                    _ = &mut deadline => panic!("no samples from the bridge"),
+                };
+                if let Event::Incoming(Packet::Publish(publish)) = event {
+                    break publish;
+                }
+            };
+            assert_eq!(publish.topic, "vss/Vehicle/Cabin/Door/Row1/Left/IsOpen");
+            let payload: Payload = serde_json::from_slice(&publish.payload).unwrap();
+            assert_eq!(payload.value, Value::Bool(true));
+
+            // the set request is published on the Target topic of the actuator
+            let set_topic = "vss/set/Vehicle/Cabin/Door/Row1/Left/Shade/Position";
+            client
+                .publish(set_topic, QoS::AtLeastOnce, false, "55")
+                .await
+                .unwrap();
+            tokio::spawn(async move { while eventloop.poll().await.is_ok() {} });
+            let mut targets = loopback.clone();
+            let requested = loop {
+                let requested = targets.take_targets();
+                if !requested.is_empty() {
+                    break requested;
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(Duration::from_millis(10)) => {}
+                    _ = &mut deadline => panic!("no request from the bridge"),
+                }
+            };
+            assert_eq!(requested[0].path, SHADE);
+            assert_eq!(requested[0].value, Value::UInt(55));
+            // the current value is unchanged
+            assert!(loopback.clone().take().is_empty());
+        });
+    }
+}
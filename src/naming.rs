@@ -0,0 +1,220 @@
+// Check project root for LICENCE
+//! Topic names and partitions for setups with several vehicles on one domain
+
+use std::ffi::CString;
+
+use cyclonedds_rs::{
+    DDSError, DdsListener, DdsParticipant, DdsQos, DdsReader, DdsWriter, PublisherBuilder,
+    ReaderBuilder, SubscriberBuilder, TopicBuilder, TopicType, WriterBuilder,
+};
+
+use crate::v3::VssSignal;
+
+/// The first element of the topic names created by [NamingPolicy::fleet].
+pub const FLEET_PREFIX: &str = "fleet";
+
+/// Decides how the topics of a vehicle are kept apart from the topics of the
+/// other vehicles on the same DDS domain. The default policy uses the topic
+/// names of the generated types and the default partition, which is fine as long
+/// as there is only one vehicle on the domain.
+///
+/// There are three ways to separate vehicles, and they can be combined:
+///
+/// * A topic name prefix. `fleet/<VIN>/v3/vehicle/speed/Speed` instead of
+///   `/v3/vehicle/speed/Speed`. Every vehicle has its own topics.
+/// * A DDS partition per vehicle. The topics are shared but the samples are only
+///   delivered to readers in the same partition. A reader with the partition `*`
+///   receives the samples of all the vehicles.
+/// * A `vehicle_id` key in every topic, with the `vehicle-id` feature. The VIN is
+///   written into every sample that is published through the policy.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NamingPolicy {
+    prefix: Option<String>,
+    partition: Option<String>,
+    vehicle_id: Option<String>,
+}
+
+impl NamingPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Prefix the topic names with `fleet/<VIN>`
+    pub fn fleet(vin: &str) -> Self {
+        Self::new().with_prefix(&format!("{}/{}", FLEET_PREFIX, vin))
+    }
+
+    /// Prefix the topic names. The prefix should not end with a `/`.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = Some(prefix.to_owned());
+        self
+    }
+
+    /// Publish and subscribe in a partition. Readers can use wildcards like `*`.
+    pub fn with_partition(mut self, partition: &str) -> Self {
+        self.partition = Some(partition.to_owned());
+        self
+    }
+
+    /// Write the identity of the vehicle into the `vehicle_id` key of every
+    /// published sample.
+    #[cfg(feature = "vehicle-id")]
+    pub fn with_vehicle_id(mut self, vin: &str) -> Self {
+        self.vehicle_id = Some(vin.to_owned());
+        self
+    }
+
+    pub fn prefix(&self) -> Option<&str> {
+        self.prefix.as_deref()
+    }
+
+    pub fn partition(&self) -> Option<&str> {
+        self.partition.as_deref()
+    }
+
+    pub fn vehicle_id(&self) -> Option<&str> {
+        self.vehicle_id.as_deref()
+    }
+
+    /// The topic name of `T` under this policy
+    pub fn topic_name<T: TopicType>(&self) -> String {
+        T::topic_name(self.prefix())
+    }
+
+    /// Set the vehicle identity on a sample before it is published. This does
+    /// nothing unless the `vehicle-id` feature is enabled and the policy has a
+    /// vehicle id.
+    pub fn stamp<T: VssSignal>(&self, _sample: &mut T) {
+        #[cfg(feature = "vehicle-id")]
+        if let Some(vin) = &self.vehicle_id {
+            _sample.set_vehicle_id(vin);
+        }
+    }
+
+    /// Create a writer for the topic of `T` with the default QoS of the signal.
+    pub fn create_writer<T: VssSignal>(
+        &self,
+        participant: &DdsParticipant,
+    ) -> Result<DdsWriter<T>, DDSError> {
+        let topic = TopicBuilder::<T>::new()
+            .with_name(self.topic_name::<T>())
+            .with_qos(T::default_qos())
+            .create(participant)?;
+        let builder = WriterBuilder::new().with_qos(T::default_qos());
+        match self.partition_qos()? {
+            Some(qos) => {
+                let publisher = PublisherBuilder::new().with_qos(qos).create(participant)?;
+                builder.create(&publisher, topic)
+            }
+            None => builder.create(participant, topic),
+        }
+    }
+
+    /// Create a reader for the topic of `T` with the default QoS of the signal.
+    pub fn create_reader<T: VssSignal>(
+        &self,
+        participant: &DdsParticipant,
+    ) -> Result<DdsReader<T>, DDSError> {
+        self.reader(participant, ReaderBuilder::new())
+    }
+
+    /// Create an async reader for the topic of `T` with the default QoS of the signal.
+    pub fn create_async_reader<T: VssSignal>(
+        &self,
+        participant: &DdsParticipant,
+    ) -> Result<DdsReader<T>, DDSError> {
+        self.reader(participant, ReaderBuilder::new().as_async())
+    }
+
+    // A reader whose listener is called when samples arrive
+    pub(crate) fn create_reader_with_listener<T: VssSignal>(
+        &self,
+        participant: &DdsParticipant,
+        listener: DdsListener,
+    ) -> Result<DdsReader<T>, DDSError> {
+        self.reader(participant, ReaderBuilder::new().with_listener(listener))
+    }
+
+    fn reader<T: VssSignal>(
+        &self,
+        participant: &DdsParticipant,
+        builder: ReaderBuilder<T>,
+    ) -> Result<DdsReader<T>, DDSError> {
+        let topic = TopicBuilder::<T>::new()
+            .with_name(self.topic_name::<T>())
+            .with_qos(T::default_qos())
+            .create(participant)?;
+        let builder = builder.with_qos(T::default_qos());
+        match self.partition_qos()? {
+            Some(qos) => {
+                let subscriber = SubscriberBuilder::new().with_qos(qos).create(participant)?;
+                builder.create(&subscriber, topic)
+            }
+            None => builder.create(participant, topic),
+        }
+    }
+
+    pub(crate) fn partition_qos(&self) -> Result<Option<DdsQos>, DDSError> {
+        match &self.partition {
+            Some(partition) => {
+                let name = CString::new(partition.as_str()).map_err(|_| DDSError::BadParameter)?;
+                let mut qos = DdsQos::default();
+                qos.set_partition(&name);
+                Ok(Some(qos))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Recover the VIN from a topic name created by [NamingPolicy::fleet]. With
+/// the `ros2` feature this is the VIN as a ROS 2 name, see
+/// [crate::ros2::topic_name].
+pub fn vehicle_of_topic(topic_name: &str) -> Option<&str> {
+    let topic_name = topic_name.strip_prefix("rt/").unwrap_or(topic_name);
+    let mut parts = topic_name.splitn(3, '/');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(FLEET_PREFIX), Some(vin), Some(_)) if !vin.is_empty() => Some(vin),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::vehicle::Speed;
+
+    #[test]
+    fn fleet_topic_names() {
+        let policy = NamingPolicy::fleet("WVW123");
+        let name = policy.topic_name::<Speed>();
+        #[cfg(not(feature = "ros2"))]
+        assert_eq!(name, format!("fleet/WVW123{}", Speed::topic_name(None)));
+        assert_eq!(vehicle_of_topic(&name), Some("WVW123"));
+        assert_eq!(vehicle_of_topic(&Speed::topic_name(None)), None);
+        assert_eq!(
+            NamingPolicy::new().topic_name::<Speed>(),
+            Speed::topic_name(None)
+        );
+    }
+
+    #[cfg(feature = "vehicle-id")]
+    #[test]
+    fn stamp_vehicle_id() {
+        use crate::v3::units::KilometrePerHour;
+        use crate::v3::vehicle::vehicleidentification::Brand;
+
+        let mut speed = Speed::new(KilometrePerHour(10.0), None).unwrap();
+        NamingPolicy::new().stamp(&mut speed);
+        assert_eq!(speed.vehicle_id(), "");
+        NamingPolicy::new()
+            .with_vehicle_id("WVW123")
+            .stamp(&mut speed);
+        assert_eq!(speed.vehicle_id(), "WVW123");
+        assert_eq!(speed.vehicle_id, "WVW123");
+
+        let mut brand = Brand::new("VW".to_owned()).unwrap();
+        brand.vehicle_id = "WVW123".to_owned();
+        assert_eq!(brand.vehicle_id(), "WVW123");
+    }
+}
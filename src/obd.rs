@@ -0,0 +1,721 @@
+// Check project root for LICENCE
+//! Decode SAE J1979 (OBD-II) responses into samples of the Vehicle.OBD signals
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, BufRead};
+
+use cyclonedds_rs::DDSError;
+
+use crate::backend::Backend;
+use crate::catalog::DynSample;
+use crate::v3::Timestamp;
+use crate::value::Value;
+
+/// A response that can't be decoded
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResponseError {
+    Empty,
+    UnsupportedMode(u8),
+    UnsupportedPid {
+        mode: u8,
+        pid: u8,
+    },
+    /// The response is shorter than the data of the PID
+    Truncated {
+        mode: u8,
+        pid: u8,
+    },
+    /// The ECU rejected the request, `7F <service> <code>`
+    Negative {
+        service: u8,
+        code: u8,
+    },
+}
+
+impl fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ResponseError::Empty => f.write_str("empty response"),
+            ResponseError::UnsupportedMode(mode) => write!(f, "unsupported mode {:02X}", mode),
+            ResponseError::UnsupportedPid { mode, pid } => {
+                write!(f, "unsupported PID {:02X} of mode {:02X}", pid, mode)
+            }
+            ResponseError::Truncated { mode, pid } => {
+                write!(
+                    f,
+                    "truncated response to PID {:02X} of mode {:02X}",
+                    pid, mode
+                )
+            }
+            ResponseError::Negative { service, code } => {
+                write!(
+                    f,
+                    "negative response to service {:02X}: {:02X}",
+                    service, code
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ResponseError {}
+
+#[derive(Debug)]
+pub enum ObdError {
+    Io(io::Error),
+    Dds(DDSError),
+}
+
+impl fmt::Display for ObdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObdError::Io(e) => e.fmt(f),
+            ObdError::Dds(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ObdError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObdError::Io(e) => Some(e),
+            ObdError::Dds(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for ObdError {
+    fn from(e: io::Error) -> Self {
+        ObdError::Io(e)
+    }
+}
+
+impl From<DDSError> for ObdError {
+    fn from(e: DDSError) -> Self {
+        ObdError::Dds(e)
+    }
+}
+
+const FUEL_TYPES: [&str; 24] = [
+    "Not available",
+    "Gasoline",
+    "Methanol",
+    "Ethanol",
+    "Diesel",
+    "LPG",
+    "CNG",
+    "Propane",
+    "Electric",
+    "Bifuel running Gasoline",
+    "Bifuel running Methanol",
+    "Bifuel running Ethanol",
+    "Bifuel running LPG",
+    "Bifuel running CNG",
+    "Bifuel running Propane",
+    "Bifuel running Electricity",
+    "Bifuel running electric and combustion engine",
+    "Hybrid gasoline",
+    "Hybrid Ethanol",
+    "Hybrid Diesel",
+    "Hybrid Electric",
+    "Hybrid running electric and combustion engine",
+    "Hybrid Regenerative",
+    "Bifuel running diesel",
+];
+
+fn fuel_status(bits: u8) -> Option<&'static str> {
+    match bits {
+        1 => Some("OL"),
+        2 => Some("CL"),
+        4 => Some("OL-Drive"),
+        8 => Some("OL-Fault"),
+        16 => Some("CL-Fault"),
+        _ => None,
+    }
+}
+
+fn air_status(bits: u8) -> Option<&'static str> {
+    match bits {
+        1 => Some("UPS"),
+        2 => Some("DNS"),
+        4 => Some("OFF"),
+        8 => Some("DIAG"),
+        _ => None,
+    }
+}
+
+/// A diagnostic trouble code like `P0301`
+pub fn dtc(high: u8, low: u8) -> String {
+    let system = ["P", "C", "B", "U"][(high >> 6) as usize];
+    format!("{}{}{:X}{:02X}", system, (high >> 4) & 3, high & 0xf, low)
+}
+
+// The number of data bytes of a mode 01 PID
+fn pid_size(pid: u8) -> Option<usize> {
+    Some(match pid {
+        0x00 | 0x01 | 0x20 | 0x24..=0x2b | 0x34..=0x3b | 0x40 | 0x41 | 0x50 => 4,
+        0x02 | 0x03 | 0x0c | 0x10 | 0x14..=0x1b | 0x1f | 0x21..=0x23 | 0x31 | 0x32 => 2,
+        0x3c..=0x3f | 0x42..=0x44 | 0x4d | 0x4e | 0x53..=0x59 | 0x5d | 0x5e => 2,
+        0x04..=0x0b | 0x0d..=0x0f | 0x11..=0x13 | 0x1c..=0x1e | 0x2c..=0x30 | 0x33 => 1,
+        0x45..=0x4c | 0x51 | 0x52 | 0x5a..=0x5c => 1,
+        _ => return None,
+    })
+}
+
+fn float(path: &str, value: f64) -> (String, Value) {
+    (path.to_owned(), Value::Float(value))
+}
+
+fn uint(path: &str, value: u64) -> (String, Value) {
+    (path.to_owned(), Value::UInt(value))
+}
+
+fn text(path: &str, value: &str) -> (String, Value) {
+    (path.to_owned(), Value::String(value.to_owned()))
+}
+
+// The values of a mode 01 PID, the data has the size of the PID
+fn pid_values(pid: u8, d: &[u8]) -> Vec<(String, Value)> {
+    let a = d[0] as f64;
+    let ab = || d[0] as f64 * 256.0 + d[1] as f64;
+    let cd = || d[2] as f64 * 256.0 + d[3] as f64;
+    let percent = |x: u8| x as f64 * 100.0 / 255.0;
+    let trim = |x: u8| (x as f64 - 128.0) * 100.0 / 128.0;
+    let status = |branch: &str| {
+        vec![
+            (format!("{}.IsMILOn", branch), Value::Bool(d[0] & 0x80 != 0)),
+            uint(&format!("{}.DTCCount", branch), (d[0] & 0x7f) as u64),
+            text(
+                &format!("{}.IgnitionType", branch),
+                if d[1] & 0x08 != 0 {
+                    "COMPRESSION"
+                } else {
+                    "SPARK"
+                },
+            ),
+        ]
+    };
+    let o2 = "Vehicle.OBD.O2";
+    let o2wr = "Vehicle.OBD.O2WR";
+    match pid {
+        0x00 => vec![uint(
+            "Vehicle.OBD.PidsA",
+            u32::from_be_bytes([d[0], d[1], d[2], d[3]]) as u64,
+        )],
+        0x20 => vec![uint(
+            "Vehicle.OBD.PidsB",
+            u32::from_be_bytes([d[0], d[1], d[2], d[3]]) as u64,
+        )],
+        0x40 => vec![uint(
+            "Vehicle.OBD.PidsC",
+            u32::from_be_bytes([d[0], d[1], d[2], d[3]]) as u64,
+        )],
+        0x01 => status("Vehicle.OBD.Status"),
+        0x41 => status("Vehicle.OBD.DriveCycleStatus"),
+        0x02 => vec![text("Vehicle.OBD.FreezeDTC", &dtc(d[0], d[1]))],
+        0x03 => fuel_status(d[0])
+            .map(|s| text("Vehicle.OBD.FuelStatus", s))
+            .into_iter()
+            .collect(),
+        0x04 => vec![float("Vehicle.OBD.EngineLoad", percent(d[0]))],
+        0x05 => vec![float("Vehicle.OBD.CoolantTemperature", a - 40.0)],
+        0x06 => vec![float("Vehicle.OBD.ShortTermFuelTrim1", trim(d[0]))],
+        0x07 => vec![float("Vehicle.OBD.LongTermFuelTrim1", trim(d[0]))],
+        0x08 => vec![float("Vehicle.OBD.ShortTermFuelTrim2", trim(d[0]))],
+        0x09 => vec![float("Vehicle.OBD.LongTermFuelTrim2", trim(d[0]))],
+        0x0a => vec![float("Vehicle.OBD.FuelPressure", 3.0 * a)],
+        0x0b => vec![float("Vehicle.OBD.MAP", a)],
+        0x0c => vec![float("Vehicle.OBD.EngineSpeed", ab() / 4.0)],
+        0x0d => vec![float("Vehicle.OBD.Speed", a)],
+        0x0e => vec![float("Vehicle.OBD.TimingAdvance", a / 2.0 - 64.0)],
+        0x0f => vec![float("Vehicle.OBD.IntakeTemp", a - 40.0)],
+        0x10 => vec![float("Vehicle.OBD.MAF", ab() / 100.0)],
+        0x11 => vec![float("Vehicle.OBD.ThrottlePosition", percent(d[0]))],
+        0x12 => air_status(d[0])
+            .map(|s| text("Vehicle.OBD.AirStatus", s))
+            .into_iter()
+            .collect(),
+        0x13 => vec![uint("Vehicle.OBD.OxygenSensorsIn2Banks", d[0] as u64)],
+        0x14..=0x1b => {
+            let sensor = format!("{}.Sensor{}", o2, pid - 0x13);
+            let mut values = vec![float(&format!("{}.Voltage", sensor), a / 200.0)];
+            // 0xFF: the sensor is not used for the fuel trim
+            if d[1] != 0xff {
+                values.push(float(&format!("{}.ShortTermFuelTrim", sensor), trim(d[1])));
+            }
+            values
+        }
+        0x1c => vec![uint("Vehicle.OBD.OBDStandards", d[0] as u64)],
+        0x1d => vec![uint("Vehicle.OBD.OxygenSensorsIn4Banks", d[0] as u64)],
+        0x1e => vec![(
+            "Vehicle.OBD.IsPTOActive".to_owned(),
+            Value::Bool(d[0] & 1 != 0),
+        )],
+        0x1f => vec![float("Vehicle.OBD.RunTime", ab())],
+        0x21 => vec![float("Vehicle.OBD.DistanceWithMIL", ab())],
+        0x22 => vec![float("Vehicle.OBD.FuelRailPressureVac", 0.079 * ab())],
+        0x23 => vec![float("Vehicle.OBD.FuelRailPressureDirect", 10.0 * ab())],
+        0x24..=0x2b => {
+            let sensor = format!("{}.Sensor{}", o2wr, pid - 0x23);
+            vec![
+                float(&format!("{}.Lambda", sensor), ab() * 2.0 / 65536.0),
+                float(&format!("{}.Voltage", sensor), cd() * 8.0 / 65536.0),
+            ]
+        }
+        0x2c => vec![float("Vehicle.OBD.CommandedEGR", percent(d[0]))],
+        0x2d => vec![float("Vehicle.OBD.EGRError", trim(d[0]))],
+        0x2e => vec![float("Vehicle.OBD.CommandedEVAP", percent(d[0]))],
+        0x2f => vec![float("Vehicle.OBD.FuelLevel", percent(d[0]))],
+        0x30 => vec![uint("Vehicle.OBD.WarmupsSinceDTCClear", d[0] as u64)],
+        0x31 => vec![float("Vehicle.OBD.DistanceSinceDTCClear", ab())],
+        0x32 => vec![float(
+            "Vehicle.OBD.EVAPVaporPressure",
+            i16::from_be_bytes([d[0], d[1]]) as f64 / 4.0,
+        )],
+        0x33 => vec![float("Vehicle.OBD.BarometricPressure", a)],
+        0x34..=0x3b => {
+            let sensor = format!("{}.Sensor{}", o2wr, pid - 0x33);
+            vec![
+                float(&format!("{}.Lambda", sensor), ab() * 2.0 / 65536.0),
+                // mA in the response
+                float(
+                    &format!("{}.Current", sensor),
+                    (cd() / 256.0 - 128.0) / 1000.0,
+                ),
+            ]
+        }
+        0x3c => vec![float(
+            "Vehicle.OBD.Catalyst.Bank1.Temperature1",
+            ab() / 10.0 - 40.0,
+        )],
+        0x3d => vec![float(
+            "Vehicle.OBD.Catalyst.Bank2.Temperature1",
+            ab() / 10.0 - 40.0,
+        )],
+        0x3e => vec![float(
+            "Vehicle.OBD.Catalyst.Bank1.Temperature2",
+            ab() / 10.0 - 40.0,
+        )],
+        0x3f => vec![float(
+            "Vehicle.OBD.Catalyst.Bank2.Temperature2",
+            ab() / 10.0 - 40.0,
+        )],
+        0x42 => vec![float("Vehicle.OBD.ControlModuleVoltage", ab() / 1000.0)],
+        0x43 => vec![float("Vehicle.OBD.AbsoluteLoad", ab() * 100.0 / 255.0)],
+        0x44 => vec![float(
+            "Vehicle.OBD.CommandedEquivalenceRatio",
+            ab() * 2.0 / 65536.0,
+        )],
+        0x45 => vec![float("Vehicle.OBD.RelativeThrottlePosition", percent(d[0]))],
+        0x46 => vec![float("Vehicle.OBD.AmbientAirTemperature", a - 40.0)],
+        0x47 => vec![float("Vehicle.OBD.ThrottlePositionB", percent(d[0]))],
+        0x48 => vec![float("Vehicle.OBD.ThrottlePositionC", percent(d[0]))],
+        0x49 => vec![float("Vehicle.OBD.AcceleratorPositionD", percent(d[0]))],
+        0x4a => vec![float("Vehicle.OBD.AcceleratorPositionE", percent(d[0]))],
+        0x4b => vec![float("Vehicle.OBD.AcceleratorPositionF", percent(d[0]))],
+        0x4c => vec![float("Vehicle.OBD.ThrottleActuator", percent(d[0]))],
+        0x4d => vec![float("Vehicle.OBD.RunTimeMIL", ab())],
+        0x4e => vec![float("Vehicle.OBD.TimeSinceDTCCleared", ab())],
+        0x50 => vec![float("Vehicle.OBD.MaxMAF", a * 10.0)],
+        0x51 => FUEL_TYPES
+            .get(d[0] as usize)
+            .map(|t| text("Vehicle.OBD.FuelType", t))
+            .into_iter()
+            .collect(),
+        0x52 => vec![float("Vehicle.OBD.EthanolPercent", percent(d[0]))],
+        0x53 => vec![float("Vehicle.OBD.EVAPVaporPressureAbsolute", ab() / 200.0)],
+        0x54 => vec![float(
+            "Vehicle.OBD.EVAPVaporPressureAlternate",
+            ab() - 32767.0,
+        )],
+        0x55..=0x58 => {
+            let name = ["ShortTermO2Trim", "LongTermO2Trim"][((pid - 0x55) % 2) as usize];
+            let bank = (pid - 0x55) / 2 + 1;
+            vec![
+                float(&format!("Vehicle.OBD.{}{}", name, bank), trim(d[0])),
+                float(&format!("Vehicle.OBD.{}{}", name, bank + 2), trim(d[1])),
+            ]
+        }
+        0x59 => vec![float("Vehicle.OBD.FuelRailPressureAbsolute", 10.0 * ab())],
+        0x5a => vec![float(
+            "Vehicle.OBD.RelativeAcceleratorPosition",
+            percent(d[0]),
+        )],
+        0x5b => vec![float("Vehicle.OBD.HybridBatteryRemaining", percent(d[0]))],
+        0x5c => vec![float("Vehicle.OBD.OilTemperature", a - 40.0)],
+        0x5d => vec![float(
+            "Vehicle.OBD.FuelInjectionTiming",
+            ab() / 128.0 - 210.0,
+        )],
+        0x5e => vec![float("Vehicle.OBD.FuelRate", ab() / 20.0)],
+        _ => Vec::new(),
+    }
+}
+
+// A mode 01 response may carry several PIDs, e.g. `41 0C 1A F8 0D 32`
+fn mode01(mut data: &[u8]) -> Result<Vec<(String, Value)>, ResponseError> {
+    let mut values = Vec::new();
+    while let Some((&pid, rest)) = data.split_first() {
+        let size = pid_size(pid).ok_or(ResponseError::UnsupportedPid { mode: 0x01, pid })?;
+        let d = rest
+            .get(..size)
+            .ok_or(ResponseError::Truncated { mode: 0x01, pid })?;
+        values.extend(pid_values(pid, d));
+        data = &rest[size..];
+    }
+    Ok(values)
+}
+
+// The stored DTCs. On CAN the response starts with the number of DTCs, the
+// older protocols pad the response to 3 DTCs.
+fn mode03(data: &[u8]) -> Vec<(String, Value)> {
+    let codes = if data.len() % 2 == 1 {
+        &data[1..]
+    } else {
+        data
+    };
+    let list = codes
+        .chunks_exact(2)
+        .filter(|c| c != &[0, 0])
+        .map(|c| Value::String(dtc(c[0], c[1])))
+        .collect();
+    vec![("Vehicle.OBD.DTCList".to_owned(), Value::Array(list))]
+}
+
+// Vehicle information, only the VIN has a signal
+fn mode09(data: &[u8]) -> Result<Vec<(String, Value)>, ResponseError> {
+    match data.split_first() {
+        Some((0x02, rest)) => {
+            let start = rest.len().checked_sub(17).ok_or(ResponseError::Truncated {
+                mode: 0x09,
+                pid: 0x02,
+            })?;
+            let vin: String = rest[start..]
+                .iter()
+                .filter(|b| **b != 0)
+                .map(|b| *b as char)
+                .collect();
+            Ok(vec![text("Vehicle.VehicleIdentification.VIN", &vin)])
+        }
+        Some((&pid, _)) => Err(ResponseError::UnsupportedPid { mode: 0x09, pid }),
+        None => Err(ResponseError::Empty),
+    }
+}
+
+/// The samples of a response payload of mode 01, 03 or 09, e.g.
+/// `41 0C 1A F8` for the engine speed. The values are checked against the
+/// specification, the values that don't fit are dropped.
+pub fn decode(
+    payload: &[u8],
+    timestamp: Option<Timestamp>,
+) -> Result<Vec<DynSample>, ResponseError> {
+    let values = match payload {
+        [] => return Err(ResponseError::Empty),
+        [0x7f, service, code, ..] => {
+            return Err(ResponseError::Negative {
+                service: *service,
+                code: *code,
+            })
+        }
+        [0x41, data @ ..] => mode01(data)?,
+        [0x43, data @ ..] => mode03(data),
+        [0x49, data @ ..] => mode09(data)?,
+        [mode, ..] => return Err(ResponseError::UnsupportedMode(*mode)),
+    };
+    Ok(values
+        .into_iter()
+        .filter_map(|(path, value)| {
+            DynSample::new(&path, value, timestamp.clone()).and_then(DynSample::checked)
+        })
+        .collect())
+}
+
+/// Reassembles ISO-TP (ISO 15765-2) payloads from the data of CAN frames.
+/// Flow control frames are skipped, this only listens.
+#[derive(Debug, Default)]
+pub struct IsoTp {
+    buffer: Vec<u8>,
+    expected: usize,
+    sequence: u8,
+}
+
+impl IsoTp {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the data of a frame. Returns the payload when it is complete.
+    pub fn push(&mut self, data: &[u8]) -> Option<Vec<u8>> {
+        let (&pci, rest) = data.split_first()?;
+        match pci >> 4 {
+            // single frame
+            0 => {
+                self.expected = 0;
+                let size = (pci & 0xf) as usize;
+                rest.get(..size)
+                    .filter(|p| !p.is_empty())
+                    .map(<[u8]>::to_vec)
+            }
+            // first frame
+            1 => {
+                let (&low, rest) = rest.split_first()?;
+                self.expected = ((pci as usize & 0xf) << 8) | low as usize;
+                self.buffer = rest.to_vec();
+                self.sequence = 1;
+                None
+            }
+            // consecutive frame
+            2 => {
+                if self.expected == 0 || pci & 0xf != self.sequence {
+                    self.expected = 0;
+                    return None;
+                }
+                self.sequence = (self.sequence + 1) & 0xf;
+                self.buffer.extend_from_slice(rest);
+                if self.buffer.len() < self.expected {
+                    return None;
+                }
+                self.buffer.truncate(self.expected);
+                self.expected = 0;
+                Some(std::mem::take(&mut self.buffer))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn hex(text: &str) -> Option<Vec<u8>> {
+    let digits: String = text.split_whitespace().collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Reassembles the responses in the text output of an ELM327 adapter, with
+/// or without spaces (`ATS0`), with or without 11 bit CAN headers (`ATH1`),
+/// and the multi-line responses like:
+///
+/// ```text
+/// 014
+/// 0: 49 02 01 31 44 34
+/// 1: 47 50 30 30 52 35 35
+/// 2: 42 31 32 33 34 35 36
+/// ```
+///
+/// Prompts and messages like `SEARCHING...` or `NO DATA` are skipped. With
+/// echo on (`ATE1`) the echo of a command is skipped when it follows a prompt,
+/// as in the output of the adapter, or when it is the command passed to
+/// [Elm327::sent].
+#[derive(Debug, Default)]
+pub struct Elm327 {
+    // the length and the data of a multi-line response without headers
+    multi: Option<(usize, Vec<u8>)>,
+    // the ISO-TP reassembly of the responses of each ECU with headers
+    ecus: BTreeMap<u32, IsoTp>,
+    // the last command sent to the adapter, until its echo is read
+    command: Option<String>,
+}
+
+// A command or a line in upper case without spaces
+fn compact(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<String>()
+        .to_ascii_uppercase()
+}
+
+impl Elm327 {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tell the parser the command that was sent to the adapter, so that its
+    /// echo is not taken for a response
+    pub fn sent(&mut self, command: &str) {
+        self.command = Some(compact(command));
+    }
+
+    /// Add a line of output. Returns the payload of a response when it is
+    /// complete.
+    pub fn push_line(&mut self, line: &str) -> Option<Vec<u8>> {
+        let line = line.trim();
+        // the adapter echoes the command after the prompt
+        if line.starts_with('>') {
+            self.command = None;
+            return None;
+        }
+        if self.command.as_deref() == Some(compact(line).as_str()) {
+            self.command = None;
+            return None;
+        }
+        if let Some((index, bytes)) = line.split_once(':') {
+            u8::from_str_radix(index.trim(), 16).ok()?;
+            let (expected, buffer) = self.multi.as_mut()?;
+            buffer.extend(hex(bytes)?);
+            if buffer.len() < *expected {
+                return None;
+            }
+            let (expected, mut payload) = self.multi.take()?;
+            payload.truncate(expected);
+            return Some(payload);
+        }
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        let (header, frame) = match tokens.as_slice() {
+            [length] if length.len() == 3 => {
+                let length = usize::from_str_radix(length, 16).ok()?;
+                self.multi = Some((length, Vec::with_capacity(length)));
+                return None;
+            }
+            // without spaces the 3 digits of the header make the length odd
+            [line] if line.len() > 3 && !line.len().is_multiple_of(2) => {
+                (line.get(..3)?, line.get(3..)?.to_owned())
+            }
+            [header, frame @ ..] if header.len() == 3 && !frame.is_empty() => {
+                (*header, frame.join(""))
+            }
+            _ => return hex(line),
+        };
+        let id = u32::from_str_radix(header, 16).ok()?;
+        let data = hex(&frame)?;
+        self.ecus.entry(id).or_default().push(&data)
+    }
+}
+
+/// Decode the output of an ELM327 adapter, e.g. a serial port or a log, and
+/// write the samples to a backend until the end of the input. The responses
+/// that can't be decoded are skipped. Returns the number of samples.
+pub fn publish_elm327<R: BufRead, B: Backend>(
+    input: R,
+    backend: &mut B,
+) -> Result<usize, ObdError> {
+    let mut elm = Elm327::new();
+    let mut published = 0;
+    // the adapter ends the lines with a carriage return
+    for chunk in input.split(b'\r') {
+        for line in String::from_utf8_lossy(&chunk?).split('\n') {
+            let payload = match elm.push_line(line) {
+                Some(payload) => payload,
+                None => continue,
+            };
+            for sample in decode(&payload, Some(Timestamp::default())).unwrap_or_default() {
+                backend.write(&sample)?;
+                published += 1;
+            }
+        }
+    }
+    Ok(published)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Loopback;
+
+    fn values(payload: &[u8]) -> Vec<(String, Value)> {
+        decode(payload, None)
+            .unwrap()
+            .into_iter()
+            .map(|s| (s.path, s.value))
+            .collect()
+    }
+
+    #[test]
+    fn mode01_and_mode03() {
+        let decoded = values(&[0x41, 0x0c, 0x1a, 0xf8, 0x0d, 0x32, 0x05, 0x7b]);
+        assert_eq!(
+            decoded,
+            vec![
+                ("Vehicle.OBD.EngineSpeed".to_owned(), Value::Float(1726.0)),
+                ("Vehicle.OBD.Speed".to_owned(), Value::Float(50.0)),
+                (
+                    "Vehicle.OBD.CoolantTemperature".to_owned(),
+                    Value::Float(83.0)
+                ),
+            ]
+        );
+        let decoded = values(&[0x41, 0x01, 0x83, 0x07, 0x65, 0x00]);
+        assert_eq!(decoded[0].1, Value::Bool(true));
+        assert_eq!(decoded[1].1, Value::UInt(3));
+        assert_eq!(decoded[2].1, Value::String("SPARK".to_owned()));
+        let decoded = values(&[0x41, 0x15, 0xb4, 0x80]);
+        assert_eq!(decoded[0].0, "Vehicle.OBD.O2.Sensor2.Voltage");
+        assert_eq!(decoded[1].1, Value::Float(0.0));
+
+        let decoded = values(&[0x43, 0x02, 0x03, 0x01, 0xc1, 0x23]);
+        assert_eq!(
+            decoded[0].1,
+            Value::Array(vec![
+                Value::String("P0301".to_owned()),
+                Value::String("U0123".to_owned())
+            ])
+        );
+
+        assert_eq!(
+            decode(&[0x41, 0x0c, 0x1a], None),
+            Err(ResponseError::Truncated { mode: 1, pid: 0x0c })
+        );
+        assert_eq!(
+            decode(&[0x7f, 0x01, 0x12], None),
+            Err(ResponseError::Negative {
+                service: 1,
+                code: 0x12
+            })
+        );
+    }
+
+    #[test]
+    fn elm327_and_isotp() {
+        let log = "ATZ\r\rELM327 v1.5\r\r>0902\rSEARCHING...\r014\r\
+                   0: 49 02 01 31 44 34\r1: 47 50 30 30 52 35 35\r\
+                   2: 42 31 32 33 34 35 36\r\r>010D\r41 0D 32\r\r>0105\rNO DATA\r\r>";
+        let loopback = Loopback::new();
+        let published = publish_elm327(log.as_bytes(), &mut loopback.clone()).unwrap();
+        assert_eq!(published, 2);
+        let samples = loopback.clone().take();
+        assert_eq!(samples[0].path, "Vehicle.VehicleIdentification.VIN");
+        assert_eq!(
+            samples[0].value,
+            Value::String("1D4GP00R55B123456".to_owned())
+        );
+        assert_eq!(samples[1].value, Value::Float(50.0));
+
+        // with headers, the same response in ISO-TP frames
+        let mut elm = Elm327::new();
+        assert_eq!(elm.push_line("7E8 10 14 49 02 01 31 44 34"), None);
+        assert_eq!(elm.push_line("7E8 21 47 50 30 30 52 35 35"), None);
+        let payload = elm.push_line("7E8 22 42 31 32 33 34 35 36").unwrap();
+        assert_eq!(payload.len(), 20);
+        assert_eq!(
+            elm.push_line("7E8 03 41 0D 32"),
+            Some(vec![0x41, 0x0d, 0x32])
+        );
+        // without spaces (ATS0)
+        assert_eq!(elm.push_line("7E803410D32"), Some(vec![0x41, 0x0d, 0x32]));
+        assert_eq!(elm.push_line("410D32"), Some(vec![0x41, 0x0d, 0x32]));
+    }
+
+    #[test]
+    fn elm327_echo() {
+        // ATE1, the echo of the command on its own line
+        let mut elm = Elm327::new();
+        elm.sent("01 0D");
+        assert_eq!(elm.push_line("010D"), None);
+        assert_eq!(elm.push_line("41 0D 32"), Some(vec![0x41, 0x0d, 0x32]));
+        elm.sent("ATE1");
+        assert_eq!(elm.push_line("ATE1"), None);
+        assert_eq!(elm.push_line("OK"), None);
+
+        // the echo after the prompt in the output of the adapter
+        assert_eq!(elm.push_line(">0902"), None);
+        assert_eq!(elm.push_line(">"), None);
+        let log = "ATE1\rOK\r\r>010D\r41 0D 32\r\r>";
+        let loopback = Loopback::new();
+        let published = publish_elm327(log.as_bytes(), &mut loopback.clone()).unwrap();
+        assert_eq!(published, 1);
+        assert_eq!(loopback.clone().take()[0].value, Value::Float(50.0));
+    }
+}
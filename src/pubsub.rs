@@ -0,0 +1,350 @@
+// Check project root for LICENCE
+//! Typed publisher and subscriber for the generated topics
+
+use std::ffi::c_void;
+use std::sync::Arc;
+
+use cyclonedds_rs::{
+    DDSError, DdsParticipant, DdsReader, DdsWriter, Entity, SampleBuffer, TopicType,
+};
+use cyclonedds_sys::{
+    dds_instance_handle_t, dds_read, dds_read_instance, dds_read_mask, dds_return_t,
+    dds_sample_state_DDS_SST_NOT_READ, dds_take, dds_take_instance, size_t,
+};
+
+use crate::naming::NamingPolicy;
+use crate::v3::VssSignal;
+
+const BUFFER_LEN: usize = 16;
+
+// Read or take the samples that are available without blocking into the
+// buffer, of all instances or of a single one. Returns the number of entries of
+// the buffer that were filled, including the invalid samples that only tell
+// about a dispose or an unregister, or a negative DDS return code.
+fn read_raw<T: TopicType>(
+    reader: &DdsReader<T>,
+    buffer: &mut SampleBuffer<T>,
+    take: bool,
+    handle: Option<dds_instance_handle_t>,
+) -> dds_return_t {
+    let len = buffer.len();
+    unsafe {
+        let entity = reader.entity().entity();
+        let (samples, infos) = buffer.as_mut_ptr();
+        let samples = samples as *mut *mut c_void;
+        match (handle, take) {
+            (None, true) => dds_take(entity, samples, infos, len as size_t, len as u32),
+            (None, false) => dds_read(entity, samples, infos, len as size_t, len as u32),
+            (Some(handle), true) => {
+                dds_take_instance(entity, samples, infos, len as size_t, len as u32, handle)
+            }
+            (Some(handle), false) => {
+                dds_read_instance(entity, samples, infos, len as size_t, len as u32, handle)
+            }
+        }
+    }
+}
+
+// Call `f` for the valid samples among the first `count` entries of the buffer.
+// The invalid ones are skipped, they don't carry data.
+fn valid_samples<T: TopicType>(buffer: &SampleBuffer<T>, count: usize, f: &mut impl FnMut(&T)) {
+    for i in 0..count {
+        if buffer.is_valid_sample(i) {
+            if let Some(sample) = buffer.get(i).try_deref() {
+                f(sample)
+            }
+        }
+    }
+}
+
+// Read or take all the samples that are available without blocking.
+pub(crate) fn read_all<T: TopicType>(
+    reader: &DdsReader<T>,
+    buffer: &mut SampleBuffer<T>,
+    take: bool,
+    mut f: impl FnMut(&T),
+) {
+    loop {
+        let result = read_raw(reader, buffer, take, None);
+        if result <= 0 {
+            break;
+        }
+        let count = result as usize;
+        valid_samples(buffer, count, &mut f);
+        // a read returns the same samples again, only a take can be repeated
+        if !take || count < buffer.len() {
+            break;
+        }
+    }
+}
+
+/// Publisher for a generated topic. The topic name and the QoS are taken from the
+/// type.
+pub struct VssPublisher<T: VssSignal> {
+    writer: DdsWriter<T>,
+    naming: NamingPolicy,
+}
+
+impl<T: VssSignal> VssPublisher<T> {
+    pub fn new(participant: &DdsParticipant) -> Result<Self, DDSError> {
+        Self::with_naming(participant, &NamingPolicy::default())
+    }
+
+    /// Publish with a topic name prefix, partition or vehicle id. See [NamingPolicy].
+    pub fn with_naming(
+        participant: &DdsParticipant,
+        naming: &NamingPolicy,
+    ) -> Result<Self, DDSError> {
+        Ok(Self {
+            writer: naming.create_writer(participant)?,
+            naming: naming.clone(),
+        })
+    }
+
+    /// Publish a sample
+    pub fn publish(&mut self, mut sample: T) -> Result<(), DDSError> {
+        self.naming.stamp(&mut sample);
+        self.writer.write(Arc::new(sample))
+    }
+
+    /// Publish a value for the given instance with the current time as timestamp.
+    /// Returns `BadParameter` if the value is out of bounds or the instance is not
+    /// defined in the specification.
+    pub fn publish_value(&mut self, value: T::Value, key: T::Key) -> Result<(), DDSError> {
+        if !T::key_instances().contains(&key) {
+            return Err(DDSError::BadParameter);
+        }
+        let sample = T::from_parts(value, None, key).ok_or(DDSError::BadParameter)?;
+        self.publish(sample)
+    }
+
+    pub fn writer(&mut self) -> &mut DdsWriter<T> {
+        &mut self.writer
+    }
+}
+
+/// Subscriber for a generated topic. The topic name and the QoS are taken from the
+/// type. The subscriber can be restricted to a single instance of a keyed topic.
+pub struct VssSubscriber<T: VssSignal> {
+    reader: DdsReader<T>,
+    buffer: SampleBuffer<T>,
+    instance: Option<Instance<T>>,
+}
+
+impl<T: VssSignal> VssSubscriber<T> {
+    /// Subscribe to all instances of the topic
+    pub fn new(participant: &DdsParticipant) -> Result<Self, DDSError> {
+        Self::with_naming(participant, &NamingPolicy::default(), None)
+    }
+
+    /// Subscribe to a single instance of the topic. Only the samples of this
+    /// instance are read or taken from the reader. Returns `BadParameter` if the
+    /// instance is not defined in the specification.
+    ///
+    /// This is not a DDS content filter: the filters of cyclonedds get no
+    /// argument to compare the keys with, so they can't be set per subscriber.
+    /// The reader still receives the samples of all the instances, and the
+    /// subscriber reads its instance by its handle.
+    pub fn with_keys(participant: &DdsParticipant, key: T::Key) -> Result<Self, DDSError> {
+        Self::with_naming(participant, &NamingPolicy::default(), Some(key))
+    }
+
+    /// Subscribe with a topic name prefix or partition, optionally to a single
+    /// instance. With the `vehicle-id` feature and a vehicle id in the naming
+    /// policy, the instance is the one of that vehicle. See [NamingPolicy].
+    pub fn with_naming(
+        participant: &DdsParticipant,
+        naming: &NamingPolicy,
+        key: Option<T::Key>,
+    ) -> Result<Self, DDSError> {
+        if let Some(key) = &key {
+            if !T::key_instances().contains(key) {
+                return Err(DDSError::BadParameter);
+            }
+        }
+        Ok(Self {
+            reader: naming.create_reader(participant)?,
+            buffer: SampleBuffer::new(BUFFER_LEN),
+            instance: key.map(|key| Instance::new(key, naming)),
+        })
+    }
+
+    /// Take the available samples. The samples are removed from the reader.
+    pub fn take(&mut self) -> Vec<T> {
+        self.collect(true)
+    }
+
+    /// Read the available samples. The samples stay in the reader and are
+    /// returned again by the next read.
+    pub fn read(&mut self) -> Vec<T> {
+        self.collect(false)
+    }
+
+    /// Return true if the sample is for the instance this subscriber is restricted to
+    pub fn matches(&self, sample: &T) -> bool {
+        match &self.instance {
+            Some(instance) => instance.contains(sample),
+            None => true,
+        }
+    }
+
+    pub fn reader(&self) -> &DdsReader<T> {
+        &self.reader
+    }
+
+    fn collect(&mut self, take: bool) -> Vec<T> {
+        let mut samples = Vec::new();
+        let push = |sample: &T| samples.push(sample.clone());
+        match &mut self.instance {
+            Some(instance) => read_instance(&self.reader, &mut self.buffer, take, instance, push),
+            None => read_all(&self.reader, &mut self.buffer, take, push),
+        }
+        samples
+    }
+}
+
+// The instance a subscriber is restricted to. A reader only knows the handle of
+// an instance once it has received a sample of it, the handle is looked up in
+// the samples until then.
+struct Instance<T: VssSignal> {
+    key: T::Key,
+    #[cfg(feature = "vehicle-id")]
+    vehicle_id: Option<String>,
+    handle: Option<dds_instance_handle_t>,
+}
+
+impl<T: VssSignal> Instance<T> {
+    fn new(key: T::Key, _naming: &NamingPolicy) -> Self {
+        Self {
+            key,
+            #[cfg(feature = "vehicle-id")]
+            vehicle_id: _naming.vehicle_id().map(str::to_owned),
+            handle: None,
+        }
+    }
+
+    fn contains(&self, sample: &T) -> bool {
+        #[cfg(feature = "vehicle-id")]
+        if let Some(vehicle_id) = &self.vehicle_id {
+            if sample.vehicle_id() != vehicle_id {
+                return false;
+            }
+        }
+        sample.key() == self.key
+    }
+}
+
+// Read or take the available samples of a single instance without blocking.
+fn read_instance<T: VssSignal>(
+    reader: &DdsReader<T>,
+    buffer: &mut SampleBuffer<T>,
+    take: bool,
+    instance: &mut Instance<T>,
+    mut f: impl FnMut(&T),
+) {
+    if instance.handle.is_none() {
+        instance.handle = find_instance(reader, buffer, instance);
+    }
+    let Some(handle) = instance.handle else {
+        return;
+    };
+    loop {
+        let result = read_raw(reader, buffer, take, Some(handle));
+        if result < 0 {
+            // the reader dropped the instance, e.g. after its writers left
+            instance.handle = None;
+            break;
+        }
+        let count = result as usize;
+        valid_samples(buffer, count, &mut f);
+        if !take || count < buffer.len() {
+            break;
+        }
+    }
+}
+
+// Look for the handle of the instance in the samples that were not read yet.
+// The samples are read and not taken, so they are still returned by
+// read_instance.
+fn find_instance<T: VssSignal>(
+    reader: &DdsReader<T>,
+    buffer: &mut SampleBuffer<T>,
+    instance: &Instance<T>,
+) -> Option<dds_instance_handle_t> {
+    let len = buffer.len();
+    loop {
+        let (result, infos) = unsafe {
+            let (samples, infos) = buffer.as_mut_ptr();
+            let result = dds_read_mask(
+                reader.entity().entity(),
+                samples as *mut *mut c_void,
+                infos,
+                len as size_t,
+                len as u32,
+                dds_sample_state_DDS_SST_NOT_READ,
+            );
+            (result, infos)
+        };
+        if result <= 0 {
+            return None;
+        }
+        let count = result as usize;
+        for i in 0..count {
+            if buffer.is_valid_sample(i) {
+                if let Some(sample) = buffer.get(i).try_deref() {
+                    if instance.contains(sample) {
+                        return Some(unsafe { (*infos.add(i)).instance_handle });
+                    }
+                }
+            }
+        }
+        if count < len {
+            return None;
+        }
+    }
+}
+
+pub(crate) fn matches_key<T: VssSignal>(filter: &Option<T::Key>, sample: &T) -> bool {
+    match filter {
+        Some(key) => sample.key() == *key,
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::units::Percent;
+    use crate::v3::vehicle::cabin::door::window::Position;
+    use crate::v3::Side;
+
+    #[test]
+    fn key_filter() {
+        let sample = Position::new(Percent(10), None, 2, Side::Right).unwrap();
+        assert!(matches_key(&None, &sample));
+        assert!(matches_key(&Some((2, Side::Right)), &sample));
+        assert!(!matches_key(&Some((1, Side::Right)), &sample));
+    }
+
+    #[test]
+    fn instance() {
+        let sample = Position::new(Percent(10), None, 2, Side::Right).unwrap();
+        let naming = NamingPolicy::new();
+        assert!(Instance::<Position>::new((2, Side::Right), &naming).contains(&sample));
+        assert!(!Instance::<Position>::new((2, Side::Left), &naming).contains(&sample));
+    }
+
+    #[cfg(feature = "vehicle-id")]
+    #[test]
+    fn instance_of_vehicle() {
+        let mut sample = Position::new(Percent(10), None, 2, Side::Right).unwrap();
+        let naming = NamingPolicy::new().with_vehicle_id("WVW123");
+        let instance = Instance::<Position>::new((2, Side::Right), &naming);
+        assert!(!instance.contains(&sample));
+        naming.stamp(&mut sample);
+        assert!(instance.contains(&sample));
+        assert!(
+            Instance::<Position>::new((2, Side::Right), &NamingPolicy::new()).contains(&sample)
+        );
+    }
+}
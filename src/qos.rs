@@ -0,0 +1,151 @@
+// Check project root for LICENCE
+//! QoS presets for the generated topics
+
+use std::time::Duration;
+
+use cyclonedds_rs::{
+    dds_durability_kind, dds_history_kind, dds_reliability_kind, DDSError, DdsParticipant, DdsQos,
+    DdsReader, DdsWriter,
+};
+
+use crate::naming::NamingPolicy;
+use crate::v3::{SignalKind, VssSignal};
+
+const MAX_BLOCKING_TIME: Duration = Duration::from_millis(100);
+
+/// The reliability, durability and history of the QoS of a kind of signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QosPreset {
+    pub reliability: dds_reliability_kind,
+    pub durability: dds_durability_kind,
+    pub history: dds_history_kind,
+    /// The number of samples kept per instance
+    pub depth: i32,
+}
+
+/// The QoS preset for a kind of signal.
+///
+/// * Attributes are static and must reach subscribers that join late. They are
+///   reliable and transient-local.
+/// * Sensors are published at a high rate and only the latest value is of interest.
+///   They are best-effort and keep only the last sample.
+/// * Actuators are reliable and keep only the last sample. The `Target` topics of
+///   the actuators are actuators too.
+///
+/// Readers and writers of the same topic must use the same preset, a reliable or
+/// transient-local reader does not match a best-effort or volatile writer.
+pub fn preset_for(kind: SignalKind) -> QosPreset {
+    let (reliability, durability) = match kind {
+        SignalKind::Attribute => (
+            dds_reliability_kind::DDS_RELIABILITY_RELIABLE,
+            dds_durability_kind::DDS_DURABILITY_TRANSIENT_LOCAL,
+        ),
+        SignalKind::Sensor => (
+            dds_reliability_kind::DDS_RELIABILITY_BEST_EFFORT,
+            dds_durability_kind::DDS_DURABILITY_VOLATILE,
+        ),
+        SignalKind::Actuator => (
+            dds_reliability_kind::DDS_RELIABILITY_RELIABLE,
+            dds_durability_kind::DDS_DURABILITY_VOLATILE,
+        ),
+    };
+    QosPreset {
+        reliability,
+        durability,
+        history: dds_history_kind::DDS_HISTORY_KEEP_LAST,
+        depth: 1,
+    }
+}
+
+/// The QoS of a kind of signal. See [preset_for].
+pub fn qos_for(kind: SignalKind) -> DdsQos {
+    let preset = preset_for(kind);
+    let mut qos = DdsQos::default();
+    qos.set_history(preset.history, preset.depth)
+        .set_durability(preset.durability)
+        .set_reliability(preset.reliability, MAX_BLOCKING_TIME);
+    qos
+}
+
+/// Create a writer for the topic of `T` with the default QoS of the signal.
+pub fn create_writer<T: VssSignal>(participant: &DdsParticipant) -> Result<DdsWriter<T>, DDSError> {
+    NamingPolicy::default().create_writer(participant)
+}
+
+/// Create a reader for the topic of `T` with the default QoS of the signal.
+pub fn create_reader<T: VssSignal>(participant: &DdsParticipant) -> Result<DdsReader<T>, DDSError> {
+    NamingPolicy::default().create_reader(participant)
+}
+
+/// Create an async reader for the topic of `T` with the default QoS of the signal.
+pub fn create_async_reader<T: VssSignal>(
+    participant: &DdsParticipant,
+) -> Result<DdsReader<T>, DDSError> {
+    NamingPolicy::default().create_async_reader(participant)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::vehicle::cabin::door::shade;
+    use crate::v3::vehicle::vehicleidentification::VIN;
+    use crate::v3::vehicle::Speed;
+
+    fn preset<T: VssSignal>() -> QosPreset {
+        preset_for(T::KIND)
+    }
+
+    #[test]
+    fn sensor() {
+        let preset = preset::<Speed>();
+        assert_eq!(
+            preset.reliability,
+            dds_reliability_kind::DDS_RELIABILITY_BEST_EFFORT
+        );
+        assert_eq!(
+            preset.durability,
+            dds_durability_kind::DDS_DURABILITY_VOLATILE
+        );
+        assert_eq!(preset.history, dds_history_kind::DDS_HISTORY_KEEP_LAST);
+        assert_eq!(preset.depth, 1);
+    }
+
+    #[test]
+    fn actuator() {
+        let preset = preset::<shade::Position>();
+        assert_eq!(
+            preset.reliability,
+            dds_reliability_kind::DDS_RELIABILITY_RELIABLE
+        );
+        assert_eq!(
+            preset.durability,
+            dds_durability_kind::DDS_DURABILITY_VOLATILE
+        );
+        assert_eq!(preset.history, dds_history_kind::DDS_HISTORY_KEEP_LAST);
+        assert_eq!(preset.depth, 1);
+    }
+
+    #[test]
+    fn target() {
+        // a request must not be lost, but a late joiner must not act on an old one
+        assert_eq!(
+            preset::<shade::PositionTarget>(),
+            preset::<shade::Position>()
+        );
+    }
+
+    #[test]
+    fn attribute() {
+        let preset = preset::<VIN>();
+        assert_eq!(
+            preset.reliability,
+            dds_reliability_kind::DDS_RELIABILITY_RELIABLE
+        );
+        assert_eq!(
+            preset.durability,
+            dds_durability_kind::DDS_DURABILITY_TRANSIENT_LOCAL
+        );
+        assert_eq!(preset.history, dds_history_kind::DDS_HISTORY_KEEP_LAST);
+        assert_eq!(preset.depth, 1);
+    }
+}
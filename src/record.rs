@@ -0,0 +1,750 @@
+// Check project root for LICENCE
+//! Recording and replay of the signals of a domain in MCAP files
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use cyclonedds_rs::cdr::{self, CdrBe, Infinite};
+use cyclonedds_rs::{DDSError, DdsParticipant};
+
+use crate::catalog::{self, DynReader, DynSample, DynWriter, SignalInfo, SignalVisitor};
+use crate::mcap::{McapReader, McapWriter, Message};
+use crate::naming::NamingPolicy;
+use crate::v3::{SignalKind, Timestamp, VssSignal};
+
+/// The profile of the MCAP files
+pub const PROFILE: &str = "vss";
+
+// How long a replay waits at most before it checks whether to stop
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Debug)]
+pub enum RecordError {
+    Io(io::Error),
+    Cdr(cdr::Error),
+    Dds(DDSError),
+    /// A replay speed that is zero, negative or not a number
+    Speed(f64),
+    /// A sample of a signal that is not in the catalog
+    UnknownSignal(String),
+    /// A sample whose path is not an instance of its signal, or whose value
+    /// does not fit the datatype or is out of bounds
+    InvalidSample(String),
+    /// A timestamp that doesn't fit in 64 bits of nanoseconds since the epoch
+    Timestamp(Timestamp),
+}
+
+impl fmt::Display for RecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordError::Io(e) => e.fmt(f),
+            RecordError::Cdr(e) => e.fmt(f),
+            RecordError::Dds(e) => e.fmt(f),
+            RecordError::Speed(speed) => write!(f, "invalid replay speed {}", speed),
+            RecordError::UnknownSignal(signal) => write!(f, "unknown signal {}", signal),
+            RecordError::InvalidSample(path) => write!(f, "invalid sample of {}", path),
+            RecordError::Timestamp(timestamp) => write!(
+                f,
+                "timestamp {}.{:09} out of range",
+                timestamp.sec, timestamp.nsec
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RecordError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RecordError::Io(e) => Some(e),
+            RecordError::Cdr(e) => Some(e),
+            RecordError::Dds(e) => Some(e),
+            RecordError::Speed(_)
+            | RecordError::UnknownSignal(_)
+            | RecordError::InvalidSample(_)
+            | RecordError::Timestamp(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for RecordError {
+    fn from(e: io::Error) -> Self {
+        RecordError::Io(e)
+    }
+}
+
+impl From<cdr::Error> for RecordError {
+    fn from(e: cdr::Error) -> Self {
+        RecordError::Cdr(e)
+    }
+}
+
+impl From<DDSError> for RecordError {
+    fn from(e: DDSError) -> Self {
+        RecordError::Dds(e)
+    }
+}
+
+fn nanos(timestamp: &Timestamp) -> Result<u64, RecordError> {
+    timestamp
+        .sec
+        .checked_mul(1_000_000_000)
+        .and_then(|nanos| nanos.checked_add(timestamp.nsec as u64))
+        .ok_or_else(|| RecordError::Timestamp(timestamp.clone()))
+}
+
+fn now_nanos() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or_default()
+}
+
+// Encodes a sample in CDR, as it is sent on the topic of its signal
+struct Encode<'a>(&'a DynSample);
+
+impl SignalVisitor for Encode<'_> {
+    type Output = Result<Vec<u8>, RecordError>;
+
+    fn visit<T: VssSignal>(self) -> Self::Output {
+        let sample = self
+            .0
+            .to_sample::<T>()
+            .ok_or_else(|| RecordError::InvalidSample(self.0.path.clone()))?;
+        Ok(cdr::serialize::<_, _, CdrBe>(&sample, Infinite)?)
+    }
+}
+
+// Decodes a sample that was encoded by Encode
+struct Decode<'a>(&'a [u8]);
+
+impl SignalVisitor for Decode<'_> {
+    type Output = Result<DynSample, RecordError>;
+
+    fn visit<T: VssSignal>(self) -> Self::Output {
+        let sample: T = cdr::deserialize(self.0)?;
+        Ok(DynSample::of(&sample))
+    }
+}
+
+// The type name of the topic of a signal
+struct TypeName;
+
+impl SignalVisitor for TypeName {
+    type Output = String;
+
+    fn visit<T: VssSignal>(self) -> Self::Output {
+        T::typename().to_string_lossy().into_owned()
+    }
+}
+
+// The IDL type of a datatype of the specification
+fn idl_type(datatype: &str) -> String {
+    if let Some(item) = datatype.strip_suffix("[]") {
+        return format!("sequence<{}>", idl_type(item));
+    }
+    match datatype {
+        "byteBuffer" => "sequence<uint8>".to_owned(),
+        // the other datatypes have the names of the IDL types
+        _ => datatype.to_owned(),
+    }
+}
+
+/// The name of the schema of a signal, which is the type name of its topic,
+/// e.g. `v3::vehicle::Speed`
+pub fn schema_name(info: &SignalInfo) -> String {
+    info.visit(TypeName)
+}
+
+/// The OMG IDL of the type of a signal, whose CDR encoding is the encoding of
+/// the samples on the topic. The members are the fields of the generated type
+/// in their order, with the unit and the limits from the specification as
+/// annotations. Enums are encoded as the index of their variant.
+pub fn idl_schema(info: &SignalInfo) -> String {
+    let name = schema_name(info);
+    let mut modules: Vec<&str> = name.split("::").collect();
+    let type_name = modules.pop().unwrap_or_default();
+    let root = modules.first().copied().unwrap_or_default();
+    let has_timestamp = info.kind != SignalKind::Attribute;
+    let keys = info.key_names();
+
+    let mut idl = format!(
+        "// {}: {}\n",
+        info.path,
+        info.description.replace('\n', " ")
+    );
+    // the types that all the signals share are in the first module, except
+    // for the time of ROS 2
+    let mut shared = Vec::new();
+    let timestamp_type = if cfg!(feature = "ros2") {
+        if has_timestamp {
+            idl.push_str(
+                "module builtin_interfaces {\n  module msg {\n    struct Time {\n      \
+                 int32 sec;\n      uint32 nanosec;\n    };\n  };\n};\n",
+            );
+        }
+        "builtin_interfaces::msg::Time".to_owned()
+    } else {
+        if has_timestamp {
+            shared.push("struct Timestamp {\n  uint64 sec;\n  uint32 nsec;\n};".to_owned());
+        }
+        format!("{}::Timestamp", root)
+    };
+    if has_timestamp && cfg!(feature = "signal-status") {
+        shared.push(
+            "enum SignalStatus { VALID, INVALID, NOT_AVAILABLE, SUBSTITUTED, OUT_OF_RANGE, TIMEOUT };"
+                .to_owned(),
+        );
+    }
+    if keys.contains(&"side") {
+        shared.push("enum Side { LEFT, RIGHT };".to_owned());
+    }
+    if keys.contains(&"position") {
+        shared.push("enum Position { FRONT, REAR };".to_owned());
+    }
+
+    let mut annotations = String::new();
+    if let Some(unit) = info.unit {
+        annotations.push_str(&format!("@unit(\"{}\") ", unit));
+    }
+    if let Some(min) = info.min {
+        annotations.push_str(&format!("@min({}) ", min));
+    }
+    if let Some(max) = info.max {
+        annotations.push_str(&format!("@max({}) ", max));
+    }
+    let mut members = vec![format!("{}{} value;", annotations, idl_type(info.datatype))];
+    if has_timestamp {
+        members.push(format!("{} timestamp;", timestamp_type));
+    }
+    if has_timestamp && cfg!(feature = "signal-status") {
+        members.push(format!("{}::SignalStatus status;", root));
+    }
+    if cfg!(feature = "vehicle-id") {
+        members.push("@key string vehicle_id;".to_owned());
+    }
+    for key in keys {
+        match key {
+            "side" => members.push(format!("@key {}::Side side;", root)),
+            "position" => members.push(format!("@key {}::Position position;", root)),
+            _ => members.push(format!("@key uint8 {};", key)),
+        }
+    }
+
+    let mut body = format!("struct {} {{\n", type_name);
+    for member in members {
+        body.push_str(&format!("  {}\n", member));
+    }
+    body.push_str("};");
+    for (depth, module) in modules.iter().enumerate().rev() {
+        let mut content = if depth == 0 {
+            shared.join("\n")
+        } else {
+            String::new()
+        };
+        if !content.is_empty() {
+            content.push('\n');
+        }
+        content.push_str(&body);
+        let indented: Vec<String> = content.lines().map(|l| format!("  {}", l)).collect();
+        body = format!("module {} {{\n{}\n}};", module, indented.join("\n"));
+    }
+    idl.push_str(&body);
+    idl.push('\n');
+    idl
+}
+
+/// Records samples into an MCAP file. Every signal gets a channel whose topic
+/// is the path of the signal, with the IDL of its type as the schema, see
+/// [idl_schema]. The messages are the samples in CDR, as they are sent on the
+/// topics.
+pub struct Recorder<W: Write> {
+    writer: McapWriter<W>,
+    channels: BTreeMap<&'static str, u16>,
+    readers: Vec<Box<dyn DynReader>>,
+    sequence: u32,
+}
+
+impl Recorder<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self, RecordError> {
+        Self::new(BufWriter::new(File::create(path)?))
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(out: W) -> Result<Self, RecordError> {
+        Ok(Self {
+            writer: McapWriter::new(out, PROFILE)?,
+            channels: BTreeMap::new(),
+            readers: Vec::new(),
+            sequence: 0,
+        })
+    }
+
+    /// Subscribe to the signals whose path matches the pattern, see
+    /// [catalog::glob]. `Vehicle.**` records all the signals.
+    pub fn subscribe(
+        &mut self,
+        participant: &DdsParticipant,
+        naming: &NamingPolicy,
+        pattern: &str,
+    ) -> Result<usize, DDSError> {
+        let mut count = 0;
+        for info in catalog::glob(pattern) {
+            if !self.readers.iter().any(|r| r.signal() == info.path) {
+                self.readers.push(info.subscribe(participant, naming)?);
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Write the received samples. Returns the number of samples.
+    pub fn poll(&mut self) -> Result<usize, RecordError> {
+        let mut samples = Vec::new();
+        for reader in &mut self.readers {
+            samples.extend(reader.take());
+        }
+        let log_time = now_nanos();
+        for sample in &samples {
+            self.write(sample, log_time)?;
+        }
+        Ok(samples.len())
+    }
+
+    /// Write a sample that was received at `log_time`, in nanoseconds since the epoch
+    pub fn write(&mut self, sample: &DynSample, log_time: u64) -> Result<(), RecordError> {
+        let channel_id = match self.channels.get(sample.signal) {
+            Some(id) => *id,
+            None => self.add_channel(sample.signal)?,
+        };
+        let info = catalog::find(sample.signal)
+            .ok_or_else(|| RecordError::UnknownSignal(sample.signal.to_owned()))?;
+        let publish_time = match &sample.source_timestamp {
+            Some(timestamp) => nanos(timestamp)?,
+            None => log_time,
+        };
+        let data = info.visit(Encode(sample))?;
+        let message = Message {
+            channel_id,
+            sequence: self.sequence,
+            log_time,
+            publish_time,
+            data,
+        };
+        self.sequence = self.sequence.wrapping_add(1);
+        self.writer.write_message(&message)?;
+        Ok(())
+    }
+
+    fn add_channel(&mut self, signal: &str) -> Result<u16, RecordError> {
+        let info =
+            catalog::find(signal).ok_or_else(|| RecordError::UnknownSignal(signal.to_owned()))?;
+        let mut metadata = BTreeMap::new();
+        metadata.insert("datatype".to_owned(), info.datatype.to_owned());
+        if let Some(unit) = info.unit {
+            metadata.insert("unit".to_owned(), unit.to_owned());
+        }
+        let id = self.writer.add_channel(
+            info.path,
+            "cdr",
+            &metadata,
+            &schema_name(info),
+            "omgidl",
+            idl_schema(info).as_bytes(),
+        )?;
+        self.channels.insert(info.path, id);
+        Ok(id)
+    }
+
+    /// Write the end of the file
+    pub fn finish(self) -> Result<W, RecordError> {
+        Ok(self.writer.finish()?)
+    }
+}
+
+/// A sample of a recording with the time it was recorded
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReplaySample {
+    /// Nanoseconds since the epoch
+    pub log_time: u64,
+    pub sample: DynSample,
+}
+
+/// Plays back a recording made by [Recorder]. The samples are published with
+/// the original timing, or faster or slower with [Replayer::with_speed]. The
+/// timestamps of the samples are set to the time of the replay.
+pub struct Replayer {
+    samples: Vec<ReplaySample>,
+    speed: f64,
+    looping: bool,
+}
+
+impl Replayer {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, RecordError> {
+        Self::from_reader(BufReader::new(File::open(path)?))
+    }
+
+    /// Read all the samples of a recording. Messages of signals that are not
+    /// in the catalog are skipped.
+    pub fn from_reader<R: Read>(input: R) -> Result<Self, RecordError> {
+        let mut samples = Vec::new();
+        let mut reader = McapReader::new(input)?;
+        while let Some(message) = reader.next() {
+            let message = message?;
+            let Some(info) = reader
+                .channel(message.channel_id)
+                .and_then(|channel| catalog::find(&channel.topic))
+            else {
+                continue;
+            };
+            samples.push(ReplaySample {
+                log_time: message.log_time,
+                sample: info.visit(Decode(&message.data))?,
+            });
+        }
+        Ok(Self::new(samples))
+    }
+
+    /// Replay samples that were not recorded, e.g. imported from a CSV file
+    /// with [crate::table::read_csv]. The samples are played at their
+    /// timestamps, samples without a timestamp at the time of the sample
+    /// before them, or of the first timestamp when no sample before them has
+    /// one. Samples without any timestamp are played at once. Returns an error
+    /// for a sample of a signal that is not in the catalog or a timestamp
+    /// after the year 2554.
+    pub fn from_samples(samples: impl IntoIterator<Item = DynSample>) -> Result<Self, RecordError> {
+        let samples: Vec<DynSample> = samples.into_iter().collect();
+        let mut log_time = match samples
+            .iter()
+            .find_map(|sample| sample.source_timestamp.as_ref())
+        {
+            Some(timestamp) => nanos(timestamp)?,
+            None => 0,
+        };
+        let mut replay = Vec::with_capacity(samples.len());
+        for sample in samples {
+            if catalog::find(sample.signal).is_none() {
+                return Err(RecordError::UnknownSignal(sample.signal.to_owned()));
+            }
+            if let Some(timestamp) = &sample.source_timestamp {
+                log_time = nanos(timestamp)?;
+            }
+            replay.push(ReplaySample { log_time, sample });
+        }
+        Ok(Self::new(replay))
+    }
+
+    fn new(mut samples: Vec<ReplaySample>) -> Self {
+        samples.sort_by_key(|s| s.log_time);
+        Self {
+            samples,
+            speed: 1.0,
+            looping: false,
+        }
+    }
+
+    /// Only keep the samples whose instance path matches the pattern, e.g.
+    /// `Vehicle.Cabin.Door.Row1.*.*` or `Vehicle.Chassis.**`
+    pub fn with_filter(mut self, pattern: &str) -> Self {
+        self.samples
+            .retain(|s| catalog::glob_match(pattern, &s.sample.path));
+        self
+    }
+
+    /// Play `speed` times faster than recorded. [f64::INFINITY] plays the
+    /// samples without waiting. The speed must be greater than zero.
+    pub fn with_speed(mut self, speed: f64) -> Result<Self, RecordError> {
+        if speed.is_nan() || speed <= 0.0 {
+            return Err(RecordError::Speed(speed));
+        }
+        self.speed = speed;
+        Ok(self)
+    }
+
+    /// Start again from the beginning at the end of the recording
+    pub fn with_loop(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    pub fn samples(&self) -> &[ReplaySample] {
+        &self.samples
+    }
+
+    /// The time of a sample from the start of the replay
+    pub fn offset(&self, sample: &ReplaySample) -> Duration {
+        let first = self.samples.first().map_or(0, |s| s.log_time);
+        let recorded = Duration::from_nanos(sample.log_time.saturating_sub(first));
+        if self.speed.is_infinite() {
+            Duration::ZERO
+        } else {
+            recorded.div_f64(self.speed)
+        }
+    }
+
+    /// Publish the samples on their topics. Returns the number of published
+    /// samples, or never returns when looping. See [Replayer::run_until].
+    pub fn run(
+        &self,
+        participant: &DdsParticipant,
+        naming: &NamingPolicy,
+    ) -> Result<usize, RecordError> {
+        self.run_until(participant, naming, &AtomicBool::new(false))
+    }
+
+    /// Publish the samples on their topics until the end of the recording, or
+    /// until `stop` is set, e.g. by another thread. Returns the number of
+    /// published samples.
+    pub fn run_until(
+        &self,
+        participant: &DdsParticipant,
+        naming: &NamingPolicy,
+        stop: &AtomicBool,
+    ) -> Result<usize, RecordError> {
+        let mut writers: BTreeMap<&'static str, Box<dyn DynWriter>> = BTreeMap::new();
+        self.play(stop, |sample| {
+            let signal = sample.signal;
+            if !writers.contains_key(signal) {
+                let info = catalog::find(signal)
+                    .ok_or_else(|| RecordError::UnknownSignal(signal.to_owned()))?;
+                writers.insert(signal, info.publish(participant, naming)?);
+            }
+            writers.get_mut(signal).unwrap().write(sample)?;
+            Ok(())
+        })
+    }
+
+    // Pass the samples to `write` at their time
+    fn play(
+        &self,
+        stop: &AtomicBool,
+        mut write: impl FnMut(&DynSample) -> Result<(), RecordError>,
+    ) -> Result<usize, RecordError> {
+        let mut count = 0;
+        loop {
+            let start = Instant::now();
+            for replay in &self.samples {
+                let due = start + self.offset(replay);
+                loop {
+                    if stop.load(Ordering::Relaxed) {
+                        return Ok(count);
+                    }
+                    let now = Instant::now();
+                    if due <= now {
+                        break;
+                    }
+                    std::thread::sleep((due - now).min(STOP_POLL_INTERVAL));
+                }
+                let mut sample = replay.sample.clone();
+                if sample.source_timestamp.is_some() {
+                    sample.source_timestamp = Some(Timestamp::default());
+                }
+                write(&sample)?;
+                count += 1;
+            }
+            if !self.looping || self.samples.is_empty() {
+                return Ok(count);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::units::{KiloPascal, KilometrePerHour};
+    use crate::v3::vehicle::chassis::axle::wheel::tire::Pressure;
+    use crate::v3::vehicle::Speed;
+    use crate::v3::Side;
+    use cyclonedds_rs::TopicType;
+
+    #[test]
+    fn record_and_replay() {
+        let mut recorder = Recorder::new(Vec::new()).unwrap();
+        let speed = Speed::new(KilometrePerHour(50.0), None).unwrap();
+        recorder
+            .write(&DynSample::of(&speed), 1_000_000_000)
+            .unwrap();
+        for (i, side) in [Side::Left, Side::Right].into_iter().enumerate() {
+            let pressure = Pressure::new(KiloPascal(220), None, 1, side).unwrap();
+            recorder
+                .write(&DynSample::of(&pressure), 1_500_000_000 + i as u64)
+                .unwrap();
+        }
+        let speed = Speed::new(KilometrePerHour(52.0), None).unwrap();
+        recorder
+            .write(&DynSample::of(&speed), 3_000_000_000)
+            .unwrap();
+        let bytes = recorder.finish().unwrap();
+
+        // the messages are the samples as they are sent on the topics
+        let mut reader = McapReader::new(bytes.as_slice()).unwrap();
+        let message = reader.next().unwrap().unwrap();
+        let channel = reader.channel(message.channel_id).unwrap();
+        assert_eq!(channel.message_encoding, "cdr");
+        assert_eq!(channel.schema_encoding, "omgidl");
+        assert_eq!(channel.schema_name, Speed::typename().to_str().unwrap());
+        let speed: Speed = cdr::deserialize(&message.data).unwrap();
+        assert_eq!(speed.value, KilometrePerHour(50.0));
+
+        let replayer = Replayer::from_reader(bytes.as_slice()).unwrap();
+        assert_eq!(replayer.samples().len(), 4);
+        let last = &replayer.samples()[3];
+        assert_eq!(replayer.offset(last), Duration::from_secs(2));
+        let restored: Speed = last.sample.to_sample().unwrap();
+        assert_eq!(restored.value, KilometrePerHour(52.0));
+
+        let replayer = replayer.with_speed(4.0).unwrap();
+        assert_eq!(
+            replayer.offset(&replayer.samples()[3]),
+            Duration::from_millis(500)
+        );
+        let replayer = replayer.with_filter("Vehicle.Chassis.Axle.Row1.Wheel.Left.**");
+        assert_eq!(replayer.samples().len(), 1);
+        assert_eq!(
+            replayer.samples()[0].sample.path,
+            "Vehicle.Chassis.Axle.Row1.Wheel.Left.Tire.Pressure"
+        );
+    }
+
+    #[test]
+    fn samples_without_timestamp() {
+        let speed = |value, timestamp| {
+            let mut sample = DynSample::of(&Speed::new(KilometrePerHour(value), None).unwrap());
+            sample.source_timestamp = timestamp;
+            sample
+        };
+        let at = |sec| Some(Timestamp { sec, nsec: 0 });
+        let replayer = Replayer::from_samples([
+            speed(10.0, None),
+            speed(11.0, at(100)),
+            speed(12.0, None),
+            speed(13.0, at(102)),
+        ])
+        .unwrap();
+        let offsets: Vec<Duration> = replayer
+            .samples()
+            .iter()
+            .map(|s| replayer.offset(s))
+            .collect();
+        assert_eq!(offsets, [0, 0, 0, 2].map(Duration::from_secs));
+    }
+
+    #[test]
+    fn record_invalid_samples() {
+        let mut recorder = Recorder::new(Vec::new()).unwrap();
+        let mut sample = DynSample::of(&Speed::new(KilometrePerHour(10.0), None).unwrap());
+        sample.source_timestamp = Some(Timestamp {
+            sec: u64::MAX / 1_000_000_000 + 1,
+            nsec: 0,
+        });
+        assert!(matches!(
+            recorder.write(&sample, 0),
+            Err(RecordError::Timestamp(_))
+        ));
+        sample.source_timestamp = None;
+        sample.path = "Vehicle.Cabin.Door.Row1.Left.IsOpen".to_owned();
+        assert!(matches!(
+            recorder.write(&sample, 0),
+            Err(RecordError::InvalidSample(_))
+        ));
+        sample.signal = "Vehicle.NoSuchSignal";
+        assert!(matches!(
+            recorder.write(&sample, 0),
+            Err(RecordError::UnknownSignal(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_samples() {
+        let mut sample = DynSample::of(&Speed::new(KilometrePerHour(10.0), None).unwrap());
+        sample.source_timestamp = Some(Timestamp {
+            sec: u64::MAX / 1_000_000_000 + 1,
+            nsec: 0,
+        });
+        assert!(matches!(
+            Replayer::from_samples([sample.clone()]),
+            Err(RecordError::Timestamp(_))
+        ));
+        sample.source_timestamp = None;
+        sample.signal = "Vehicle.NoSuchSignal";
+        assert!(matches!(
+            Replayer::from_samples([sample]),
+            Err(RecordError::UnknownSignal(_))
+        ));
+    }
+
+    #[test]
+    fn stop_looping_replay() {
+        let speed = DynSample::of(&Speed::new(KilometrePerHour(10.0), None).unwrap());
+        let samples = [speed.clone(), speed].map(|sample| ReplaySample {
+            log_time: 0,
+            sample,
+        });
+        let replayer = Replayer::new(samples.into()).with_loop(true);
+        let stop = AtomicBool::new(false);
+        let count = replayer
+            .play(&stop, |_| {
+                stop.store(true, Ordering::Relaxed);
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(count, 1);
+        let replayer = replayer.with_loop(false);
+        let count = replayer.play(&AtomicBool::new(false), |_| Ok(())).unwrap();
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn invalid_speed() {
+        let replayer = Replayer::new(Vec::new());
+        assert!(matches!(
+            replayer.with_speed(0.0),
+            Err(RecordError::Speed(_))
+        ));
+        let replayer = Replayer::new(Vec::new());
+        assert!(replayer.with_speed(f64::NAN).is_err());
+        let replayer = Replayer::new(Vec::new());
+        assert!(replayer.with_speed(f64::INFINITY).is_ok());
+    }
+
+    #[test]
+    #[cfg(not(feature = "ros2"))]
+    fn schema_from_type() {
+        let info = catalog::find("Vehicle.Cabin.Door.Window.Position").unwrap();
+        assert_eq!(
+            schema_name(info),
+            "v3::vehicle::cabin::door::window::Position"
+        );
+        let idl = idl_schema(info);
+        assert!(idl.starts_with("// Vehicle.Cabin.Door.Window.Position: "));
+        assert!(idl.contains("\nmodule v3 {\n  struct Timestamp {\n"));
+        assert!(idl.contains("  enum Side { LEFT, RIGHT };\n  module vehicle {\n"));
+        assert!(idl.contains("        struct Position {\n"));
+        assert!(idl.contains("@unit(\"percent\") @min(0) @max(100) uint8 value;"));
+        assert!(idl.contains("v3::Timestamp timestamp;"));
+        assert!(idl.contains("@key uint8 row;\n") && idl.contains("@key v3::Side side;\n"));
+        assert!(idl.ends_with("};\n};\n"));
+    }
+
+    #[test]
+    #[cfg(feature = "ros2")]
+    fn ros2_schema_from_type() {
+        let info = catalog::find("Vehicle.Cabin.Door.Window.Position").unwrap();
+        assert_eq!(
+            schema_name(info),
+            "vss_msgs::msg::dds_::CabinDoorWindowPosition_"
+        );
+        let idl = idl_schema(info);
+        assert!(idl.contains("    struct Time {\n      int32 sec;\n      uint32 nanosec;\n"));
+        assert!(idl.contains("\nmodule vss_msgs {\n"));
+        assert!(idl.contains("  enum Side { LEFT, RIGHT };\n  module msg {\n"));
+        assert!(idl.contains("struct CabinDoorWindowPosition_ {\n"));
+        assert!(idl.contains("  builtin_interfaces::msg::Time timestamp;\n"));
+    }
+}
@@ -0,0 +1,258 @@
+// Check project root for LICENCE
+//! ROS 2 type and topic names for the generated topics. With the `ros2`
+//! feature the generated types are the ROS 2 messages of the `vss_msgs`
+//! package: their type names are the DDS names of the messages, their topic
+//! names are ROS 2 topics and their timestamps are `builtin_interfaces/Time`.
+
+use cyclonedds_rs::cdr::{self, CdrBe, Infinite};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::v3::{Timestamp, VssSignal};
+
+/// The ROS 2 package of the messages that build.rs generates
+pub const PACKAGE: &str = "vss_msgs";
+
+/// The ROS 2 message and topic of a generated type. Implemented by every
+/// generated type with the `ros2` feature.
+pub trait RosMessage: VssSignal {
+    /// The name of the message, e.g. `CabinDoorWindowPosition`
+    const MESSAGE: &'static str;
+    /// The DDS topic name of the ROS 2 topic, e.g.
+    /// `rt/vss/vehicle/cabin/door/window/position`
+    const TOPIC: &'static str;
+}
+
+/// The `builtin_interfaces/Time` message of ROS 2
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Time {
+    /// seconds since epoch
+    pub sec: i32,
+    /// nanoseconds
+    pub nanosec: u32,
+}
+
+impl TryFrom<&Timestamp> for Time {
+    type Error = std::num::TryFromIntError;
+
+    fn try_from(timestamp: &Timestamp) -> Result<Self, Self::Error> {
+        Ok(Time {
+            sec: i32::try_from(timestamp.sec)?,
+            nanosec: timestamp.nsec,
+        })
+    }
+}
+
+impl TryFrom<Time> for Timestamp {
+    type Error = std::num::TryFromIntError;
+
+    fn try_from(time: Time) -> Result<Self, Self::Error> {
+        Ok(Timestamp {
+            sec: u64::try_from(time.sec)?,
+            nsec: time.nanosec,
+        })
+    }
+}
+
+/// The encoding of the timestamps of the generated types as
+/// `builtin_interfaces/Time`. A sample whose timestamp is after 2038 can't be
+/// written, and a received message whose time is before the epoch is dropped.
+pub mod time {
+    use serde::{de, ser, Deserialize, Deserializer, Serialize, Serializer};
+
+    use super::Time;
+    use crate::v3::Timestamp;
+
+    pub fn serialize<S: Serializer>(
+        timestamp: &Timestamp,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        Time::try_from(timestamp)
+            .map_err(|_| ser::Error::custom("timestamp does not fit builtin_interfaces/Time"))?
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Timestamp, D::Error> {
+        Timestamp::try_from(Time::deserialize(deserializer)?)
+            .map_err(|_| de::Error::custom("time before the epoch"))
+    }
+}
+
+/// The DDS type name of the ROS 2 message of `T`, e.g.
+/// `vss_msgs::msg::dds_::Speed_`
+pub fn type_name<T: RosMessage>() -> String {
+    format!("{}::msg::dds_::{}_", PACKAGE, T::MESSAGE)
+}
+
+// A token of a ROS 2 name has only letters, digits and underscores and does
+// not start with a digit
+fn ros_token(token: &str) -> String {
+    let mut name: String = token
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// The DDS topic name of `T`. A prefix is inserted as a ROS 2 namespace, e.g.
+/// `rt/<prefix>/vss/...`, with the characters that are not valid in a ROS 2
+/// name replaced by `_` and a `_` before the tokens that start with a digit:
+/// `fleet/1HGCM82633A004352` becomes `fleet/_1HGCM82633A004352`.
+pub fn topic_name<T: RosMessage>(maybe_prefix: Option<&str>) -> String {
+    let namespace: Vec<String> = maybe_prefix
+        .unwrap_or_default()
+        .split('/')
+        .filter(|token| !token.is_empty())
+        .map(ros_token)
+        .collect();
+    if namespace.is_empty() {
+        return T::TOPIC.to_owned();
+    }
+    format!(
+        "rt/{}/{}",
+        namespace.join("/"),
+        T::TOPIC.trim_start_matches("rt/")
+    )
+}
+
+/// Whether the topic of `T` has keys, for the TopicType implementation of the
+/// generated types
+pub fn has_key<T: VssSignal>() -> bool {
+    cfg!(feature = "vehicle-id") || std::mem::size_of::<T::Key>() > 0
+}
+
+/// The CDR encoding of the keys of a sample, for the TopicType implementation
+/// of the generated types. The keys are the last fields of the types, so this
+/// is the encoding of the key fields in their order.
+pub fn key_cdr<T: VssSignal>(sample: &T) -> Vec<u8> {
+    #[cfg(feature = "vehicle-id")]
+    let key = (sample.vehicle_id(), sample.key());
+    #[cfg(not(feature = "vehicle-id"))]
+    let key = sample.key();
+    cdr::serialize::<_, _, CdrBe>(&key, Infinite).expect("Unable to serialize key")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::naming::NamingPolicy;
+    use crate::v3::units::Percent;
+    use crate::v3::vehicle::cabin::door::{shade, window};
+    use crate::v3::vehicle::Speed;
+    use crate::v3::Side;
+    use cyclonedds_rs::cdr::CdrLe;
+    use cyclonedds_rs::TopicType;
+
+    #[test]
+    fn names() {
+        assert_eq!(type_name::<Speed>(), "vss_msgs::msg::dds_::Speed_");
+        assert_eq!(
+            Speed::typename().to_str().unwrap(),
+            "vss_msgs::msg::dds_::Speed_"
+        );
+        assert_eq!(
+            NamingPolicy::new().topic_name::<Speed>(),
+            "rt/vss/vehicle/speed"
+        );
+        assert_eq!(
+            NamingPolicy::fleet("WVW123").topic_name::<Speed>(),
+            "rt/fleet/WVW123/vss/vehicle/speed"
+        );
+        assert_eq!(window::IsOpen::MESSAGE, "CabinDoorWindowIsOpen");
+        assert_eq!(
+            window::IsOpen::TOPIC,
+            "rt/vss/vehicle/cabin/door/window/is_open"
+        );
+        assert_eq!(
+            shade::PositionTarget::MESSAGE,
+            "CabinDoorShadePositionTarget"
+        );
+        assert_eq!(
+            shade::PositionTarget::topic_name(None),
+            "rt/vss/vehicle/cabin/door/shade/position_target"
+        );
+    }
+
+    #[test]
+    fn namespaces() {
+        // a VIN that starts with a digit is not a valid ROS 2 name
+        assert_eq!(
+            NamingPolicy::fleet("1HGCM82633A004352").topic_name::<Speed>(),
+            "rt/fleet/_1HGCM82633A004352/vss/vehicle/speed"
+        );
+        assert_eq!(
+            Speed::topic_name(Some("/site-3/2nd floor/")),
+            "rt/site_3/_2nd_floor/vss/vehicle/speed"
+        );
+        assert_eq!(Speed::topic_name(Some("")), "rt/vss/vehicle/speed");
+    }
+
+    fn encode<T: serde::Serialize>(sample: &T) -> Vec<u8> {
+        cdr::serialize::<_, _, CdrLe>(sample, Infinite).unwrap()
+    }
+
+    fn decode<T: serde::de::DeserializeOwned>(encoded: &[u8]) -> Option<T> {
+        cdr::deserialize_from::<_, T, _>(encoded, Infinite).ok()
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "signal-status", feature = "vehicle-id")))]
+    fn encoding() {
+        use crate::v3::units::KilometrePerHour;
+
+        let timestamp = Timestamp {
+            sec: 1_700_000_000,
+            nsec: 5,
+        };
+        let time = Time {
+            sec: 1_700_000_000,
+            nanosec: 5,
+        };
+        let speed = Speed::new(KilometrePerHour(12.5), Some(timestamp.clone())).unwrap();
+        assert_eq!(encode(&speed), encode(&(12.5f32, time)));
+        // the keys are the last fields, the side is a uint32 with the index
+        // of the variant
+        let position = window::Position::new(Percent(40), Some(timestamp), 2, Side::Right).unwrap();
+        assert_eq!(encode(&position), encode(&(40u8, time, 2u8, 1u32)));
+        assert_eq!(
+            position.key_cdr(),
+            cdr::serialize::<_, _, CdrBe>(&(2u8, 1u32), Infinite).unwrap()
+        );
+        assert!(window::Position::has_key());
+        assert!(!Speed::has_key());
+        assert!(!Speed::force_md5_keyhash());
+    }
+
+    #[test]
+    fn round_trip() {
+        let timestamp = Timestamp {
+            sec: 1_700_000_000,
+            nsec: 5,
+        };
+        let position = window::Position::new(Percent(40), Some(timestamp), 2, Side::Right).unwrap();
+        let decoded: window::Position = decode(&encode(&position)).unwrap();
+        assert_eq!(decoded.timestamp, position.timestamp);
+        assert_eq!(decoded.key(), position.key());
+
+        // a time after 2038 does not fit builtin_interfaces/Time
+        let late = Timestamp {
+            sec: 1 << 32,
+            nsec: 0,
+        };
+        let position = window::Position::new(Percent(40), Some(late), 2, Side::Right).unwrap();
+        assert!(cdr::serialize::<_, _, CdrLe>(&position, Infinite).is_err());
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "signal-status", feature = "vehicle-id")))]
+    fn invalid_messages() {
+        let before_epoch = Time {
+            sec: -1,
+            nanosec: 0,
+        };
+        let encoded = encode(&(40u8, before_epoch, 2u8, 1u32));
+        assert!(decode::<window::Position>(&encoded).is_none());
+    }
+}
@@ -0,0 +1,367 @@
+// Check project root for LICENCE
+//! Plausibility and consistency rules across signals
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use cyclonedds_rs::{DDSError, DdsParticipant};
+use serde_derive::{Deserialize, Serialize};
+
+use crate::catalog::{self, DynReader, DynSample};
+use crate::expr::{Condition, ExprError, History};
+use crate::naming::NamingPolicy;
+use crate::v3::Timestamp;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// A condition that must hold for the signals of a vehicle, see [Condition].
+/// Rates like `rate(Vehicle.Powertrain.TractionBattery.StateOfCharge.Current)`
+/// are computed from the timestamps of consecutive samples.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub name: String,
+    pub check: Condition,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Rule {
+    /// Parse the check and verify that its paths exist and its units agree
+    pub fn new(name: &str, check: &str, severity: Severity) -> Result<Self, ExprError> {
+        let condition = Condition::parse(check)?;
+        condition.check_units()?;
+        Ok(Self {
+            name: name.to_owned(),
+            check: condition,
+            severity,
+            message: check.to_owned(),
+        })
+    }
+
+    /// The message of the events, the check is used by default
+    pub fn with_message(mut self, message: &str) -> Self {
+        self.message = message.to_owned();
+        self
+    }
+}
+
+/// The description of a rule in a configuration file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuleConfig {
+    pub name: String,
+    pub check: String,
+    pub severity: Severity,
+    pub message: Option<String>,
+}
+
+/// The rules of a configuration file, e.g. in TOML:
+///
+/// ```toml
+/// [[rule]]
+/// name = "moving-in-park"
+/// check = "Vehicle.Speed > 0 implies Vehicle.Powertrain.Transmission.SelectedGear != 126"
+/// severity = "error"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RulesConfig {
+    #[serde(default)]
+    pub rule: Vec<RuleConfig>,
+}
+
+#[derive(Debug)]
+pub enum RuleError {
+    /// The check of a rule is invalid
+    Check {
+        rule: String,
+        error: ExprError,
+    },
+    #[cfg(feature = "toml")]
+    Toml(toml::de::Error),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for RuleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleError::Check { rule, error } => {
+                write!(f, "invalid check of rule {}: {}", rule, error)
+            }
+            #[cfg(feature = "toml")]
+            RuleError::Toml(e) => write!(f, "invalid rules file: {}", e),
+            RuleError::Io(e) => write!(f, "unable to read the rules: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for RuleError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RuleError::Check { error, .. } => Some(error),
+            #[cfg(feature = "toml")]
+            RuleError::Toml(e) => Some(e),
+            RuleError::Io(e) => Some(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for RuleError {
+    fn from(e: std::io::Error) -> Self {
+        RuleError::Io(e)
+    }
+}
+
+/// Reported when a rule is violated and when it holds again
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RuleEvent {
+    pub rule: String,
+    pub severity: Severity,
+    pub message: String,
+    /// True when the rule is violated, false when it holds again
+    pub violated: bool,
+    /// The timestamp of the sample that changed the outcome of the rule
+    pub timestamp: Timestamp,
+    /// The instance path of that sample
+    pub trigger: String,
+    /// The values of the instances the rule depends on
+    pub values: BTreeMap<String, f64>,
+}
+
+/// Evaluates rules over samples of signals, from DDS or from a recording. An
+/// event is reported when the outcome of a rule changes, rules that can't be
+/// evaluated because a value is missing are skipped.
+pub struct RuleEngine {
+    rules: Vec<Rule>,
+    violated: Vec<bool>,
+    values: History,
+    readers: Vec<Box<dyn DynReader>>,
+}
+
+impl RuleEngine {
+    pub fn new(rules: Vec<Rule>) -> Self {
+        Self {
+            violated: vec![false; rules.len()],
+            rules,
+            values: History::new(),
+            readers: Vec::new(),
+        }
+    }
+
+    pub fn from_config(config: &RulesConfig) -> Result<Self, RuleError> {
+        let mut rules = Vec::new();
+        for r in &config.rule {
+            let rule =
+                Rule::new(&r.name, &r.check, r.severity).map_err(|error| RuleError::Check {
+                    rule: r.name.clone(),
+                    error,
+                })?;
+            rules.push(match &r.message {
+                Some(message) => rule.with_message(message),
+                None => rule,
+            });
+        }
+        Ok(Self::new(rules))
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn from_toml(text: &str) -> Result<Self, RuleError> {
+        let config: RulesConfig = toml::from_str(text).map_err(RuleError::Toml)?;
+        Self::from_config(&config)
+    }
+
+    /// Load the rules from a TOML file
+    #[cfg(feature = "toml")]
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, RuleError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Apply a sample and evaluate the rules that depend on it. Samples
+    /// without a timestamp are taken at the current time.
+    pub fn process(&mut self, sample: &DynSample) -> Vec<RuleEvent> {
+        let value = match sample.value.as_f64() {
+            Some(value) => value,
+            None => return Vec::new(),
+        };
+        let timestamp = sample.source_timestamp.clone().unwrap_or_default();
+        let time = timestamp.sec as f64 + timestamp.nsec as f64 * 1e-9;
+        self.values.update(&sample.path, value, time);
+
+        let mut events = Vec::new();
+        for (rule, violated) in self.rules.iter().zip(&mut self.violated) {
+            let paths = rule.check.paths();
+            if !paths.iter().any(|p| catalog::glob_match(p, &sample.path)) {
+                continue;
+            }
+            let now_violated = match rule.check.eval(&self.values) {
+                Some(holds) => !holds,
+                None => continue,
+            };
+            if now_violated == *violated {
+                continue;
+            }
+            *violated = now_violated;
+            events.push(RuleEvent {
+                rule: rule.name.clone(),
+                severity: rule.severity,
+                message: rule.message.clone(),
+                violated: now_violated,
+                timestamp: timestamp.clone(),
+                trigger: sample.path.clone(),
+                values: paths
+                    .iter()
+                    .flat_map(|p| self.values.matching(p))
+                    .map(|(path, value)| (path.clone(), value))
+                    .collect(),
+            });
+        }
+        events
+    }
+
+    /// The rules that are violated at the moment
+    pub fn violated(&self) -> impl Iterator<Item = &Rule> {
+        self.rules
+            .iter()
+            .zip(&self.violated)
+            .filter(|(_, v)| **v)
+            .map(|(r, _)| r)
+    }
+
+    /// Subscribe to all the signals the rules depend on, see [RuleEngine::poll]
+    pub fn subscribe(
+        &mut self,
+        participant: &DdsParticipant,
+        naming: &NamingPolicy,
+    ) -> Result<(), DDSError> {
+        let mut signals: Vec<&'static catalog::SignalInfo> = Vec::new();
+        for rule in &self.rules {
+            for path in rule.check.paths() {
+                for info in catalog::glob_instances(path) {
+                    if !signals.iter().any(|s| s.path == info.path) {
+                        signals.push(info);
+                    }
+                }
+            }
+        }
+        for info in signals {
+            if !self.readers.iter().any(|r| r.signal() == info.path) {
+                self.readers.push(info.subscribe(participant, naming)?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Take the received samples and evaluate the rules
+    pub fn poll(&mut self) -> Vec<RuleEvent> {
+        let mut samples = Vec::new();
+        for reader in &mut self.readers {
+            samples.extend(reader.take());
+        }
+        samples
+            .iter()
+            .flat_map(|sample| self.process(sample))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+
+    fn sample(path: &str, value: Value, sec: u64, nsec: u32) -> DynSample {
+        DynSample {
+            signal: "",
+            path: path.to_owned(),
+            value,
+            source_timestamp: Some(Timestamp { sec, nsec }),
+        }
+    }
+
+    #[test]
+    fn violations() {
+        let config = RulesConfig {
+            rule: vec![
+                RuleConfig {
+                    name: "moving-in-park".to_owned(),
+                    check: "Vehicle.Speed > 0 implies \
+                            Vehicle.Powertrain.Transmission.SelectedGear != 126"
+                        .to_owned(),
+                    severity: Severity::Error,
+                    message: None,
+                },
+                RuleConfig {
+                    name: "soc-jump".to_owned(),
+                    check:
+                        "abs(rate(Vehicle.Powertrain.TractionBattery.StateOfCharge.Current)) <= 5"
+                            .to_owned(),
+                    severity: Severity::Warning,
+                    message: Some("state of charge jumped".to_owned()),
+                },
+            ],
+        };
+        let mut engine = RuleEngine::from_config(&config).unwrap();
+
+        let gear = "Vehicle.Powertrain.Transmission.SelectedGear";
+        assert!(engine
+            .process(&sample(gear, Value::Int(126), 10, 0))
+            .is_empty());
+        let events = engine.process(&sample("Vehicle.Speed", Value::Float(3.0), 10, 0));
+        assert_eq!(events.len(), 1);
+        assert!(events[0].violated);
+        assert_eq!(events[0].values.get(gear), Some(&126.0));
+        // still violated, no new event
+        assert!(engine
+            .process(&sample("Vehicle.Speed", Value::Float(4.0), 11, 0))
+            .is_empty());
+        let events = engine.process(&sample(gear, Value::Int(1), 12, 0));
+        assert!(!events[0].violated);
+
+        let soc = "Vehicle.Powertrain.TractionBattery.StateOfCharge.Current";
+        assert!(engine
+            .process(&sample(soc, Value::Float(50.0), 10, 0))
+            .is_empty());
+        assert!(engine
+            .process(&sample(soc, Value::Float(51.0), 11, 0))
+            .is_empty());
+        let events = engine.process(&sample(soc, Value::Float(55.0), 11, 500_000_000));
+        assert_eq!(events[0].message, "state of charge jumped");
+        assert_eq!(engine.violated().count(), 1);
+
+        let bad = RulesConfig {
+            rule: vec![RuleConfig {
+                name: "units".to_owned(),
+                check: "Vehicle.Speed > Vehicle.Powertrain.FuelSystem.Level".to_owned(),
+                severity: Severity::Info,
+                message: None,
+            }],
+        };
+        assert!(matches!(
+            RuleEngine::from_config(&bad),
+            Err(RuleError::Check { .. })
+        ));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn toml_config() {
+        let engine = RuleEngine::from_toml(
+            r#"
+            [[rule]]
+            name = "door-open-while-driving"
+            check = "not (max(Vehicle.Cabin.Door.Row*.*.IsOpen) and Vehicle.Speed > 5)"
+            severity = "warning"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(engine.rules()[0].severity, Severity::Warning);
+    }
+}
@@ -0,0 +1,141 @@
+// Check project root for LICENCE
+//! Traits implemented by the generated signal types
+
+use cyclonedds_rs::{DdsQos, TopicType};
+use serde_derive::{Deserialize, Serialize};
+
+/// The kind of a signal as defined in the specification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SignalKind {
+    /// A value that is measured by the vehicle and published periodically or on change.
+    Sensor,
+    /// A value that can be changed by a request. The topic carries the current value.
+    Actuator,
+    /// A static value like the VIN that does not change during the lifetime of the vehicle.
+    Attribute,
+}
+
+/// The quality of the value carried by a sensor or the current value of an actuator.
+/// This is only part of the topics when the `signal-status` feature is enabled.
+#[repr(C)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SignalStatus {
+    /// The value was measured and is within the limits of the specification.
+    Valid = 1,
+    /// The value could not be determined correctly.
+    Invalid = 2,
+    /// The sensor is not available. The value has no meaning.
+    #[default]
+    NotAvailable = 3,
+    /// The value is a substitute, for example a default or an estimate.
+    Substituted = 4,
+    /// The measured value is outside the limits of the specification.
+    OutOfRange = 5,
+    /// The value was not updated in time.
+    Timeout = 6,
+}
+
+/// Common interface implemented by every generated topic. This allows code
+/// to be written once for any signal in the specification.
+pub trait VssSignal: TopicType + Default + Clone + Send + Sync + 'static {
+    /// The type of the value, including the unit if the specification has one.
+    type Value: Clone + PartialEq + serde::Serialize + serde::de::DeserializeOwned;
+    /// The keys of the topic. This is `()` for topics without keys, the key type
+    /// for topics with a single key and a tuple for topics with multiple keys.
+    type Key: Clone + PartialEq + serde::Serialize + serde::de::DeserializeOwned;
+
+    /// The path of the signal in the specification. The branches that are
+    /// converted into keys are not part of the path.
+    const PATH: &'static str;
+    /// Sensor, actuator or attribute
+    const KIND: SignalKind;
+    /// The unit as written in the specification.
+    const UNIT: Option<&'static str>;
+    /// The description from the specification.
+    const DESCRIPTION: &'static str;
+    /// The lower limit from the specification.
+    const MIN: Option<f64>;
+    /// The upper limit from the specification.
+    const MAX: Option<f64>;
+    /// The datatype as written in the specification, e.g. `uint8` or `string[]`.
+    const DATATYPE: &'static str;
+    /// The path with the key branches replaced by the name of the key in braces,
+    /// e.g. `Vehicle.Cabin.Door.Row{row}.{side}.Window.Position`.
+    /// See [crate::catalog::instance_path].
+    const PATH_TEMPLATE: &'static str;
+
+    /// The value carried by this sample
+    fn signal_value(&self) -> &Self::Value;
+    /// The keys of this sample
+    fn key(&self) -> Self::Key;
+    /// The timestamp of this sample. Attributes don't have a timestamp.
+    fn sample_timestamp(&self) -> Option<&crate::v3::Timestamp>;
+    /// Check the value against the limits in the specification.
+    fn in_bounds(value: &Self::Value) -> bool;
+    /// All the key combinations that are defined in the specification.
+    fn key_instances() -> Vec<Self::Key>;
+    /// The value as a number. Booleans are converted to 0 or 1. Returns None
+    /// for strings and arrays.
+    fn value_as_f64(value: &Self::Value) -> Option<f64>;
+    /// Convert the value to a value whose type is only known at runtime.
+    fn value_to_dyn(value: &Self::Value) -> crate::value::Value;
+    /// Convert a value whose type is only known at runtime. Returns None if the
+    /// value does not fit the datatype. The bounds are not checked.
+    fn value_from_dyn(value: &crate::value::Value) -> Option<Self::Value>;
+    /// The keys by name
+    fn key_values(key: &Self::Key) -> Vec<(&'static str, crate::value::KeyValue)>;
+    /// The keys from their names. Returns None if a key is missing or has the
+    /// wrong type.
+    fn key_from_values(values: &[(&str, crate::value::KeyValue)]) -> Option<Self::Key>;
+    /// The quality of the value. Topics that don't carry a status are always valid.
+    #[cfg(feature = "signal-status")]
+    fn signal_status(&self) -> SignalStatus {
+        SignalStatus::Valid
+    }
+    /// Set the quality of the value. Does nothing for topics that don't carry a status.
+    #[cfg(feature = "signal-status")]
+    fn set_signal_status(&mut self, _status: SignalStatus) {}
+    /// The identity of the vehicle that published the sample. This key is only
+    /// part of the topics when the `vehicle-id` feature is enabled.
+    #[cfg(feature = "vehicle-id")]
+    fn vehicle_id(&self) -> &str;
+    #[cfg(feature = "vehicle-id")]
+    fn set_vehicle_id(&mut self, vehicle_id: &str);
+    /// The QoS preset for this signal. See [crate::qos::qos_for].
+    fn default_qos() -> DdsQos {
+        crate::qos::qos_for(Self::KIND)
+    }
+    /// Create a sample. Returns None if the value is out of bounds.
+    /// The timestamp is ignored for attributes.
+    fn from_parts(
+        value: Self::Value,
+        timestamp: Option<crate::v3::Timestamp>,
+        key: Self::Key,
+    ) -> Option<Self>;
+}
+
+/// Implemented by the generated actuator types. The actuator topic carries the
+/// current value of the actuator. Requests to change the value are published
+/// on the `Target` topic.
+pub trait Actuator: VssSignal {
+    /// The topic used to request a new value for this actuator.
+    type Target: ActuatorTarget + VssSignal<Value = Self::Value, Key = Self::Key>;
+
+    /// Create a request to move the given instance of the actuator to `value`.
+    /// Returns None if the value is out of bounds.
+    fn request(value: Self::Value, key: Self::Key) -> Option<Self::Target> {
+        Self::Target::from_parts(value, None, key)
+    }
+
+    /// Return true if this current value has reached the requested target.
+    /// The request must be for the same instance.
+    fn has_reached(&self, target: &Self::Target) -> bool {
+        self.key() == target.key() && self.signal_value() == target.signal_value()
+    }
+}
+
+/// Implemented by the set-request topics generated for each actuator.
+pub trait ActuatorTarget: VssSignal {
+    /// The actuator this request is for.
+    type Actuator: Actuator;
+}
@@ -0,0 +1,905 @@
+// Check project root for LICENCE
+//! SOME/IP serialization of the topics, and the IDs of their SOME/IP fields
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::OnceLock;
+
+use serde::de::{self, DeserializeSeed, IntoDeserializer, Visitor};
+use serde::ser::{self, Serialize};
+use serde::Deserialize;
+
+use crate::catalog::SignalInfo;
+use crate::v3::{Position, Side, SignalKind, SignalStatus, VssSignal};
+
+const BOM: [u8; 3] = [0xef, 0xbb, 0xbf];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SomeIpError {
+    /// The payload ends before the value
+    Eof,
+    /// A length field that doesn't fit in 32 bits or in the payload
+    Length,
+    /// A string that is not valid UTF-8
+    Utf8,
+    /// An enum value that is not a variant of the enum
+    Discriminant {
+        name: &'static str,
+        value: u8,
+    },
+    Message(String),
+}
+
+impl fmt::Display for SomeIpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SomeIpError::Eof => write!(f, "the payload ends before the value"),
+            SomeIpError::Length => write!(f, "invalid length field"),
+            SomeIpError::Utf8 => write!(f, "the string is not valid UTF-8"),
+            SomeIpError::Discriminant { name, value } => {
+                write!(f, "{} is not a variant of {}", value, name)
+            }
+            SomeIpError::Message(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for SomeIpError {}
+
+impl ser::Error for SomeIpError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SomeIpError::Message(msg.to_string())
+    }
+}
+
+impl de::Error for SomeIpError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SomeIpError::Message(msg.to_string())
+    }
+}
+
+// The underlying integers of the enums of the topics, serde only knows the
+// index of a variant. Other enums use the index.
+fn discriminants(name: &str) -> Option<Vec<u8>> {
+    use SignalStatus::*;
+    Some(match name {
+        "Side" => vec![Side::Left as u8, Side::Right as u8],
+        "Position" => vec![Position::Front as u8, Position::Rear as u8],
+        "SignalStatus" => [
+            Valid,
+            Invalid,
+            NotAvailable,
+            Substituted,
+            OutOfRange,
+            Timeout,
+        ]
+        .iter()
+        .map(|s| *s as u8)
+        .collect(),
+        _ => return None,
+    })
+}
+
+fn discriminant(name: &'static str, index: u32) -> Result<u8, SomeIpError> {
+    match discriminants(name) {
+        Some(values) => values.get(index as usize).copied(),
+        None => u8::try_from(index).ok(),
+    }
+    .ok_or(SomeIpError::Message(format!(
+        "variant {} of {}",
+        index, name
+    )))
+}
+
+fn variant_index(name: &'static str, value: u8) -> Result<u32, SomeIpError> {
+    match discriminants(name) {
+        Some(values) => values
+            .iter()
+            .position(|v| *v == value)
+            .map(|i| i as u32)
+            .ok_or(SomeIpError::Discriminant { name, value }),
+        None => Ok(value as u32),
+    }
+}
+
+/// The SOME/IP payload of a value, e.g. a topic. Numbers are big-endian,
+/// booleans and enums are one byte, strings are UTF-8 with a byte order mark
+/// and a terminating zero, and strings, sequences and maps have a 32 bit
+/// length field with the length in bytes. Structs and tuples have no length
+/// field. An option is a byte that is 1 when the value follows.
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, SomeIpError> {
+    let mut serializer = Serializer { output: Vec::new() };
+    value.serialize(&mut serializer)?;
+    Ok(serializer.output)
+}
+
+/// The value of a SOME/IP payload, see [to_bytes]. The bytes after the value
+/// are ignored, like the members added by a newer version of a type.
+pub fn from_bytes<'de, T: Deserialize<'de>>(bytes: &'de [u8]) -> Result<T, SomeIpError> {
+    T::deserialize(&mut Deserializer { input: bytes })
+}
+
+pub struct Serializer {
+    output: Vec<u8>,
+}
+
+/// A sequence or a map, the length field is written at the end
+pub struct LengthPrefixed<'a> {
+    serializer: &'a mut Serializer,
+    start: usize,
+}
+
+impl<'a> LengthPrefixed<'a> {
+    fn new(serializer: &'a mut Serializer) -> Self {
+        serializer.output.extend_from_slice(&[0; 4]);
+        let start = serializer.output.len();
+        Self { serializer, start }
+    }
+
+    fn finish(self) -> Result<(), SomeIpError> {
+        let output = &mut self.serializer.output;
+        let length = u32::try_from(output.len() - self.start).map_err(|_| SomeIpError::Length)?;
+        output[self.start - 4..self.start].copy_from_slice(&length.to_be_bytes());
+        Ok(())
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut Serializer {
+    type Ok = ();
+    type Error = SomeIpError;
+    type SerializeSeq = LengthPrefixed<'a>;
+    type SerializeTuple = Self;
+    type SerializeTupleStruct = Self;
+    type SerializeTupleVariant = Self;
+    type SerializeMap = LengthPrefixed<'a>;
+    type SerializeStruct = Self;
+    type SerializeStructVariant = Self;
+
+    fn serialize_bool(self, v: bool) -> Result<(), SomeIpError> {
+        self.output.push(v as u8);
+        Ok(())
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<(), SomeIpError> {
+        self.output.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<(), SomeIpError> {
+        self.output.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<(), SomeIpError> {
+        self.output.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<(), SomeIpError> {
+        self.output.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), SomeIpError> {
+        self.output.push(v);
+        Ok(())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), SomeIpError> {
+        self.output.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), SomeIpError> {
+        self.output.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), SomeIpError> {
+        self.output.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<(), SomeIpError> {
+        self.output.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<(), SomeIpError> {
+        self.output.extend_from_slice(&v.to_be_bytes());
+        Ok(())
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), SomeIpError> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), SomeIpError> {
+        let length = u32::try_from(BOM.len() + v.len() + 1).map_err(|_| SomeIpError::Length)?;
+        self.output.extend_from_slice(&length.to_be_bytes());
+        self.output.extend_from_slice(&BOM);
+        self.output.extend_from_slice(v.as_bytes());
+        self.output.push(0);
+        Ok(())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), SomeIpError> {
+        let length = u32::try_from(v.len()).map_err(|_| SomeIpError::Length)?;
+        self.output.extend_from_slice(&length.to_be_bytes());
+        self.output.extend_from_slice(v);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), SomeIpError> {
+        self.output.push(0);
+        Ok(())
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), SomeIpError> {
+        self.output.push(1);
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), SomeIpError> {
+        Ok(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<(), SomeIpError> {
+        Ok(())
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        _variant: &'static str,
+    ) -> Result<(), SomeIpError> {
+        self.output.push(discriminant(name, index)?);
+        Ok(())
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<(), SomeIpError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        index: u32,
+        _variant: &'static str,
+        value: &T,
+    ) -> Result<(), SomeIpError> {
+        self.output.push(discriminant(name, index)?);
+        value.serialize(self)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<LengthPrefixed<'a>, SomeIpError> {
+        Ok(LengthPrefixed::new(self))
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self, SomeIpError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, _len: usize) -> Result<Self, SomeIpError> {
+        Ok(self)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, SomeIpError> {
+        self.output.push(discriminant(name, index)?);
+        Ok(self)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<LengthPrefixed<'a>, SomeIpError> {
+        Ok(LengthPrefixed::new(self))
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self, SomeIpError> {
+        Ok(self)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self, SomeIpError> {
+        self.output.push(discriminant(name, index)?);
+        Ok(self)
+    }
+}
+
+impl ser::SerializeSeq for LengthPrefixed<'_> {
+    type Ok = ();
+    type Error = SomeIpError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SomeIpError> {
+        value.serialize(&mut *self.serializer)
+    }
+
+    fn end(self) -> Result<(), SomeIpError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeMap for LengthPrefixed<'_> {
+    type Ok = ();
+    type Error = SomeIpError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SomeIpError> {
+        key.serialize(&mut *self.serializer)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SomeIpError> {
+        value.serialize(&mut *self.serializer)
+    }
+
+    fn end(self) -> Result<(), SomeIpError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for &mut Serializer {
+    type Ok = ();
+    type Error = SomeIpError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SomeIpError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SomeIpError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleStruct for &mut Serializer {
+    type Ok = ();
+    type Error = SomeIpError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SomeIpError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SomeIpError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeTupleVariant for &mut Serializer {
+    type Ok = ();
+    type Error = SomeIpError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SomeIpError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SomeIpError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStruct for &mut Serializer {
+    type Ok = ();
+    type Error = SomeIpError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), SomeIpError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SomeIpError> {
+        Ok(())
+    }
+}
+
+impl ser::SerializeStructVariant for &mut Serializer {
+    type Ok = ();
+    type Error = SomeIpError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _key: &'static str,
+        value: &T,
+    ) -> Result<(), SomeIpError> {
+        value.serialize(&mut **self)
+    }
+
+    fn end(self) -> Result<(), SomeIpError> {
+        Ok(())
+    }
+}
+
+pub struct Deserializer<'de> {
+    input: &'de [u8],
+}
+
+impl<'de> Deserializer<'de> {
+    fn take(&mut self, n: usize) -> Result<&'de [u8], SomeIpError> {
+        if self.input.len() < n {
+            return Err(SomeIpError::Eof);
+        }
+        let (bytes, rest) = self.input.split_at(n);
+        self.input = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], SomeIpError> {
+        Ok(self.take(N)?.try_into().expect("N bytes"))
+    }
+
+    // The bytes after a length field
+    fn prefixed(&mut self) -> Result<&'de [u8], SomeIpError> {
+        let length = u32::from_be_bytes(self.array()?) as usize;
+        self.take(length).map_err(|_| SomeIpError::Length)
+    }
+
+    fn string(&mut self) -> Result<&'de str, SomeIpError> {
+        let bytes = self.prefixed()?;
+        let bytes = bytes.strip_prefix(&BOM).unwrap_or(bytes);
+        let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+        std::str::from_utf8(bytes).map_err(|_| SomeIpError::Utf8)
+    }
+}
+
+// The elements of a sequence or a map, until the end of the length field
+struct Prefixed<'de> {
+    de: Deserializer<'de>,
+}
+
+impl<'de> de::SeqAccess<'de> for Prefixed<'de> {
+    type Error = SomeIpError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, SomeIpError> {
+        if self.de.input.is_empty() {
+            return Ok(None);
+        }
+        seed.deserialize(&mut self.de).map(Some)
+    }
+}
+
+impl<'de> de::MapAccess<'de> for Prefixed<'de> {
+    type Error = SomeIpError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, SomeIpError> {
+        if self.de.input.is_empty() {
+            return Ok(None);
+        }
+        seed.deserialize(&mut self.de).map(Some)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, SomeIpError> {
+        seed.deserialize(&mut self.de)
+    }
+}
+
+// The members of a struct or a tuple
+struct Fixed<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    remaining: usize,
+}
+
+impl<'de> de::SeqAccess<'de> for Fixed<'_, 'de> {
+    type Error = SomeIpError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, SomeIpError> {
+        if self.remaining == 0 {
+            return Ok(None);
+        }
+        self.remaining -= 1;
+        seed.deserialize(&mut *self.de).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.remaining)
+    }
+}
+
+struct Variant<'a, 'de> {
+    de: &'a mut Deserializer<'de>,
+    index: u32,
+}
+
+impl<'a, 'de> de::EnumAccess<'de> for Variant<'a, 'de> {
+    type Error = SomeIpError;
+    type Variant = Self;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self), SomeIpError> {
+        let index: de::value::U32Deserializer<SomeIpError> = self.index.into_deserializer();
+        Ok((seed.deserialize(index)?, self))
+    }
+}
+
+impl<'de> de::VariantAccess<'de> for Variant<'_, 'de> {
+    type Error = SomeIpError;
+
+    fn unit_variant(self) -> Result<(), SomeIpError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, SomeIpError> {
+        seed.deserialize(self.de)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SomeIpError> {
+        de::Deserializer::deserialize_tuple(self.de, len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SomeIpError> {
+        de::Deserializer::deserialize_tuple(self.de, fields.len(), visitor)
+    }
+}
+
+impl<'de> de::Deserializer<'de> for &mut Deserializer<'de> {
+    type Error = SomeIpError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, SomeIpError> {
+        Err(SomeIpError::Message(
+            "SOME/IP payloads are not self-describing".to_owned(),
+        ))
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        visitor.visit_bool(self.take(1)?[0] != 0)
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        visitor.visit_i8(i8::from_be_bytes(self.array()?))
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        visitor.visit_i16(i16::from_be_bytes(self.array()?))
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        visitor.visit_i32(i32::from_be_bytes(self.array()?))
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        visitor.visit_i64(i64::from_be_bytes(self.array()?))
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        visitor.visit_u8(self.take(1)?[0])
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        visitor.visit_u16(u16::from_be_bytes(self.array()?))
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        visitor.visit_u32(u32::from_be_bytes(self.array()?))
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        visitor.visit_u64(u64::from_be_bytes(self.array()?))
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        visitor.visit_f32(f32::from_be_bytes(self.array()?))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        visitor.visit_f64(f64::from_be_bytes(self.array()?))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        let mut chars = self.string()?.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(SomeIpError::Message("a string of one character".to_owned())),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        visitor.visit_borrowed_str(self.string()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        visitor.visit_borrowed_bytes(self.prefixed()?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        match self.take(1)?[0] {
+            0 => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SomeIpError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, SomeIpError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        let input = self.prefixed()?;
+        visitor.visit_seq(Prefixed {
+            de: Deserializer { input },
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SomeIpError> {
+        visitor.visit_seq(Fixed {
+            de: self,
+            remaining: len,
+        })
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, SomeIpError> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        let input = self.prefixed()?;
+        visitor.visit_map(Prefixed {
+            de: Deserializer { input },
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SomeIpError> {
+        self.deserialize_tuple(fields.len(), visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, SomeIpError> {
+        let index = variant_index(name, self.take(1)?[0])?;
+        visitor.visit_enum(Variant { de: self, index })
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, _visitor: V) -> Result<V::Value, SomeIpError> {
+        Err(SomeIpError::Message(
+            "SOME/IP payloads have no identifiers".to_owned(),
+        ))
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, SomeIpError> {
+        self.deserialize_any(visitor)
+    }
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+}
+
+/// The IDs of the SOME/IP field of a signal: the methods to get and set the
+/// value, and the event that notifies the changes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldIds {
+    pub getter: u16,
+    /// Only actuators have a setter
+    pub setter: Option<u16>,
+    pub notifier: u16,
+}
+
+// The index of the field of every signal. The index of a signal never
+// changes, a new signal of the specification is added with the next free
+// index and the indexes of removed signals are not reused.
+const FIELDS: &str = include_str!("../someip_fields.csv");
+
+// The indexes of the fields by path, and the paths by index
+struct Fields {
+    indexes: BTreeMap<&'static str, u16>,
+    paths: BTreeMap<u16, &'static str>,
+}
+
+static FIELD_TABLE: OnceLock<Fields> = OnceLock::new();
+
+fn fields() -> &'static Fields {
+    FIELD_TABLE.get_or_init(|| {
+        let mut indexes = BTreeMap::new();
+        let mut paths = BTreeMap::new();
+        for line in FIELDS.lines().skip(1) {
+            let (path, index) = line.split_once(',').expect("a path and an index");
+            let index: u16 = index.parse().expect("an index of a field");
+            indexes.insert(path, index);
+            paths.insert(index, path);
+        }
+        Fields { indexes, paths }
+    })
+}
+
+/// The IDs of the field of a signal, from the index of the signal in the
+/// table `someip_fields.csv`. The IDs of a signal are the same in every
+/// release, signals that are added to the specification get new IDs.
+pub fn field_ids(path: &str) -> Option<FieldIds> {
+    let index = *fields().indexes.get(path)?;
+    let actuator = crate::catalog::find(path)?.kind == SignalKind::Actuator;
+    Some(FieldIds {
+        getter: 2 * index + 1,
+        setter: actuator.then_some(2 * index + 2),
+        notifier: 0x8000 | (index + 1),
+    })
+}
+
+/// The IDs of the field of a topic
+pub fn field_ids_of<T: VssSignal>() -> FieldIds {
+    field_ids(T::PATH).expect("the topics are in the catalog")
+}
+
+/// The signal of a method or an event ID of a field
+pub fn field_signal(id: u16) -> Option<&'static SignalInfo> {
+    let index = match id & 0x8000 {
+        0 => id.checked_sub(1)? / 2,
+        _ => (id & 0x7fff).checked_sub(1)?,
+    };
+    let info = crate::catalog::find(fields().paths.get(&index)?)?;
+    let ids = field_ids(info.path)?;
+    (ids.getter == id || ids.setter == Some(id) || ids.notifier == id).then_some(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::units::Percent;
+    use crate::v3::vehicle::cabin::door::{shade, window};
+    use crate::v3::Timestamp;
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Door {
+        name: String,
+        values: Vec<u16>,
+        side: Side,
+        open: Option<bool>,
+    }
+
+    #[test]
+    fn transformation_rules() {
+        let door = Door {
+            name: "A".to_owned(),
+            values: vec![1, 258],
+            side: Side::Right,
+            open: Some(true),
+        };
+        let bytes = to_bytes(&door).unwrap();
+        assert_eq!(
+            bytes,
+            [0, 0, 0, 5, 0xef, 0xbb, 0xbf, b'A', 0, 0, 0, 0, 4, 0, 1, 1, 2, 2, 1, 1]
+        );
+        assert_eq!(from_bytes::<Door>(&bytes).unwrap(), door);
+        assert_eq!(from_bytes::<Door>(&bytes[..10]), Err(SomeIpError::Eof));
+        let mut wrong = bytes.clone();
+        wrong[17] = 3;
+        assert_eq!(
+            from_bytes::<Door>(&wrong),
+            Err(SomeIpError::Discriminant {
+                name: "Side",
+                value: 3
+            })
+        );
+        assert_eq!(
+            from_bytes::<Door>(&wrong).unwrap_err().to_string(),
+            "3 is not a variant of Side"
+        );
+
+        let timestamp = Timestamp { sec: 1, nsec: 2 };
+        let position = window::Position::new(Percent(40), Some(timestamp), 2, Side::Left).unwrap();
+        let bytes = to_bytes(&position).unwrap();
+        #[cfg(not(feature = "ros2"))]
+        assert_eq!(bytes[..13], [40, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 2]);
+        // a builtin_interfaces/Time with the ros2 feature
+        #[cfg(feature = "ros2")]
+        assert_eq!(bytes[..9], [40, 0, 0, 0, 1, 0, 0, 0, 2]);
+        let decoded: window::Position = from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.value, Percent(40));
+        assert_eq!((decoded.row, decoded.side), (2, Side::Left));
+    }
+
+    #[test]
+    fn ids() {
+        // every signal has a field, with an index that fits the method and
+        // event IDs
+        for info in crate::v3::CATALOG {
+            assert!(fields().indexes.contains_key(info.path), "{}", info.path);
+        }
+        assert_eq!(fields().indexes.len(), fields().paths.len());
+        assert!(fields().paths.keys().all(|index| *index < 0x3fff));
+
+        // the IDs don't change from one release to the next
+        let speed = field_ids("Vehicle.Speed").unwrap();
+        assert_eq!(
+            speed,
+            FieldIds {
+                getter: 897,
+                setter: None,
+                notifier: 0x81c1,
+            }
+        );
+        assert_eq!(
+            field_ids_of::<shade::Position>(),
+            FieldIds {
+                getter: 165,
+                setter: Some(166),
+                notifier: 0x8053,
+            }
+        );
+        assert_eq!(field_signal(speed.notifier).unwrap().path, "Vehicle.Speed");
+        assert_eq!(field_signal(speed.getter).unwrap().path, "Vehicle.Speed");
+        let shade = field_ids_of::<shade::Position>();
+        assert_eq!(
+            field_signal(shade.setter.unwrap()).unwrap().path,
+            shade::Position::PATH
+        );
+        assert!(field_signal(speed.getter + 1).is_none());
+    }
+}
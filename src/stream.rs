@@ -0,0 +1,548 @@
+// Check project root for LICENCE
+//! Async streams of samples of the generated topics
+
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use cyclonedds_rs::{DDSError, DdsListenerBuilder, DdsParticipant, SampleBuffer};
+use futures_core::Stream;
+use futures_util::stream::{self, StreamExt};
+
+use crate::naming::NamingPolicy;
+use crate::pubsub::{matches_key, read_all};
+use crate::v3::VssSignal;
+
+const BUFFER_LEN: usize = 16;
+
+/// Subscribe to the topic of `T` and return the received samples as a stream.
+/// If a key is given, only the samples of that instance are returned. The stream
+/// does not end, the samples that tell about a dispose or an unregister are
+/// skipped.
+pub fn subscribe<T: VssSignal>(
+    participant: &DdsParticipant,
+    key: Option<T::Key>,
+) -> Result<impl Stream<Item = T>, DDSError> {
+    subscribe_with_naming(participant, &NamingPolicy::default(), key)
+}
+
+/// Like [subscribe], with a topic name prefix or partition. See [NamingPolicy].
+pub fn subscribe_with_naming<T: VssSignal>(
+    participant: &DdsParticipant,
+    naming: &NamingPolicy,
+    key: Option<T::Key>,
+) -> Result<impl Stream<Item = T>, DDSError> {
+    if let Some(key) = &key {
+        if !T::key_instances().contains(key) {
+            return Err(DDSError::BadParameter);
+        }
+    }
+    // The listener wakes the task that polls the stream when samples arrive.
+    // The async take of cyclonedds-rs is not used, it drops the samples taken
+    // with an invalid one and keeps failing after a missed deadline.
+    let waker: Arc<Mutex<Option<Waker>>> = Arc::default();
+    let on_data = waker.clone();
+    let listener = DdsListenerBuilder::new()
+        .on_data_available(move |_| {
+            if let Some(waker) = on_data.lock().unwrap().take() {
+                waker.wake();
+            }
+        })
+        .build();
+    let reader = naming.create_reader_with_listener::<T>(participant, listener)?;
+    let mut buffer = SampleBuffer::new(BUFFER_LEN);
+    let mut pending = VecDeque::new();
+
+    Ok(stream::poll_fn(move |cx| {
+        if pending.is_empty() {
+            // register the waker before taking, so that the samples that arrive
+            // after the take wake the task
+            *waker.lock().unwrap() = Some(cx.waker().clone());
+            read_all(&reader, &mut buffer, true, |sample| {
+                if matches_key(&key, sample) {
+                    pending.push_back(sample.clone());
+                }
+            });
+        }
+        match pending.pop_front() {
+            Some(sample) => Poll::Ready(Some(sample)),
+            None => Poll::Pending,
+        }
+    }))
+}
+
+/// Combinators for streams of samples
+pub trait VssStreamExt: Stream + Sized
+where
+    Self::Item: VssSignal,
+{
+    /// Drop the samples whose value did not change since the last sample of the
+    /// same instance.
+    fn dedup(self) -> Dedup<Self> {
+        Dedup {
+            stream: Box::pin(self),
+            last: Vec::new(),
+        }
+    }
+
+    /// Return the samples at a fixed period. At every tick the latest sample of
+    /// every instance that was received since the previous tick is returned, the
+    /// older ones are dropped. The ticks that are missed because the stream is
+    /// not polled are not made up for.
+    fn sample(self, period: Duration) -> Sample<Self> {
+        Sample {
+            stream: Box::pin(self),
+            ticker: Ticker::new(period),
+            latest: Vec::new(),
+            ready: VecDeque::new(),
+            done: false,
+        }
+    }
+
+    /// Keep the latest sample of every instance. The returned future receives the
+    /// samples and must be spawned or awaited, the `Latest` handle can be cloned
+    /// and read from anywhere.
+    fn latest(self) -> (Latest<Self::Item>, LatestDriver<Self>) {
+        let latest = Latest {
+            shared: Arc::new(Mutex::new(LatestState {
+                samples: Vec::new(),
+                version: 0,
+                closed: false,
+                wakers: HashMap::new(),
+            })),
+            id: next_id(),
+            seen: 0,
+        };
+        let driver = LatestDriver {
+            stream: Box::pin(self),
+            shared: latest.shared.clone(),
+        };
+        (latest, driver)
+    }
+}
+
+impl<S> VssStreamExt for S
+where
+    S: Stream,
+    S::Item: VssSignal,
+{
+}
+
+// Replace the sample of the same instance, or add it. Returns the replaced sample.
+fn update<T: VssSignal>(samples: &mut Vec<T>, sample: T) -> Option<T> {
+    let key = sample.key();
+    match samples.iter_mut().find(|s| s.key() == key) {
+        Some(old) => Some(std::mem::replace(old, sample)),
+        None => {
+            samples.push(sample);
+            None
+        }
+    }
+}
+
+/// Stream returned by [`VssStreamExt::dedup`]
+pub struct Dedup<S: Stream> {
+    stream: Pin<Box<S>>,
+    last: Vec<S::Item>,
+}
+
+impl<S: Stream> Unpin for Dedup<S> {}
+
+impl<S> Stream for Dedup<S>
+where
+    S: Stream,
+    S::Item: VssSignal,
+{
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match self.stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(sample)) => {
+                    let changed = match update(&mut self.last, sample.clone()) {
+                        Some(old) => old.signal_value() != sample.signal_value(),
+                        None => true,
+                    };
+                    if changed {
+                        return Poll::Ready(Some(sample));
+                    }
+                }
+                other => return other,
+            }
+        }
+    }
+}
+
+// Wakes the tasks of the sampling streams at their next tick. A single thread
+// serves all the streams, it is started by the first one.
+struct Timer {
+    deadlines: Mutex<HashMap<u64, (Instant, Waker)>>,
+    changed: Condvar,
+}
+
+static TIMER: OnceLock<Timer> = OnceLock::new();
+
+fn timer() -> &'static Timer {
+    TIMER.get_or_init(|| {
+        thread::spawn(|| timer().run());
+        Timer {
+            deadlines: Mutex::new(HashMap::new()),
+            changed: Condvar::new(),
+        }
+    })
+}
+
+impl Timer {
+    fn wake_at(&self, id: u64, deadline: Instant, waker: &Waker) {
+        self.deadlines
+            .lock()
+            .unwrap()
+            .insert(id, (deadline, waker.clone()));
+        self.changed.notify_one();
+    }
+
+    fn cancel(&self, id: u64) {
+        self.deadlines.lock().unwrap().remove(&id);
+    }
+
+    fn run(&self) {
+        let mut deadlines = self.deadlines.lock().unwrap();
+        loop {
+            let now = Instant::now();
+            deadlines.retain(|_, (deadline, waker)| {
+                if *deadline <= now {
+                    waker.wake_by_ref();
+                }
+                *deadline > now
+            });
+            deadlines = match deadlines.values().map(|(deadline, _)| *deadline).min() {
+                Some(next) => self.changed.wait_timeout(deadlines, next - now).unwrap().0,
+                None => self.changed.wait(deadlines).unwrap(),
+            };
+        }
+    }
+}
+
+// An id for the tickers and the Latest handles
+fn next_id() -> u64 {
+    static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+// The ticks of a sampling stream. The stream checks for a tick when it is
+// polled and the timer wakes it at the next one.
+struct Ticker {
+    id: u64,
+    period: Duration,
+    next: Instant,
+}
+
+impl Ticker {
+    fn new(period: Duration) -> Self {
+        Self {
+            id: next_id(),
+            period,
+            next: Instant::now() + period,
+        }
+    }
+
+    // Return true if a tick has passed since the last tick, and have the task
+    // woken at the next one.
+    fn poll_tick(&mut self, cx: &mut Context<'_>) -> bool {
+        let now = Instant::now();
+        let ticked = now >= self.next;
+        if ticked {
+            self.next += self.period;
+            if self.next <= now {
+                self.next = now + self.period;
+            }
+        }
+        timer().wake_at(self.id, self.next, cx.waker());
+        ticked
+    }
+}
+
+impl Drop for Ticker {
+    fn drop(&mut self) {
+        if let Some(timer) = TIMER.get() {
+            timer.cancel(self.id);
+        }
+    }
+}
+
+/// Stream returned by [`VssStreamExt::sample`]
+pub struct Sample<S: Stream> {
+    stream: Pin<Box<S>>,
+    ticker: Ticker,
+    latest: Vec<S::Item>,
+    ready: VecDeque<S::Item>,
+    done: bool,
+}
+
+impl<S: Stream> Unpin for Sample<S> {}
+
+impl<S> Stream for Sample<S>
+where
+    S: Stream,
+    S::Item: VssSignal,
+{
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        while !this.done {
+            match this.stream.poll_next_unpin(cx) {
+                Poll::Ready(Some(sample)) => {
+                    update(&mut this.latest, sample);
+                }
+                Poll::Ready(None) => this.done = true,
+                Poll::Pending => break,
+            }
+        }
+
+        if this.ticker.poll_tick(cx) {
+            this.ready.extend(this.latest.drain(..));
+        }
+        if let Some(sample) = this.ready.pop_front() {
+            Poll::Ready(Some(sample))
+        } else if this.done && this.latest.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+struct LatestState<T> {
+    samples: Vec<T>,
+    version: u64,
+    closed: bool,
+    // The task waiting in Latest::changed, by the id of the handle
+    wakers: HashMap<u64, Waker>,
+}
+
+/// Handle to the latest samples of a stream, returned by [`VssStreamExt::latest`]
+pub struct Latest<T: VssSignal> {
+    shared: Arc<Mutex<LatestState<T>>>,
+    id: u64,
+    seen: u64,
+}
+
+impl<T: VssSignal> Clone for Latest<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            id: next_id(),
+            seen: self.seen,
+        }
+    }
+}
+
+impl<T: VssSignal> Drop for Latest<T> {
+    fn drop(&mut self) {
+        self.shared.lock().unwrap().wakers.remove(&self.id);
+    }
+}
+
+impl<T: VssSignal> Latest<T> {
+    /// The latest sample of the instance
+    pub fn get(&self, key: &T::Key) -> Option<T> {
+        let state = self.shared.lock().unwrap();
+        state.samples.iter().find(|s| s.key() == *key).cloned()
+    }
+
+    /// The latest sample of every instance that was received
+    pub fn get_all(&self) -> Vec<T> {
+        self.shared.lock().unwrap().samples.clone()
+    }
+
+    /// Wait until a sample is received that this handle has not seen yet. Returns
+    /// false if the stream has ended.
+    pub fn changed(&mut self) -> Changed<'_, T> {
+        Changed { latest: self }
+    }
+}
+
+/// Future returned by [`Latest::changed`]
+pub struct Changed<'a, T: VssSignal> {
+    latest: &'a mut Latest<T>,
+}
+
+impl<'a, T: VssSignal> Future for Changed<'a, T> {
+    type Output = bool;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<bool> {
+        let shared = self.latest.shared.clone();
+        let mut state = shared.lock().unwrap();
+        if state.version != self.latest.seen {
+            self.latest.seen = state.version;
+            Poll::Ready(true)
+        } else if state.closed {
+            Poll::Ready(false)
+        } else {
+            // a task that polls again replaces its waker
+            state.wakers.insert(self.latest.id, cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+/// Future that feeds a [`Latest`] handle, returned by [`VssStreamExt::latest`]
+pub struct LatestDriver<S: Stream> {
+    stream: Pin<Box<S>>,
+    shared: Arc<Mutex<LatestState<S::Item>>>,
+}
+
+impl<S: Stream> Unpin for LatestDriver<S> {}
+
+impl<S> Future for LatestDriver<S>
+where
+    S: Stream,
+    S::Item: VssSignal,
+{
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        loop {
+            let next = self.stream.poll_next_unpin(cx);
+            let mut state = self.shared.lock().unwrap();
+            let result = match next {
+                Poll::Ready(Some(sample)) => {
+                    update(&mut state.samples, sample);
+                    state.version += 1;
+                    None
+                }
+                Poll::Ready(None) => {
+                    state.closed = true;
+                    Some(Poll::Ready(()))
+                }
+                Poll::Pending => return Poll::Pending,
+            };
+            for (_, waker) in state.wakers.drain() {
+                waker.wake();
+            }
+            if let Some(result) = result {
+                return result;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::units::Percent;
+    use crate::v3::vehicle::cabin::door::window::Position;
+    use crate::v3::Side;
+    use std::task::Wake;
+
+    struct ThreadWaker(thread::Thread);
+
+    impl Wake for ThreadWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.unpark();
+        }
+    }
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = Box::pin(future);
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        loop {
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(output) => return output,
+                Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    fn position(value: u8, row: u8, side: Side) -> Position {
+        Position::new(Percent(value), None, row, side).unwrap()
+    }
+
+    #[test]
+    fn dedup() {
+        let samples = vec![
+            position(10, 1, Side::Left),
+            position(10, 1, Side::Right),
+            position(10, 1, Side::Left),
+            position(20, 1, Side::Left),
+            position(10, 1, Side::Right),
+        ];
+        let values: Vec<(Percent<u8>, bool)> =
+            block_on(stream::iter(samples).dedup().collect::<Vec<_>>())
+                .into_iter()
+                .map(|s| (s.value, s.side == Side::Left))
+                .collect();
+        assert_eq!(
+            values,
+            vec![
+                (Percent(10), true),
+                (Percent(10), false),
+                (Percent(20), true)
+            ]
+        );
+    }
+
+    #[test]
+    fn sample() {
+        let samples = vec![
+            position(10, 1, Side::Left),
+            position(20, 1, Side::Left),
+            position(30, 1, Side::Right),
+        ];
+        let period = Duration::from_millis(20);
+        let start = Instant::now();
+        let values: Vec<(Percent<u8>, bool)> =
+            block_on(stream::iter(samples).sample(period).collect::<Vec<_>>())
+                .into_iter()
+                .map(|s| (s.value, s.side == Side::Left))
+                .collect();
+        assert!(start.elapsed() >= period);
+        assert_eq!(values, vec![(Percent(20), true), (Percent(30), false)]);
+
+        // two streams with their own period on the same timer
+        let (a, b) = block_on(futures_util::future::join(
+            stream::iter(vec![position(10, 1, Side::Left)])
+                .sample(period)
+                .collect::<Vec<_>>(),
+            stream::iter(vec![position(20, 2, Side::Left)])
+                .sample(period * 2)
+                .collect::<Vec<_>>(),
+        ));
+        assert_eq!((&a[0].value, &b[0].value), (&Percent(10), &Percent(20)));
+        assert!(start.elapsed() >= period * 3);
+    }
+
+    #[test]
+    fn latest() {
+        let samples = vec![position(10, 1, Side::Left), position(20, 1, Side::Left)];
+        let (mut latest, driver) = stream::iter(samples).latest();
+        block_on(driver);
+        assert!(block_on(latest.changed()));
+        assert!(!block_on(latest.changed()));
+        assert_eq!(latest.get(&(1, Side::Left)).unwrap().value, Percent(20));
+        assert!(latest.get(&(2, Side::Left)).is_none());
+    }
+
+    #[test]
+    fn one_waker_per_handle() {
+        let (mut latest, _driver) = stream::pending::<Position>().latest();
+        let mut other = latest.clone();
+        let waker = Arc::new(ThreadWaker(thread::current())).into();
+        let mut cx = Context::from_waker(&waker);
+        // polling again, e.g. from a select! loop, replaces the waker
+        for _ in 0..3 {
+            assert!(Pin::new(&mut latest.changed()).poll(&mut cx).is_pending());
+        }
+        assert!(Pin::new(&mut other.changed()).poll(&mut cx).is_pending());
+        assert_eq!(latest.shared.lock().unwrap().wakers.len(), 2);
+        drop(other);
+        assert_eq!(latest.shared.lock().unwrap().wakers.len(), 1);
+    }
+}
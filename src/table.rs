@@ -0,0 +1,379 @@
+// Check project root for LICENCE
+//! Samples as rows of CSV and JSON lines files
+
+use std::fmt;
+use std::io::{self, BufRead, Read, Write};
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::catalog::{self, DynSample};
+use crate::v3::Timestamp;
+use crate::value::Value;
+
+#[derive(Debug)]
+pub enum TableError {
+    Io(io::Error),
+    Csv(csv::Error),
+    Json(serde_json::Error),
+    /// A row that can't be imported, the lines start at 1
+    Row {
+        line: usize,
+        error: RowError,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RowError {
+    /// The path is not an instance of a signal of the catalog
+    UnknownPath(String),
+    /// The unit is not the unit of the signal
+    Unit {
+        expected: Option<&'static str>,
+        found: String,
+    },
+    Timestamp(String),
+    /// The value does not fit the datatype of the signal or is out of bounds
+    Value(String),
+}
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TableError::Io(e) => e.fmt(f),
+            TableError::Csv(e) => e.fmt(f),
+            TableError::Json(e) => e.fmt(f),
+            TableError::Row { line, error } => write!(f, "line {}: {}", line, error),
+        }
+    }
+}
+
+impl std::error::Error for TableError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TableError::Io(e) => Some(e),
+            TableError::Csv(e) => Some(e),
+            TableError::Json(e) => Some(e),
+            TableError::Row { error, .. } => Some(error),
+        }
+    }
+}
+
+impl fmt::Display for RowError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RowError::UnknownPath(path) => write!(f, "unknown path {}", path),
+            RowError::Unit { expected, found } => write!(
+                f,
+                "expected the unit {}, found {}",
+                expected.unwrap_or("none"),
+                found
+            ),
+            RowError::Timestamp(t) => write!(f, "invalid timestamp {}", t),
+            RowError::Value(v) => write!(f, "invalid value {}", v),
+        }
+    }
+}
+
+impl std::error::Error for RowError {}
+
+impl From<io::Error> for TableError {
+    fn from(e: io::Error) -> Self {
+        TableError::Io(e)
+    }
+}
+
+impl From<csv::Error> for TableError {
+    fn from(e: csv::Error) -> Self {
+        TableError::Csv(e)
+    }
+}
+
+impl From<serde_json::Error> for TableError {
+    fn from(e: serde_json::Error) -> Self {
+        TableError::Json(e)
+    }
+}
+
+// A row of a CSV file, the timestamp is in seconds since the epoch like
+// `1697712000.250000000`
+#[derive(Serialize, Deserialize)]
+struct CsvRow {
+    timestamp: String,
+    path: String,
+    value: String,
+    unit: String,
+}
+
+// A row of a JSON lines file, the timestamp is in seconds since the epoch
+#[derive(Serialize, Deserialize)]
+struct JsonRow {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    timestamp: Option<f64>,
+    path: String,
+    value: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    unit: Option<String>,
+}
+
+// The timestamps are replayed in nanoseconds since the epoch in 64 bits, which
+// end in the year 2554
+const MAX_SECONDS: u64 = u64::MAX / 1_000_000_000;
+
+fn format_seconds(timestamp: &Timestamp) -> String {
+    format!("{}.{:09}", timestamp.sec, timestamp.nsec)
+}
+
+fn parse_seconds(text: &str) -> Option<Timestamp> {
+    let (sec, fraction) = text.trim().split_once('.').unwrap_or((text.trim(), ""));
+    if fraction.len() > 9 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let nsec = if fraction.is_empty() {
+        0
+    } else {
+        fraction.parse::<u32>().ok()? * 10u32.pow(9 - fraction.len() as u32)
+    };
+    let sec: u64 = sec.parse().ok()?;
+    if sec >= MAX_SECONDS {
+        return None;
+    }
+    Some(Timestamp { sec, nsec })
+}
+
+fn to_seconds(timestamp: &Timestamp) -> f64 {
+    timestamp.sec as f64 + timestamp.nsec as f64 * 1e-9
+}
+
+fn from_seconds(seconds: f64) -> Option<Timestamp> {
+    if !(seconds >= 0.0 && seconds < MAX_SECONDS as f64) {
+        return None;
+    }
+    let sec = seconds.floor();
+    let nsec = ((seconds - sec) * 1e9).round() as u32;
+    Some(Timestamp {
+        sec: sec as u64,
+        nsec: nsec.min(999_999_999),
+    })
+}
+
+fn unit_of(sample: &DynSample) -> Option<&'static str> {
+    catalog::find(sample.signal).and_then(|info| info.unit)
+}
+
+// Check a row against the catalog. The value is converted to the datatype of
+// the signal, `parse` gets the datatype.
+fn import(
+    path: &str,
+    timestamp: Option<Timestamp>,
+    unit: Option<&str>,
+    parse: impl FnOnce(&str) -> Option<Value>,
+) -> Result<DynSample, RowError> {
+    let info =
+        catalog::find_instance(path).ok_or_else(|| RowError::UnknownPath(path.to_owned()))?;
+    if let Some(unit) = unit.filter(|u| !u.is_empty()) {
+        if Some(unit) != info.unit {
+            return Err(RowError::Unit {
+                expected: info.unit,
+                found: unit.to_owned(),
+            });
+        }
+    }
+    let invalid = || RowError::Value(path.to_owned());
+    let value = parse(info.datatype).ok_or_else(invalid)?;
+    DynSample::new(path, value, timestamp)
+        .and_then(DynSample::checked)
+        .ok_or_else(invalid)
+}
+
+/// Write samples as CSV with the columns `timestamp`, `path`, `value` and
+/// `unit`. The timestamps are in seconds since the epoch, arrays are written
+/// as JSON arrays.
+pub fn write_csv<'a, W: Write>(
+    out: W,
+    samples: impl IntoIterator<Item = &'a DynSample>,
+) -> Result<(), TableError> {
+    let mut writer = csv::Writer::from_writer(out);
+    for sample in samples {
+        let value = match &sample.value {
+            Value::Array(_) => serde_json::to_string(&sample.value)?,
+            value => value.to_string(),
+        };
+        writer.serialize(CsvRow {
+            timestamp: sample
+                .source_timestamp
+                .as_ref()
+                .map(format_seconds)
+                .unwrap_or_default(),
+            path: sample.path.clone(),
+            value,
+            unit: unit_of(sample).unwrap_or_default().to_owned(),
+        })?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Read samples written by [write_csv] or edited in a spreadsheet. The paths,
+/// units and values are checked against the specification. The timestamp and
+/// the unit may be empty.
+pub fn read_csv<R: Read>(input: R) -> Result<Vec<DynSample>, TableError> {
+    let mut reader = csv::Reader::from_reader(input);
+    let mut samples = Vec::new();
+    for (i, row) in reader.deserialize::<CsvRow>().enumerate() {
+        let row = row?;
+        let line = i + 2;
+        let timestamp = if row.timestamp.trim().is_empty() {
+            None
+        } else {
+            Some(
+                parse_seconds(&row.timestamp).ok_or_else(|| TableError::Row {
+                    line,
+                    error: RowError::Timestamp(row.timestamp.clone()),
+                })?,
+            )
+        };
+        let sample = import(&row.path, timestamp, Some(row.unit.trim()), |datatype| {
+            Value::parse(datatype, &row.value)
+        })
+        .map_err(|error| TableError::Row { line, error })?;
+        samples.push(sample);
+    }
+    Ok(samples)
+}
+
+/// Write samples as JSON lines with the fields `timestamp`, `path`, `value`
+/// and `unit`, e.g.
+/// `{"timestamp":1697712000.25,"path":"Vehicle.Speed","value":50.0,"unit":"km/h"}`
+pub fn write_jsonl<'a, W: Write>(
+    mut out: W,
+    samples: impl IntoIterator<Item = &'a DynSample>,
+) -> Result<(), TableError> {
+    for sample in samples {
+        let row = JsonRow {
+            timestamp: sample.source_timestamp.as_ref().map(to_seconds),
+            path: sample.path.clone(),
+            value: sample.value.clone(),
+            unit: unit_of(sample).map(str::to_owned),
+        };
+        serde_json::to_writer(&mut out, &row)?;
+        out.write_all(b"\n")?;
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Read samples written by [write_jsonl]. Empty lines are skipped. The
+/// paths, units and values are checked against the specification.
+pub fn read_jsonl<R: BufRead>(input: R) -> Result<Vec<DynSample>, TableError> {
+    let mut samples = Vec::new();
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row: JsonRow = serde_json::from_str(&line)?;
+        let line = i + 1;
+        let timestamp = match row.timestamp {
+            Some(seconds) => Some(from_seconds(seconds).ok_or(TableError::Row {
+                line,
+                error: RowError::Timestamp(seconds.to_string()),
+            })?),
+            None => None,
+        };
+        let sample = import(&row.path, timestamp, row.unit.as_deref(), |_| {
+            Some(row.value.clone())
+        })
+        .map_err(|error| TableError::Row { line, error })?;
+        samples.push(sample);
+    }
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::v3::units::{KiloPascal, KilometrePerHour};
+    use crate::v3::vehicle::chassis::axle::wheel::tire::Pressure;
+    use crate::v3::vehicle::Speed;
+    use crate::v3::Side;
+
+    #[test]
+    fn csv_and_jsonl() {
+        let mut speed = DynSample::of(&Speed::new(KilometrePerHour(50.5), None).unwrap());
+        speed.source_timestamp = Some(Timestamp {
+            sec: 1000,
+            nsec: 250_000_000,
+        });
+        let pressure =
+            DynSample::of(&Pressure::new(KiloPascal(220), None, 2, Side::Right).unwrap());
+        let samples = vec![speed, pressure];
+
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &samples).unwrap();
+        let text = String::from_utf8(csv).unwrap();
+        assert!(
+            text.starts_with("timestamp,path,value,unit\n1000.250000000,Vehicle.Speed,50.5,km/h\n")
+        );
+        assert_eq!(read_csv(text.as_bytes()).unwrap()[0], samples[0]);
+
+        let mut jsonl = Vec::new();
+        write_jsonl(&mut jsonl, &samples).unwrap();
+        let read = read_jsonl(jsonl.as_slice()).unwrap();
+        assert_eq!(
+            read[1].path,
+            "Vehicle.Chassis.Axle.Row2.Wheel.Right.Tire.Pressure"
+        );
+        assert_eq!(read[0].source_timestamp, samples[0].source_timestamp);
+
+        // edited in a spreadsheet
+        let edited = "timestamp,path,value,unit\n\
+                      12.5,Vehicle.Speed,42,\n\
+                      13,Vehicle.Cabin.Door.Row1.Left.Window.Position,120,percent\n";
+        match read_csv(edited.as_bytes()) {
+            Err(TableError::Row { line: 3, error }) => {
+                assert_eq!(
+                    error,
+                    RowError::Value("Vehicle.Cabin.Door.Row1.Left.Window.Position".to_owned())
+                )
+            }
+            other => panic!("{:?}", other),
+        }
+        let edited = "timestamp,path,value,unit\n12.5,Vehicle.Speed,42,m/s\n";
+        assert!(matches!(
+            read_csv(edited.as_bytes()),
+            Err(TableError::Row {
+                error: RowError::Unit { .. },
+                ..
+            })
+        ));
+        let edited = "timestamp,path,value,unit\n12.5,Vehicle.Speed,42,\n";
+        let read = read_csv(edited.as_bytes()).unwrap();
+        assert_eq!(read[0].value, Value::Float(42.0));
+        assert_eq!(read[0].source_timestamp.as_ref().unwrap().nsec, 500_000_000);
+
+        // times that can't be replayed
+        for time in ["18446744073", "99999999999999999999", "-1"] {
+            let edited = format!("timestamp,path,value,unit\n{},Vehicle.Speed,42,\n", time);
+            assert!(matches!(
+                read_csv(edited.as_bytes()),
+                Err(TableError::Row {
+                    line: 2,
+                    error: RowError::Timestamp(_)
+                })
+            ));
+        }
+        for time in ["1e11", "1e300", "-1"] {
+            let edited = format!(
+                "{{\"timestamp\":{},\"path\":\"Vehicle.Speed\",\"value\":42.0}}\n",
+                time
+            );
+            assert!(matches!(
+                read_jsonl(edited.as_bytes()),
+                Err(TableError::Row {
+                    line: 1,
+                    error: RowError::Timestamp(_)
+                })
+            ));
+        }
+    }
+}
@@ -1,122 +1,287 @@
 
+use std::ops;
+
 use serde_derive::{Serialize, Deserialize};
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct KilometrePerHour<T>(pub T) ;
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct MetrePerSec<T>(pub T) ;
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Celsius<T>(pub T) ;
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Millibar<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Pascal<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct KiloPascal<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Percent<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Ratio<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Latitude<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Longitude<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Inch<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Millimetre<T>(pub T);
 
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Metre<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Kilometre<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct RPM<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Hertz<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Watt<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Kilowatt<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct KilowattHour<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Millisecond<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Second<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Minute<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Hour<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Gram<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Kilogram<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct GramPerSec<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct LiterPerHour<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct MeterPerSecondSq<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct CentimeterPerSecondSq<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Newton<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct NewtonMetre<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Litre<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Millilitre<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Degree<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct DegreePerSecond<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct LiterPerHundredKm<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct MilliliterPerHundredKm<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Volt<T>(pub T);
 
-#[derive(Serialize,Deserialize, Default, Clone)]
+#[derive(Serialize,Deserialize, Default, Clone, Debug, PartialEq)]
 pub struct Amp<T>(pub T);
 
-
+/// A unit as the factor and the offset to the SI unit of its quantity,
+/// `si = value * factor + offset`, and the exponents of length, time, mass and
+/// temperature of that SI unit. Units with the same exponents can be converted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Dimension {
+    pub factor: f64,
+    pub offset: f64,
+    pub exponents: [i32; 4],
+}
+
+impl Dimension {
+    /// A number without a unit
+    pub const NONE: Dimension = Dimension {
+        factor: 1.0,
+        offset: 0.0,
+        exponents: [0, 0, 0, 0],
+    };
+    /// The unit of time, `s`
+    pub const SECOND: Dimension = Dimension {
+        factor: 1.0,
+        offset: 0.0,
+        exponents: [0, 1, 0, 0],
+    };
+}
+
+/// The dimension of the product of two values. The offsets are ignored, the
+/// product of a unit with an offset, like celsius, has no meaning.
+impl ops::Mul for Dimension {
+    type Output = Dimension;
+
+    fn mul(self, other: Dimension) -> Dimension {
+        let mut exponents = self.exponents;
+        for (e, o) in exponents.iter_mut().zip(other.exponents) {
+            *e += o;
+        }
+        Dimension {
+            factor: self.factor * other.factor,
+            offset: 0.0,
+            exponents,
+        }
+    }
+}
+
+/// The dimension of the quotient of two values. The offsets are ignored like
+/// for the product.
+impl ops::Div for Dimension {
+    type Output = Dimension;
+
+    fn div(self, other: Dimension) -> Dimension {
+        let mut exponents = self.exponents;
+        for (e, o) in exponents.iter_mut().zip(other.exponents) {
+            *e -= o;
+        }
+        Dimension {
+            factor: self.factor / other.factor,
+            offset: 0.0,
+            exponents,
+        }
+    }
+}
+
+// The units of the specification, and other units of vehicle buses, that can
+// be converted
+const DIMENSIONS: &[(&str, f64, f64, [i32; 4])] = &[
+    ("mm", 1e-3, 0.0, [1, 0, 0, 0]),
+    ("cm", 1e-2, 0.0, [1, 0, 0, 0]),
+    ("m", 1.0, 0.0, [1, 0, 0, 0]),
+    ("km", 1e3, 0.0, [1, 0, 0, 0]),
+    ("inch", 0.0254, 0.0, [1, 0, 0, 0]),
+    ("mile", 1609.344, 0.0, [1, 0, 0, 0]),
+    ("ms", 1e-3, 0.0, [0, 1, 0, 0]),
+    ("s", 1.0, 0.0, [0, 1, 0, 0]),
+    ("min", 60.0, 0.0, [0, 1, 0, 0]),
+    ("h", 3600.0, 0.0, [0, 1, 0, 0]),
+    ("m/s", 1.0, 0.0, [1, -1, 0, 0]),
+    ("km/h", 1.0 / 3.6, 0.0, [1, -1, 0, 0]),
+    ("mph", 1609.344 / 3600.0, 0.0, [1, -1, 0, 0]),
+    ("m/s^2", 1.0, 0.0, [1, -2, 0, 0]),
+    ("cm^3", 1e-6, 0.0, [3, 0, 0, 0]),
+    ("ml", 1e-6, 0.0, [3, 0, 0, 0]),
+    ("l", 1e-3, 0.0, [3, 0, 0, 0]),
+    ("l/h", 1e-3 / 3600.0, 0.0, [3, -1, 0, 0]),
+    ("l/100km", 1e-3 / 1e5, 0.0, [2, 0, 0, 0]),
+    ("ml/100km", 1e-6 / 1e5, 0.0, [2, 0, 0, 0]),
+    ("g", 1e-3, 0.0, [0, 0, 1, 0]),
+    ("kg", 1.0, 0.0, [0, 0, 1, 0]),
+    ("g/s", 1e-3, 0.0, [0, -1, 1, 0]),
+    ("g/km", 1e-6, 0.0, [-1, 0, 1, 0]),
+    ("Pa", 1.0, 0.0, [-1, -2, 1, 0]),
+    ("kPa", 1e3, 0.0, [-1, -2, 1, 0]),
+    ("mbar", 100.0, 0.0, [-1, -2, 1, 0]),
+    ("bar", 1e5, 0.0, [-1, -2, 1, 0]),
+    ("psi", 6894.757, 0.0, [-1, -2, 1, 0]),
+    ("W", 1.0, 0.0, [2, -3, 1, 0]),
+    ("kW", 1e3, 0.0, [2, -3, 1, 0]),
+    ("Wh", 3600.0, 0.0, [2, -2, 1, 0]),
+    ("kWh", 3.6e6, 0.0, [2, -2, 1, 0]),
+    ("kelvin", 1.0, 0.0, [0, 0, 0, 1]),
+    ("celsius", 1.0, 273.15, [0, 0, 0, 1]),
+    ("fahrenheit", 5.0 / 9.0, 459.67 * 5.0 / 9.0, [0, 0, 0, 1]),
+];
+
+/// The dimension of a unit, None if it can't be converted
+pub fn dimension(unit: &str) -> Option<Dimension> {
+    DIMENSIONS
+        .iter()
+        .find(|(u, _, _, _)| *u == unit)
+        .map(|&(_, factor, offset, exponents)| Dimension {
+            factor,
+            offset,
+            exponents,
+        })
+}
+
+// The factor of the scalings in mapping files that don't give one
+pub(crate) fn one() -> f64 {
+    1.0
+}
+
+/// The factor and the offset that convert a value from a unit to another
+/// unit of the same quantity, `to = from * factor + offset`
+pub fn conversion(from: &str, to: &str) -> Option<(f64, f64)> {
+    if from == to {
+        return Some((1.0, 0.0));
+    }
+    let (from, to) = (dimension(from)?, dimension(to)?);
+    if from.exponents != to.exponents {
+        return None;
+    }
+    let factor = from.factor / to.factor;
+    Some((factor, (from.offset - to.offset) / to.factor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let close = |from, to, expected: (f64, f64)| {
+            let (factor, offset) = conversion(from, to).unwrap();
+            (factor - expected.0).abs() < 1e-9 && (offset - expected.1).abs() < 1e-9
+        };
+        assert!(close("m/s", "km/h", (3.6, 0.0)));
+        assert!(close("mph", "km/h", (1.609344, 0.0)));
+        assert!(close("fahrenheit", "celsius", (5.0 / 9.0, -160.0 / 9.0)));
+        assert!(close("celsius", "fahrenheit", (1.8, 32.0)));
+        assert!(close("psi", "kPa", (6.894757, 0.0)));
+        assert!(close("h", "min", (60.0, 0.0)));
+        assert_eq!(conversion("km", "km"), Some((1.0, 0.0)));
+        assert_eq!(conversion("km/h", "kPa"), None);
+        assert_eq!(conversion("furlong", "km"), None);
+    }
+
+    #[test]
+    fn products() {
+        let speed = dimension("km").unwrap() / dimension("h").unwrap();
+        assert_eq!(speed.exponents, dimension("km/h").unwrap().exponents);
+        assert!((speed.factor - 1.0 / 3.6).abs() < 1e-9);
+        let distance = speed * Dimension::SECOND;
+        assert_eq!(distance.exponents, dimension("m").unwrap().exponents);
+        assert_eq!(Dimension::NONE * distance, distance);
+    }
+}
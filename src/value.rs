@@ -0,0 +1,321 @@
+// Check project root for LICENCE
+//! Values and keys whose type is only known at runtime
+
+use std::convert::TryFrom;
+use std::fmt;
+
+use serde_derive::{Deserialize, Serialize};
+
+use crate::v3::{Position, Side};
+
+/// A value of any of the datatypes of the specification. Used where the signal
+/// is chosen at runtime, like the catalog, the state cache and the bridges to
+/// other protocols. It is serialized as a plain JSON value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    String(String),
+    Array(Vec<Value>),
+}
+
+impl Value {
+    /// The value as a number. Booleans are converted to 0 or 1.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+            Value::Int(i) => Some(*i as f64),
+            Value::UInt(u) => Some(*u as f64),
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    /// Parse the text of a value of a datatype of the specification, e.g. from
+    /// a CSV file. Arrays are written as JSON arrays.
+    pub fn parse(datatype: &str, text: &str) -> Option<Value> {
+        if datatype == "string" {
+            return Some(Value::String(text.to_owned()));
+        }
+        let text = text.trim();
+        if datatype.ends_with("[]") {
+            return serde_json::from_str(text).ok();
+        }
+        match datatype {
+            "boolean" => text.parse().ok().map(Value::Bool),
+            _ => text
+                .parse()
+                .map(Value::Int)
+                .or_else(|_| text.parse().map(Value::UInt))
+                .or_else(|_| text.parse().map(Value::Float))
+                .ok(),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::UInt(u) => write!(f, "{}", u),
+            Value::Float(v) => write!(f, "{}", v),
+            Value::String(s) => write!(f, "{}", s),
+            Value::Array(a) => {
+                write!(f, "[")?;
+                for (i, v) in a.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", v)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+/// Conversion of the datatypes of the specification from and to [Value].
+pub trait ToValue: Sized {
+    fn to_value(&self) -> Value;
+    /// Returns None if the value does not fit the type. Integers are accepted for
+    /// floating point types, and floating point values without a fraction for
+    /// integer types.
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl ToValue for bool {
+    fn to_value(&self) -> Value {
+        Value::Bool(*self)
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! int_value {
+    ($variant:ident, $wide:ty, $($t:ty),*) => {
+        $(
+            impl ToValue for $t {
+                fn to_value(&self) -> Value {
+                    Value::$variant(*self as $wide)
+                }
+
+                fn from_value(value: &Value) -> Option<Self> {
+                    match value {
+                        Value::Int(i) => <$t>::try_from(*i).ok(),
+                        Value::UInt(u) => <$t>::try_from(*u).ok(),
+                        Value::Float(f) if f.fract() == 0.0 => {
+                            let i = *f as i128;
+                            if i as f64 == *f {
+                                <$t>::try_from(i).ok()
+                            } else {
+                                None
+                            }
+                        }
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+int_value!(Int, i64, i8, i16, i32, i64);
+int_value!(UInt, u64, u8, u16, u32, u64);
+
+impl ToValue for f32 {
+    fn to_value(&self) -> Value {
+        Value::Float(*self as f64)
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(_) => None,
+            v => v.as_f64().map(|f| f as f32),
+        }
+    }
+}
+
+impl ToValue for f64 {
+    fn to_value(&self) -> Value {
+        Value::Float(*self)
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Bool(_) => None,
+            v => v.as_f64(),
+        }
+    }
+}
+
+impl ToValue for String {
+    fn to_value(&self) -> Value {
+        Value::String(self.clone())
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::String(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl<T: ToValue> ToValue for Vec<T> {
+    fn to_value(&self) -> Value {
+        Value::Array(self.iter().map(ToValue::to_value).collect())
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Array(a) => a.iter().map(T::from_value).collect(),
+            _ => None,
+        }
+    }
+}
+
+/// The value of a key of a topic. Numbered branches like `Row1` are converted
+/// to an index, `Left` and `Right` to a [Side] and `Front` and `Rear` to a
+/// [Position].
+#[derive(Debug, Clone, PartialEq)]
+pub enum KeyValue {
+    Index(u8),
+    Side(Side),
+    Position(Position),
+}
+
+impl KeyValue {
+    /// Parse the key from the text in a path. The name of the key decides the type.
+    pub fn parse(name: &str, text: &str) -> Option<Self> {
+        match (name, text) {
+            ("side", "Left") => Some(KeyValue::Side(Side::Left)),
+            ("side", "Right") => Some(KeyValue::Side(Side::Right)),
+            ("position", "Front") => Some(KeyValue::Position(Position::Front)),
+            ("position", "Rear") => Some(KeyValue::Position(Position::Rear)),
+            ("side", _) | ("position", _) => None,
+            (_, text) => text.parse().ok().map(KeyValue::Index),
+        }
+    }
+}
+
+impl fmt::Display for KeyValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyValue::Index(i) => write!(f, "{}", i),
+            KeyValue::Side(Side::Left) => write!(f, "Left"),
+            KeyValue::Side(Side::Right) => write!(f, "Right"),
+            KeyValue::Position(Position::Front) => write!(f, "Front"),
+            KeyValue::Position(Position::Rear) => write!(f, "Rear"),
+        }
+    }
+}
+
+impl From<u8> for KeyValue {
+    fn from(i: u8) -> Self {
+        KeyValue::Index(i)
+    }
+}
+
+impl From<&u8> for KeyValue {
+    fn from(i: &u8) -> Self {
+        KeyValue::Index(*i)
+    }
+}
+
+impl From<Side> for KeyValue {
+    fn from(side: Side) -> Self {
+        KeyValue::Side(side)
+    }
+}
+
+impl From<&Side> for KeyValue {
+    fn from(side: &Side) -> Self {
+        KeyValue::Side(side.clone())
+    }
+}
+
+impl From<Position> for KeyValue {
+    fn from(position: Position) -> Self {
+        KeyValue::Position(position)
+    }
+}
+
+impl From<&Position> for KeyValue {
+    fn from(position: &Position) -> Self {
+        KeyValue::Position(position.clone())
+    }
+}
+
+/// The types that are used as keys in the topics
+pub trait KeyType: Sized {
+    fn from_key_value(value: &KeyValue) -> Option<Self>;
+}
+
+impl KeyType for u8 {
+    fn from_key_value(value: &KeyValue) -> Option<Self> {
+        match value {
+            KeyValue::Index(i) => Some(*i),
+            _ => None,
+        }
+    }
+}
+
+impl KeyType for Side {
+    fn from_key_value(value: &KeyValue) -> Option<Self> {
+        match value {
+            KeyValue::Side(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl KeyType for Position {
+    fn from_key_value(value: &KeyValue) -> Option<Self> {
+        match value {
+            KeyValue::Position(p) => Some(p.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Find a key by name and convert it to the type of the key
+pub fn find_key<K: KeyType>(values: &[(&str, KeyValue)], name: &str) -> Option<K> {
+    values
+        .iter()
+        .find(|(n, _)| *n == name)
+        .and_then(|(_, v)| K::from_key_value(v))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        assert_eq!(u8::from_value(&Value::Int(200)), Some(200));
+        assert_eq!(u8::from_value(&Value::Int(300)), None);
+        assert_eq!(u8::from_value(&Value::Float(3.0)), Some(3));
+        assert_eq!(u8::from_value(&Value::Float(3.5)), None);
+        assert_eq!(f32::from_value(&Value::UInt(3)), Some(3.0));
+        assert_eq!(bool::from_value(&Value::Int(1)), None);
+        assert_eq!(
+            Vec::<String>::from_value(&Value::Array(vec![Value::String("a".into())])),
+            Some(vec!["a".to_owned()])
+        );
+        assert_eq!(
+            KeyValue::parse("side", "Left"),
+            Some(KeyValue::Side(Side::Left))
+        );
+        assert_eq!(KeyValue::parse("row", "2"), Some(KeyValue::Index(2)));
+        assert_eq!(KeyValue::parse("row", "x"), None);
+    }
+}
@@ -0,0 +1,625 @@
+// Check project root for LICENCE
+//! Mapping of Android Automotive VHAL properties and areas to the signals of the specification
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Read};
+
+use cyclonedds_rs::DDSError;
+use serde_derive::{Deserialize, Serialize};
+
+use crate::backend::Backend;
+use crate::catalog::{self, DynSample, SignalInfo};
+use crate::units;
+use crate::v3::{SignalKind, Timestamp};
+use crate::value::Value;
+
+#[derive(Debug)]
+pub enum VhalError {
+    /// The path has no instance of a signal of the catalog for the areas of
+    /// the property
+    UnknownPath(String),
+    /// The unit of the property can't be converted to the unit of the signal
+    Unit {
+        path: String,
+        vhal: String,
+        vss: Option<&'static str>,
+    },
+    #[cfg(feature = "toml")]
+    Toml(toml::de::Error),
+    Json(serde_json::Error),
+    Io(io::Error),
+    Dds(DDSError),
+}
+
+impl fmt::Display for VhalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            VhalError::UnknownPath(path) => write!(f, "unknown path {}", path),
+            VhalError::Unit { path, vhal, vss } => write!(
+                f,
+                "the unit {} of the property can't be converted to the unit {} of {}",
+                vhal,
+                vss.unwrap_or("(none)"),
+                path
+            ),
+            #[cfg(feature = "toml")]
+            VhalError::Toml(e) => e.fmt(f),
+            VhalError::Json(e) => e.fmt(f),
+            VhalError::Io(e) => e.fmt(f),
+            VhalError::Dds(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for VhalError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VhalError::UnknownPath(_) | VhalError::Unit { .. } => None,
+            #[cfg(feature = "toml")]
+            VhalError::Toml(e) => Some(e),
+            VhalError::Json(e) => Some(e),
+            VhalError::Io(e) => Some(e),
+            VhalError::Dds(e) => Some(e),
+        }
+    }
+}
+
+impl From<serde_json::Error> for VhalError {
+    fn from(e: serde_json::Error) -> Self {
+        VhalError::Json(e)
+    }
+}
+
+impl From<io::Error> for VhalError {
+    fn from(e: io::Error) -> Self {
+        VhalError::Io(e)
+    }
+}
+
+impl From<DDSError> for VhalError {
+    fn from(e: DDSError) -> Self {
+        VhalError::Dds(e)
+    }
+}
+
+pub const PERF_ODOMETER: u32 = 0x1160_0204;
+pub const PERF_VEHICLE_SPEED: u32 = 0x1160_0207;
+pub const ENGINE_COOLANT_TEMP: u32 = 0x1160_0301;
+pub const ENGINE_RPM: u32 = 0x1160_0305;
+pub const TIRE_PRESSURE: u32 = 0x1760_0309;
+pub const GEAR_SELECTION: u32 = 0x1140_0400;
+pub const CURRENT_GEAR: u32 = 0x1140_0401;
+pub const PARKING_BRAKE_ON: u32 = 0x1120_0402;
+pub const HVAC_TEMPERATURE_SET: u32 = 0x1560_0503;
+pub const ENV_OUTSIDE_TEMPERATURE: u32 = 0x1160_0703;
+pub const DOOR_LOCK: u32 = 0x1620_0b02;
+
+const AREA_MASK: u32 = 0x0f00_0000;
+const WINDOW: u32 = 0x0300_0000;
+const MIRROR: u32 = 0x0400_0000;
+const SEAT: u32 = 0x0500_0000;
+const DOOR: u32 = 0x0600_0000;
+const WHEEL: u32 = 0x0700_0000;
+
+const TYPE_MASK: u32 = 0x00ff_0000;
+const BOOLEAN: u32 = 0x0020_0000;
+const INT32: u32 = 0x0040_0000;
+const INT64: u32 = 0x0050_0000;
+
+type Keys = Vec<(&'static str, String)>;
+
+fn row_side(bit: u32, row: u32, side: &str) -> (u32, Keys) {
+    let keys = vec![("row", row.to_string()), ("side", side.to_owned())];
+    (bit, keys)
+}
+
+/// The single areas of the area type of a property, with the keys they fill
+/// in a path: `row`, `side` and `pos` for seats (`Pos1` to `Pos3` from left
+/// to right), and `position` (`Front` or `Rear`) for the hood, the rear door
+/// and the windshields. Global properties have the area 0 without keys.
+pub fn areas(property: u32) -> Vec<(u32, Keys)> {
+    let sides = ["Left", "Center", "Right"];
+    let position = |bit: u32, position: &str| (bit, vec![("position", position.to_owned())]);
+    let mut areas = Vec::new();
+    match property & AREA_MASK {
+        SEAT => {
+            for row in 0..3 {
+                for (i, side) in sides.iter().enumerate() {
+                    let (bit, mut keys) = row_side(1 << (row * 4 + i as u32), row + 1, side);
+                    keys.push(("pos", (i + 1).to_string()));
+                    areas.push((bit, keys));
+                }
+            }
+        }
+        DOOR => {
+            for row in 0..3 {
+                areas.push(row_side(1 << (row * 4), row + 1, "Left"));
+                areas.push(row_side(1 << (row * 4 + 2), row + 1, "Right"));
+            }
+            areas.push(position(0x1000_0000, "Front"));
+            areas.push(position(0x2000_0000, "Rear"));
+        }
+        WINDOW => {
+            for row in 0..3 {
+                areas.push(row_side(1 << (row * 4 + 4), row + 1, "Left"));
+                areas.push(row_side(1 << (row * 4 + 6), row + 1, "Right"));
+            }
+            areas.push(position(0x1, "Front"));
+            areas.push(position(0x2, "Rear"));
+        }
+        MIRROR => {
+            for (bit, side) in [(0x1, "Left"), (0x2, "Right"), (0x4, "Center")] {
+                areas.push((bit, vec![("side", side.to_owned())]));
+            }
+        }
+        WHEEL => {
+            areas.push(row_side(0x1, 1, "Left"));
+            areas.push(row_side(0x2, 1, "Right"));
+            areas.push(row_side(0x4, 2, "Left"));
+            areas.push(row_side(0x8, 2, "Right"));
+        }
+        _ => areas.push((0, Vec::new())),
+    }
+    areas
+}
+
+// The path with the keys, None when a placeholder has no key
+fn fill(template: &str, keys: &Keys) -> Option<String> {
+    let mut path = template.to_owned();
+    for (name, value) in keys {
+        path = path.replace(&format!("{{{}}}", name), value);
+    }
+    (!path.contains('{')).then_some(path)
+}
+
+/// The mapping of a property to a signal. The path has placeholders for the
+/// keys of the areas, e.g.
+/// `Vehicle.Cabin.HVAC.Station.Row{row}.{side}.Temperature`, the areas
+/// without an instance of the signal are skipped. The VSS value is
+/// `value * factor + offset`, converted from `unit` to the unit of the
+/// signal, or the entry of `values` for the property value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PropertyMapping {
+    /// The property ID, e.g. `0x11600207` for PERF_VEHICLE_SPEED
+    pub property: u32,
+    pub path: String,
+    /// The unit of the property values, e.g. `m/s`
+    #[serde(default)]
+    pub unit: Option<String>,
+    #[serde(default = "units::one")]
+    pub factor: f64,
+    #[serde(default)]
+    pub offset: f64,
+    /// The VSS values of property values, e.g. `"4" = "126"` for GEAR_PARK
+    #[serde(default)]
+    pub values: BTreeMap<String, String>,
+    /// The area IDs configured for the property, e.g. `0x11` for a zone of
+    /// the left seats. Every single area of the area type by default.
+    #[serde(default)]
+    pub areas: Vec<u32>,
+}
+
+impl PropertyMapping {
+    pub fn new(property: u32, path: &str, unit: Option<&str>) -> Self {
+        Self {
+            property,
+            path: path.to_owned(),
+            unit: unit.map(str::to_owned),
+            factor: 1.0,
+            offset: 0.0,
+            values: BTreeMap::new(),
+            areas: Vec::new(),
+        }
+    }
+}
+
+/// The mappings of a mapping file, e.g. in TOML:
+///
+/// ```toml
+/// [[property]]
+/// property = 0x15600503
+/// path = "Vehicle.Cabin.HVAC.Station.Row{row}.{side}.Temperature"
+/// unit = "fahrenheit"
+/// areas = [0x11, 0x44]
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct VhalMappingConfig {
+    #[serde(default)]
+    pub property: Vec<PropertyMapping>,
+}
+
+impl VhalMappingConfig {
+    /// The mappings of common properties of Android Automotive
+    pub fn standard() -> Self {
+        // VehicleGear to the gears of the specification
+        let gears = [("1", "0"), ("2", "-1"), ("4", "126"), ("8", "127")]
+            .into_iter()
+            .map(|(g, v)| (g.to_owned(), v.to_owned()))
+            .chain((1..=9).map(|n| ((8 << n).to_string(), n.to_string())))
+            .collect::<BTreeMap<_, _>>();
+        let property = vec![
+            PropertyMapping::new(PERF_ODOMETER, "Vehicle.TravelledDistance", Some("km")),
+            PropertyMapping::new(PERF_VEHICLE_SPEED, "Vehicle.Speed", Some("m/s")),
+            PropertyMapping::new(
+                ENGINE_COOLANT_TEMP,
+                "Vehicle.Powertrain.CombustionEngine.ECT",
+                Some("celsius"),
+            ),
+            PropertyMapping::new(
+                ENGINE_RPM,
+                "Vehicle.Powertrain.CombustionEngine.Speed",
+                Some("rpm"),
+            ),
+            PropertyMapping::new(
+                TIRE_PRESSURE,
+                "Vehicle.Chassis.Axle.Row{row}.Wheel.{side}.Tire.Pressure",
+                Some("kPa"),
+            ),
+            PropertyMapping {
+                values: gears.clone(),
+                ..PropertyMapping::new(
+                    GEAR_SELECTION,
+                    "Vehicle.Powertrain.Transmission.SelectedGear",
+                    None,
+                )
+            },
+            PropertyMapping {
+                values: gears,
+                ..PropertyMapping::new(
+                    CURRENT_GEAR,
+                    "Vehicle.Powertrain.Transmission.CurrentGear",
+                    None,
+                )
+            },
+            PropertyMapping::new(
+                PARKING_BRAKE_ON,
+                "Vehicle.Chassis.ParkingBrake.IsEngaged",
+                None,
+            ),
+            PropertyMapping::new(
+                HVAC_TEMPERATURE_SET,
+                "Vehicle.Cabin.HVAC.Station.Row{row}.{side}.Temperature",
+                Some("celsius"),
+            ),
+            PropertyMapping::new(
+                ENV_OUTSIDE_TEMPERATURE,
+                "Vehicle.Exterior.AirTemperature",
+                Some("celsius"),
+            ),
+            PropertyMapping::new(
+                DOOR_LOCK,
+                "Vehicle.Cabin.Door.Row{row}.{side}.IsLocked",
+                None,
+            ),
+            PropertyMapping::new(DOOR_LOCK, "Vehicle.Body.Trunk.{position}.IsLocked", None),
+        ];
+        Self { property }
+    }
+}
+
+/// A value of a property, in the JSON format of the fake value files of the
+/// reference VHAL: `{"timestamp": 0, "areaId": 0, "value": 8, "prop": 289408000}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PropertyEvent {
+    pub prop: u32,
+    #[serde(rename = "areaId", default)]
+    pub area_id: i32,
+    /// A number, a string, or the vector of a vector property
+    pub value: serde_json::Value,
+    /// The elapsed realtime in nanoseconds. A sample has no such clock, so
+    /// [VhalMapping::encode] puts its source timestamp in nanoseconds since the
+    /// epoch here, or 0 if it has none or it does not fit.
+    #[serde(default)]
+    pub timestamp: i64,
+}
+
+struct Instance {
+    // the configured area that has the bit
+    area: u32,
+    bit: u32,
+    path: String,
+    info: &'static SignalInfo,
+}
+
+struct Mapped {
+    config: PropertyMapping,
+    // the scaling to the unit of the signal
+    factor: f64,
+    offset: f64,
+    instances: Vec<Instance>,
+}
+
+/// Converts property values into samples of the specification, and samples
+/// of actuators into property values to set
+pub struct VhalMapping {
+    mapped: Vec<Mapped>,
+}
+
+impl VhalMapping {
+    pub fn new(config: &VhalMappingConfig) -> Result<Self, VhalError> {
+        let mut mapped = Vec::new();
+        for m in &config.property {
+            let mut instances = Vec::new();
+            for (bit, keys) in areas(m.property) {
+                let area = match m.areas.iter().find(|a| **a & bit != 0) {
+                    _ if bit == 0 || m.areas.is_empty() => bit,
+                    Some(area) => *area,
+                    None => continue,
+                };
+                if let Some(path) = fill(&m.path, &keys) {
+                    if let Some(info) = catalog::find_instance(&path) {
+                        instances.push(Instance {
+                            area,
+                            bit,
+                            path,
+                            info,
+                        });
+                    }
+                }
+            }
+            let info = match instances.first() {
+                Some(instance) => instance.info,
+                None => return Err(VhalError::UnknownPath(m.path.clone())),
+            };
+            let (factor, offset) = match &m.unit {
+                Some(unit) => {
+                    let (f, o) =
+                        info.unit
+                            .and_then(|vss| units::conversion(unit, vss))
+                            .ok_or_else(|| VhalError::Unit {
+                                path: m.path.clone(),
+                                vhal: unit.clone(),
+                                vss: info.unit,
+                            })?;
+                    (m.factor * f, m.offset * f + o)
+                }
+                None => (m.factor, m.offset),
+            };
+            mapped.push(Mapped {
+                config: m.clone(),
+                factor,
+                offset,
+                instances,
+            });
+        }
+        Ok(Self { mapped })
+    }
+
+    /// The mapping of `VhalMappingConfig::standard`
+    pub fn standard() -> Self {
+        Self::new(&VhalMappingConfig::standard()).expect("the standard mapping is valid")
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn from_toml(text: &str) -> Result<Self, VhalError> {
+        let config: VhalMappingConfig = toml::from_str(text).map_err(VhalError::Toml)?;
+        Self::new(&config)
+    }
+
+    #[cfg(feature = "toml")]
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, VhalError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// The signals of the specification that are mapped
+    pub fn signals(&self) -> impl Iterator<Item = &'static SignalInfo> + '_ {
+        self.mapped
+            .iter()
+            .flat_map(|m| m.instances.iter().map(|i| i.info))
+    }
+
+    fn to_value(
+        &self,
+        mapped: &Mapped,
+        info: &SignalInfo,
+        value: &serde_json::Value,
+    ) -> Option<Value> {
+        // the scalar properties are vectors of one element in some recordings
+        let value = match value {
+            serde_json::Value::Array(values) if values.len() == 1 => &values[0],
+            value => value,
+        };
+        let text = match value {
+            serde_json::Value::Bool(b) => (*b as u8).to_string(),
+            serde_json::Value::String(s) => s.clone(),
+            value => value.to_string(),
+        };
+        if let Some(text) = mapped.config.values.get(&text) {
+            return Value::parse(info.datatype, text);
+        }
+        if let serde_json::Value::String(s) = value {
+            return Some(Value::String(s.clone()));
+        }
+        let value = value
+            .as_f64()
+            .or_else(|| Some(value.as_bool()? as u8 as f64))?;
+        let value = value * mapped.factor + mapped.offset;
+        Some(match info.datatype {
+            "boolean" => Value::Bool(value != 0.0),
+            datatype if catalog::is_integer(datatype) => Value::Float(value.round()),
+            _ => Value::Float(value),
+        })
+    }
+
+    /// The samples of a property value, one for each single area of the
+    /// area ID. The values are checked against the datatype and bounds of
+    /// the specification, the values that don't fit are dropped.
+    pub fn decode(&self, event: &PropertyEvent, timestamp: Option<Timestamp>) -> Vec<DynSample> {
+        let area = event.area_id as u32;
+        self.mapped
+            .iter()
+            .filter(|m| m.config.property == event.prop)
+            .flat_map(|m| {
+                m.instances
+                    .iter()
+                    .filter(move |i| i.bit == 0 || area & i.bit != 0)
+                    .map(move |i| (m, i))
+            })
+            .filter_map(|(m, i)| {
+                let value = self.to_value(m, i.info, &event.value)?;
+                DynSample::new(&i.path, value, timestamp.clone()).and_then(DynSample::checked)
+            })
+            .collect()
+    }
+
+    /// The property value that sets a sample of an actuator, with the
+    /// configured area of the instance. Returns None for sensors and
+    /// attributes, and for unmapped paths. The timestamp of the event is not
+    /// elapsed realtime, see [PropertyEvent::timestamp].
+    pub fn encode(&self, sample: &DynSample) -> Option<PropertyEvent> {
+        let (m, instance) = self
+            .mapped
+            .iter()
+            .flat_map(|m| m.instances.iter().map(move |i| (m, i)))
+            .find(|(_, i)| i.path == sample.path && i.info.kind == SignalKind::Actuator)?;
+        let text = sample.value.to_string();
+        let raw = m.config.values.iter().find(|(_, v)| **v == text);
+        let value = match (raw, &sample.value) {
+            (Some((raw, _)), _) => serde_json::from_str(raw).ok()?,
+            (None, Value::String(s)) => serde_json::Value::String(s.clone()),
+            (None, Value::Bool(b)) => (*b as i32).into(),
+            (None, value) => {
+                let value = (value.as_f64()? - m.offset) / m.factor;
+                match m.config.property & TYPE_MASK {
+                    BOOLEAN | INT32 | INT64 => (value.round() as i64).into(),
+                    _ => value.into(),
+                }
+            }
+        };
+        let timestamp = sample
+            .source_timestamp
+            .as_ref()
+            .and_then(|t| {
+                i64::try_from(t.sec)
+                    .ok()?
+                    .checked_mul(1_000_000_000)?
+                    .checked_add(t.nsec as i64)
+            })
+            .unwrap_or_default();
+        Some(PropertyEvent {
+            prop: m.config.property,
+            area_id: instance.area as i32,
+            value,
+            timestamp,
+        })
+    }
+}
+
+/// Read a recorded property stream: a JSON array of events like the fake
+/// value files of the reference VHAL, or one event per line
+pub fn read_events<R: Read>(mut input: R) -> Result<Vec<PropertyEvent>, VhalError> {
+    let mut text = String::new();
+    input.read_to_string(&mut text)?;
+    if text.trim_start().starts_with('[') {
+        return Ok(serde_json::from_str(&text)?);
+    }
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Ok(serde_json::from_str(line)?))
+        .collect()
+}
+
+/// Write the samples of property values to a backend. Returns the number of
+/// samples.
+pub fn publish<B: Backend>(
+    mapping: &VhalMapping,
+    events: &[PropertyEvent],
+    backend: &mut B,
+) -> Result<usize, VhalError> {
+    let mut published = 0;
+    for event in events {
+        for sample in mapping.decode(event, Some(Timestamp::default())) {
+            backend.write(&sample)?;
+            published += 1;
+        }
+    }
+    Ok(published)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Loopback;
+
+    #[test]
+    fn recorded_stream() {
+        let recording = r#"[
+            {"timestamp": 1000, "areaId": 0, "value": 12.5, "prop": 291504647},
+            {"timestamp": 2000, "areaId": 0, "value": 4, "prop": 289408000},
+            {"timestamp": 3000, "areaId": 17, "value": [21.5], "prop": 358614275},
+            {"timestamp": 4000, "areaId": 8, "value": 230.0, "prop": 392168201},
+            {"timestamp": 5000, "areaId": 536870912, "value": 1, "prop": 371198722}
+        ]"#;
+        let events = read_events(recording.as_bytes()).unwrap();
+        let loopback = Loopback::new();
+        let published = publish(&VhalMapping::standard(), &events, &mut loopback.clone()).unwrap();
+        assert_eq!(published, 6);
+        let samples: Vec<(String, Value)> = loopback
+            .clone()
+            .take()
+            .into_iter()
+            .map(|s| (s.path, s.value))
+            .collect();
+        assert_eq!(samples[0], ("Vehicle.Speed".to_owned(), Value::Float(45.0)));
+        assert_eq!(samples[1].1, Value::Int(126));
+        assert_eq!(
+            samples[2].0,
+            "Vehicle.Cabin.HVAC.Station.Row1.Left.Temperature"
+        );
+        assert_eq!(
+            samples[3].0,
+            "Vehicle.Cabin.HVAC.Station.Row2.Left.Temperature"
+        );
+        assert_eq!(
+            samples[4].0,
+            "Vehicle.Chassis.Axle.Row2.Wheel.Right.Tire.Pressure"
+        );
+        assert_eq!(
+            samples[5],
+            (
+                "Vehicle.Body.Trunk.Rear.IsLocked".to_owned(),
+                Value::Bool(true)
+            )
+        );
+    }
+
+    #[test]
+    fn areas_and_units() {
+        let mut fahrenheit = PropertyMapping::new(
+            HVAC_TEMPERATURE_SET,
+            "Vehicle.Cabin.HVAC.Station.Row{row}.{side}.Temperature",
+            Some("fahrenheit"),
+        );
+        fahrenheit.areas = vec![0x11, 0x44];
+        let config = VhalMappingConfig {
+            property: vec![fahrenheit],
+        };
+        let mapping = VhalMapping::new(&config).unwrap();
+        let path = "Vehicle.Cabin.HVAC.Station.Row2.Right.Temperature";
+        let sample = DynSample::new(path, Value::Float(20.0), None)
+            .and_then(DynSample::checked)
+            .unwrap();
+        let event = mapping.encode(&sample).unwrap();
+        assert_eq!(event.area_id, 0x44);
+        assert!((event.value.as_f64().unwrap() - 68.0).abs() < 1e-9);
+        assert_eq!(event.timestamp, 0);
+        let late = Timestamp {
+            sec: u64::MAX,
+            nsec: 0,
+        };
+        let late = DynSample::new(path, Value::Float(20.0), Some(late)).unwrap();
+        assert_eq!(mapping.encode(&late).unwrap().timestamp, 0);
+        let decoded = mapping.decode(&event, None);
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[1].path, path);
+
+        let speed = PropertyMapping::new(PERF_VEHICLE_SPEED, "Vehicle.Speed", Some("celsius"));
+        assert!(matches!(
+            VhalMapping::new(&VhalMappingConfig {
+                property: vec![speed]
+            }),
+            Err(VhalError::Unit { .. })
+        ));
+    }
+}
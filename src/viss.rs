@@ -0,0 +1,1184 @@
+// Check project root for LICENCE
+//! W3C VISS v2 over WebSockets on top of the generated topics
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use cyclonedds_rs::DdsParticipant;
+use futures_util::{SinkExt, StreamExt};
+use serde_derive::Deserialize;
+use serde_json::json;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::backend::{Backend, DdsBackend};
+use crate::cache::VehicleStateCache;
+use crate::catalog::{self, DynSample};
+use crate::naming::NamingPolicy;
+use crate::v3::{SignalKind, Timestamp, VssSignal};
+use crate::value::Value;
+
+/// The WebSocket subprotocol of VISS v2
+pub const SUBPROTOCOL: &str = "VISSv2";
+
+// How often the server takes new samples and evaluates the subscriptions
+const UPDATE_PERIOD: Duration = Duration::from_millis(10);
+
+/// The comparison of the `change` and `range` filters
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogicOp {
+    Eq,
+    Ne,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+}
+
+impl LogicOp {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "eq" => Some(LogicOp::Eq),
+            "ne" => Some(LogicOp::Ne),
+            "gt" => Some(LogicOp::Gt),
+            "gte" => Some(LogicOp::Gte),
+            "lt" => Some(LogicOp::Lt),
+            "lte" => Some(LogicOp::Lte),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LogicOp::Eq => "eq",
+            LogicOp::Ne => "ne",
+            LogicOp::Gt => "gt",
+            LogicOp::Gte => "gte",
+            LogicOp::Lt => "lt",
+            LogicOp::Lte => "lte",
+        }
+    }
+
+    pub fn eval(&self, a: f64, b: f64) -> bool {
+        match self {
+            LogicOp::Eq => a == b,
+            LogicOp::Ne => a != b,
+            LogicOp::Gt => a > b,
+            LogicOp::Gte => a >= b,
+            LogicOp::Lt => a < b,
+            LogicOp::Lte => a <= b,
+        }
+    }
+}
+
+/// A filter of a get or subscribe request
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    /// Paths relative to the path of the request, with `*` for any element
+    Paths(Vec<String>),
+    /// Notify the values at a fixed period
+    TimeBased(Duration),
+    /// Notify when the value changed from the last notified value, e.g. by
+    /// more than 10 with `gt` and 10. `ne` and 0 notifies every change.
+    Change(LogicOp, f64),
+    /// Notify when the value satisfies all the comparisons, e.g. `gt` 50
+    Range(Vec<(LogicOp, f64)>),
+}
+
+// A number that may be written as a string, as VISS does
+fn number(value: &serde_json::Value) -> Option<f64> {
+    match value {
+        serde_json::Value::String(s) => s.trim().parse().ok(),
+        v => v.as_f64(),
+    }
+}
+
+fn comparison(value: &serde_json::Value, operand: &str) -> Option<(LogicOp, f64)> {
+    Some((
+        LogicOp::parse(value.get("logic-op")?.as_str()?)?,
+        number(value.get(operand)?)?,
+    ))
+}
+
+impl Filter {
+    /// Parse the `filter` member of a request, a filter or an array of filters
+    pub fn parse(value: &serde_json::Value) -> Option<Vec<Filter>> {
+        if let Some(filters) = value.as_array() {
+            return filters.iter().map(Filter::parse_one).collect();
+        }
+        Some(vec![Filter::parse_one(value)?])
+    }
+
+    fn parse_one(value: &serde_json::Value) -> Option<Filter> {
+        let parameter = value.get("parameter")?;
+        match value.get("type")?.as_str()? {
+            "paths" => match parameter {
+                serde_json::Value::String(path) => Some(Filter::Paths(vec![path.clone()])),
+                serde_json::Value::Array(paths) => paths
+                    .iter()
+                    .map(|p| p.as_str().map(str::to_owned))
+                    .collect::<Option<_>>()
+                    .map(Filter::Paths),
+                _ => None,
+            },
+            "timebased" => {
+                let period = number(parameter.get("period")?)?;
+                let period = Duration::try_from_secs_f64(period / 1000.0).ok()?;
+                (!period.is_zero()).then_some(Filter::TimeBased(period))
+            }
+            "change" => {
+                let (op, diff) = comparison(parameter, "diff")?;
+                Some(Filter::Change(op, diff))
+            }
+            "range" => {
+                let comparisons = match parameter.as_array() {
+                    Some(a) => a.iter().map(|c| comparison(c, "boundary")).collect(),
+                    None => comparison(parameter, "boundary").map(|c| vec![c]),
+                }?;
+                Some(Filter::Range(comparisons))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        let comparison = |(op, value): &(LogicOp, f64), operand: &str| {
+            let mut comparison = json!({ "logic-op": op.as_str() });
+            comparison[operand] = json!(value.to_string());
+            comparison
+        };
+        match self {
+            Filter::Paths(paths) => json!({"type": "paths", "parameter": paths}),
+            Filter::TimeBased(period) => json!({
+                "type": "timebased",
+                "parameter": {"period": period.as_millis().to_string()}
+            }),
+            Filter::Change(op, diff) => {
+                json!({"type": "change", "parameter": comparison(&(*op, *diff), "diff")})
+            }
+            Filter::Range(comparisons) => json!({
+                "type": "range",
+                "parameter": comparisons
+                    .iter()
+                    .map(|c| comparison(c, "boundary"))
+                    .collect::<Vec<_>>()
+            }),
+        }
+    }
+}
+
+/// The error of a response
+#[derive(Debug, Clone, PartialEq)]
+pub struct VissError {
+    pub number: u16,
+    pub reason: String,
+    pub message: String,
+}
+
+impl fmt::Display for VissError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {}: {}", self.number, self.reason, self.message)
+    }
+}
+
+impl std::error::Error for VissError {}
+
+impl VissError {
+    pub fn new(number: u16, reason: &str, message: &str) -> Self {
+        Self {
+            number,
+            reason: reason.to_owned(),
+            message: message.to_owned(),
+        }
+    }
+
+    pub fn to_json(&self) -> serde_json::Value {
+        json!({
+            "number": self.number.to_string(),
+            "reason": self.reason,
+            "message": self.message
+        })
+    }
+
+    pub fn from_json(value: &serde_json::Value) -> Option<Self> {
+        Some(Self {
+            number: number(value.get("number")?)? as u16,
+            reason: value.get("reason")?.as_str()?.to_owned(),
+            message: value
+                .get("message")
+                .and_then(|m| m.as_str())
+                .unwrap_or_default()
+                .to_owned(),
+        })
+    }
+}
+
+/// A value as written by VISS, a string or an array of strings
+pub fn to_json(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Array(items) => items.iter().map(to_json).collect(),
+        value => serde_json::Value::String(value.to_string()),
+    }
+}
+
+/// A value of a datatype of the specification from VISS. Strings are parsed,
+/// plain JSON values are accepted too.
+pub fn from_json(datatype: &str, value: &serde_json::Value) -> Option<Value> {
+    match value {
+        serde_json::Value::String(s) => Value::parse(datatype, s),
+        serde_json::Value::Array(items) => {
+            let item = datatype.strip_suffix("[]")?;
+            items
+                .iter()
+                .map(|v| from_json(item, v))
+                .collect::<Option<_>>()
+                .map(Value::Array)
+        }
+        v => serde_json::from_value(v.clone()).ok(),
+    }
+}
+
+/// A time as written by VISS, e.g. `2023-10-19T10:00:00.250000Z`
+pub fn format_time(timestamp: &Timestamp) -> String {
+    DateTime::<Utc>::from_timestamp(timestamp.sec as i64, timestamp.nsec)
+        .unwrap_or_default()
+        .to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+pub fn parse_time(text: &str) -> Option<Timestamp> {
+    let time = DateTime::parse_from_rfc3339(text).ok()?;
+    Some(Timestamp {
+        sec: u64::try_from(time.timestamp()).ok()?,
+        nsec: time.timestamp_subsec_nanos(),
+    })
+}
+
+fn now() -> String {
+    format_time(&Timestamp::default())
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct VissRequest {
+    action: String,
+    path: Option<String>,
+    value: Option<serde_json::Value>,
+    filter: Option<serde_json::Value>,
+    request_id: Option<serde_json::Value>,
+    subscription_id: Option<serde_json::Value>,
+}
+
+struct Subscription {
+    client: u64,
+    patterns: Vec<String>,
+    period: Option<Duration>,
+    change: Option<(LogicOp, f64)>,
+    range: Vec<(LogicOp, f64)>,
+    last_values: BTreeMap<String, Value>,
+    last_time: Option<Instant>,
+}
+
+impl Subscription {
+    fn matches(&self, path: &str) -> bool {
+        self.patterns.iter().any(|p| catalog::glob_match(p, path))
+    }
+
+    fn in_range(&self, value: &Value) -> bool {
+        self.range
+            .iter()
+            .all(|(op, boundary)| value.as_f64().is_some_and(|v| op.eval(v, *boundary)))
+    }
+
+    fn changed(&self, path: &str, value: &Value) -> bool {
+        let last = match self.last_values.get(path) {
+            Some(last) => last,
+            None => return true,
+        };
+        match (self.change, value.as_f64(), last.as_f64()) {
+            (Some((op, diff)), Some(new), Some(old)) => op.eval(new - old, diff),
+            (Some(_), _, _) => value != last,
+            (None, _, _) => true,
+        }
+    }
+}
+
+fn data_point(path: &str, value: &Value, timestamp: Option<&Timestamp>) -> serde_json::Value {
+    json!({
+        "path": path,
+        "dp": {
+            "value": to_json(value),
+            "ts": timestamp.map(format_time).unwrap_or_else(now)
+        }
+    })
+}
+
+// One data point is written as an object, several as an array
+fn data(mut points: Vec<serde_json::Value>) -> serde_json::Value {
+    if points.len() == 1 {
+        points.remove(0)
+    } else {
+        serde_json::Value::Array(points)
+    }
+}
+
+/// A VISS v2 server. Get requests read the latest samples of the topics, set
+/// requests write the topics of actuators, and subscriptions notify the
+/// samples of the topics with the `timebased`, `change` and `range` filters.
+/// Paths are instance paths like `Vehicle.Cabin.Door.Row1.Left.IsOpen`, `*`
+/// matches an element, and the `paths` filter selects several signals below a
+/// branch.
+///
+/// The requests can be handled without a network with [VissServer::handle],
+/// or served over WebSockets with [VissServer::serve].
+pub struct VissServer<B: Backend> {
+    backend: B,
+    cache: VehicleStateCache,
+    // samples not yet evaluated by the subscriptions
+    pending: Vec<DynSample>,
+    subscriptions: BTreeMap<u64, Subscription>,
+    next_subscription: u64,
+}
+
+impl VissServer<DdsBackend> {
+    pub fn with_dds(participant: DdsParticipant, naming: NamingPolicy) -> Self {
+        Self::new(DdsBackend::new(participant, naming))
+    }
+}
+
+impl<B: Backend> VissServer<B> {
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend,
+            cache: VehicleStateCache::new(),
+            pending: Vec::new(),
+            subscriptions: BTreeMap::new(),
+            next_subscription: 1,
+        }
+    }
+
+    fn receive(&mut self) {
+        for sample in self.backend.take() {
+            self.cache.insert(sample.clone());
+            self.pending.push(sample);
+        }
+    }
+
+    // The patterns of the instance paths of a request. The signals are added
+    // to the backend.
+    fn patterns(
+        &mut self,
+        path: Option<&str>,
+        filters: &[Filter],
+    ) -> Result<Vec<String>, VissError> {
+        let path = path
+            .ok_or_else(|| VissError::new(400, "bad_request", "missing path"))?
+            .replace('/', ".");
+        let patterns: Vec<String> = match filters.iter().find_map(|f| match f {
+            Filter::Paths(paths) => Some(paths),
+            _ => None,
+        }) {
+            Some(paths) => paths
+                .iter()
+                .map(|p| format!("{}.{}", path, p.replace('/', ".")))
+                .collect(),
+            None => vec![path],
+        };
+        let mut found = false;
+        for pattern in &patterns {
+            for info in catalog::glob_instances(pattern) {
+                found = true;
+                self.backend
+                    .add(info)
+                    .map_err(|_| VissError::new(503, "service_unavailable", info.path))?;
+            }
+        }
+        if !found {
+            return Err(VissError::new(404, "invalid_path", &patterns.join(", ")));
+        }
+        Ok(patterns)
+    }
+
+    fn get(&mut self, request: &VissRequest) -> Result<serde_json::Value, VissError> {
+        let filters = self.filters(request)?;
+        let patterns = self.patterns(request.path.as_deref(), &filters)?;
+        self.receive();
+        let mut points = Vec::new();
+        for (path, cached) in self.cache.matching("**") {
+            if patterns.iter().any(|p| catalog::glob_match(p, path)) {
+                points.push(data_point(
+                    path,
+                    &cached.value,
+                    cached.source_timestamp.as_ref(),
+                ));
+            }
+        }
+        if points.is_empty() {
+            return Err(VissError::new(
+                404,
+                "unavailable_data",
+                "no value was received",
+            ));
+        }
+        Ok(json!({ "data": data(points) }))
+    }
+
+    fn set(&mut self, request: &VissRequest) -> Result<serde_json::Value, VissError> {
+        let path = request
+            .path
+            .as_deref()
+            .ok_or_else(|| VissError::new(400, "bad_request", "missing path"))?
+            .replace('/', ".");
+        let info = catalog::find_instance(&path)
+            .ok_or_else(|| VissError::new(404, "invalid_path", &path))?;
+        if info.kind != SignalKind::Actuator {
+            return Err(VissError::new(403, "read_only", &path));
+        }
+        let invalid = || VissError::new(400, "bad_request", "invalid value");
+        let value = request.value.as_ref().ok_or_else(invalid)?;
+        let value = from_json(info.datatype, value).ok_or_else(invalid)?;
+        let sample = DynSample::new(&path, value, Some(Timestamp::default()))
+            .and_then(DynSample::checked)
+            .ok_or_else(invalid)?;
+        // the topic of the actuator carries the current value, the request
+        // goes to its Target topic
+        self.backend
+            .write_target(&sample)
+            .map_err(|_| VissError::new(503, "service_unavailable", &path))?;
+        Ok(json!({}))
+    }
+
+    fn subscribe(
+        &mut self,
+        client: u64,
+        request: &VissRequest,
+    ) -> Result<serde_json::Value, VissError> {
+        let filters = self.filters(request)?;
+        let patterns = self.patterns(request.path.as_deref(), &filters)?;
+        let mut subscription = Subscription {
+            client,
+            patterns,
+            period: None,
+            change: None,
+            range: Vec::new(),
+            last_values: BTreeMap::new(),
+            last_time: None,
+        };
+        for filter in filters {
+            match filter {
+                Filter::Paths(_) => {}
+                Filter::TimeBased(period) => subscription.period = Some(period),
+                Filter::Change(op, diff) => subscription.change = Some((op, diff)),
+                Filter::Range(range) => subscription.range.extend(range),
+            }
+        }
+        let id = self.next_subscription;
+        self.next_subscription += 1;
+        self.subscriptions.insert(id, subscription);
+        Ok(json!({ "subscriptionId": id.to_string() }))
+    }
+
+    fn unsubscribe(
+        &mut self,
+        client: u64,
+        request: &VissRequest,
+    ) -> Result<serde_json::Value, VissError> {
+        let invalid = || VissError::new(400, "invalid_data", "unknown subscription");
+        let id = request
+            .subscription_id
+            .as_ref()
+            .and_then(number)
+            .ok_or_else(invalid)? as u64;
+        match self.subscriptions.get(&id) {
+            Some(s) if s.client == client => {
+                self.subscriptions.remove(&id);
+                Ok(json!({ "subscriptionId": id.to_string() }))
+            }
+            _ => Err(invalid()),
+        }
+    }
+
+    fn filters(&self, request: &VissRequest) -> Result<Vec<Filter>, VissError> {
+        match &request.filter {
+            Some(filter) => Filter::parse(filter)
+                .ok_or_else(|| VissError::new(400, "bad_request", "invalid filter")),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    /// Handle a request of a client and return the response. The client is
+    /// any number that identifies the connection.
+    pub fn handle(&mut self, client: u64, request: &str) -> String {
+        let request: VissRequest = match serde_json::from_str(request) {
+            Ok(request) => request,
+            Err(e) => {
+                let error = VissError::new(400, "bad_request", &e.to_string());
+                return json!({"error": error.to_json(), "ts": now()}).to_string();
+            }
+        };
+        let result = match request.action.as_str() {
+            "get" => self.get(&request),
+            "set" => self.set(&request),
+            "subscribe" => self.subscribe(client, &request),
+            "unsubscribe" => self.unsubscribe(client, &request),
+            _ => Err(VissError::new(400, "bad_request", "unknown action")),
+        };
+        let mut response = match result {
+            Ok(response) => response,
+            Err(error) => {
+                let mut response = json!({ "error": error.to_json() });
+                if let Some(id) = &request.subscription_id {
+                    response["subscriptionId"] = id.clone();
+                }
+                response
+            }
+        };
+        response["action"] = json!(request.action);
+        if let Some(id) = request.request_id {
+            response["requestId"] = id;
+        }
+        response["ts"] = json!(now());
+        response.to_string()
+    }
+
+    /// Take the new samples and evaluate the subscriptions. Returns the
+    /// notifications with the client they are for.
+    pub fn update(&mut self, instant: Instant) -> Vec<(u64, String)> {
+        self.receive();
+        let samples = std::mem::take(&mut self.pending);
+        let mut notifications = Vec::new();
+        for (id, subscription) in &mut self.subscriptions {
+            let mut points = Vec::new();
+            if let Some(period) = subscription.period {
+                if subscription
+                    .last_time
+                    .is_none_or(|last| instant.saturating_duration_since(last) >= period)
+                {
+                    subscription.last_time = Some(instant);
+                    for (path, cached) in self.cache.matching("**") {
+                        if subscription.matches(path) && subscription.in_range(&cached.value) {
+                            points.push(data_point(
+                                path,
+                                &cached.value,
+                                cached.source_timestamp.as_ref(),
+                            ));
+                        }
+                    }
+                }
+            } else {
+                for sample in &samples {
+                    if subscription.matches(&sample.path)
+                        && subscription.in_range(&sample.value)
+                        && subscription.changed(&sample.path, &sample.value)
+                    {
+                        subscription
+                            .last_values
+                            .insert(sample.path.clone(), sample.value.clone());
+                        points.push(data_point(
+                            &sample.path,
+                            &sample.value,
+                            sample.source_timestamp.as_ref(),
+                        ));
+                    }
+                }
+            }
+            if !points.is_empty() {
+                let notification = json!({
+                    "action": "subscription",
+                    "subscriptionId": id.to_string(),
+                    "data": data(points),
+                    "ts": now()
+                });
+                notifications.push((subscription.client, notification.to_string()));
+            }
+        }
+        notifications
+    }
+
+    /// Remove the subscriptions of a client
+    pub fn disconnect(&mut self, client: u64) {
+        self.subscriptions.retain(|_, s| s.client != client);
+    }
+}
+
+type Clients = Rc<RefCell<BTreeMap<u64, mpsc::UnboundedSender<String>>>>;
+
+impl<B: Backend + 'static> VissServer<B> {
+    /// Serve the clients that connect to the listener. This runs on the
+    /// current thread and must be called in a [tokio::task::LocalSet], see
+    /// [VissServer::run].
+    pub async fn serve(self, listener: TcpListener) -> io::Result<()> {
+        let server = Rc::new(RefCell::new(self));
+        let clients: Clients = Rc::default();
+        let (updated, notified) = (server.clone(), clients.clone());
+        tokio::task::spawn_local(async move {
+            let mut interval = tokio::time::interval(UPDATE_PERIOD);
+            loop {
+                interval.tick().await;
+                let notifications = updated.borrow_mut().update(Instant::now());
+                for (client, notification) in notifications {
+                    if let Some(sender) = notified.borrow().get(&client) {
+                        let _ = sender.send(notification);
+                    }
+                }
+            }
+        });
+        let mut next_client = 0;
+        loop {
+            let (stream, _) = listener.accept().await?;
+            next_client += 1;
+            tokio::task::spawn_local(connection(
+                server.clone(),
+                clients.clone(),
+                next_client,
+                stream,
+            ));
+        }
+    }
+
+    /// Serve the clients of the listener on the current thread until an error
+    /// occurs, e.g. on `127.0.0.1:8090`
+    pub fn run(self, listener: std::net::TcpListener) -> io::Result<()> {
+        listener.set_nonblocking(true)?;
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let local = tokio::task::LocalSet::new();
+        local.block_on(&runtime, async {
+            self.serve(TcpListener::from_std(listener)?).await
+        })
+    }
+}
+
+// Accept the VISS subprotocol if the client offers it. The signature is the
+// one of the handshake callbacks of tungstenite.
+#[allow(clippy::result_large_err)]
+fn subprotocol(request: &Request, mut response: Response) -> Result<Response, ErrorResponse> {
+    let offered = request
+        .headers()
+        .get("Sec-WebSocket-Protocol")
+        .and_then(|p| p.to_str().ok())
+        .is_some_and(|p| p.split(',').any(|p| p.trim() == SUBPROTOCOL));
+    if offered {
+        response
+            .headers_mut()
+            .insert("Sec-WebSocket-Protocol", SUBPROTOCOL.parse().unwrap());
+    }
+    Ok(response)
+}
+
+async fn connection<B: Backend>(
+    server: Rc<RefCell<VissServer<B>>>,
+    clients: Clients,
+    client: u64,
+    stream: TcpStream,
+) {
+    let socket = match tokio_tungstenite::accept_hdr_async(stream, subprotocol).await {
+        Ok(socket) => socket,
+        Err(_) => return,
+    };
+    let (mut sink, mut source) = socket.split();
+    let (sender, mut receiver) = mpsc::unbounded_channel();
+    clients.borrow_mut().insert(client, sender);
+    loop {
+        let outgoing = tokio::select! {
+            incoming = source.next() => match incoming {
+                Some(Ok(Message::Text(request))) => {
+                    server.borrow_mut().handle(client, request.as_str())
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => continue,
+            },
+            Some(notification) = receiver.recv() => notification,
+        };
+        if sink.send(Message::text(outgoing)).await.is_err() {
+            break;
+        }
+    }
+    clients.borrow_mut().remove(&client);
+    server.borrow_mut().disconnect(client);
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    WebSocket(tokio_tungstenite::tungstenite::Error),
+    Json(serde_json::Error),
+    /// The server responded with an error
+    Viss(VissError),
+    /// The server sent a value that does not fit the datatype of the signal
+    /// or is out of bounds, or a path that is not in the catalog
+    InvalidValue(String),
+    /// The server sent a message that is not a VISS message
+    Protocol(String),
+    Closed,
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::WebSocket(e) => e.fmt(f),
+            ClientError::Json(e) => e.fmt(f),
+            ClientError::Viss(e) => e.fmt(f),
+            ClientError::InvalidValue(message) => write!(f, "invalid value: {}", message),
+            ClientError::Protocol(message) => write!(f, "not a VISS message: {}", message),
+            ClientError::Closed => f.write_str("the connection is closed"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ClientError::WebSocket(e) => Some(e),
+            ClientError::Json(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<tokio_tungstenite::tungstenite::Error> for ClientError {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        ClientError::WebSocket(e)
+    }
+}
+
+impl From<serde_json::Error> for ClientError {
+    fn from(e: serde_json::Error) -> Self {
+        ClientError::Json(e)
+    }
+}
+
+/// The samples of a subscription sent by the server
+#[derive(Debug, Clone, PartialEq)]
+pub struct Notification {
+    pub subscription_id: String,
+    pub samples: Vec<DynSample>,
+}
+
+impl Notification {
+    /// The samples that are instances of `T`
+    pub fn samples<T: VssSignal>(&self) -> Vec<T> {
+        self.samples
+            .iter()
+            .filter_map(DynSample::to_sample)
+            .collect()
+    }
+}
+
+// The samples of the `data` member of a response or notification. The values
+// are checked against the datatype and the bounds of the signal.
+fn samples(data: &serde_json::Value) -> Result<Vec<DynSample>, ClientError> {
+    let points = match data {
+        serde_json::Value::Array(points) => points.as_slice(),
+        point => std::slice::from_ref(point),
+    };
+    let mut samples = Vec::new();
+    for point in points {
+        let path = point["path"]
+            .as_str()
+            .ok_or_else(|| ClientError::Protocol(point.to_string()))?;
+        let info =
+            catalog::find_instance(path).ok_or_else(|| ClientError::InvalidValue(path.into()))?;
+        let dps = match &point["dp"] {
+            serde_json::Value::Array(dps) => dps.as_slice(),
+            dp => std::slice::from_ref(dp),
+        };
+        for dp in dps {
+            let sample = from_json(info.datatype, &dp["value"])
+                .and_then(|value| {
+                    let timestamp = dp["ts"].as_str().and_then(parse_time);
+                    DynSample::new(path, value, timestamp)
+                })
+                .and_then(DynSample::checked)
+                .ok_or_else(|| ClientError::InvalidValue(path.into()))?;
+            samples.push(sample);
+        }
+    }
+    Ok(samples)
+}
+
+type Socket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<TcpStream>>;
+
+/// A VISS v2 client that returns the values as the generated types, e.g.
+/// [crate::v3::vehicle::Speed], after checking them against the datatype and
+/// the bounds of the specification.
+pub struct VissClient {
+    socket: Socket,
+    next_request: u64,
+    // notifications received while waiting for a response
+    notifications: std::collections::VecDeque<serde_json::Value>,
+}
+
+impl VissClient {
+    /// Connect to a server, e.g. `ws://127.0.0.1:8090`
+    pub async fn connect(url: &str) -> Result<Self, ClientError> {
+        use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+        let mut request = url.into_client_request()?;
+        request
+            .headers_mut()
+            .insert("Sec-WebSocket-Protocol", SUBPROTOCOL.parse().unwrap());
+        let (socket, _) = tokio_tungstenite::connect_async(request).await?;
+        Ok(Self {
+            socket,
+            next_request: 1,
+            notifications: Default::default(),
+        })
+    }
+
+    async fn receive(&mut self) -> Result<serde_json::Value, ClientError> {
+        loop {
+            match self.socket.next().await.ok_or(ClientError::Closed)?? {
+                Message::Text(text) => return Ok(serde_json::from_str(text.as_str())?),
+                Message::Close(_) => return Err(ClientError::Closed),
+                _ => {}
+            }
+        }
+    }
+
+    // Send a request and wait for its response
+    async fn request(
+        &mut self,
+        mut request: serde_json::Value,
+    ) -> Result<serde_json::Value, ClientError> {
+        let id = self.next_request.to_string();
+        self.next_request += 1;
+        request["requestId"] = json!(id);
+        self.socket.send(Message::text(request.to_string())).await?;
+        loop {
+            let message = self.receive().await?;
+            if message["action"] == "subscription" {
+                self.notifications.push_back(message);
+            } else if message["requestId"] == id.as_str() {
+                return match VissError::from_json(&message["error"]) {
+                    Some(error) => Err(ClientError::Viss(error)),
+                    None => Ok(message),
+                };
+            }
+        }
+    }
+
+    /// The samples of the instance paths that match the path and the filters
+    pub async fn get_dyn(
+        &mut self,
+        path: &str,
+        filters: &[Filter],
+    ) -> Result<Vec<DynSample>, ClientError> {
+        let mut request = json!({"action": "get", "path": path});
+        if !filters.is_empty() {
+            request["filter"] = filters.iter().map(Filter::to_json).collect();
+        }
+        samples(&self.request(request).await?["data"])
+    }
+
+    /// The latest sample of an instance of a signal
+    pub async fn get<T: VssSignal>(&mut self, key: &T::Key) -> Result<T, ClientError> {
+        let path = catalog::instance_path::<T>(key);
+        self.get_dyn(&path, &[])
+            .await?
+            .iter()
+            .find_map(DynSample::to_sample)
+            .ok_or(ClientError::InvalidValue(path))
+    }
+
+    /// Request a new value for an instance of an actuator. The server publishes
+    /// it on the Target topic of the actuator, [VissClient::get] returns the new
+    /// value once the actuator reports it.
+    pub async fn set<T: VssSignal>(
+        &mut self,
+        value: T::Value,
+        key: T::Key,
+    ) -> Result<(), ClientError> {
+        let request = json!({
+            "action": "set",
+            "path": catalog::instance_path::<T>(&key),
+            "value": to_json(&T::value_to_dyn(&value))
+        });
+        self.request(request).await.map(|_| ())
+    }
+
+    /// Subscribe to the instance paths that match the path and the filters.
+    /// Returns the id of the subscription, see [VissClient::notification].
+    pub async fn subscribe_dyn(
+        &mut self,
+        path: &str,
+        filters: &[Filter],
+    ) -> Result<String, ClientError> {
+        let mut request = json!({"action": "subscribe", "path": path});
+        if !filters.is_empty() {
+            request["filter"] = filters.iter().map(Filter::to_json).collect();
+        }
+        let response = self.request(request).await?;
+        response["subscriptionId"]
+            .as_str()
+            .map(str::to_owned)
+            .ok_or_else(|| ClientError::Protocol(response.to_string()))
+    }
+
+    /// Subscribe to an instance of a signal, see [Notification::samples]
+    pub async fn subscribe<T: VssSignal>(
+        &mut self,
+        key: &T::Key,
+        filters: &[Filter],
+    ) -> Result<String, ClientError> {
+        self.subscribe_dyn(&catalog::instance_path::<T>(key), filters)
+            .await
+    }
+
+    pub async fn unsubscribe(&mut self, subscription_id: &str) -> Result<(), ClientError> {
+        let request = json!({"action": "unsubscribe", "subscriptionId": subscription_id});
+        self.request(request).await.map(|_| ())
+    }
+
+    /// Wait for the next notification of any subscription
+    pub async fn notification(&mut self) -> Result<Notification, ClientError> {
+        let message = match self.notifications.pop_front() {
+            Some(message) => message,
+            None => loop {
+                let message = self.receive().await?;
+                if message["action"] == "subscription" {
+                    break message;
+                }
+            },
+        };
+        if let Some(error) = VissError::from_json(&message["error"]) {
+            return Err(ClientError::Viss(error));
+        }
+        Ok(Notification {
+            subscription_id: message["subscriptionId"]
+                .as_str()
+                .unwrap_or_default()
+                .to_owned(),
+            samples: samples(&message["data"])?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::Loopback;
+    use crate::v3::units::{KilometrePerHour, Percent};
+    use crate::v3::vehicle::cabin::hvac::station::FanSpeed;
+    use crate::v3::vehicle::Speed;
+    use crate::v3::Side;
+
+    type Socket = tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<TcpStream>>;
+
+    async fn receive(socket: &mut Socket) -> serde_json::Value {
+        let message = socket.next().await.unwrap().unwrap();
+        serde_json::from_str(message.to_text().unwrap()).unwrap()
+    }
+
+    async fn request(socket: &mut Socket, request: serde_json::Value) -> serde_json::Value {
+        socket
+            .send(Message::text(request.to_string()))
+            .await
+            .unwrap();
+        receive(socket).await
+    }
+
+    fn speed(value: f32) -> DynSample {
+        DynSample::of(&Speed::new(KilometrePerHour(value), None).unwrap())
+    }
+
+    #[test]
+    fn requests_over_websocket() {
+        let loopback = Loopback::new();
+        loopback.publish(speed(50.0));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = VissServer::new(loopback.clone());
+        std::thread::spawn(move || server.run(listener));
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}", address))
+                .await
+                .unwrap();
+
+            let reply = request(
+                &mut socket,
+                json!({"action": "get", "path": "Vehicle.Speed", "requestId": "1"}),
+            )
+            .await;
+            assert_eq!(reply["requestId"], "1");
+            assert_eq!(reply["data"]["dp"]["value"], "50");
+
+            let reply = request(
+                &mut socket,
+                json!({"action": "get", "path": "Vehicle.Sped", "requestId": "2"}),
+            )
+            .await;
+            assert_eq!(reply["error"]["number"], "404");
+
+            let reply = request(
+                &mut socket,
+                json!({"action": "set", "path": "Vehicle.Speed", "value": "3"}),
+            )
+            .await;
+            assert_eq!(reply["error"]["reason"], "read_only");
+
+            let fan = "Vehicle.Cabin.HVAC.Station.Row1.Left.FanSpeed";
+            loopback.publish(DynSample::new(fan, Value::UInt(20), None).unwrap());
+            let reply = request(
+                &mut socket,
+                json!({"action": "set", "path": fan, "value": "120"}),
+            )
+            .await;
+            assert_eq!(reply["error"]["number"], "400");
+            let reply = request(
+                &mut socket,
+                json!({"action": "set", "path": fan, "value": "40"}),
+            )
+            .await;
+            assert!(reply.get("error").is_none());
+            let reply = request(
+                &mut socket,
+                json!({
+                    "action": "get",
+                    "path": "Vehicle.Cabin.HVAC.Station",
+                    "filter": {"type": "paths", "parameter": ["*.*.FanSpeed"]}
+                }),
+            )
+            .await;
+            // the set is a request on the Target topic, the current value
+            // only changes when the actuator reports it
+            assert_eq!(reply["data"]["path"], fan);
+            assert_eq!(reply["data"]["dp"]["value"], "20");
+            let targets = loopback.clone().take_targets();
+            assert_eq!(targets.len(), 1);
+            assert_eq!(targets[0].path, fan);
+            assert_eq!(targets[0].value, Value::UInt(40));
+            let sent = targets[0].source_timestamp.as_ref().unwrap();
+            assert!(sent.sec >= Timestamp::default().sec - 60);
+
+            let reply = request(
+                &mut socket,
+                json!({
+                    "action": "subscribe",
+                    "path": "Vehicle.Speed",
+                    "filter": {"type": "range", "parameter": {"logic-op": "gt", "boundary": "60"}},
+                    "requestId": "3"
+                }),
+            )
+            .await;
+            let id = reply["subscriptionId"].clone();
+            loopback.publish(speed(55.0));
+            loopback.publish(speed(70.0));
+            let notification = receive(&mut socket).await;
+            assert_eq!(notification["action"], "subscription");
+            assert_eq!(notification["subscriptionId"], id);
+            assert_eq!(notification["data"]["dp"]["value"], "70");
+
+            let reply = request(
+                &mut socket,
+                json!({"action": "unsubscribe", "subscriptionId": id}),
+            )
+            .await;
+            assert!(reply.get("error").is_none());
+        });
+    }
+
+    #[test]
+    fn filters() {
+        let filter = json!([
+            {"type": "timebased", "parameter": {"period": "100"}},
+            {"type": "change", "parameter": {"logic-op": "ne", "diff": "0"}},
+            {"type": "range", "parameter": [
+                {"logic-op": "gte", "boundary": "10"},
+                {"logic-op": "lt", "boundary": 20}
+            ]}
+        ]);
+        let filters = Filter::parse(&filter).unwrap();
+        assert_eq!(filters[0], Filter::TimeBased(Duration::from_millis(100)));
+        assert_eq!(filters[1], Filter::Change(LogicOp::Ne, 0.0));
+        assert_eq!(
+            filters[2],
+            Filter::Range(vec![(LogicOp::Gte, 10.0), (LogicOp::Lt, 20.0)])
+        );
+        for period in ["0", "-5", "1e400"] {
+            let filter = json!({"type": "timebased", "parameter": {"period": period}});
+            assert!(Filter::parse(&filter).is_none());
+        }
+        let again: Vec<serde_json::Value> = filters.iter().map(Filter::to_json).collect();
+        assert_eq!(Filter::parse(&json!(again)).unwrap(), filters);
+
+        let mut server = VissServer::new(Loopback::new());
+        let reply = server.handle(
+            1,
+            r#"{"action": "subscribe", "path": "Vehicle.Speed",
+                "filter": {"type": "change", "parameter": {"logic-op": "gt", "diff": "5"}}}"#,
+        );
+        assert!(reply.contains("\"subscriptionId\":\"1\""));
+        server.backend.publish(speed(10.0));
+        assert_eq!(server.update(Instant::now()).len(), 1);
+        server.backend.publish(speed(12.0));
+        assert!(server.update(Instant::now()).is_empty());
+        server.backend.publish(speed(16.0));
+        assert_eq!(server.update(Instant::now()).len(), 1);
+    }
+
+    #[test]
+    fn typed_client() {
+        let loopback = Loopback::new();
+        loopback.publish(speed(50.0));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = VissServer::new(loopback.clone());
+        std::thread::spawn(move || server.run(listener));
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            let mut client = VissClient::connect(&format!("ws://{}", address))
+                .await
+                .unwrap();
+            let current: Speed = client.get(&()).await.unwrap();
+            assert_eq!(current.value, KilometrePerHour(50.0));
+            assert!(matches!(
+                client.set::<Speed>(KilometrePerHour(3.0), ()).await,
+                Err(ClientError::Viss(VissError { number: 403, .. }))
+            ));
+
+            client
+                .set::<FanSpeed>(Percent(40), (1, Side::Left))
+                .await
+                .unwrap();
+            // the actuator reports the requested value as its current value
+            for target in loopback.clone().take_targets() {
+                loopback.publish(target);
+            }
+            let fan: FanSpeed = client.get(&(1, Side::Left)).await.unwrap();
+            assert_eq!(fan.value, Percent(40));
+            // out of bounds values from the server are rejected
+            let path = "Vehicle.Cabin.HVAC.Station.Row1.Left.FanSpeed";
+            loopback.publish(DynSample::new(path, Value::UInt(140), None).unwrap());
+            assert!(matches!(
+                client.get::<FanSpeed>(&(1, Side::Left)).await,
+                Err(ClientError::InvalidValue(_))
+            ));
+
+            let id = client
+                .subscribe::<Speed>(&(), &[Filter::Change(LogicOp::Ne, 0.0)])
+                .await
+                .unwrap();
+            loopback.publish(speed(60.0));
+            let notification = client.notification().await.unwrap();
+            assert_eq!(notification.subscription_id, id);
+            let samples: Vec<Speed> = notification.samples();
+            assert_eq!(samples[0].value, KilometrePerHour(60.0));
+            client.unsubscribe(&id).await.unwrap();
+        });
+    }
+}
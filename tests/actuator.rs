@@ -0,0 +1,65 @@
+// Check project root for LICENCE
+//! The actuator service between a client and a server on a local DDS domain
+
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use cyclonedds_rs::DdsParticipant;
+use vehicle_signals::actuator::{ActuatorClient, ActuatorServer, ActuatorStatus};
+use vehicle_signals::v3::vehicle::cabin::door::IsOpen;
+use vehicle_signals::v3::{Actuator, Side, VssSignal};
+
+// Call `f` until it returns a value, for at most a few seconds
+fn wait_for<T>(mut f: impl FnMut() -> Option<T>) -> T {
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        if let Some(value) = f() {
+            return value;
+        }
+        assert!(Instant::now() < deadline, "timed out");
+        sleep(Duration::from_millis(10));
+    }
+}
+
+#[test]
+fn request_over_dds() {
+    let participant = DdsParticipant::create(None, None, None).unwrap();
+    let mut server = ActuatorServer::<IsOpen>::new(&participant)
+        .unwrap()
+        .with_tolerance(1.0);
+    let mut client = ActuatorClient::<IsOpen>::new(&participant).unwrap();
+
+    let id = client.request(true, (1, Side::Left)).unwrap();
+    let accepted = wait_for(|| server.poll().unwrap().pop());
+    assert_eq!(accepted.key(), (1, Side::Left));
+    assert!(*accepted.signal_value());
+    assert_eq!(
+        wait_for(|| client.poll().pop()),
+        (id, ActuatorStatus::Accepted)
+    );
+
+    // the door has not moved, the tolerance does not apply to booleans
+    let closed = IsOpen::new(false, None, 1, Side::Left).unwrap();
+    server.publish_current(closed).unwrap();
+    sleep(Duration::from_millis(100));
+    assert!(client.poll().is_empty());
+    assert!(client.is_pending(id));
+
+    let open = IsOpen::new(true, None, 1, Side::Left).unwrap();
+    server.publish_current(open).unwrap();
+    assert_eq!(
+        wait_for(|| client.poll().pop()),
+        (id, ActuatorStatus::Completed)
+    );
+    assert!(!client.is_pending(id));
+
+    // an instance that is not in the specification
+    let mut target = IsOpen::request(true, (1, Side::Left)).unwrap();
+    target.row = 9;
+    let id = client.send(target).unwrap();
+    let status = wait_for(|| {
+        assert!(server.poll().unwrap().is_empty());
+        client.poll().pop()
+    });
+    assert_eq!(status, (id, ActuatorStatus::Rejected));
+}